@@ -41,6 +41,28 @@ pub struct Stress {
 	#[clap(long)]
 	pub readers: Option<usize>,
 
+	/// Number of keys looked up at once by each reading thread. Batches are read with
+	/// `Db::get_multi` [default: 1].
+	#[clap(long)]
+	pub read_batch: Option<usize>,
+
+	/// Draw keys queried by reading threads from a Zipf distribution, so that a few keys are
+	/// read much more often than the rest.
+	#[clap(long)]
+	pub zipf: bool,
+
+	/// Number of recently read values to cache [default: 0].
+	#[clap(long)]
+	pub value_cache: Option<usize>,
+
+	/// Map this many bytes at the end of each value table into memory [default: 0].
+	#[clap(long)]
+	pub tail_map: Option<usize>,
+
+	/// Combine queued commits into log records of up to this many changes [default: 0].
+	#[clap(long)]
+	pub write_buffer: Option<usize>,
+
 	/// Number of iterating threads [default: 0].
 	#[clap(long)]
 	pub iter: Option<usize>,
@@ -76,11 +98,25 @@ pub struct Stress {
 	/// Use btree index.
 	#[clap(long)]
 	pub ordered: bool,
+
+	/// Reserve index capacity for all inserted keys before writing, see `Db::reserve`.
+	#[clap(long)]
+	pub reserve: bool,
+
+	/// Read index chunks on the rayon thread pool when reindexing, see
+	/// `Options::parallel_reindex`. Needs the `parallel-reindex` feature.
+	#[clap(long)]
+	pub parallel_reindex: bool,
 }
 
 #[derive(Clone)]
 pub struct Args {
 	pub readers: usize,
+	pub read_batch: usize,
+	pub zipf: bool,
+	pub value_cache: usize,
+	pub tail_map: usize,
+	pub write_buffer: usize,
 	pub iter: usize,
 	pub commits: usize,
 	pub writers: usize,
@@ -90,12 +126,19 @@ pub struct Args {
 	pub no_check: bool,
 	pub compress: bool,
 	pub ordered: bool,
+	pub reserve: bool,
+	pub parallel_reindex: bool,
 }
 
 impl Stress {
 	pub(super) fn get_args(&self) -> Args {
 		Args {
 			readers: self.readers.unwrap_or(0),
+			read_batch: self.read_batch.unwrap_or(1),
+			zipf: self.zipf,
+			value_cache: self.value_cache.unwrap_or(0),
+			tail_map: self.tail_map.unwrap_or(0),
+			write_buffer: self.write_buffer.unwrap_or(0),
 			iter: self.iter.unwrap_or(0),
 			writers: self.writers.unwrap_or(1),
 			commits: self.commits.unwrap_or(100_000),
@@ -105,6 +148,8 @@ impl Stress {
 			no_check: self.no_check,
 			compress: self.compress,
 			ordered: self.ordered,
+			reserve: self.reserve,
+			parallel_reindex: self.parallel_reindex,
 		}
 	}
 }
@@ -202,24 +247,54 @@ fn writer(
 	}
 }
 
-fn reader(db: Arc<Db>, pool: Arc<SizePool>, seed: u64, index: u64, shutdown: Arc<AtomicBool>) {
+// Key index in `0..num_keys`. With `zipf`, the `i`-th most recently written key is drawn with a
+// probability of roughly `1 / (i + 1)`, sampled as a log-uniform value.
+fn key_index(rng: &mut rand::rngs::SmallRng, num_keys: u64, zipf: bool) -> u64 {
+	if !zipf {
+		return rng.next_u64() % num_keys
+	}
+	let u = (rng.next_u64() >> 11) as f64 / (1u64 << 53) as f64;
+	let index = ((num_keys + 1) as f64).powf(u) as u64 - 1;
+	num_keys - 1 - std::cmp::min(index, num_keys - 1)
+}
+
+fn reader(
+	db: Arc<Db>,
+	pool: Arc<SizePool>,
+	seed: u64,
+	index: u64,
+	batch: usize,
+	zipf: bool,
+	shutdown: Arc<AtomicBool>,
+) {
 	// Query random keys while writing
 	let mut rng = rand::rngs::SmallRng::seed_from_u64(seed + index);
+	let mut keys = Vec::with_capacity(batch);
 	while !shutdown.load(Ordering::Relaxed) {
 		let commits = COMMITS.load(Ordering::Relaxed) as u64;
 		if commits == 0 {
 			continue
 		}
 		let num_keys = commits * COMMIT_SIZE as u64;
-		let key = pool.key(rng.next_u64() % num_keys + seed);
-		match db.get(0, &key).unwrap() {
-			Some(_) => {
-				QUERIES_HIT.fetch_add(1, Ordering::SeqCst);
-			},
-			None => {
-				QUERIES_MISS.fetch_add(1, Ordering::SeqCst);
-			},
+		if batch <= 1 {
+			let key = pool.key(key_index(&mut rng, num_keys, zipf) + seed);
+			match db.get(0, &key).unwrap() {
+				Some(_) => {
+					QUERIES_HIT.fetch_add(1, Ordering::SeqCst);
+				},
+				None => {
+					QUERIES_MISS.fetch_add(1, Ordering::SeqCst);
+				},
+			}
+			continue
 		}
+		keys.clear();
+		keys.extend((0..batch).map(|_| pool.key(key_index(&mut rng, num_keys, zipf) + seed)));
+		let key_refs: Vec<&[u8]> = keys.iter().map(|k| k.as_slice()).collect();
+		let values = db.get_multi(0, &key_refs).unwrap();
+		let hits = values.iter().filter(|v| v.is_some()).count();
+		QUERIES_HIT.fetch_add(hits, Ordering::SeqCst);
+		QUERIES_MISS.fetch_add(values.len() - hits, Ordering::SeqCst);
 	}
 }
 
@@ -255,6 +330,10 @@ pub fn run_internal(args: Args, db: Db) {
 	COMMITS.store(start_commit, Ordering::SeqCst);
 	NEXT_COMMIT.store(start_commit, Ordering::SeqCst);
 
+	if args.reserve && !args.ordered {
+		db.reserve(0, (args.commits * COMMIT_SIZE) as u64).unwrap();
+	}
+
 	{
 		let commits = args.commits;
 		let start = start_commit;
@@ -267,11 +346,13 @@ pub fn run_internal(args: Args, db: Db) {
 		let shutdown = shutdown.clone();
 		let offset = args.seed.unwrap_or(0);
 		let pool = pool.clone();
+		let batch = args.read_batch;
+		let zipf = args.zipf;
 
 		threads.push(
 			thread::Builder::new()
 				.name(format!("reader {}", i))
-				.spawn(move || reader(db, pool, offset, i as u64, shutdown))
+				.spawn(move || reader(db, pool, offset, i as u64, batch, zipf, shutdown))
 				.unwrap(),
 		);
 	}
@@ -329,6 +410,12 @@ pub fn run_internal(args: Args, db: Db) {
 		iterations,
 		(hits + misses) as f64 / elapsed,
 	);
+	if let Ok(Some(stats)) = db.get_value_cache_stats(0) {
+		println!(
+			"Value cache: {} entries, {} hits, {} misses",
+			stats.entries, stats.hits, stats.misses
+		);
+	}
 
 	if args.no_check {
 		return
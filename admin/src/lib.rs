@@ -115,6 +115,18 @@ pub fn run() -> Result<(), String> {
 			}
 
 			let mut db_options = options.clone();
+			if args.value_cache > 0 {
+				db_options.value_cache_capacity.insert(0, args.value_cache);
+			}
+			if args.tail_map > 0 {
+				db_options.tail_map_window.insert(0, args.tail_map);
+			}
+			if args.write_buffer > 0 {
+				db_options.write_buffer_capacity.insert(0, args.write_buffer);
+			}
+			if args.parallel_reindex {
+				db_options.parallel_reindex.insert(0);
+			}
 			if args.compress {
 				for mut c in &mut db_options.columns {
 					c.compression = parity_db::CompressionType::Lz4;
@@ -4,7 +4,7 @@
 use arbitrary::Arbitrary;
 use std::{
 	cmp::{Ordering, PartialOrd},
-	collections::HashMap,
+	collections::{HashMap, HashSet},
 	fmt::Debug,
 };
 use tempfile::tempdir;
@@ -145,6 +145,19 @@ pub trait DbSimulator {
 			stats: false,
 			salt: Some([0; 32]),
 			compression_threshold: HashMap::new(),
+			lock_timeout: None,
+			stats_log_interval: None,
+			audit_log: None,
+			changelog: HashSet::new(),
+			track_hot_keys: false,
+			value_cache_capacity: HashMap::new(),
+			write_buffer_capacity: HashMap::new(),
+			verbose_trace: HashSet::new(),
+			reindex_batch_size: HashMap::new(),
+			tail_map_window: HashMap::new(),
+			max_reindex_queue_depth: HashMap::new(),
+			parallel_reindex: HashSet::new(),
+			max_log_segment_bytes: 64 * 1024 * 1024,
 			always_flush: true,
 			with_background_thread: false,
 		};
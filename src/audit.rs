@@ -0,0 +1,107 @@
+// Copyright 2021-2022 Parity Technologies (UK) Ltd.
+// This file is dual-licensed as Apache-2.0 or MIT.
+
+//! Append-only audit trail of committed writes.
+
+use crate::{
+	column::ColId,
+	db::{CommitChangeSet, Operation},
+	error::{try_io, Result},
+	parking_lot::Mutex,
+};
+use std::{collections::VecDeque, io::Write, path::Path};
+
+/// Writes a JSON line for each changed key, e.g.
+/// `{"ts":1700000000,"col":0,"key":"0xab..","op":"insert","len":256}`.
+/// Keys of hash columns are logged hashed, as the original key is not kept.
+///
+/// Changes are kept in memory until their log record is enacted, so that the trail never
+/// claims a write that was not persisted. Changes of records that are still in the
+/// write-ahead log when the process stops are not recorded.
+#[derive(Debug)]
+pub struct AuditLog {
+	file: Mutex<std::fs::File>,
+	// Changes of log records that are not enacted yet, along with the record id.
+	pending: Mutex<VecDeque<(u64, Vec<Change>)>>,
+}
+
+#[derive(Debug)]
+struct Change {
+	col: ColId,
+	op: &'static str,
+	key: Vec<u8>,
+	len: usize,
+}
+
+impl AuditLog {
+	pub fn open(path: &Path) -> Result<AuditLog> {
+		let file = try_io!(std::fs::OpenOptions::new().create(true).append(true).open(path));
+		Ok(AuditLog { file: Mutex::new(file), pending: Default::default() })
+	}
+
+	/// Keep all changes of a commit that is written to log record `record_id`.
+	pub fn add(&self, record_id: u64, changeset: &CommitChangeSet) {
+		let mut changes = Vec::new();
+		let mut push = |col: ColId, op: &Operation<&[u8], &[u8]>| {
+			let (op, key, len) = match op {
+				Operation::Set(k, v) => ("insert", k, v.len()),
+				Operation::Dereference(k) => ("remove", k, 0),
+				Operation::Reference(k) => ("reference", k, 0),
+			};
+			changes.push(Change { col, op, key: key.to_vec(), len });
+		};
+		for (col, set) in &changeset.indexed {
+			for change in &set.changes {
+				push(*col, &as_slices(change));
+			}
+		}
+		for (col, set) in &changeset.btree_indexed {
+			for change in &set.changes {
+				push(*col, &as_slices(change));
+			}
+		}
+		if !changes.is_empty() {
+			self.pending.lock().push_back((record_id, changes));
+		}
+	}
+
+	/// Record the changes of all log records up to `record_id`, which was just enacted, with a
+	/// single write. The changes are already in the database at this point, so a failed write is
+	/// only logged.
+	pub fn enacted(&self, record_id: u64) {
+		let ts = std::time::SystemTime::now()
+			.duration_since(std::time::UNIX_EPOCH)
+			.map(|d| d.as_secs())
+			.unwrap_or(0);
+		let mut lines = String::new();
+		{
+			let mut pending = self.pending.lock();
+			while pending.front().is_some_and(|(id, _)| *id <= record_id) {
+				let (_, changes) = pending.pop_front().expect("Checked above; qed");
+				for Change { col, op, key, len } in changes {
+					lines.push_str(&format!(
+						"{{\"ts\":{},\"col\":{},\"key\":\"0x{}\",\"op\":\"{}\",\"len\":{}}}\n",
+						ts,
+						col,
+						hex::encode(key),
+						op,
+						len
+					));
+				}
+			}
+		}
+		if !lines.is_empty() {
+			if let Err(e) = self.file.lock().write_all(lines.as_bytes()) {
+				log::warn!(target: "parity-db", "Error writing audit log: {:?}", e);
+			}
+		}
+	}
+}
+
+fn as_slices<K: AsRef<[u8]>, V: AsRef<[u8]>>(op: &Operation<K, V>) -> Operation<&[u8], &[u8]> {
+	match op {
+		Operation::Set(k, v) => Operation::Set(k.as_ref(), v.as_ref()),
+		Operation::Dereference(k) => Operation::Dereference(k.as_ref()),
+		Operation::Reference(k) => Operation::Reference(k.as_ref()),
+	}
+}
@@ -0,0 +1,215 @@
+// Copyright 2021-2022 Parity Technologies (UK) Ltd.
+// This file is dual-licensed as Apache-2.0 or MIT.
+
+//! Single column backups.
+//!
+//! A backup is a directory holding copies of the index and value table files of a column,
+//! along with a manifest. The manifest records the column configuration and the BLAKE2b hash
+//! of each file, so that a backup is only restored into a matching column and not restored at
+//! all if any of its files is damaged. The manifest is written last, so an interrupted backup
+//! can't be restored.
+
+use crate::{
+	column::{ColId, Salt},
+	db::Db,
+	error::{try_io, Error, Result},
+	options::{ColumnOptions, Options},
+};
+use blake2::{digest::typenum::U32, Blake2b, Digest};
+use std::{
+	collections::BTreeMap,
+	io::{Read, Write},
+	path::Path,
+};
+
+const MANIFEST_FILE: &str = "manifest";
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+struct Manifest {
+	col: ColId,
+	version: u32,
+	salt: Salt,
+	options: String,
+	// File name and hash of each copied file.
+	files: BTreeMap<String, String>,
+}
+
+impl Manifest {
+	fn write(&self, dir: &Path) -> Result<()> {
+		let mut manifest = vec![
+			format!("col={}", self.col),
+			format!("version={}", self.version),
+			format!("salt={}", hex::encode(self.salt)),
+			format!("options={}", self.options),
+		];
+		for (file, hash) in &self.files {
+			manifest.push(format!("{}={}", file, hash));
+		}
+		try_io!(std::fs::write(dir.join(MANIFEST_FILE), manifest.join("\n")));
+		Ok(())
+	}
+
+	fn read(dir: &Path) -> Result<Manifest> {
+		let path = dir.join(MANIFEST_FILE);
+		if !path.exists() {
+			return Err(Error::InvalidInput(format!("No backup manifest in {}", dir.display())))
+		}
+		let bad_manifest = || Error::Corruption("Bad backup manifest".into());
+		let mut col = None;
+		let mut version = None;
+		let mut salt = None;
+		let mut options = None;
+		let mut files = BTreeMap::new();
+		for l in try_io!(std::fs::read_to_string(path)).lines() {
+			let (k, v) = l.split_once('=').ok_or_else(bad_manifest)?;
+			match k {
+				"col" => col = Some(v.parse().map_err(|_| bad_manifest())?),
+				"version" => version = Some(v.parse().map_err(|_| bad_manifest())?),
+				"salt" => {
+					let salt_slice = hex::decode(v).map_err(|_| bad_manifest())?;
+					let mut s = Salt::default();
+					if salt_slice.len() != s.len() {
+						return Err(bad_manifest())
+					}
+					s.copy_from_slice(&salt_slice);
+					salt = Some(s);
+				},
+				"options" => options = Some(v.to_string()),
+				file => {
+					files.insert(file.to_string(), v.to_string());
+				},
+			}
+		}
+		Ok(Manifest {
+			col: col.ok_or_else(bad_manifest)?,
+			version: version.ok_or_else(bad_manifest)?,
+			salt: salt.ok_or_else(bad_manifest)?,
+			options: options.ok_or_else(bad_manifest)?,
+			files,
+		})
+	}
+}
+
+fn is_column_file(col: ColId, file: &str) -> bool {
+	crate::index::TableId::is_file_name(col, file) || crate::table::TableId::is_file_name(col, file)
+}
+
+// Copy `from` to `to` and return the hex encoded hash of the content. The hash is computed
+// without copying if `to` is `None`.
+fn copy_hashed(from: &Path, to: Option<&Path>) -> Result<String> {
+	let mut source = try_io!(std::fs::File::open(from));
+	let mut dest = match to {
+		Some(to) => Some(try_io!(std::fs::File::create(to))),
+		None => None,
+	};
+	let mut hasher = Blake2b::<U32>::new();
+	let mut buf = vec![0u8; COPY_BUFFER_SIZE];
+	loop {
+		let read = try_io!(source.read(&mut buf));
+		if read == 0 {
+			break
+		}
+		hasher.update(&buf[..read]);
+		if let Some(dest) = &mut dest {
+			try_io!(dest.write_all(&buf[..read]));
+		}
+	}
+	if let Some(dest) = dest {
+		try_io!(dest.sync_all());
+	}
+	Ok(hex::encode(hasher.finalize()))
+}
+
+/// Copy the files of column `col` of the database at `path` to the empty or missing directory
+/// `dest`. The caller makes sure the files are not modified meanwhile.
+pub(crate) fn backup_column(
+	path: &Path,
+	col: ColId,
+	options: &ColumnOptions,
+	salt: &Salt,
+	version: u32,
+	dest: &Path,
+) -> Result<()> {
+	if dest.exists() && try_io!(std::fs::read_dir(dest)).next().is_some() {
+		return Err(Error::InvalidInput(format!(
+			"Backup destination {} is not empty",
+			dest.display()
+		)))
+	}
+	try_io!(std::fs::create_dir_all(dest));
+	let mut manifest = Manifest {
+		col,
+		version,
+		salt: *salt,
+		options: options.as_string(),
+		files: BTreeMap::new(),
+	};
+	for entry in try_io!(std::fs::read_dir(path)) {
+		let entry = try_io!(entry);
+		if let Some(file) = entry.file_name().to_str() {
+			if is_column_file(col, file) {
+				let hash = copy_hashed(&entry.path(), Some(&dest.join(file)))?;
+				manifest.files.insert(file.to_string(), hash);
+			}
+		}
+	}
+	manifest.write(dest)?;
+	log::info!(target: "parity-db", "Backed up column {} to {}", col, dest.display());
+	Ok(())
+}
+
+/// Replace column `column` of the database at `path` with a backup made by `Db::backup_column`
+/// from directory `src`. The column must be configured the same way as when the backup was
+/// made, in a database with the same salt. All backup files are checked against the hashes
+/// in the backup manifest before the current column files are deleted. Database must be closed
+/// before calling this.
+///
+/// If restoring is interrupted, the column is left incomplete and the restore needs to be
+/// repeated.
+pub fn restore_column(path: &Path, column: ColId, src: &Path) -> Result<()> {
+	let meta = Options::load_metadata(path)?
+		.ok_or_else(|| Error::Migration("Error loading source metadata".into()))?;
+
+	if (column as usize) >= meta.columns.len() {
+		return Err(Error::Migration("Invalid column index".into()))
+	}
+	let manifest = Manifest::read(src)?;
+	if manifest.col != column {
+		return Err(Error::InvalidConfiguration(format!(
+			"Backup of column {} can't be restored to column {}",
+			manifest.col, column
+		)))
+	}
+	let options = meta.columns[column as usize].as_string();
+	if manifest.version != meta.version || manifest.salt != meta.salt || manifest.options != options
+	{
+		return Err(Error::InvalidConfiguration(format!(
+			"Backup column config mismatch. Expected \"{}\", got \"{}\"",
+			options, manifest.options
+		)))
+	}
+	for (file, hash) in &manifest.files {
+		if !is_column_file(column, file) {
+			return Err(Error::Corruption(format!("Unexpected backup file {}", file)))
+		}
+		if copy_hashed(&src.join(file), None)? != *hash {
+			return Err(Error::Corruption(format!("Backup file {} does not match its hash", file)))
+		}
+	}
+
+	// Validate the database by opening. This also makes sure all the logs are enacted, so that
+	// no leftover commit writes to the restored column.
+	let mut options = Options::with_columns(path, meta.columns.len() as u8);
+	options.columns = meta.columns;
+	options.salt = Some(meta.salt);
+	drop(Db::open(&options)?);
+
+	crate::migration::remove_column_files(path, column)?;
+	for (file, hash) in &manifest.files {
+		if copy_hashed(&src.join(file), Some(&path.join(file)))? != *hash {
+			return Err(Error::Corruption(format!("Backup file {} changed while restoring", file)))
+		}
+	}
+	log::info!(target: "parity-db", "Restored column {} from {}", column, src.display());
+	Ok(())
+}
@@ -0,0 +1,211 @@
+// Copyright 2021-2022 Parity Technologies (UK) Ltd.
+// This file is dual-licensed as Apache-2.0 or MIT.
+
+//! Counting Bloom filter over hash column keys, used to skip index probes for missing keys.
+
+use crate::{
+	parking_lot::{Mutex, MutexGuard, RwLock},
+	Key,
+};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Filters are never sized for fewer keys than this.
+const MIN_CAPACITY: u64 = 1 << 16;
+const MAX_HASHES: u32 = 16;
+
+/// Bloom filter with 8-bit counters, so that keys can be removed. Saturated counters are never
+/// decremented, which may only lead to false positives.
+///
+/// Keys are filtered by their first 48 bits, which can be recovered from index entries, so the
+/// filter can be rebuilt without reading value tables.
+#[derive(Debug)]
+pub struct BloomFilter {
+	counters: Vec<u8>,
+	hashes: u32,
+	entries: u64,
+	removed: u64,
+	capacity: u64,
+}
+
+impl BloomFilter {
+	/// Create a filter holding at least `entries` keys with the given false positive rate. The
+	/// filter is sized for twice as many keys to leave room for growth.
+	pub fn new(entries: u64, false_positive_rate: f64) -> BloomFilter {
+		let capacity = std::cmp::max(entries.saturating_mul(2), MIN_CAPACITY);
+		let ln2 = std::f64::consts::LN_2;
+		let bits = (-(capacity as f64) * false_positive_rate.ln() / (ln2 * ln2)).ceil();
+		let bits = std::cmp::max(bits as u64, 64);
+		let hashes = ((bits as f64 / capacity as f64) * ln2).round() as u32;
+		BloomFilter {
+			counters: vec![0; bits as usize],
+			hashes: hashes.clamp(1, MAX_HASHES),
+			entries: 0,
+			removed: 0,
+			capacity,
+		}
+	}
+
+	fn slots(&self, key: &Key) -> impl Iterator<Item = usize> {
+		let prefix = u64::from_be_bytes(key[0..8].try_into().unwrap()) >> 16;
+		let h1 = mix(prefix);
+		let h2 = mix(h1) | 1;
+		let len = self.counters.len() as u64;
+		(0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+	}
+
+	pub fn insert(&mut self, key: &Key) {
+		for slot in self.slots(key) {
+			self.counters[slot] = self.counters[slot].saturating_add(1);
+		}
+		self.entries += 1;
+	}
+
+	pub fn remove(&mut self, key: &Key) {
+		for slot in self.slots(key) {
+			let counter = &mut self.counters[slot];
+			if *counter != 0 && *counter != u8::MAX {
+				*counter -= 1;
+			}
+		}
+		self.entries = self.entries.saturating_sub(1);
+		self.removed += 1;
+	}
+
+	/// Returns `false` if the key is definitely absent.
+	pub fn may_contain(&self, key: &Key) -> bool {
+		self.slots(key).all(|slot| self.counters[slot] != 0)
+	}
+
+	/// The filter holds more keys than it was sized for and should be rebuilt.
+	pub fn is_full(&self) -> bool {
+		self.entries > self.capacity
+	}
+}
+
+// splitmix64 finalizer.
+fn mix(mut x: u64) -> u64 {
+	x = x.wrapping_add(0x9e3779b97f4a7c15);
+	x = (x ^ (x >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+	x = (x ^ (x >> 27)).wrapping_mul(0x94d049bb133111eb);
+	x ^ (x >> 31)
+}
+
+/// Bloom filter statistics of a column.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BloomStats {
+	/// Filter size in bytes.
+	pub size: u64,
+	/// Keys added to the filter since it was last built.
+	pub entries: u64,
+	/// Keys removed from the filter since it was last built. Removed keys may still match.
+	pub removed: u64,
+	/// Number of lookups checked against the filter.
+	pub queries: u64,
+	/// Number of lookups answered by the filter without probing the index.
+	pub rejected: u64,
+}
+
+/// Bloom filter of a hash column along with its query counters.
+#[derive(Debug)]
+pub struct ColumnBloom {
+	filter: RwLock<BloomFilter>,
+	// Keys added while a rebuild is in progress, which the index scan may miss.
+	added: Mutex<Option<Vec<Key>>>,
+	rebuild: Mutex<()>,
+	full: AtomicBool,
+	false_positive_rate: f64,
+	queries: AtomicU64,
+	rejected: AtomicU64,
+}
+
+impl ColumnBloom {
+	pub fn new(false_positive_rate: f64) -> ColumnBloom {
+		ColumnBloom {
+			filter: RwLock::new(BloomFilter::new(0, false_positive_rate)),
+			added: Mutex::new(None),
+			rebuild: Mutex::new(()),
+			full: AtomicBool::new(false),
+			false_positive_rate,
+			queries: AtomicU64::new(0),
+			rejected: AtomicU64::new(0),
+		}
+	}
+
+	/// Start a rebuild, waiting for the one in progress if any. Keys added from now on are kept
+	/// for the new filter, so the index must be scanned after this returns.
+	pub fn start_rebuild(&self) -> BloomRebuild<'_> {
+		let lock = self.rebuild.lock();
+		self.full.store(false, Ordering::Relaxed);
+		*self.added.lock() = Some(Vec::new());
+		BloomRebuild { bloom: self, _lock: lock }
+	}
+
+	/// The filter holds more keys than it was sized for and should be rebuilt.
+	pub fn is_full(&self) -> bool {
+		self.full.load(Ordering::Relaxed)
+	}
+
+	pub fn may_contain(&self, key: &Key) -> bool {
+		self.queries.fetch_add(1, Ordering::Relaxed);
+		let found = self.filter.read().may_contain(key);
+		if !found {
+			self.rejected.fetch_add(1, Ordering::Relaxed);
+		}
+		found
+	}
+
+	pub fn insert(&self, key: &Key) {
+		let mut filter = self.filter.write();
+		filter.insert(key);
+		if let Some(added) = &mut *self.added.lock() {
+			added.push(*key);
+		}
+		if filter.is_full() {
+			self.full.store(true, Ordering::Relaxed);
+		}
+	}
+
+	pub fn remove(&self, key: &Key) {
+		self.filter.write().remove(key);
+	}
+
+	pub fn stats(&self) -> BloomStats {
+		let filter = self.filter.read();
+		BloomStats {
+			size: filter.counters.len() as u64,
+			entries: filter.entries,
+			removed: filter.removed,
+			queries: self.queries.load(Ordering::Relaxed),
+			rejected: self.rejected.load(Ordering::Relaxed),
+		}
+	}
+}
+
+/// Bloom filter rebuild in progress, see `ColumnBloom::start_rebuild`.
+pub struct BloomRebuild<'a> {
+	bloom: &'a ColumnBloom,
+	_lock: MutexGuard<'a, ()>,
+}
+
+impl BloomRebuild<'_> {
+	/// Replace the filter with one sized for `keys`, read from the index, that also holds the keys
+	/// added since the rebuild was started. Keys removed in the meantime are kept, which may only
+	/// lead to false positives.
+	pub fn finish(self, keys: &[Key]) {
+		let mut filter = BloomFilter::new(keys.len() as u64, self.bloom.false_positive_rate);
+		for key in keys {
+			filter.insert(key);
+		}
+		let mut current = self.bloom.filter.write();
+		for key in self.bloom.added.lock().take().into_iter().flatten() {
+			filter.insert(&key);
+		}
+		*current = filter;
+	}
+}
+
+impl Drop for BloomRebuild<'_> {
+	fn drop(&mut self) {
+		*self.bloom.added.lock() = None;
+	}
+}
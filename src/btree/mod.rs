@@ -12,12 +12,13 @@ use crate::{
 	parking_lot::RwLock,
 	table::{
 		key::{TableKey, TableKeyQuery},
-		Entry as ValueTableEntry, Value, ValueTable,
+		Entry as ValueTableEntry, TableId, Value, ValueTable,
 	},
 	Operation,
 };
 pub use iter::{BTreeIterator, LastIndex, LastKey};
 use node::SeparatorInner;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 #[allow(clippy::module_inception)]
 mod btree;
@@ -122,6 +123,8 @@ pub struct BTreeTable {
 	tables: RwLock<Vec<ValueTable>>,
 	ref_counted: bool,
 	compression: Compress,
+	verbose_trace: bool,
+	read_only: AtomicBool,
 }
 
 impl BTreeTable {
@@ -131,13 +134,7 @@ impl BTreeTable {
 		options: &Options,
 		metadata: &Metadata,
 	) -> Result<Self> {
-		let size_tier = HEADER_ADDRESS.size_tier() as usize;
-		if !values[size_tier].is_init() {
-			let btree_header = BTreeHeader { root: NULL_ADDRESS, depth: 0 };
-			let mut entry = Entry::empty();
-			entry.write_header(&btree_header);
-			values[size_tier].init_with_entry(&*entry.encoded.inner_mut())?;
-		}
+		Self::init_header(&values)?;
 		let col_options = &metadata.columns[id as usize];
 		Ok(BTreeTable {
 			id,
@@ -151,9 +148,42 @@ impl BTreeTable {
 					.copied()
 					.unwrap_or(DEFAULT_COMPRESSION_THRESHOLD),
 			),
+			verbose_trace: options.verbose_trace.contains(&id),
+			read_only: AtomicBool::new(false),
 		})
 	}
 
+	pub fn set_read_only(&self) {
+		self.read_only.store(true, Ordering::Relaxed);
+	}
+
+	fn check_writable(&self) -> Result<()> {
+		if self.read_only.load(Ordering::Relaxed) {
+			return Err(Error::ReadOnly)
+		}
+		Ok(())
+	}
+
+	fn init_header(values: &[ValueTable]) -> Result<()> {
+		let size_tier = HEADER_ADDRESS.size_tier() as usize;
+		if !values[size_tier].is_init() {
+			let btree_header = BTreeHeader { root: NULL_ADDRESS, depth: 0 };
+			let mut entry = Entry::empty();
+			entry.write_header(&btree_header);
+			values[size_tier].init_with_entry(&*entry.encoded.inner_mut())?;
+		}
+		Ok(())
+	}
+
+	/// Remove all value table files and start with an empty tree.
+	pub fn hard_reset(&self) -> Result<()> {
+		let tables = self.tables.write();
+		for table in tables.iter() {
+			table.clear()?;
+		}
+		Self::init_header(&tables)
+	}
+
 	fn btree_header(log: &impl LogQuery, values: TablesRef) -> Result<BTreeHeader> {
 		let mut root = NULL_ADDRESS;
 		let mut depth = 0;
@@ -212,6 +242,7 @@ impl BTreeTable {
 			preimage: false,
 			compression: &self.compression,
 			col: self.id,
+			verbose_trace: self.verbose_trace,
 		}
 	}
 
@@ -221,13 +252,24 @@ impl BTreeTable {
 		apply(locked)
 	}
 
+	// Value table of a log action.
+	fn logged_table(tables: &[ValueTable], table: TableId) -> Result<&ValueTable> {
+		tables
+			.get(table.size_tier() as usize)
+			.ok_or_else(|| Error::Corruption(format!("Missing value table {}", table)))
+	}
+
 	pub fn enact_plan(&self, action: LogAction, log: &mut LogReader) -> Result<()> {
+		self.check_writable()?;
 		let tables = self.tables.read();
 		match action {
 			LogAction::InsertValue(record) => {
-				tables[record.table.size_tier() as usize].enact_plan(record.index, log)?;
+				Self::logged_table(&tables, record.table)?.enact_plan(record.index, log)?;
+			},
+			LogAction::DeleteValue { table, index } => {
+				Self::logged_table(&tables, table)?.enact_delete_plan(index, log)?;
 			},
-			_ => panic!("Unexpected log action"),
+			_ => return Err(Error::Corruption("Unexpected log action".to_string())),
 		}
 		Ok(())
 	}
@@ -236,7 +278,10 @@ impl BTreeTable {
 		let tables = self.tables.upgradable_read();
 		match action {
 			LogAction::InsertValue(record) => {
-				tables[record.table.size_tier() as usize].validate_plan(record.index, log)?;
+				Self::logged_table(&tables, record.table)?.validate_plan(record.index, log)?;
+			},
+			LogAction::DeleteValue { table, index } => {
+				Self::logged_table(&tables, table)?.validate_delete_plan(index, log)?;
 			},
 			_ => {
 				log::error!(target: "parity-db", "Unexpected log action");
@@ -247,6 +292,7 @@ impl BTreeTable {
 	}
 
 	pub fn complete_plan(&self, log: &mut LogWriter) -> Result<()> {
+		self.check_writable()?;
 		let tables = self.tables.read();
 		for t in tables.iter() {
 			t.complete_plan(log)?;
@@ -434,6 +480,7 @@ pub mod commit_overlay {
 			writer: &mut LogWriter,
 			ops: &mut u64,
 		) -> Result<()> {
+			btree.check_writable()?;
 			let record_id = writer.record_id();
 
 			let locked_tables = btree.tables.read();
@@ -0,0 +1,120 @@
+// Copyright 2021-2022 Parity Technologies (UK) Ltd.
+// This file is dual-licensed as Apache-2.0 or MIT.
+
+//! Read cache for hash column values.
+
+use crate::{parking_lot::RwLock, table::Value, Key};
+use std::{
+	collections::HashMap,
+	sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Value cache statistics of a column.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ValueCacheStats {
+	/// Number of cached values.
+	pub entries: u64,
+	/// Lookups answered from the cache.
+	pub hits: u64,
+	/// Lookups that had to read the value table.
+	pub misses: u64,
+}
+
+#[derive(Debug)]
+struct CachedValue {
+	key: Key,
+	value: Value,
+	last_used: AtomicU64,
+}
+
+/// Values keyed by size tier and entry index. Lookups only take a read lock and update the
+/// access time of the entry, so eviction is approximately LRU: once the cache is full, the
+/// least recently used eighth of it is dropped at once.
+#[derive(Debug)]
+pub struct ValueCache {
+	entries: RwLock<HashMap<(u8, u64), CachedValue>>,
+	capacity: usize,
+	clock: AtomicU64,
+	// Incremented on every invalidation, so that values read before an entry was overwritten
+	// are not inserted afterwards.
+	generation: AtomicU64,
+	hits: AtomicU64,
+	misses: AtomicU64,
+}
+
+impl ValueCache {
+	pub fn new(capacity: usize) -> ValueCache {
+		ValueCache {
+			entries: RwLock::new(HashMap::new()),
+			capacity,
+			clock: AtomicU64::new(0),
+			generation: AtomicU64::new(0),
+			hits: AtomicU64::new(0),
+			misses: AtomicU64::new(0),
+		}
+	}
+
+	/// Current generation, to be passed to `insert` for a value that is about to be read.
+	pub fn generation(&self) -> u64 {
+		self.generation.load(Ordering::SeqCst)
+	}
+
+	/// Value stored for `key` at the given entry.
+	pub fn get(&self, tier: u8, index: u64, key: &Key) -> Option<Value> {
+		let entries = self.entries.read();
+		match entries.get(&(tier, index)) {
+			Some(cached) if cached.key == *key => {
+				cached
+					.last_used
+					.store(self.clock.fetch_add(1, Ordering::Relaxed), Ordering::Relaxed);
+				self.hits.fetch_add(1, Ordering::Relaxed);
+				Some(cached.value.clone())
+			},
+			_ => {
+				self.misses.fetch_add(1, Ordering::Relaxed);
+				None
+			},
+		}
+	}
+
+	/// Cache a value that was read from the given entry. Ignored if any entry was invalidated
+	/// since `generation` was obtained.
+	pub fn insert(&self, tier: u8, index: u64, key: &Key, value: &Value, generation: u64) {
+		if self.capacity == 0 {
+			return
+		}
+		let mut entries = self.entries.write();
+		if self.generation.load(Ordering::SeqCst) != generation {
+			return
+		}
+		let last_used = AtomicU64::new(self.clock.fetch_add(1, Ordering::Relaxed));
+		entries.insert((tier, index), CachedValue { key: *key, value: value.clone(), last_used });
+		if entries.len() > self.capacity {
+			let mut by_age: Vec<_> = entries
+				.iter()
+				.map(|(address, cached)| (cached.last_used.load(Ordering::Relaxed), *address))
+				.collect();
+			let evict = std::cmp::max(by_age.len() / 8, 1);
+			by_age.select_nth_unstable(evict - 1);
+			for (_, address) in &by_age[..evict] {
+				entries.remove(address);
+			}
+		}
+	}
+
+	/// Drop the value cached for an entry that is being overwritten.
+	pub fn invalidate(&self, tier: u8, index: u64) {
+		self.generation.fetch_add(1, Ordering::SeqCst);
+		if self.entries.read().contains_key(&(tier, index)) {
+			self.entries.write().remove(&(tier, index));
+		}
+	}
+
+	pub fn stats(&self) -> ValueCacheStats {
+		ValueCacheStats {
+			entries: self.entries.read().len() as u64,
+			hits: self.hits.load(Ordering::Relaxed),
+			misses: self.misses.load(Ordering::Relaxed),
+		}
+	}
+}
@@ -0,0 +1,118 @@
+// Copyright 2021-2022 Parity Technologies (UK) Ltd.
+// This file is dual-licensed as Apache-2.0 or MIT.
+
+//! Append-only archive of the changes made by sequenced commits, see `Db::get_changelog`.
+
+use crate::{
+	column::ColId,
+	db::Value,
+	error::{try_io, Result},
+	parking_lot::Mutex,
+	Key,
+};
+use std::{io::Write, path::Path};
+
+pub const CHANGELOG_FILE: &str = "changelog";
+
+// Length written for a missing value.
+const NO_VALUE: u32 = u32::MAX;
+
+/// A change of a single key, see `Db::get_changelog`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEntry {
+	/// Sequence number of the commit, as passed to `Db::write_with_sequence`.
+	pub seq: u64,
+	/// Hashed key, as the original key is not kept.
+	pub key: Key,
+	/// Value before the commit, or `None` if the key was missing.
+	pub old_value: Option<Value>,
+	/// Value after the commit, or `None` if the key was removed.
+	pub new_value: Option<Value>,
+}
+
+/// Each entry is written as the sequence number, column and key, followed by the old and new
+/// values, each prefixed with a 32-bit length.
+#[derive(Debug)]
+pub struct ChangeLog {
+	file: Mutex<std::fs::File>,
+}
+
+impl ChangeLog {
+	pub fn open(path: &Path) -> Result<ChangeLog> {
+		let file = try_io!(std::fs::OpenOptions::new()
+			.create(true)
+			.append(true)
+			.open(path.join(CHANGELOG_FILE)));
+		Ok(ChangeLog { file: Mutex::new(file) })
+	}
+
+	/// Append `entries` with a single write.
+	pub fn record(&self, entries: &[(ColId, ChangeEntry)]) -> Result<()> {
+		if entries.is_empty() {
+			return Ok(())
+		}
+		let mut buf = Vec::new();
+		for (col, entry) in entries {
+			buf.extend_from_slice(&entry.seq.to_le_bytes());
+			buf.push(*col);
+			buf.extend_from_slice(&entry.key);
+			for value in [&entry.old_value, &entry.new_value] {
+				match value {
+					Some(value) => {
+						buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+						buf.extend_from_slice(value);
+					},
+					None => buf.extend_from_slice(&NO_VALUE.to_le_bytes()),
+				}
+			}
+		}
+		try_io!(self.file.lock().write_all(&buf));
+		Ok(())
+	}
+}
+
+/// Read all entries of the changelog in the database directory `path`. An entry cut short by a
+/// crash at the end of the file is ignored.
+pub fn read(path: &Path) -> Result<Vec<(ColId, ChangeEntry)>> {
+	let path = path.join(CHANGELOG_FILE);
+	if !path.exists() {
+		return Ok(Vec::new())
+	}
+	let data = try_io!(std::fs::read(path));
+	let mut reader = Reader { data: &data, pos: 0 };
+	let mut entries = Vec::new();
+	while let Some(entry) = reader.entry() {
+		entries.push(entry);
+	}
+	Ok(entries)
+}
+
+struct Reader<'a> {
+	data: &'a [u8],
+	pos: usize,
+}
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+		let bytes = self.data.get(self.pos..self.pos.checked_add(len)?)?;
+		self.pos += len;
+		Some(bytes)
+	}
+
+	fn value(&mut self) -> Option<Option<Value>> {
+		let len = u32::from_le_bytes(self.take(4)?.try_into().ok()?);
+		if len == NO_VALUE {
+			return Some(None)
+		}
+		Some(Some(self.take(len as usize)?.to_vec()))
+	}
+
+	fn entry(&mut self) -> Option<(ColId, ChangeEntry)> {
+		let seq = u64::from_le_bytes(self.take(8)?.try_into().ok()?);
+		let col = self.take(1)?[0];
+		let key = self.take(32)?.try_into().ok()?;
+		let old_value = self.value()?;
+		let new_value = self.value()?;
+		Some((col, ChangeEntry { seq, key, old_value, new_value }))
+	}
+}
@@ -1,34 +1,57 @@
 // Copyright 2021-2022 Parity Technologies (UK) Ltd.
 // This file is dual-licensed as Apache-2.0 or MIT.
 
+#[cfg(feature = "bloom")]
+use crate::bloom::{BloomStats, ColumnBloom};
 use crate::{
-	btree::BTreeTable,
-	compress::Compress,
+	btree::{commit_overlay::BTreeChangeSet, BTreeTable},
+	cache::{ValueCache, ValueCacheStats},
+	compress::{Compress, CompressionType},
 	db::{check::CheckDisplay, Operation},
-	display::hex,
+	display::{hex, human_count},
 	error::{Error, Result},
-	index::{Address, IndexTable, PlanOutcome, TableId as IndexTableId},
-	log::{Log, LogAction, LogOverlays, LogQuery, LogReader, LogWriter},
-	options::{ColumnOptions, Metadata, Options, DEFAULT_COMPRESSION_THRESHOLD},
-	parking_lot::{RwLock, RwLockUpgradableReadGuard, RwLockWriteGuard},
-	stats::{ColumnStatSummary, ColumnStats},
+	index::{
+		Address, CorruptAddress, IndexTable, PlanOutcome, TableId as IndexTableId, CHUNK_ENTRIES,
+		ENTRY_BYTES, KEY_PREFIX_BITS, MAX_INDEX_BITS,
+	},
+	log::{
+		InsertIndexAction, InsertValueAction, Log, LogAction, LogOverlays, LogQuery, LogReader,
+		LogWriter,
+	},
+	options::{ColumnOptions, Metadata, Options, DEFAULT_COMPRESSION_THRESHOLD, MIN_START_BITS},
+	parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockUpgradableReadGuard, RwLockWriteGuard},
+	stats::{
+		CachedStats, CmSketch, ColumnCounters, ColumnStatSummary, ColumnStats, FragmentationReport,
+		TierFragmentation,
+	},
 	table::{
 		key::{TableKey, TableKeyQuery},
-		TableId as ValueTableId, Value, ValueTable, SIZE_TIERS,
+		FsckReport, TableCheckpoint, TableId as ValueTableId, Value, ValueTable, SIZE_TIERS,
 	},
 	Key,
 };
 use std::{
-	collections::VecDeque,
+	collections::{hash_map, HashMap, HashSet, VecDeque},
 	sync::{
-		atomic::{AtomicU64, Ordering},
+		atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
 		Arc,
 	},
 };
 
-const MIN_INDEX_BITS: u8 = 16;
-// Measured in index entries
-const MAX_REINDEX_BATCH: usize = 8192;
+// Value bytes included in trace logs with `Options::verbose_trace`.
+const TRACE_VALUE_BYTES: usize = 64;
+// Default for `Options::reindex_batch_size`, measured in index entries.
+const DEFAULT_REINDEX_BATCH: u32 = 8192;
+// Index fill rate targeted by `reserve`. Chunks of fuller indexes are likely to overflow.
+const RESERVE_FILL_RATE: f64 = 0.5;
+// Maximum index fill rate after a reindex started by a full index chunk.
+const REINDEX_FILL_RATE: f64 = 0.75;
+// Index bits are stored above the slot position in pagination cursors.
+const CURSOR_SLOT_BITS: u32 = 56;
+// Set in cursors of tables in the reindex queue. Index slots are below this bit.
+const CURSOR_QUEUED: u64 = 1 << 55;
+/// Size of the write time prefix of values in columns with `ColumnOptions::ttl`.
+pub const TTL_TIMESTAMP_SIZE: usize = 8;
 
 pub type ColId = u8;
 pub type Salt = [u8; 32];
@@ -70,6 +93,11 @@ const SIZES: [u16; SIZE_TIERS - 1] = [
 	24265, 24936, 25626, 26335, 27064, 27812, 28582, 29372, 30185, 31020, 31878, 32760,
 ];
 
+/// Entry sizes of the fixed size value tables of a column.
+pub fn value_sizes(options: &ColumnOptions) -> &[u16] {
+	options.value_sizes.as_deref().unwrap_or(&SIZES)
+}
+
 #[derive(Debug)]
 struct Tables {
 	index: IndexTable,
@@ -100,9 +128,32 @@ pub struct HashColumn {
 	collect_stats: bool,
 	ref_counted: bool,
 	salt: Salt,
+	start_bits: u8,
+	max_bits: Option<u8>,
+	// Time-to-live of values in milliseconds.
+	ttl: Option<u64>,
 	stats: ColumnStats,
+	hot_keys: Option<Mutex<CmSketch>>,
+	#[cfg(feature = "bloom")]
+	bloom: Option<ColumnBloom>,
+	value_cache: Option<ValueCache>,
+	counters: Arc<ColumnCounters>,
 	compression: Compress,
+	verbose_trace: bool,
+	reindex_batch_size: AtomicU32,
+	#[cfg(feature = "parallel-reindex")]
+	parallel_reindex: bool,
 	db_version: u32,
+	// Set once the database is opened read only and the logs are replayed.
+	read_only: AtomicBool,
+}
+
+// Smallest number of index bits that keeps `entries` within `fill_rate` of the index capacity,
+// or `None` if that is more than `MAX_INDEX_BITS`.
+fn index_bits_for(entries: u64, fill_rate: f64) -> Option<u8> {
+	let chunks = entries as f64 / (fill_rate * CHUNK_ENTRIES as f64);
+	let bits = chunks.log2().ceil().max(0.0);
+	(bits <= MAX_INDEX_BITS as f64).then_some(bits as u8)
 }
 
 #[derive(Clone, Copy)]
@@ -112,6 +163,17 @@ pub struct TablesRef<'a> {
 	pub col: ColId,
 	pub preimage: bool,
 	pub ref_counted: bool,
+	pub verbose_trace: bool,
+}
+
+// Value suffix of write plan trace lines, see `Options::verbose_trace`. Empty unless enabled, so
+// that nothing is allocated when trace logging is off.
+fn trace_value(tables: &TablesRef, value: &[u8]) -> String {
+	if tables.verbose_trace && log::log_enabled!(target: "parity-db", log::Level::Trace) {
+		format!(", value = {}", hex(&value[..value.len().min(TRACE_VALUE_BYTES)]))
+	} else {
+		String::new()
+	}
 }
 
 pub struct IterState {
@@ -126,6 +188,36 @@ enum IterStateOrCorrupted {
 	Corrupted(crate::index::Entry, Option<Error>),
 }
 
+/// Current time in milliseconds since the Unix epoch, as stored with values of columns with a
+/// time-to-live.
+pub fn unix_millis() -> u64 {
+	std::time::SystemTime::now()
+		.duration_since(std::time::UNIX_EPOCH)
+		.map_or(0, |d| d.as_millis() as u64)
+}
+
+/// Remove the write time prefix of a value of a column with a time-to-live of `ttl`
+/// milliseconds. Returns `None` if the value is expired at `now`.
+pub fn strip_expired(mut value: Value, ttl: u64, now: u64) -> Result<Option<Value>> {
+	if value.len() < TTL_TIMESTAMP_SIZE {
+		return Err(Error::Corruption("Missing value timestamp".into()))
+	}
+	let written = u64::from_le_bytes(value[..TTL_TIMESTAMP_SIZE].try_into().unwrap());
+	if written.saturating_add(ttl) <= now {
+		return Ok(None)
+	}
+	value.drain(..TTL_TIMESTAMP_SIZE);
+	Ok(Some(value))
+}
+
+/// An all-zero key can't be told apart from an empty index slot and is rejected.
+pub fn check_key(key: &Key) -> Result<()> {
+	if key.iter().all(|b| *b == 0) {
+		return Err(Error::InvalidKey("zero key not allowed".into()))
+	}
+	Ok(())
+}
+
 #[inline]
 pub fn hash_key(key: &[u8], salt: &Salt, uniform: bool, db_version: u32) -> Key {
 	use blake2::{
@@ -154,34 +246,627 @@ pub fn hash_key(key: &[u8], salt: &Salt, uniform: bool, db_version: u32) -> Key
 	k
 }
 
+// Original key of a uniform column. Such keys are only XOR-ed with the salt.
+fn unhash_uniform_key(key: &Key, salt: &Salt, db_version: u32) -> Key {
+	let mut k = *key;
+	if db_version > 5 {
+		for i in 0..32 {
+			k[i] ^= salt[i];
+		}
+	}
+	k
+}
+
 pub struct ReindexBatch {
 	pub drop_index: Option<IndexTableId>,
 	pub batch: Vec<(Key, Address)>,
 }
 
-impl HashColumn {
+/// Progress of moving entries from an old index table into the current one, see
+/// `Db::watch_rebalance`.
+#[cfg(feature = "async-tokio")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RebalanceProgress {
+	/// Column that is being reindexed.
+	pub col: ColId,
+	/// Number of chunks of the old index table that were moved.
+	pub processed_chunks: u64,
+	/// Total number of chunks in the old index table.
+	pub total_chunks: u64,
+	/// Number of old index tables left, including the current one.
+	pub pending_tables: usize,
+}
+
+/// Index defragmentation statistics.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct DefragIndexStats {
+	/// Number of live index entries.
+	pub entries: u64,
+	/// Average number of entries scanned to find a key before defragmentation.
+	pub avg_probe_before: f64,
+	/// Average number of entries scanned to find a key after defragmentation.
+	pub avg_probe_after: f64,
+	/// Largest number of entries scanned to find a key before defragmentation.
+	pub max_probe_before: u64,
+	/// Largest number of entries scanned to find a key after defragmentation.
+	pub max_probe_after: u64,
+}
+
+/// Orphaned value collection statistics.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct GarbageStats {
+	/// Number of occupied value table entries that were not referenced by the index.
+	pub orphaned_slots: u64,
+	/// Total size of the freed entries.
+	pub bytes_reclaimed: u64,
+}
+
+/// Most problems listed in `IntegrityReport::corruptions`. Further problems are only counted.
+pub const MAX_REPORTED_CORRUPTIONS: usize = 1000;
+
+/// A problem found by `Db::check_integrity`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptionError {
+	pub col: ColId,
+	/// Size tier of the value table entry.
+	pub size_tier: u8,
+	/// Position of the entry in the value table.
+	pub offset: u64,
+	/// Description of the problem.
+	pub message: String,
+}
+
+/// Result of `Db::check_integrity`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct IntegrityReport {
+	/// Number of checked index entries.
+	pub index_entries: u64,
+	/// Number of checked value table slots.
+	pub value_slots: u64,
+	/// Number of problems found.
+	pub errors: u64,
+	/// Details of the first `MAX_REPORTED_CORRUPTIONS` problems.
+	pub corruptions: Vec<CorruptionError>,
+}
+
+impl IntegrityReport {
+	fn add(&mut self, error: CorruptionError) {
+		log::warn!(target: "parity-db", "Column {}: {}", error.col, error.message);
+		self.errors += 1;
+		if self.corruptions.len() < MAX_REPORTED_CORRUPTIONS {
+			self.corruptions.push(error);
+		}
+	}
+}
+
+/// Index read locks of a hash column, held for a series of lookups.
+pub struct HashColumnReadGuard<'a> {
+	column: &'a HashColumn,
+	tables: RwLockReadGuard<'a, Tables>,
+	reindex: RwLockReadGuard<'a, Reindex>,
+}
+
+impl<'a> HashColumnReadGuard<'a> {
 	pub fn get(&self, key: &Key, log: &impl LogQuery) -> Result<Option<Value>> {
-		let tables = self.tables.read();
-		let values = self.as_ref(&tables.value);
-		if let Some((tier, value)) = self.get_in_index(key, &tables.index, values, log)? {
-			if self.collect_stats {
-				self.stats.query_hit(tier);
+		let column = self.column;
+		self.lookup(key, |index, values| column.get_in_index(key, index, values, log))
+	}
+
+	/// Write the value of `key` to `writer`, see `HashColumn::write_value`. Returns the number of
+	/// bytes written.
+	pub fn write_value(
+		&self,
+		key: &Key,
+		log: &impl LogQuery,
+		writer: &mut dyn std::io::Write,
+	) -> Result<Option<usize>> {
+		let column = self.column;
+		self.lookup(key, |index, values| column.write_in_index(key, index, values, log, writer))
+	}
+
+	// Look `key` up in the main index and then in the reindex queue with `find`.
+	fn lookup<R>(
+		&self,
+		key: &Key,
+		mut find: impl FnMut(&IndexTable, TablesRef) -> Result<Option<(u8, R)>>,
+	) -> Result<Option<R>> {
+		let column = self.column;
+		if let Some(hot_keys) = &column.hot_keys {
+			hot_keys.lock().increment(key);
+		}
+		#[cfg(feature = "bloom")]
+		if column.bloom.as_ref().is_some_and(|bloom| !bloom.may_contain(key)) {
+			if column.collect_stats {
+				column.stats.query_miss();
+			}
+			return Ok(None)
+		}
+		let values = column.as_ref(&self.tables.value);
+		if let Some((tier, value)) = find(&self.tables.index, values)? {
+			if column.collect_stats {
+				column.stats.query_hit(tier);
 			}
 			return Ok(Some(value))
 		}
-		for r in &self.reindex.read().queue {
-			if let Some((tier, value)) = self.get_in_index(key, r, values, log)? {
-				if self.collect_stats {
-					self.stats.query_hit(tier);
+		for r in &self.reindex.queue {
+			if let Some((tier, value)) = find(r, values)? {
+				if column.collect_stats {
+					column.stats.query_hit(tier);
 				}
 				return Ok(Some(value))
 			}
 		}
-		if self.collect_stats {
-			self.stats.query_miss();
+		if column.collect_stats {
+			column.stats.query_miss();
+		}
+		Ok(None)
+	}
+}
+
+/// Hash column entries in ascending size tier order. Index locks are held until the iterator
+/// is dropped.
+pub struct SizeOrderedIterator<'a> {
+	guard: HashColumnReadGuard<'a>,
+	log: &'a Log,
+	// Address and recovered key prefix, sorted by tier and offset.
+	entries: Vec<(Address, Key)>,
+	pos: usize,
+}
+
+impl<'a> SizeOrderedIterator<'a> {
+	/// Returns the next entry key and value.
+	#[allow(clippy::should_implement_trait)]
+	pub fn next(&mut self) -> Result<Option<(Key, Value)>> {
+		Ok(self.next_with_address()?.map(|(_, key, value)| (key, value)))
+	}
+
+	/// Returns the next entry value table address, key and value.
+	pub fn next_with_address(&mut self) -> Result<Option<(Address, Key, Value)>> {
+		let column = self.guard.column;
+		while let Some((address, key)) = self.entries.get(self.pos) {
+			self.pos += 1;
+			let table = &self.guard.tables.value[address.size_tier() as usize];
+			if let Some((value, _rc, pk, compressed)) =
+				table.get_with_meta(address.offset(), self.log.overlays())?
+			{
+				let mut key = *key;
+				key[6..].copy_from_slice(&pk);
+				let value = if compressed { column.compression.decompress(&value)? } else { value };
+				return Ok(Some((*address, key, value)))
+			}
 		}
 		Ok(None)
 	}
+}
+
+/// Hash column entries in index order that can be resumed from a cursor, e.g. after a restart.
+/// The main index is walked first, followed by the tables of the reindex queue. Entries of a
+/// queued table are skipped if a lookup would find them in an earlier table.
+/// Index locks are only held while reading an index chunk, so other writes are not blocked.
+/// If the index is modified after the iterator was created, `next` returns
+/// `Error::ConcurrentModification`. Iteration may be restarted from `cursor` in that case.
+pub struct PaginatedIterator<'a> {
+	column: &'a HashColumn,
+	log: &'a Log,
+	// Index bits of the walked table. They are different for each table of the column.
+	index_bits: u8,
+	// The walked table is in the reindex queue.
+	queued: bool,
+	iteration_version: u64,
+	chunk_index: u64,
+	sub_index: usize,
+}
+
+impl<'a> PaginatedIterator<'a> {
+	fn new(column: &'a HashColumn, log: &'a Log, cursor: u64) -> Result<Self> {
+		let cursor_bits = (cursor >> CURSOR_SLOT_BITS) as u8;
+		if cursor != 0 && !(MIN_START_BITS..=MAX_INDEX_BITS).contains(&cursor_bits) {
+			return Err(Error::InvalidInput(format!("Invalid iteration cursor {cursor:#x}")))
+		}
+		let tables = column.tables.read();
+		let reindex = column.reindex.read();
+		let index = &tables.index;
+		let index_bits = index.id.index_bits();
+		let mut iter = PaginatedIterator {
+			column,
+			log,
+			index_bits,
+			queued: false,
+			iteration_version: index.iteration_version(),
+			chunk_index: 0,
+			sub_index: 0,
+		};
+		let slot = cursor & (CURSOR_QUEUED - 1);
+		if cursor & CURSOR_QUEUED != 0 {
+			// If the table is no longer queued, its entries were moved to the main index, which
+			// is walked again.
+			if let Some(table) = reindex.queue.iter().find(|t| t.id.index_bits() == cursor_bits) {
+				iter.queued = true;
+				iter.index_bits = cursor_bits;
+				iter.iteration_version = table.iteration_version();
+				iter.chunk_index = slot / CHUNK_ENTRIES as u64;
+				iter.sub_index = (slot % CHUNK_ENTRIES as u64) as usize;
+			}
+		} else if cursor_bits == index_bits {
+			iter.chunk_index = slot / CHUNK_ENTRIES as u64;
+			iter.sub_index = (slot % CHUNK_ENTRIES as u64) as usize;
+		} else if cursor_bits != 0 {
+			iter.index_bits = cursor_bits;
+			iter.chunk_index = slot / CHUNK_ENTRIES as u64;
+			iter.rescale(index_bits);
+		}
+		Ok(iter)
+	}
+
+	// The cursor was taken before the index was resized. Chunks are ordered by key prefix, so the
+	// position is mapped to the first chunk that may contain keys that were not returned yet.
+	// This may return some entries of the cursor chunk again.
+	fn rescale(&mut self, index_bits: u8) {
+		if index_bits > self.index_bits {
+			self.chunk_index <<= index_bits - self.index_bits;
+		} else {
+			self.chunk_index >>= self.index_bits - index_bits;
+		}
+		self.sub_index = 0;
+		self.index_bits = index_bits;
+	}
+
+	/// Position of the next entry. Pass it to `iter_with_offset` to continue from here.
+	pub fn cursor(&self) -> u64 {
+		let queued = if self.queued { CURSOR_QUEUED } else { 0 };
+		((self.index_bits as u64) << CURSOR_SLOT_BITS) |
+			queued | (self.chunk_index * CHUNK_ENTRIES as u64 + self.sub_index as u64)
+	}
+
+	/// Returns the next entry key and value. Expired values of columns with a time-to-live are
+	/// skipped, and the write time prefix is removed from the others.
+	#[allow(clippy::should_implement_trait)]
+	pub fn next(&mut self) -> Result<Option<(Key, Value)>> {
+		Ok(self.next_with_address()?.map(|(_, key, value)| (key, value)))
+	}
+
+	/// Returns the next entry value table address, key and value, see `next`.
+	pub fn next_with_address(&mut self) -> Result<Option<(Address, Key, Value)>> {
+		let column = self.column;
+		let now = unix_millis();
+		let tables = column.tables.read();
+		let reindex = column.reindex.read();
+		let index = &tables.index;
+		loop {
+			let (position, table) = if self.queued {
+				match reindex
+					.queue
+					.iter()
+					.enumerate()
+					.find(|(_, t)| t.id.index_bits() == self.index_bits)
+				{
+					Some((position, table)) => (Some(position), table),
+					None => return Err(Error::ConcurrentModification),
+				}
+			} else {
+				(None, index)
+			};
+			if table.id.index_bits() != self.index_bits ||
+				table.iteration_version() != self.iteration_version
+			{
+				return Err(Error::ConcurrentModification)
+			}
+			while self.chunk_index < table.id.total_chunks() {
+				let entries = table.entries(self.chunk_index, self.log.overlays())?;
+				while let Some(entry) = entries.get(self.sub_index) {
+					self.sub_index += 1;
+					if entry.is_empty() {
+						continue
+					}
+					let address = entry.address(self.index_bits);
+					let value_table = &tables.value[address.size_tier() as usize];
+					let (value, _rc, pk, compressed) = value_table
+						.get_with_meta(address.offset(), self.log.overlays())?
+						.ok_or_else(|| Error::Corruption("Missing indexed value".into()))?;
+					let mut key = table.recover_key_prefix(self.chunk_index, *entry);
+					key[6..].copy_from_slice(&pk);
+					if self.queued {
+						let found = column.search_all_indexes(
+							&key,
+							&tables,
+							&reindex,
+							self.log.overlays(),
+						)?;
+						if found.map(|(found, ..)| found.id) != Some(table.id) {
+							continue
+						}
+					}
+					let value =
+						if compressed { column.compression.decompress(&value)? } else { value };
+					let value = match column.ttl() {
+						Some(ttl) => match strip_expired(value, ttl, now)? {
+							Some(value) => value,
+							None => continue,
+						},
+						None => value,
+					};
+					return Ok(Some((address, key, value)))
+				}
+				self.chunk_index += 1;
+				self.sub_index = 0;
+			}
+			let next = match position {
+				None => reindex.queue.front(),
+				Some(position) => reindex.queue.get(position + 1),
+			};
+			match next {
+				Some(next) => {
+					self.queued = true;
+					self.index_bits = next.id.index_bits();
+					self.iteration_version = next.iteration_version();
+					self.chunk_index = 0;
+					self.sub_index = 0;
+				},
+				None => return Ok(None),
+			}
+		}
+	}
+}
+
+impl HashColumn {
+	pub fn get(&self, key: &Key, log: &impl LogQuery) -> Result<Option<Value>> {
+		self.read_lock().get(key, log)
+	}
+
+	/// Look up a batch of keys under a single acquisition of the index locks. Values are
+	/// returned in the order of `keys`.
+	pub fn get_multi(&self, keys: &[Key], log: &impl LogQuery) -> Result<Vec<Option<Value>>> {
+		let guard = self.read_lock();
+		keys.iter().map(|key| guard.get(key, log)).collect()
+	}
+
+	/// Iterate over the entries of all index tables, starting at a cursor returned by
+	/// `PaginatedIterator::cursor`, or at the beginning for `0`. Fails with `Error::InvalidInput`
+	/// if the cursor can't have been returned by an iterator.
+	pub fn iter_with_offset<'a>(
+		&'a self,
+		offset: u64,
+		log: &'a Log,
+	) -> Result<PaginatedIterator<'a>> {
+		PaginatedIterator::new(self, log, offset)
+	}
+
+	/// Iterate over all entries, smallest size tier first. Entries within a tier are returned
+	/// in file order. This requires a full index scan up front.
+	pub fn iter_by_size<'a>(&'a self, log: &'a Log) -> Result<SizeOrderedIterator<'a>> {
+		let guard = self.read_lock();
+		let mut entries = Vec::new();
+		for index in std::iter::once(&guard.tables.index).chain(guard.reindex.queue.iter()) {
+			for c in 0..index.id.total_chunks() {
+				for entry in index.entries(c, log.overlays())?.iter() {
+					if entry.is_empty() {
+						continue
+					}
+					let address = entry.address(index.id.index_bits());
+					entries.push((address, index.recover_key_prefix(c, *entry)));
+				}
+			}
+		}
+		entries.sort_unstable_by_key(|(address, _)| (address.size_tier(), address.offset()));
+		// An entry may be present both in the new index and in the one being reindexed.
+		entries.dedup_by_key(|(address, _)| *address);
+		Ok(SizeOrderedIterator { guard, log, entries, pos: 0 })
+	}
+
+	/// Count keys that start with the first `prefix_bits` bits of `prefix`. Only supported for
+	/// columns with uniform keys, as other keys are hashed.
+	pub fn count_keys_by_prefix(&self, prefix: &[u8], prefix_bits: u8, log: &Log) -> Result<u64> {
+		if !self.uniform_keys {
+			return Err(Error::InvalidConfiguration(
+				"Prefix counting requires a column with uniform keys.".into(),
+			))
+		}
+		if prefix_bits > KEY_PREFIX_BITS || prefix.len() * 8 < prefix_bits as usize {
+			return Err(Error::InvalidInput(format!(
+				"Invalid key prefix: {} bits requested, {} bytes provided, at most {} bits supported",
+				prefix_bits,
+				prefix.len(),
+				KEY_PREFIX_BITS,
+			)))
+		}
+		let mut key = Key::default();
+		let len = std::cmp::min(prefix.len(), key.len());
+		key[..len].copy_from_slice(&prefix[..len]);
+		let key = self.hash_key(&key);
+		let prefix = u64::from_be_bytes(key[0..8].try_into().unwrap());
+
+		let tables = self.tables.read();
+		let reindex = self.reindex.read();
+		let mut count = tables.index.count_prefix(prefix, prefix_bits, 0, log.overlays())?;
+		for (i, source) in reindex.queue.iter().enumerate() {
+			// Chunks before `progress` have already been copied to the new index.
+			let from_chunk = if i == 0 { reindex.progress.load(Ordering::Relaxed) } else { 0 };
+			count += source.count_prefix(prefix, prefix_bits, from_chunk, log.overlays())?;
+		}
+		Ok(count)
+	}
+
+	/// Iterate over entries with keys that start with `prefix`. Original keys are returned. Only
+	/// supported for columns with uniform keys, as other keys are hashed.
+	///
+	/// Matching keys are stored in a contiguous range of index chunks, so this is not a lookup by
+	/// prefix but a scan of that range: every entry in it is read and compared with `prefix`.
+	/// Prefixes shorter than the index bits cover a proportional share of the column, and the
+	/// empty prefix scans the whole column. The index is locked until the iterator is dropped.
+	pub fn scan_prefix<'a>(
+		&'a self,
+		prefix: &[u8],
+		log: &'a Log,
+	) -> Result<impl Iterator<Item = Result<(Key, Value)>> + 'a> {
+		if !self.uniform_keys {
+			return Err(Error::InvalidConfiguration(
+				"Prefix scan requires a column with uniform keys.".into(),
+			))
+		}
+		let mut key = Key::default();
+		if prefix.len() > key.len() {
+			return Err(Error::InvalidInput(format!(
+				"Key prefix is longer than {} bytes",
+				key.len()
+			)))
+		}
+		key[..prefix.len()].copy_from_slice(prefix);
+		let key = self.hash_key(&key);
+		let hashed_prefix = u64::from_be_bytes(key[0..8].try_into().unwrap());
+		let prefix_bits = std::cmp::min(prefix.len() * 8, 64);
+		let mask = if prefix_bits == 0 { 0 } else { u64::MAX << (64 - prefix_bits) };
+
+		let guard = self.read_lock();
+		let mut entries = Vec::new();
+		let mut addresses = std::collections::HashSet::new();
+		for index in std::iter::once(&guard.tables.index).chain(guard.reindex.queue.iter()) {
+			let shift = 64 - index.id.index_bits();
+			for c in (hashed_prefix & mask) >> shift..=(hashed_prefix | !mask) >> shift {
+				for entry in index.entries(c, log.overlays())?.iter() {
+					if entry.is_empty() {
+						continue
+					}
+					// An entry may be present both in the new index and in the one being
+					// reindexed.
+					let address = entry.address(index.id.index_bits());
+					if addresses.insert(address) {
+						entries.push((address, index.recover_key_prefix(c, *entry)));
+					}
+				}
+			}
+		}
+
+		let prefix = prefix.to_vec();
+		Ok(entries.into_iter().filter_map(move |(address, key)| {
+			let column = guard.column;
+			let table = &guard.tables.value[address.size_tier() as usize];
+			let (value, _rc, pk, compressed) =
+				match table.get_with_meta(address.offset(), log.overlays()) {
+					Ok(Some(entry)) => entry,
+					Ok(None) => return None,
+					Err(e) => return Some(Err(e)),
+				};
+			let mut key = key;
+			key[6..].copy_from_slice(&pk);
+			let key = unhash_uniform_key(&key, &column.salt, column.db_version);
+			if !key.starts_with(&prefix) {
+				return None
+			}
+			if compressed {
+				Some(column.compression.decompress(&value).map(|value| (key, value)))
+			} else {
+				Some(Ok((key, value)))
+			}
+		}))
+	}
+
+	pub fn read_lock(&self) -> HashColumnReadGuard<'_> {
+		HashColumnReadGuard {
+			column: self,
+			tables: self.tables.read(),
+			reindex: self.reindex.read(),
+		}
+	}
+
+	/// Check if the column has a value for `key`. Only the entry header with the partial key is
+	/// read, not the value itself.
+	pub fn has_key(&self, key: &Key, log: &impl LogQuery) -> Result<bool> {
+		#[cfg(feature = "bloom")]
+		if self.bloom.as_ref().is_some_and(|bloom| !bloom.may_contain(key)) {
+			return Ok(false)
+		}
+		let tables = self.tables.read();
+		let reindex = self.reindex.read();
+		Ok(self.search_all_indexes(key, &tables, &reindex, log)?.is_some())
+	}
+
+	/// Value table address of the entry for `key`, searching the index and the tables that are
+	/// being reindexed like `get`. The value itself is not read.
+	pub fn get_index_entry(&self, key: &Key, log: &impl LogQuery) -> Result<Option<Address>> {
+		let tables = self.tables.read();
+		let reindex = self.reindex.read();
+		Ok(self
+			.search_all_indexes(key, &tables, &reindex, log)?
+			.map(|(_, _, address)| address))
+	}
+
+	/// Check a batch of keys like `has_key`. Keys are checked against the Bloom filter first,
+	/// and the index locks are acquired once for the keys that pass it, if any.
+	pub fn has_keys(&self, keys: &[Key], log: &impl LogQuery) -> Result<Vec<bool>> {
+		let mut found = vec![false; keys.len()];
+		#[cfg(feature = "bloom")]
+		let candidates: Vec<usize> = (0..keys.len())
+			.filter(|i| self.bloom.as_ref().map_or(true, |bloom| bloom.may_contain(&keys[*i])))
+			.collect();
+		#[cfg(not(feature = "bloom"))]
+		let candidates: Vec<usize> = (0..keys.len()).collect();
+		if candidates.is_empty() {
+			return Ok(found)
+		}
+		let tables = self.tables.read();
+		let reindex = self.reindex.read();
+		for i in candidates {
+			found[i] = self.search_all_indexes(&keys[i], &tables, &reindex, log)?.is_some();
+		}
+		Ok(found)
+	}
+
+	/// Estimate the effect of writing `changes`, given as keys with new value sizes, without
+	/// planning them. Returns the number of value table and index bytes that would be written,
+	/// the size tiers that would receive values, and whether an index chunk would overflow and
+	/// trigger a reindex. Compression is not taken into account.
+	pub fn dry_run(
+		&self,
+		changes: &[(Key, Option<usize>)],
+		log: &impl LogQuery,
+	) -> Result<(u64, Vec<usize>, bool)> {
+		let tables = self.tables.read();
+		let reindex = self.reindex.read();
+		// Only the last change to a key is written.
+		let changes: HashMap<&Key, Option<usize>> =
+			changes.iter().map(|(key, len)| (key, *len)).collect();
+		let mut bytes = 0;
+		let mut tiers = Vec::new();
+		let mut inserted: HashMap<u64, (usize, usize)> = HashMap::new();
+		for (key, len) in changes {
+			let exists = self.search_all_indexes(key, &tables, &reindex, log)?.is_some();
+			let len = match len {
+				Some(len) => len,
+				None => {
+					if exists {
+						bytes += ENTRY_BYTES as u64;
+					}
+					continue
+				},
+			};
+			let tier = Column::target_tier(&TableKey::Partial(*key), len, &tables.value);
+			let entry_size = tables.value[tier].entry_size as usize;
+			bytes += (std::cmp::max(len.div_ceil(entry_size), 1) * entry_size) as u64;
+			if !tiers.contains(&tier) {
+				tiers.push(tier);
+			}
+			if !exists {
+				bytes += ENTRY_BYTES as u64;
+				let (chunk_index, free) = tables.index.chunk_free_entries(key, log)?;
+				inserted.entry(chunk_index).or_insert((0, free)).0 += 1;
+			}
+		}
+		tiers.sort_unstable();
+		let reindex_needed = inserted.values().any(|(count, free)| count > free);
+		Ok((bytes, tiers, reindex_needed))
+	}
+
+	/// Write the value of `key` to `writer` as it is read from the value table, so that large
+	/// values are never held in memory as a whole. Values of columns with compression are
+	/// read in full and decompressed first. Returns the number of bytes written.
+	pub fn write_value(
+		&self,
+		key: &Key,
+		log: &impl LogQuery,
+		writer: &mut dyn std::io::Write,
+	) -> Result<Option<usize>> {
+		self.read_lock().write_value(key, log, writer)
+	}
 
 	pub fn get_size(&self, key: &Key, log: &RwLock<LogOverlays>) -> Result<Option<u32>> {
 		self.get(key, log).map(|v| v.map(|v| v.len() as u32))
@@ -197,6 +882,19 @@ impl HashColumn {
 		let (mut entry, mut sub_index) = index.get(key, 0, log)?;
 		while !entry.is_empty() {
 			let address = entry.address(index.id.index_bits());
+			let tier = address.size_tier();
+			// Entries with pending changes are read from the log overlay.
+			let cache = self
+				.value_cache
+				.as_ref()
+				.filter(|_| !log.value(tables.tables[tier as usize].id, address.offset(), &mut []));
+			if let Some(cache) = cache {
+				if let Some(value) = cache.get(tier, address.offset(), key) {
+					self.counters.cache_hits.fetch_add(1, Ordering::Relaxed);
+					return Ok(Some((tier, value)))
+				}
+			}
+			let generation = cache.map(|cache| cache.generation());
 			let value = Column::get_value(
 				TableKeyQuery::Check(&TableKey::Partial(*key)),
 				address,
@@ -204,8 +902,51 @@ impl HashColumn {
 				log,
 			)?;
 			match value {
-				Some(result) => return Ok(Some(result)),
+				Some(result) => {
+					if let (Some(cache), Some(generation)) = (cache, generation) {
+						cache.insert(tier, address.offset(), key, &result.1, generation);
+					}
+					return Ok(Some(result))
+				},
+				None => {
+					self.counters.index_conflicts.fetch_add(1, Ordering::Relaxed);
+					let (next_entry, next_index) = index.get(key, sub_index + 1, log)?;
+					entry = next_entry;
+					sub_index = next_index;
+				},
+			}
+		}
+		Ok(None)
+	}
+
+	// Same as `get_in_index`, but the value is written to `writer`.
+	fn write_in_index(
+		&self,
+		key: &Key,
+		index: &IndexTable,
+		tables: TablesRef,
+		log: &impl LogQuery,
+		writer: &mut dyn std::io::Write,
+	) -> Result<Option<(u8, usize)>> {
+		let (mut entry, mut sub_index) = index.get(key, 0, log)?;
+		while !entry.is_empty() {
+			let address = entry.address(index.id.index_bits());
+			let tier = address.size_tier();
+			let cache = self
+				.value_cache
+				.as_ref()
+				.filter(|_| !log.value(tables.tables[tier as usize].id, address.offset(), &mut []));
+			if let Some(cache) = cache {
+				if let Some(value) = cache.get(tier, address.offset(), key) {
+					self.counters.cache_hits.fetch_add(1, Ordering::Relaxed);
+					writer.write_all(&value).map_err(Error::Io)?;
+					return Ok(Some((tier, value.len())))
+				}
+			}
+			match Column::write_value(&TableKey::Partial(*key), address, tables, log, writer)? {
+				Some(written) => return Ok(Some((tier, written))),
 				None => {
+					self.counters.index_conflicts.fetch_add(1, Ordering::Relaxed);
 					let (next_entry, next_index) = index.get(key, sub_index + 1, log)?;
 					entry = next_entry;
 					sub_index = next_index;
@@ -222,6 +963,7 @@ impl HashColumn {
 			col: self.col,
 			ref_counted: self.ref_counted,
 			compression: &self.compression,
+			verbose_trace: self.verbose_trace,
 		}
 	}
 }
@@ -243,6 +985,52 @@ impl Column {
 		Ok(None)
 	}
 
+	/// Write the value at `address` to `writer`. Parts of uncompressed values are written as
+	/// they are read. Returns `None` if the key does not match.
+	pub fn write_value(
+		key: &TableKey,
+		address: Address,
+		tables: TablesRef,
+		log: &impl LogQuery,
+		writer: &mut dyn std::io::Write,
+	) -> Result<Option<usize>> {
+		let table = &tables.tables[address.size_tier() as usize];
+		if CompressionType::from(tables.compression) != CompressionType::NoCompression {
+			let value =
+				Self::get_value(TableKeyQuery::Check(key), address, tables, log)?.map(|(_, v)| v);
+			return match value {
+				Some(value) => {
+					writer.write_all(&value).map_err(Error::Io)?;
+					Ok(Some(value.len()))
+				},
+				None => Ok(None),
+			}
+		}
+		let mut written = 0;
+		let mut write_error = None;
+		let compressed = table.for_each_part(key, address.offset(), log, |part| {
+			match writer.write_all(part) {
+				Ok(()) => {
+					written += part.len();
+					true
+				},
+				Err(e) => {
+					write_error = Some(e);
+					false
+				},
+			}
+		})?;
+		if let Some(e) = write_error {
+			return Err(Error::Io(e))
+		}
+		match compressed {
+			Some(true) =>
+				Err(Error::Corruption("Compressed value in a column without compression".into())),
+			Some(false) => Ok(Some(written)),
+			None => Ok(None),
+		}
+	}
+
 	pub fn compress(
 		compression: &Compress,
 		key: &TableKey,
@@ -259,30 +1047,67 @@ impl Column {
 		} else {
 			(value.len(), None)
 		};
+		(result, Self::target_tier(key, len, tables))
+	}
+
+	fn target_tier(key: &TableKey, len: usize, tables: &[ValueTable]) -> usize {
 		let target_tier = tables
 			.iter()
 			.position(|t| t.value_size(key).map_or(false, |s| len <= s as usize));
-		let target_tier = target_tier.unwrap_or_else(|| {
+		target_tier.unwrap_or_else(|| {
 			log::trace!(target: "parity-db", "Using blob {}", key);
 			tables.len() - 1
-		});
+		})
+	}
 
-		(result, target_tier)
+	/// Size tier a value of `len` bytes is stored in, assuming no compression is applied.
+	pub fn tier_for_value(&self, len: usize) -> Result<usize> {
+		match self {
+			Column::Hash(column) => Ok(Self::target_tier(
+				&TableKey::Partial(Default::default()),
+				len,
+				&column.tables.read().value,
+			)),
+			Column::Tree(column) => column
+				.with_locked(|tables| Ok(Self::target_tier(&TableKey::NoHash, len, tables.tables))),
+		}
 	}
 
-	pub fn open(col: ColId, options: &Options, metadata: &Metadata) -> Result<Column> {
+	pub fn open(
+		col: ColId,
+		options: &Options,
+		metadata: &Metadata,
+		counters: Arc<ColumnCounters>,
+	) -> Result<Column> {
 		let path = &options.path;
 		let arc_path = Arc::new(path.clone());
 		let column_options = &metadata.columns[col as usize];
 		let db_version = metadata.version;
-		let value = (0..SIZE_TIERS)
-			.map(|i| Self::open_table(arc_path.clone(), col, i as u8, column_options, db_version))
-			.collect::<Result<_>>()?;
+		let sizes = value_sizes(column_options);
+		// The last table holds values that don't fit any of the fixed sizes.
+		let value = (0..=sizes.len())
+			.map(|i| {
+				let entry_size = sizes.get(i).cloned();
+				Self::open_table(
+					arc_path.clone(),
+					col,
+					i as u8,
+					entry_size,
+					column_options,
+					db_version,
+				)
+			})
+			.collect::<Result<Vec<ValueTable>>>()?;
+		if let Some(window) = options.tail_map_window.get(&col) {
+			for table in value.iter() {
+				table.tail_mmap(*window)?;
+			}
+		}
 
 		if column_options.btree_index {
 			Ok(Column::Tree(BTreeTable::open(col, value, options, metadata)?))
 		} else {
-			Ok(Column::Hash(HashColumn::open(col, value, options, metadata)?))
+			Ok(Column::Hash(HashColumn::open(col, value, options, metadata, counters)?))
 		}
 	}
 
@@ -290,11 +1115,11 @@ impl Column {
 		path: Arc<std::path::PathBuf>,
 		col: ColId,
 		tier: u8,
+		entry_size: Option<u16>,
 		options: &ColumnOptions,
 		db_version: u32,
 	) -> Result<ValueTable> {
 		let id = ValueTableId::new(col, tier);
-		let entry_size = SIZES.get(tier as usize).cloned();
 		ValueTable::open(path, id, entry_size, options, db_version)
 	}
 }
@@ -305,11 +1130,13 @@ impl HashColumn {
 		value: Vec<ValueTable>,
 		options: &Options,
 		metadata: &Metadata,
+		counters: Arc<ColumnCounters>,
 	) -> Result<HashColumn> {
-		let (index, reindexing, stats) = Self::open_index(&options.path, col)?;
+		let col_options = &metadata.columns[col as usize];
+		let (index, reindexing, stats) =
+			Self::open_index(&options.path, col, col_options.start_bits)?;
 		let collect_stats = options.stats;
 		let path = &options.path;
-		let col_options = &metadata.columns[col as usize];
 		let db_version = metadata.version;
 		Ok(HashColumn {
 			col,
@@ -321,7 +1148,15 @@ impl HashColumn {
 			ref_counted: col_options.ref_counted,
 			collect_stats,
 			salt: metadata.salt,
+			start_bits: col_options.start_bits,
+			max_bits: col_options.max_bits,
+			ttl: col_options.ttl_millis(),
 			stats,
+			hot_keys: options.track_hot_keys.then(|| Mutex::new(CmSketch::new())),
+			#[cfg(feature = "bloom")]
+			bloom: options.bloom_false_positive_rate.get(&col).map(|rate| ColumnBloom::new(*rate)),
+			value_cache: options.value_cache_capacity.get(&col).map(|c| ValueCache::new(*c)),
+			counters,
 			compression: Compress::new(
 				col_options.compression,
 				options
@@ -330,10 +1165,24 @@ impl HashColumn {
 					.copied()
 					.unwrap_or(DEFAULT_COMPRESSION_THRESHOLD),
 			),
+			verbose_trace: options.verbose_trace.contains(&col),
+			reindex_batch_size: AtomicU32::new(
+				options.reindex_batch_size.get(&col).copied().unwrap_or(DEFAULT_REINDEX_BATCH),
+			),
+			#[cfg(feature = "parallel-reindex")]
+			parallel_reindex: options.parallel_reindex.contains(&col),
 			db_version,
+			read_only: AtomicBool::new(false),
 		})
 	}
 
+	fn check_writable(&self) -> Result<()> {
+		if self.read_only.load(Ordering::Relaxed) {
+			return Err(Error::ReadOnly)
+		}
+		Ok(())
+	}
+
 	pub fn hash_key(&self, key: &[u8]) -> Key {
 		hash_key(key, &self.salt, self.uniform_keys, self.db_version)
 	}
@@ -350,11 +1199,12 @@ impl HashColumn {
 	fn open_index(
 		path: &std::path::Path,
 		col: ColId,
+		start_bits: u8,
 	) -> Result<(IndexTable, VecDeque<IndexTable>, ColumnStats)> {
 		let mut reindexing = VecDeque::new();
 		let mut top = None;
 		let mut stats = ColumnStats::empty();
-		for bits in (MIN_INDEX_BITS..65).rev() {
+		for bits in (MIN_START_BITS..65).rev() {
 			let id = IndexTableId::new(col, bits);
 			if let Some(table) = IndexTable::open_existing(path, id)? {
 				if top.is_none() {
@@ -367,30 +1217,120 @@ impl HashColumn {
 		}
 		let table = match top {
 			Some(table) => table,
-			None => IndexTable::create_new(path, IndexTableId::new(col, MIN_INDEX_BITS)),
+			None => IndexTable::create_new(path, IndexTableId::new(col, start_bits)),
 		};
 		Ok((table, reindexing, stats))
 	}
 
-	fn trigger_reindex<'a, 'b>(
-		tables: RwLockUpgradableReadGuard<'a, Tables>,
-		reindex: RwLockUpgradableReadGuard<'b, Reindex>,
-		path: &std::path::Path,
-	) -> (RwLockUpgradableReadGuard<'a, Tables>, RwLockUpgradableReadGuard<'b, Reindex>) {
-		let mut tables = RwLockUpgradableReadGuard::upgrade(tables);
-		let mut reindex = RwLockUpgradableReadGuard::upgrade(reindex);
-		log::info!(
-			target: "parity-db",
-			"Started reindex for {}",
-			tables.index.id,
-		);
-		// Start reindex
-		let new_index_id =
-			IndexTableId::new(tables.index.id.col(), tables.index.id.index_bits() + 1);
-		let new_table = IndexTable::create_new(path, new_index_id);
-		let old_table = std::mem::replace(&mut tables.index, new_table);
-		reindex.queue.push_back(old_table);
-		(
+	// A full index chunk is only resolved by doubling the index.
+	fn check_index_growth(&self, index: &IndexTable) -> Result<()> {
+		if index.id.index_bits() >= self.max_bits.unwrap_or(MAX_INDEX_BITS) {
+			return Err(Error::IndexFull(self.col))
+		}
+		Ok(())
+	}
+
+	/// Fail with `Error::IndexFull` if inserting `keys` could overflow a chunk of the largest
+	/// index the column may grow to, see `ColumnOptions::max_bits`. `pending` are keys set by
+	/// queued commits, which may not be in the index yet. Entries of index tables that are still
+	/// being reindexed are counted for the chunk they are moved to.
+	pub fn check_index_space<'a>(
+		&self,
+		keys: &[&Key],
+		pending: impl Iterator<Item = &'a Key>,
+		log: &impl LogQuery,
+	) -> Result<()> {
+		let max_bits = self.max_bits.unwrap_or(MAX_INDEX_BITS);
+		let chunk_of = |key: &Key| TableKey::index_from_partial(key) >> (64 - max_bits);
+		let tables = self.tables.read();
+		let reindex = self.reindex.read();
+		// New entries per chunk, starting with the number of taken entries.
+		let mut chunks: HashMap<u64, usize> = HashMap::new();
+		let keys: HashSet<&Key> = keys.iter().copied().collect();
+		for key in keys.iter().copied() {
+			if self.search_all_indexes(key, &tables, &reindex, log)?.is_some() {
+				continue
+			}
+			let count = match chunks.entry(chunk_of(key)) {
+				hash_map::Entry::Occupied(entry) => entry.into_mut(),
+				hash_map::Entry::Vacant(entry) => {
+					let chunk = *entry.key();
+					let progress = reindex.progress.load(Ordering::Relaxed);
+					let mut taken = tables.index.count_entries_for(chunk, max_bits, 0, log)?;
+					for (i, table) in reindex.queue.iter().enumerate() {
+						let from_chunk = if i == 0 { progress } else { 0 };
+						taken += table.count_entries_for(chunk, max_bits, from_chunk, log)?;
+					}
+					entry.insert(taken)
+				},
+			};
+			*count += 1;
+		}
+		if chunks.is_empty() {
+			return Ok(())
+		}
+		for key in pending.filter(|key| !keys.contains(key)) {
+			if let Some(count) = chunks.get_mut(&chunk_of(key)) {
+				if self.search_all_indexes(key, &tables, &reindex, log)?.is_none() {
+					*count += 1;
+				}
+			}
+		}
+		if chunks.values().any(|count| *count > CHUNK_ENTRIES) {
+			return Err(Error::IndexFull(self.col))
+		}
+		Ok(())
+	}
+
+	// The index is at least doubled. When many values are inserted at once, the new index may
+	// fill up before the previous reindex completes, so it is sized for the number of values
+	// in the column instead, which takes a single reindex rather than a series of doublings.
+	fn trigger_reindex<'a, 'b>(
+		&self,
+		tables: RwLockUpgradableReadGuard<'a, Tables>,
+		reindex: RwLockUpgradableReadGuard<'b, Reindex>,
+	) -> (RwLockUpgradableReadGuard<'a, Tables>, RwLockUpgradableReadGuard<'b, Reindex>) {
+		let mut index_bits = std::cmp::max(
+			tables.index.id.index_bits() + 1,
+			index_bits_for(Self::estimated_entries(&tables.value), REINDEX_FILL_RATE)
+				.unwrap_or(MAX_INDEX_BITS),
+		);
+		if let Some(max_bits) = self.max_bits {
+			index_bits = std::cmp::min(index_bits, max_bits);
+		}
+		self.trigger_reindex_to(tables, reindex, index_bits)
+	}
+
+	// Number of allocated entries of all value tables but the multipart one, whose values take
+	// several entries. Removed entries are counted until they are reused.
+	fn estimated_entries(value: &[ValueTable]) -> u64 {
+		value
+			.iter()
+			.filter(|table| !table.is_multipart())
+			.map(|table| table.filled().saturating_sub(1))
+			.sum()
+	}
+
+	fn trigger_reindex_to<'a, 'b>(
+		&self,
+		tables: RwLockUpgradableReadGuard<'a, Tables>,
+		reindex: RwLockUpgradableReadGuard<'b, Reindex>,
+		index_bits: u8,
+	) -> (RwLockUpgradableReadGuard<'a, Tables>, RwLockUpgradableReadGuard<'b, Reindex>) {
+		self.counters.rebalance_count.fetch_add(1, Ordering::Relaxed);
+		let mut tables = RwLockUpgradableReadGuard::upgrade(tables);
+		let mut reindex = RwLockUpgradableReadGuard::upgrade(reindex);
+		log::info!(
+			target: "parity-db",
+			"Started reindex for {}",
+			tables.index.id,
+		);
+		// Start reindex
+		let new_index_id = IndexTableId::new(tables.index.id.col(), index_bits);
+		let new_table = IndexTable::create_new(&self.path, new_index_id);
+		let old_table = std::mem::replace(&mut tables.index, new_table);
+		reindex.queue.push_back(old_table);
+		(
 			RwLockWriteGuard::downgrade_to_upgradable(tables),
 			RwLockWriteGuard::downgrade_to_upgradable(reindex),
 		)
@@ -402,6 +1342,7 @@ impl HashColumn {
 		address: Address,
 		log: &mut LogWriter,
 	) -> Result<PlanOutcome> {
+		self.check_writable()?;
 		let tables = self.tables.upgradable_read();
 		let reindex = self.reindex.upgradable_read();
 		self.write_reindex_plan_locked(tables, reindex, key, address, log)
@@ -415,13 +1356,14 @@ impl HashColumn {
 		address: Address,
 		log: &mut LogWriter,
 	) -> Result<PlanOutcome> {
-		if Self::search_index(key, &tables.index, &tables, log)?.is_some() {
+		if self.search_index(key, &tables.index, &tables, log)?.is_some() {
 			return Ok(PlanOutcome::Skipped)
 		}
 		match tables.index.write_insert_plan(key, address, None, log)? {
 			PlanOutcome::NeedReindex => {
 				log::debug!(target: "parity-db", "{}: Index chunk full {} when reindexing", tables.index.id, hex(key));
-				let (tables, reindex) = Self::trigger_reindex(tables, reindex, self.path.as_path());
+				self.check_index_growth(&tables.index)?;
+				let (tables, reindex) = self.trigger_reindex(tables, reindex);
 				self.write_reindex_plan_locked(tables, reindex, key, address, log)?;
 				Ok(PlanOutcome::NeedReindex)
 			},
@@ -430,10 +1372,11 @@ impl HashColumn {
 	}
 
 	fn search_index<'a>(
+		&self,
 		key: &Key,
 		index: &'a IndexTable,
-		tables: &'a Tables,
-		log: &LogWriter,
+		tables: &Tables,
+		log: &impl LogQuery,
 	) -> Result<Option<(&'a IndexTable, usize, Address)>> {
 		let (mut existing_entry, mut sub_index) = index.get(key, 0, log)?;
 		while !existing_entry.is_empty() {
@@ -447,6 +1390,7 @@ impl HashColumn {
 			)? {
 				return Ok(Some((index, sub_index, existing_address)))
 			}
+			self.counters.index_conflicts.fetch_add(1, Ordering::Relaxed);
 
 			let (next_entry, next_index) = index.get(key, sub_index + 1, log)?;
 			existing_entry = next_entry;
@@ -456,53 +1400,88 @@ impl HashColumn {
 	}
 
 	fn search_all_indexes<'a>(
+		&self,
 		key: &Key,
 		tables: &'a Tables,
 		reindex: &'a Reindex,
-		log: &LogWriter,
+		log: &impl LogQuery,
 	) -> Result<Option<(&'a IndexTable, usize, Address)>> {
-		if let Some(r) = Self::search_index(key, &tables.index, tables, log)? {
+		if let Some(r) = self.search_index(key, &tables.index, tables, log)? {
 			return Ok(Some(r))
 		}
 		// Check old indexes
 		// TODO: don't search if index precedes reindex progress
 		for index in &reindex.queue {
-			if let Some(r) = Self::search_index(key, index, tables, log)? {
+			if let Some(r) = self.search_index(key, index, tables, log)? {
 				return Ok(Some(r))
 			}
 		}
 		Ok(None)
 	}
 
-	pub fn write_plan(
+	/// Plan a set of changes under a single lock acquisition. All keys are checked before
+	/// anything is planned. The changes end up in the same log record, which is validated as a
+	/// whole on replay, so they are enacted together or not at all.
+	pub fn write_plan_txn(
+		&self,
+		changes: &[Operation<Key, Vec<u8>>],
+		log: &mut LogWriter,
+	) -> Result<Vec<PlanOutcome>> {
+		self.check_writable()?;
+		for change in changes {
+			check_key(change.key())?;
+		}
+		let mut tables = self.tables.upgradable_read();
+		let mut reindex = self.reindex.upgradable_read();
+		let mut outcomes = Vec::with_capacity(changes.len());
+		for change in changes {
+			let (outcome, t, r) = self.write_plan_locked(tables, reindex, change, log)?;
+			tables = t;
+			reindex = r;
+			outcomes.push(outcome);
+		}
+		Ok(outcomes)
+	}
+
+	fn write_plan_locked<'a, 'b>(
 		&self,
+		tables: RwLockUpgradableReadGuard<'a, Tables>,
+		reindex: RwLockUpgradableReadGuard<'b, Reindex>,
 		change: &Operation<Key, Vec<u8>>,
 		log: &mut LogWriter,
-	) -> Result<PlanOutcome> {
-		let tables = self.tables.upgradable_read();
-		let reindex = self.reindex.upgradable_read();
-		let existing = Self::search_all_indexes(change.key(), &tables, &reindex, log)?;
+	) -> Result<(
+		PlanOutcome,
+		RwLockUpgradableReadGuard<'a, Tables>,
+		RwLockUpgradableReadGuard<'b, Reindex>,
+	)> {
+		let existing = self.search_all_indexes(change.key(), &tables, &reindex, log)?;
 		if let Some((table, sub_index, existing_address)) = existing {
-			self.write_plan_existing(&tables, change, log, table, sub_index, existing_address)
+			let outcome =
+				self.write_plan_existing(&tables, change, log, table, sub_index, existing_address)?;
+			Ok((outcome, tables, reindex))
 		} else {
 			match change {
 				Operation::Set(key, value) => {
-					let (r, _, _) = self.write_plan_new(tables, reindex, key, value, log)?;
-					Ok(r)
+					// A full filter is rebuilt by `DbInner::rebuild_full_blooms`.
+					#[cfg(feature = "bloom")]
+					if let Some(bloom) = &self.bloom {
+						bloom.insert(key);
+					}
+					self.write_plan_new(tables, reindex, key, value, log)
 				},
 				Operation::Dereference(key) => {
 					log::trace!(target: "parity-db", "{}: Deleting missing key {}", tables.index.id, hex(key));
 					if self.collect_stats {
 						self.stats.remove_miss();
 					}
-					Ok(PlanOutcome::Skipped)
+					Ok((PlanOutcome::Skipped, tables, reindex))
 				},
 				Operation::Reference(key) => {
 					log::trace!(target: "parity-db", "{}: Ignoring increase rc, missing key {}", tables.index.id, hex(key));
 					if self.collect_stats {
 						self.stats.reference_increase_miss();
 					}
-					Ok(PlanOutcome::Skipped)
+					Ok((PlanOutcome::Skipped, tables, reindex))
 				},
 			}
 		}
@@ -541,6 +1520,10 @@ impl HashColumn {
 			(None, None) => {
 				log::trace!(target: "parity-db", "{}: Removing from index {}", tables.index.id, hex(key));
 				index.write_remove_plan(key, sub_index, log)?;
+				#[cfg(feature = "bloom")]
+				if let Some(bloom) = &self.bloom {
+					bloom.remove(key);
+				}
 				Ok(PlanOutcome::Written)
 			},
 		}
@@ -570,7 +1553,8 @@ impl HashColumn {
 		match tables.index.write_insert_plan(key, address, None, log)? {
 			PlanOutcome::NeedReindex => {
 				log::debug!(target: "parity-db", "{}: Index chunk full {}", tables.index.id, hex(key));
-				let (tables, reindex) = Self::trigger_reindex(tables, reindex, self.path.as_path());
+				self.check_index_growth(&tables.index)?;
+				let (tables, reindex) = self.trigger_reindex(tables, reindex);
 				let (_, t, r) = self.write_plan_new(tables, reindex, key, value, log)?;
 				Ok((PlanOutcome::NeedReindex, t, r))
 			},
@@ -579,28 +1563,50 @@ impl HashColumn {
 	}
 
 	pub fn enact_plan(&self, action: LogAction, log: &mut LogReader) -> Result<()> {
+		self.check_writable()?;
 		let tables = self.tables.read();
 		let reindex = self.reindex.read();
+		let delete =
+			matches!(action, LogAction::DeleteIndex { .. } | LogAction::DeleteValue { .. });
 		match action {
-			LogAction::InsertIndex(record) => {
-				if tables.index.id == record.table {
-					tables.index.enact_plan(record.index, log)?;
-				} else if let Some(table) = reindex.queue.iter().find(|r| r.id == record.table) {
-					table.enact_plan(record.index, log)?;
+			LogAction::InsertIndex(InsertIndexAction { table: id, index: chunk }) |
+			LogAction::DeleteIndex { table: id, index: chunk } => {
+				let table = if tables.index.id == id {
+					Some(&tables.index)
 				} else {
-					// This may happen when removal is planed for an old index when reindexing.
-					// We can safely skip the removal since the new index does not have the entry
-					// anyway and the old index is already dropped.
-					log::debug!(
-						target: "parity-db",
-						"Missing index {}. Skipped",
-						record.table,
-					);
-					IndexTable::skip_plan(log)?;
+					reindex.queue.iter().find(|r| r.id == id)
+				};
+				match table {
+					Some(table) if delete => table.enact_delete_plan(chunk, log)?,
+					Some(table) => table.enact_plan(chunk, log)?,
+					None => {
+						// This may happen when removal is planed for an old index when
+						// reindexing. We can safely skip the removal since the new index does
+						// not have the entry anyway and the old index is already dropped.
+						log::debug!(
+							target: "parity-db",
+							"Missing index {}. Skipped",
+							id,
+						);
+						if delete {
+							IndexTable::skip_delete_plan(log)?;
+						} else {
+							IndexTable::skip_plan(log)?;
+						}
+					},
 				}
 			},
-			LogAction::InsertValue(record) => {
-				tables.value[record.table.size_tier() as usize].enact_plan(record.index, log)?;
+			LogAction::InsertValue(InsertValueAction { table, index }) |
+			LogAction::DeleteValue { table, index } => {
+				let value = Self::logged_value_table(&tables, table)?;
+				if delete {
+					value.enact_delete_plan(index, log)?;
+				} else {
+					value.enact_plan(index, log)?;
+				}
+				if let Some(cache) = &self.value_cache {
+					cache.invalidate(table.size_tier(), index);
+				}
 			},
 			// This should never happen, unless something has modified the log file while the
 			// database is running. Existing logs should be validated with `validate_plan` on
@@ -610,29 +1616,63 @@ impl HashColumn {
 		Ok(())
 	}
 
+	// Value table of a log action. Columns with `ColumnOptions::value_sizes` have fewer tables
+	// than there are size tiers.
+	fn logged_value_table(tables: &Tables, table: ValueTableId) -> Result<&ValueTable> {
+		tables
+			.value
+			.get(table.size_tier() as usize)
+			.ok_or_else(|| Error::Corruption(format!("Missing value table {}", table)))
+	}
+
 	pub fn validate_plan(&self, action: LogAction, log: &mut LogReader) -> Result<()> {
 		let tables = self.tables.upgradable_read();
 		let reindex = self.reindex.upgradable_read();
+		let delete =
+			matches!(action, LogAction::DeleteIndex { .. } | LogAction::DeleteValue { .. });
 		match action {
-			LogAction::InsertIndex(record) => {
-				if tables.index.id == record.table {
-					tables.index.validate_plan(record.index, log)?;
-				} else if let Some(table) = reindex.queue.iter().find(|r| r.id == record.table) {
-					table.validate_plan(record.index, log)?;
+			LogAction::InsertIndex(InsertIndexAction { table: id, index: chunk }) |
+			LogAction::DeleteIndex { table: id, index: chunk } => {
+				let table = if tables.index.id == id {
+					Some(&tables.index)
+				} else {
+					reindex.queue.iter().find(|r| r.id == id)
+				};
+				if let Some(table) = table {
+					if delete {
+						table.validate_delete_plan(chunk, log)?;
+					} else {
+						table.validate_plan(chunk, log)?;
+					}
 				} else {
 					// Re-launch previously started reindex
 					// TODO: add explicit log records for reindexing events.
 					log::warn!(
 						target: "parity-db",
 						"Missing table {}, starting reindex",
-						record.table,
+						id,
 					);
-					let _lock = Self::trigger_reindex(tables, reindex, self.path.as_path());
-					return self.validate_plan(LogAction::InsertIndex(record), log)
+					if id.index_bits() > tables.index.id.index_bits() {
+						let _lock = self.trigger_reindex_to(tables, reindex, id.index_bits());
+					} else {
+						let _lock = self.trigger_reindex(tables, reindex);
+					}
+					let action = if delete {
+						LogAction::DeleteIndex { table: id, index: chunk }
+					} else {
+						LogAction::InsertIndex(InsertIndexAction { table: id, index: chunk })
+					};
+					return self.validate_plan(action, log)
 				}
 			},
-			LogAction::InsertValue(record) => {
-				tables.value[record.table.size_tier() as usize].validate_plan(record.index, log)?;
+			LogAction::InsertValue(InsertValueAction { table, index }) |
+			LogAction::DeleteValue { table, index } => {
+				let value = Self::logged_value_table(&tables, table)?;
+				if delete {
+					value.validate_delete_plan(index, log)?;
+				} else {
+					value.validate_plan(index, log)?;
+				}
 			},
 			_ => {
 				log::error!(target: "parity-db", "Unexpected log action");
@@ -643,6 +1683,7 @@ impl HashColumn {
 	}
 
 	pub fn complete_plan(&self, log: &mut LogWriter) -> Result<()> {
+		self.check_writable()?;
 		let tables = self.tables.read();
 		for t in tables.value.iter() {
 			t.complete_plan(log)?;
@@ -661,6 +1702,51 @@ impl HashColumn {
 		Ok(())
 	}
 
+	/// Rebuild the Bloom filter from the index, sizing it for the current number of entries.
+	/// `block_writes` is held while the rebuild starts and must make sure that all keys already
+	/// added to the filter are in the index or in `log`.
+	#[cfg(feature = "bloom")]
+	pub fn rebuild_bloom<T>(
+		&self,
+		log: &impl LogQuery,
+		block_writes: impl FnOnce() -> T,
+	) -> Result<()> {
+		let Some(bloom) = &self.bloom else { return Ok(()) };
+		let rebuild = {
+			let _blocked = block_writes();
+			bloom.start_rebuild()
+		};
+		let tables = self.tables.read();
+		let reindex = self.reindex.read();
+		let mut keys = Vec::new();
+		for source in std::iter::once(&tables.index).chain(reindex.queue.iter()) {
+			for chunk_index in 0..source.id.total_chunks() {
+				for entry in source.entries(chunk_index, log)?.iter() {
+					if !entry.is_empty() {
+						keys.push(source.recover_key_prefix(chunk_index, *entry));
+					}
+				}
+			}
+		}
+		log::debug!(target: "parity-db", "{}: Rebuilt bloom filter with {} entries", tables.index.id, keys.len());
+		rebuild.finish(&keys);
+		Ok(())
+	}
+
+	#[cfg(feature = "bloom")]
+	pub fn is_bloom_full(&self) -> bool {
+		self.bloom.as_ref().is_some_and(|bloom| bloom.is_full())
+	}
+
+	#[cfg(feature = "bloom")]
+	pub fn bloom_stats(&self) -> Option<BloomStats> {
+		self.bloom.as_ref().map(|bloom| bloom.stats())
+	}
+
+	pub fn value_cache_stats(&self) -> Option<ValueCacheStats> {
+		self.value_cache.as_ref().map(|cache| cache.stats())
+	}
+
 	pub fn write_stats_text(&self, writer: &mut impl std::io::Write) -> Result<()> {
 		let tables = self.tables.read();
 		tables.index.write_stats(&self.stats)?;
@@ -671,6 +1757,26 @@ impl HashColumn {
 		self.stats.summary()
 	}
 
+	pub fn summary_line(&self) -> String {
+		let total_values = self.stats.summary().total_values;
+		let capacity = self.tables.read().index.id.total_entries();
+		format!("{} keys {}% full", human_count(total_values), total_values * 100 / capacity)
+	}
+
+	pub fn is_reindexing(&self) -> bool {
+		!self.reindex.read().queue.is_empty()
+	}
+
+	/// Time-to-live of the column values in milliseconds, see `ColumnOptions::ttl`.
+	pub fn ttl(&self) -> Option<u64> {
+		self.ttl
+	}
+
+	/// Number of index tables waiting to be reindexed.
+	pub fn reindex_queue_len(&self) -> usize {
+		self.reindex.read().queue.len()
+	}
+
 	fn clear_stats(&self) -> Result<()> {
 		let tables = self.tables.read();
 		self.stats.clear();
@@ -729,6 +1835,7 @@ impl HashColumn {
 
 		for c in start_chunk..source.id.total_chunks() {
 			let entries = source.entries(c, log.overlays())?;
+			let mut addresses = Vec::with_capacity(entries.len());
 			for entry in entries.iter() {
 				if entry.is_empty() {
 					continue
@@ -749,7 +1856,28 @@ impl HashColumn {
 				{
 					continue
 				}
-				let value = tables.value[size_tier as usize].get_with_meta(offset, log.overlays());
+				addresses.push((*entry, size_tier as usize, offset));
+			}
+			// Read all entries of a tier at once. On failure, entries are read one by one
+			// so that errors are reported per entry.
+			let mut tiers: HashMap<usize, Vec<u64>> = HashMap::new();
+			for (_, tier, offset) in addresses.iter() {
+				tiers.entry(*tier).or_default().push(*offset);
+			}
+			let mut prefetched = HashMap::new();
+			for (tier, offsets) in tiers {
+				if offsets.len() < 2 {
+					continue
+				}
+				if let Ok(values) = tables.value[tier].bulk_read(&offsets, log.overlays()) {
+					prefetched.extend(offsets.into_iter().map(|o| (tier, o)).zip(values));
+				}
+			}
+			for (entry, size_tier, offset) in addresses.iter() {
+				let value = match prefetched.remove(&(*size_tier, *offset)) {
+					Some(value) => Ok(value),
+					None => tables.value[*size_tier].get_with_meta(*offset, log.overlays()),
+				};
 				let (value, rc, pk, compressed) = match value {
 					Ok(Some(v)) => v,
 					Ok(None) => {
@@ -783,6 +1911,66 @@ impl HashColumn {
 		Ok(())
 	}
 
+	/// Probe distance histogram of all index tables, see `IndexTable::probe_distance_histogram`.
+	pub fn probe_distance_histogram(&self, log: &Log) -> Result<Vec<u64>> {
+		let tables = self.tables.read();
+		let reindex = self.reindex.read();
+		let mut histogram = tables.index.probe_distance_histogram(log.overlays())?;
+		for source in reindex.queue.iter() {
+			let source = source.probe_distance_histogram(log.overlays())?;
+			histogram.iter_mut().zip(source).for_each(|(total, count)| *total += count);
+		}
+		Ok(histogram)
+	}
+
+	/// Probe depth histogram of all index tables, see `IndexTable::probe_depth_histogram`.
+	pub fn probe_depth_histogram(&self, log: &Log) -> Result<Vec<u64>> {
+		let tables = self.tables.read();
+		let reindex = self.reindex.read();
+		let mut histogram = tables.index.probe_depth_histogram(log.overlays())?;
+		for source in reindex.queue.iter() {
+			let source = source.probe_depth_histogram(log.overlays())?;
+			histogram.iter_mut().zip(source).for_each(|(total, count)| *total += count);
+		}
+		Ok(histogram)
+	}
+
+	/// Compute derived index metrics, including tables in the reindex queue.
+	pub fn index_stats(&self, log: &Log) -> Result<CachedStats> {
+		let tables = self.tables.read();
+		let reindex = self.reindex.read();
+		let mut histogram = tables.index.probe_distance_histogram(log.overlays())?;
+		let mut capacity = tables.index.id.total_entries();
+		for source in reindex.queue.iter() {
+			let source_histogram = source.probe_distance_histogram(log.overlays())?;
+			histogram
+				.iter_mut()
+				.zip(source_histogram)
+				.for_each(|(total, count)| *total += count);
+			capacity += source.id.total_entries();
+		}
+		let entries = histogram.iter().sum();
+		Ok(CachedStats {
+			index_entries: entries,
+			index_capacity: capacity,
+			fill_rate: entries as f64 / capacity as f64,
+			probe_distance_histogram: histogram,
+			computed_at: std::time::SystemTime::now(),
+		})
+	}
+
+	/// Find index entries that point to missing values, including ones in the reindex queue.
+	pub fn check_referential_integrity(&self, log: &Log) -> Result<Vec<CorruptAddress>> {
+		let tables = self.tables.read();
+		let reindex = self.reindex.read();
+		let log = log.overlays();
+		let mut corrupt = tables.index.check_referential_integrity(&tables.value, log)?;
+		for source in reindex.queue.iter() {
+			corrupt.extend(source.check_referential_integrity(&tables.value, log)?);
+		}
+		Ok(corrupt)
+	}
+
 	fn dump(&self, log: &Log, check_param: &crate::CheckOptions, col: ColId) -> Result<()> {
 		let start_chunk = check_param.from.unwrap_or(0);
 		let end_chunk = check_param.bound;
@@ -833,6 +2021,39 @@ impl HashColumn {
 			false,
 		)?;
 
+		for corrupt in self.check_referential_integrity(log)? {
+			log::info!(
+				target: "parity-db",
+				"Index entry {}:{} points to a missing value, {}",
+				corrupt.chunk_index,
+				corrupt.sub_index,
+				corrupt.address,
+			);
+		}
+
+		let format_histogram = |histogram: &[u64]| {
+			let last = histogram.iter().rposition(|count| *count != 0).map_or(0, |i| i + 1);
+			let histogram: Vec<_> = histogram[..last]
+				.iter()
+				.enumerate()
+				.map(|(i, count)| format!("{}: {}", i + 1, count))
+				.collect();
+			histogram.join(", ")
+		};
+		let histogram = self.probe_distance_histogram(log)?;
+		log::info!(
+			target: "parity-db",
+			"Probe distance histogram: [{}]",
+			format_histogram(&histogram),
+		);
+		let histogram = self.probe_depth_histogram(log)?;
+		log::info!(
+			target: "parity-db",
+			"Probe depth histogram: [{}], max {}, mean {:.2}",
+			format_histogram(&histogram),
+			IndexTable::max_probe_depth(&histogram),
+			IndexTable::mean_probe_depth(&histogram),
+		);
 		log::info!(target: "parity-db", "Ended full index check, elapsed {:?}", start_time.elapsed());
 		Ok(())
 	}
@@ -850,17 +2071,28 @@ impl HashColumn {
 					log::debug!(target: "parity-db", "{}: Reindexing at {}/{}", tables.index.id, source_index, source.id.total_chunks());
 				}
 				log::debug!(target: "parity-db", "{}: Continue reindex at {}/{}", tables.index.id, source_index, source.id.total_chunks());
-				while source_index < source.id.total_chunks() && plan.len() < MAX_REINDEX_BATCH {
-					log::trace!(target: "parity-db", "{}: Reindexing {}", source.id, source_index);
-					let entries = source.entries(source_index, log.overlays())?;
-					for entry in entries.iter() {
-						if entry.is_empty() {
-							continue
-						}
-						// We only need key prefix to reindex.
-						let key = source.recover_key_prefix(source_index, *entry);
-						plan.push((key, entry.address(source.id.index_bits())))
+				// At least one chunk is moved per batch, so that a size of zero can't stall the
+				// reindex.
+				let batch_size = self.reindex_batch_size.load(Ordering::Relaxed) as usize;
+				while source_index < source.id.total_chunks() &&
+					(source_index == progress || plan.len() < batch_size)
+				{
+					#[cfg(feature = "parallel-reindex")]
+					if self.parallel_reindex {
+						use rayon::prelude::*;
+						// Read as many chunks as could still fit the batch if they were full.
+						let chunks = (batch_size.saturating_sub(plan.len()) as u64)
+							.div_ceil(CHUNK_ENTRIES as u64)
+							.clamp(1, source.id.total_chunks() - source_index);
+						let moved = (source_index..source_index + chunks)
+							.into_par_iter()
+							.map(|chunk| Self::reindex_chunk(source, chunk, log))
+							.collect::<Result<Vec<_>>>()?;
+						plan.extend(moved.into_iter().flatten());
+						source_index += chunks;
+						continue
 					}
+					plan.extend(Self::reindex_chunk(source, source_index, log)?);
 					source_index += 1;
 				}
 				log::trace!(target: "parity-db", "{}: End reindex batch {} ({})", tables.index.id, source_index, plan.len());
@@ -874,81 +2106,657 @@ impl HashColumn {
 		Ok(ReindexBatch { drop_index, batch: plan })
 	}
 
-	pub fn drop_index(&self, id: IndexTableId) -> Result<()> {
-		log::debug!(target: "parity-db", "Dropping {}", id);
-		let mut reindex = self.reindex.write();
-		if reindex.queue.front_mut().map_or(false, |index| index.id == id) {
-			let table = reindex.queue.pop_front();
-			reindex.progress.store(0, Ordering::Relaxed);
-			table.unwrap().drop_file()?;
-		} else {
-			log::warn!(target: "parity-db", "Dropping invalid index {}", id);
-			return Ok(())
-		}
-		log::debug!(target: "parity-db", "Dropped {}", id);
-		Ok(())
+	fn reindex_chunk(source: &IndexTable, chunk: u64, log: &Log) -> Result<Vec<(Key, Address)>> {
+		log::trace!(target: "parity-db", "{}: Reindexing {}", source.id, chunk);
+		let entries = source.entries(chunk, log.overlays())?;
+		Ok(entries
+			.iter()
+			.filter(|entry| !entry.is_empty())
+			// We only need key prefix to reindex.
+			.map(|entry| {
+				(source.recover_key_prefix(chunk, *entry), entry.address(source.id.index_bits()))
+			})
+			.collect())
 	}
-}
 
-impl Column {
-	pub fn write_existing_value_plan<K, V: AsRef<[u8]>>(
-		key: &TableKey,
-		tables: TablesRef,
-		address: Address,
-		change: &Operation<K, V>,
-		log: &mut LogWriter,
-		stats: Option<&ColumnStats>,
-		ref_counted: bool,
-	) -> Result<(Option<PlanOutcome>, Option<Address>)> {
-		let tier = address.size_tier() as usize;
+	/// Progress of the current reindex, if any.
+	#[cfg(feature = "async-tokio")]
+	pub fn reindex_progress(&self) -> Option<RebalanceProgress> {
+		let reindex = self.reindex.read();
+		let source = reindex.queue.front()?;
+		Some(RebalanceProgress {
+			col: self.col,
+			processed_chunks: reindex.progress.load(Ordering::Relaxed),
+			total_chunks: source.id.total_chunks(),
+			pending_tables: reindex.queue.len(),
+		})
+	}
 
-		let fetch_size = || -> Result<(u32, u32)> {
-			let (cur_size, compressed) =
-				tables.tables[tier].size(key, address.offset(), log)?.unwrap_or((0, false));
-			Ok(if compressed {
-				// This is very costly.
-				let compressed = tables.tables[tier]
-					.get(key, address.offset(), log)?
-					.expect("Same query as size")
-					.0;
-				let uncompressed = tables.compression.decompress(compressed.as_slice())?;
+	// Each key maps to a single index chunk and is looked up with a linear scan of that chunk.
+	// Removals leave gaps in front of the remaining entries, so packing each chunk gives
+	// the minimal probe length.
+	pub fn defrag_index(&self, log: &mut LogWriter) -> Result<DefragIndexStats> {
+		let tables = self.tables.read();
+		// Reindexed entries would be moved into chunks that are being rewritten.
+		if !self.reindex.read().queue.is_empty() {
+			return Err(Error::InvalidInput(format!("Column {} is being reindexed", self.col)))
+		}
+		let stats = tables.index.compact_chains(log)?;
+		log::debug!(
+			target: "parity-db",
+			"{}: Defragmented index, {} entries, longest chain {} -> {}",
+			tables.index.id,
+			stats.entries,
+			stats.max_chain_before,
+			stats.max_chain_after,
+		);
+		Ok(DefragIndexStats {
+			entries: stats.entries,
+			avg_probe_before: stats.avg_chain_before,
+			avg_probe_after: stats.avg_chain_after,
+			max_probe_before: stats.max_chain_before,
+			max_probe_after: stats.max_chain_after,
+		})
+	}
 
-				(cur_size, uncompressed.len() as u32)
-			} else {
-				(cur_size, cur_size)
-			})
-		};
+	/// Grow the index to hold `expected_entries` at about half capacity, limited by the
+	/// configured maximum. Existing entries are moved by a single reindex, instead of one for
+	/// each doubling during a bulk load. The index never shrinks. Returns `true` if a reindex
+	/// was started.
+	pub fn reserve(&self, expected_entries: u64) -> Result<bool> {
+		self.check_writable()?;
+		let mut index_bits =
+			index_bits_for(expected_entries, RESERVE_FILL_RATE).ok_or_else(|| {
+				Error::InvalidInput(format!(
+					"Column {} can't index {} entries",
+					self.col, expected_entries
+				))
+			})?;
+		if let Some(max_bits) = self.max_bits {
+			index_bits = std::cmp::min(index_bits, max_bits);
+		}
+		let tables = self.tables.upgradable_read();
+		if index_bits <= tables.index.id.index_bits() {
+			return Ok(false)
+		}
+		let reindex = self.reindex.upgradable_read();
+		let _lock = self.trigger_reindex_to(tables, reindex, index_bits);
+		Ok(true)
+	}
 
-		match change {
-			Operation::Reference(_) =>
-				if ref_counted {
-					log::trace!(target: "parity-db", "{}: Increment ref {}", tables.col, key);
-					tables.tables[tier].write_inc_ref(address.offset(), log)?;
-					if let Some(stats) = stats {
-						stats.reference_increase();
+	/// Double the index capacity, reusing the index file instead of reindexing into a new one.
+	pub fn resize_index_in_place(&self, log: &mut LogWriter) -> Result<()> {
+		let mut tables = self.tables.write();
+		if !self.reindex.read().queue.is_empty() {
+			return Err(Error::InvalidInput(format!("Column {} is being reindexed", self.col)))
+		}
+		self.check_index_growth(&tables.index)?;
+		let table = tables.index.resize_in_place(log)?;
+		log::info!(target: "parity-db", "Resized index {} in place to {}", tables.index.id, table.id);
+		tables.index = table;
+		Ok(())
+	}
+
+	/// Remove all values stored in size tier `tier` along with their index entries.
+	/// Returns the number of removed values.
+	pub fn purge_tier(&self, tier: usize, log: &mut LogWriter) -> Result<u64> {
+		let tables = self.tables.write();
+		let reindex = self.reindex.write();
+		let table = tables.value.get(tier).ok_or_else(|| {
+			Error::InvalidInput(format!("Column {} has no size tier {}", self.col, tier))
+		})?;
+		// Entries that were already copied by reindexing point to the same value.
+		let mut removed = std::collections::HashSet::new();
+		for source in std::iter::once(&tables.index).chain(reindex.queue.iter()) {
+			for chunk_index in 0..source.id.total_chunks() {
+				let entries = source.entries(chunk_index, &*log)?;
+				for (sub_index, entry) in entries.iter().enumerate() {
+					if entry.is_empty() {
+						continue
 					}
-					Ok((Some(PlanOutcome::Written), None))
-				} else {
-					Ok((Some(PlanOutcome::Skipped), None))
-				},
-			Operation::Set(_, val) => {
-				if ref_counted {
-					log::trace!(target: "parity-db", "{}: Increment ref {}", tables.col, key);
-					tables.tables[tier].write_inc_ref(address.offset(), log)?;
-					return Ok((Some(PlanOutcome::Written), None))
-				}
-				if tables.preimage {
-					// Replace is not supported
-					return Ok((Some(PlanOutcome::Skipped), None))
+					let address = entry.address(source.id.index_bits());
+					if address.size_tier() as usize != tier {
+						continue
+					}
+					let key = source.recover_key_prefix(chunk_index, *entry);
+					source.write_remove_plan(&key, sub_index, log)?;
+					if !removed.insert(address.offset()) {
+						continue
+					}
+					if self.collect_stats {
+						let (size, compressed) = table
+							.size(&TableKey::NoHash, address.offset(), &*log)?
+							.unwrap_or((0, false));
+						let uncompressed = if compressed {
+							// This is very costly.
+							let value = table.get(&TableKey::NoHash, address.offset(), &*log)?;
+							let value = value.map(|(v, _)| v).unwrap_or_default();
+							self.compression.decompress(&value)?.len() as u32
+						} else {
+							size
+						};
+						self.stats.remove_val(uncompressed, size);
+					}
+					table.write_remove_plan(address.offset(), log)?;
 				}
+			}
+		}
+		log::debug!(target: "parity-db", "{}: Purged {} values from tier {}", tables.index.id, removed.len(), tier);
+		Ok(removed.len() as u64)
+	}
 
-				let (cval, target_tier) =
-					Column::compress(tables.compression, key, val.as_ref(), tables.tables);
-				let (cval, compressed) = cval
-					.as_ref()
-					.map(|cval| (cval.as_slice(), true))
+	/// Free all slots of size tier `tier` in `start..end`, along with the index entries of the
+	/// values stored there. Parts of these values outside of the range are freed as well.
+	/// Returns the number of freed slots in the range.
+	pub fn free_range(
+		&self,
+		tier: usize,
+		start: u64,
+		end: u64,
+		log: &mut LogWriter,
+	) -> Result<u64> {
+		let tables = self.tables.write();
+		let reindex = self.reindex.write();
+		let table = tables.value.get(tier).ok_or_else(|| {
+			Error::InvalidInput(format!("Column {} has no size tier {}", self.col, tier))
+		})?;
+		let in_range = |offset: u64| offset >= start && offset < end;
+		// Entries that were already copied by reindexing point to the same value.
+		let mut removed = HashSet::new();
+		let mut outside = Vec::new();
+		for source in std::iter::once(&tables.index).chain(reindex.queue.iter()) {
+			for chunk_index in 0..source.id.total_chunks() {
+				let entries = source.entries(chunk_index, &*log)?;
+				for (sub_index, entry) in entries.iter().enumerate() {
+					if entry.is_empty() {
+						continue
+					}
+					let address = entry.address(source.id.index_bits());
+					if address.size_tier() as usize != tier {
+						continue
+					}
+					let mut parts = vec![address.offset()];
+					while let Some(next) = table.read_next_part(parts[parts.len() - 1], &*log)? {
+						if next == 0 || parts.contains(&next) {
+							break
+						}
+						parts.push(next);
+					}
+					if !parts.iter().any(|part| in_range(*part)) {
+						continue
+					}
+					let key = source.recover_key_prefix(chunk_index, *entry);
+					source.write_remove_plan(&key, sub_index, log)?;
+					if removed.insert(address.offset()) {
+						outside.extend(parts.into_iter().filter(|part| !in_range(*part)));
+					}
+				}
+			}
+		}
+		let freed = table.write_free_range_plan(start, end, log)?;
+		for part in outside {
+			table.write_free_range_plan(part, part + 1, log)?;
+		}
+		table.complete_plan(log)?;
+		log::debug!(target: "parity-db", "{}: Freed {} slots of tier {}, dropping {} values", tables.index.id, freed, tier, removed.len());
+		Ok(freed)
+	}
+
+	/// Remove all values that were written longer than the column time-to-live ago, see
+	/// `ColumnOptions::ttl`. Returns the number of removed values. The column is scanned under
+	/// read locks, so it stays readable. The caller must prevent index changes until the removal
+	/// is planned.
+	pub fn expire(&self, log: &mut LogWriter) -> Result<u64> {
+		let ttl = self.ttl.ok_or_else(|| {
+			Error::InvalidConfiguration(format!("Column {} has no time-to-live", self.col))
+		})?;
+		let now = unix_millis();
+		// Expired value slots, with their uncompressed and stored size.
+		let mut values = HashMap::new();
+		// Index entries of expired values, by index table and position.
+		let mut entries = Vec::new();
+		{
+			let tables = self.tables.read();
+			let reindex = self.reindex.read();
+			// Entries that were already copied by reindexing point to the same value.
+			let mut live = HashSet::new();
+			for source in std::iter::once(&tables.index).chain(reindex.queue.iter()) {
+				for chunk_index in 0..source.id.total_chunks() {
+					let chunk = source.entries(chunk_index, &*log)?;
+					for (sub_index, entry) in chunk.iter().enumerate() {
+						if entry.is_empty() {
+							continue
+						}
+						let address = entry.address(source.id.index_bits());
+						let slot = (address.size_tier(), address.offset());
+						if live.contains(&slot) {
+							continue
+						}
+						if let hash_map::Entry::Vacant(vacant) = values.entry(slot) {
+							let table = &tables.value[address.size_tier() as usize];
+							let (value, _rc, _pk, compressed) =
+								match table.get_with_meta(address.offset(), &*log)? {
+									Some(entry) => entry,
+									None => continue,
+								};
+							let value = if compressed {
+								self.compression.decompress(&value)?
+							} else {
+								value
+							};
+							let uncompressed = value.len() as u32;
+							if strip_expired(value, ttl, now)?.is_some() {
+								live.insert(slot);
+								continue
+							}
+							let size = if self.collect_stats {
+								table
+									.size(&TableKey::NoHash, address.offset(), &*log)?
+									.map_or(0, |(size, _)| size)
+							} else {
+								0
+							};
+							vacant.insert((uncompressed, size));
+						}
+						entries.push((source.id, chunk_index, sub_index, *entry));
+					}
+				}
+			}
+		}
+
+		let tables = self.tables.upgradable_read();
+		let reindex = self.reindex.upgradable_read();
+		for (id, chunk_index, sub_index, entry) in entries {
+			let source =
+				std::iter::once(&tables.index).chain(reindex.queue.iter()).find(|s| s.id == id);
+			let source = match source {
+				Some(source) if source.entries(chunk_index, &*log)?[sub_index] == entry => source,
+				// The value is kept if any of its entries changed since the scan.
+				_ => {
+					let address = entry.address(id.index_bits());
+					values.remove(&(address.size_tier(), address.offset()));
+					continue
+				},
+			};
+			let key = source.recover_key_prefix(chunk_index, entry);
+			source.write_remove_plan(&key, sub_index, log)?;
+		}
+		for ((tier, offset), (uncompressed, size)) in &values {
+			if self.collect_stats {
+				self.stats.remove_val(*uncompressed, *size);
+			}
+			tables.value[*tier as usize].write_remove_plan(*offset, log)?;
+		}
+		log::debug!(target: "parity-db", "{}: Expired {} values", tables.index.id, values.len());
+		Ok(values.len() as u64)
+	}
+
+	/// Delete all index and value table files and start with an empty column.
+	pub fn hard_reset(&self) -> Result<()> {
+		let mut tables = self.tables.write();
+		let mut reindex = self.reindex.write();
+		for table in reindex.queue.drain(..) {
+			table.drop_file()?;
+		}
+		reindex.progress.store(0, Ordering::Relaxed);
+		let empty =
+			IndexTable::create_new(&self.path, IndexTableId::new(self.col, self.start_bits));
+		std::mem::replace(&mut tables.index, empty).drop_file()?;
+		for table in &tables.value {
+			table.clear()?;
+		}
+		self.stats.clear();
+		log::debug!(target: "parity-db", "Column {} reset", self.col);
+		Ok(())
+	}
+
+	// Number of entries of `source` that are missing from the current index. Entries that point
+	// to removed values are not counted. This may over-report if a removed value slot was reused.
+	fn unmigrated_entries(
+		&self,
+		tables: &Tables,
+		source: &IndexTable,
+		log: &impl LogQuery,
+	) -> Result<u64> {
+		let index = &tables.index;
+		let mut missing = 0;
+		for chunk_index in 0..source.id.total_chunks() {
+			for entry in source.entries(chunk_index, log)?.iter() {
+				if entry.is_empty() {
+					continue
+				}
+				let address = entry.address(source.id.index_bits());
+				let key = source.recover_key_prefix(chunk_index, *entry);
+				let (mut existing, mut sub_index) = index.get(&key, 0, log)?;
+				while !existing.is_empty() && existing.address(index.id.index_bits()) != address {
+					(existing, sub_index) = index.get(&key, sub_index + 1, log)?;
+				}
+				if existing.is_empty() &&
+					!tables.value[address.size_tier() as usize]
+						.is_tombstone(address.offset(), log)?
+				{
+					missing += 1;
+				}
+			}
+		}
+		Ok(missing)
+	}
+
+	/// Plan dropping tables at the front of the reindex queue that have no entries left to
+	/// migrate, e.g. because all their keys were rewritten since the reindex started. This scans
+	/// each table. Returns the ids of tables that will be dropped once the plan is enacted.
+	pub fn trim_rebalance_queue(&self, log: &mut LogWriter) -> Result<Vec<IndexTableId>> {
+		let tables = self.tables.read();
+		let reindex = self.reindex.read();
+		let mut dropped = Vec::new();
+		for source in reindex.queue.iter() {
+			if self.unmigrated_entries(&tables, source, &*log)? != 0 {
+				break
+			}
+			log::debug!(target: "parity-db", "{}: Nothing left to reindex", source.id);
+			log.drop_table(source.id);
+			dropped.push(source.id);
+		}
+		Ok(dropped)
+	}
+
+	/// Free value table entries that are not referenced by the index or any table waiting to
+	/// be reindexed.
+	pub fn collect_garbage(&self, log: &mut LogWriter) -> Result<GarbageStats> {
+		let tables = self.tables.read();
+		let reindex = self.reindex.read();
+		let mut heads: Vec<Vec<u64>> = vec![Vec::new(); tables.value.len()];
+		for source in std::iter::once(&tables.index).chain(reindex.queue.iter()) {
+			for chunk_index in 0..source.id.total_chunks() {
+				for entry in source.entries(chunk_index, &*log)?.iter() {
+					if entry.is_empty() {
+						continue
+					}
+					let address = entry.address(source.id.index_bits());
+					if let Some(tier) = heads.get_mut(address.size_tier() as usize) {
+						tier.push(address.offset());
+					}
+				}
+			}
+		}
+		let mut stats = GarbageStats::default();
+		for (table, heads) in tables.value.iter().zip(heads) {
+			let freed = table.write_garbage_plan(heads, log)?;
+			table.complete_plan(log)?;
+			stats.orphaned_slots += freed;
+			stats.bytes_reclaimed += freed * table.entry_size as u64;
+		}
+		if stats.orphaned_slots != 0 {
+			log::info!(target: "parity-db", "{}: Freed {} orphaned value entries", tables.index.id, stats.orphaned_slots);
+		}
+		Ok(stats)
+	}
+
+	/// Check that each index entry points to an occupied value table slot holding a key that
+	/// belongs to the entry, that no index table references a slot twice, and that every
+	/// occupied value table slot is referenced by the index.
+	pub fn check_integrity(&self, log: &impl LogQuery, report: &mut IntegrityReport) -> Result<()> {
+		let tables = self.tables.read();
+		let reindex = self.reindex.read();
+		let mut heads: Vec<Vec<u64>> = vec![Vec::new(); tables.value.len()];
+		for source in std::iter::once(&tables.index).chain(reindex.queue.iter()) {
+			// Entries that were already copied by reindexing point to the same value, so
+			// duplicates are only looked for within each index table.
+			let mut referenced = std::collections::HashSet::new();
+			for chunk_index in 0..source.id.total_chunks() {
+				for (sub_index, entry) in source.entries(chunk_index, log)?.iter().enumerate() {
+					if entry.is_empty() {
+						continue
+					}
+					report.index_entries += 1;
+					let address = entry.address(source.id.index_bits());
+					let mut corrupt = |message: String| {
+						report.add(CorruptionError {
+							col: self.col,
+							size_tier: address.size_tier(),
+							offset: address.offset(),
+							message: format!(
+								"{} entry {}.{}: {}",
+								source.id, chunk_index, sub_index, message
+							),
+						})
+					};
+					let table = match tables.value.get(address.size_tier() as usize) {
+						Some(table)
+							if address.offset() != 0 && address.offset() < table.filled() =>
+							table,
+						_ => {
+							corrupt(format!("Address {} is out of bounds", address));
+							continue
+						},
+					};
+					if !referenced.insert(address) {
+						corrupt(format!("Address {} is referenced twice", address));
+						continue
+					}
+					heads[address.size_tier() as usize].push(address.offset());
+					match table.get_with_meta(address.offset(), log) {
+						Ok(Some((_value, _rc, pk, _compressed))) => {
+							let mut key = source.recover_key_prefix(chunk_index, *entry);
+							key[6..].copy_from_slice(&pk);
+							if !source.is_entry_of(chunk_index, *entry, &key) {
+								corrupt(format!("Key {} does not match the entry", hex(&key)));
+							}
+						},
+						Ok(None) => corrupt(format!("Address {} is not occupied", address)),
+						Err(e) => corrupt(format!("Value at {} can't be read: {}", address, e)),
+					}
+				}
+			}
+		}
+		for ((tier, table), heads) in tables.value.iter().enumerate().zip(heads) {
+			report.value_slots += table.filled().saturating_sub(1);
+			for offset in table.orphaned_slots(heads, log)? {
+				report.add(CorruptionError {
+					col: self.col,
+					size_tier: tier as u8,
+					offset,
+					message: format!(
+						"{}: Slot {} is not referenced by the index",
+						table.id, offset
+					),
+				});
+			}
+		}
+		Ok(())
+	}
+
+	/// Plan moving values from the end of each value table into the lowest free slots, updating
+	/// the index entries that point to them, and lowering the fill mark past the last value.
+	/// Moving stops at the first entry that is not referenced by the index, such as a part of a
+	/// multipart value or an orphaned entry, see `collect_garbage`. Returns the number of moved
+	/// values. Files are truncated by `shrink_value_files` once the plan is enacted.
+	pub fn defragment(&self, log: &mut LogWriter) -> Result<u64> {
+		let tables = self.tables.read();
+		if !self.reindex.read().queue.is_empty() {
+			return Err(Error::InvalidInput(format!("Column {} is being reindexed", self.col)))
+		}
+		// Index entry pointing to each value, by size tier and offset.
+		let mut referrers: Vec<HashMap<u64, (u64, usize, crate::index::Entry)>> =
+			vec![HashMap::new(); tables.value.len()];
+		for chunk_index in 0..tables.index.id.total_chunks() {
+			for (sub_index, entry) in tables.index.entries(chunk_index, &*log)?.iter().enumerate() {
+				if entry.is_empty() {
+					continue
+				}
+				let address = entry.address(tables.index.id.index_bits());
+				if let Some(tier) = referrers.get_mut(address.size_tier() as usize) {
+					tier.insert(address.offset(), (chunk_index, sub_index, *entry));
+				}
+			}
+		}
+		let mut moved = 0;
+		for (tier, (table, referrers)) in tables.value.iter().zip(referrers).enumerate() {
+			let mut free = table.free_slots(&*log)?;
+			let mut last = table.filled() - 1;
+			// Slots before `next_free` have been taken by moved values.
+			let mut next_free = 0;
+			loop {
+				while free.len() > next_free && free.last() == Some(&last) {
+					free.pop();
+					last -= 1;
+				}
+				let (to, (chunk_index, sub_index, entry)) =
+					match (free.get(next_free), referrers.get(&last)) {
+						(Some(to), Some(referrer)) => (*to, *referrer),
+						_ => break,
+					};
+				table.write_move_plan(last, to, log)?;
+				let key = tables.index.recover_key_prefix(chunk_index, entry);
+				tables.index.write_insert_plan(
+					&key,
+					Address::new(to, tier as u8),
+					Some(sub_index),
+					log,
+				)?;
+				next_free += 1;
+				last -= 1;
+				moved += 1;
+			}
+			if last + 1 < table.filled() {
+				table.write_truncate_plan(last + 1, &free[next_free..], log)?;
+				table.complete_plan(log)?;
+			}
+		}
+		log::debug!(target: "parity-db", "{}: Moved {} values for defragmentation", tables.index.id, moved);
+		Ok(moved)
+	}
+
+	/// Truncate value table files after their last filled slot. Returns the number of released
+	/// bytes.
+	pub fn shrink_value_files(&self) -> Result<u64> {
+		// Reads don't go past the fill mark, but they may still use the old one.
+		let tables = self.tables.write();
+		let mut released = 0;
+		for table in &tables.value {
+			released += table.shrink_file()?;
+		}
+		Ok(released)
+	}
+
+	pub fn drop_index(&self, id: IndexTableId, log: &impl LogQuery) -> Result<()> {
+		log::debug!(target: "parity-db", "Dropping {}", id);
+		if cfg!(debug_assertions) {
+			let tables = self.tables.read();
+			if let Some(source) = self.reindex.read().queue.iter().find(|index| index.id == id) {
+				let missing = self.unmigrated_entries(&tables, source, log)?;
+				if missing != 0 {
+					log::warn!(target: "parity-db", "Dropping {} with {} entries missing from {}", id, missing, tables.index.id);
+				}
+			}
+		}
+		let index_id = self.tables.read().index.id;
+		let mut reindex = self.reindex.write();
+		match reindex.queue.front().map(|index| index.id) {
+			Some(front) if front == id => {
+				let table = reindex.queue.pop_front();
+				reindex.progress.store(0, Ordering::Relaxed);
+				table.unwrap().drop_file()?;
+			},
+			front => {
+				// Tables are dropped in order of increasing size, so a smaller table has already
+				// been dropped. This happens when the log is replayed after a crash.
+				let expected = front.unwrap_or(index_id);
+				if id.index_bits() < expected.index_bits() {
+					log::debug!(target: "parity-db", "Index {} is already dropped", id);
+					return Ok(())
+				}
+				return Err(match front {
+					Some(front) => Error::Corruption(format!(
+						"Out of order drop of index {}, expected {}",
+						id, front
+					)),
+					None => Error::Corruption(format!(
+						"Unexpected drop of index {}, reindex queue is empty, current index is {}",
+						id, index_id
+					)),
+				})
+			},
+		}
+		log::debug!(target: "parity-db", "Dropped {}", id);
+		Ok(())
+	}
+}
+
+impl Column {
+	pub fn write_existing_value_plan<K, V: AsRef<[u8]>>(
+		key: &TableKey,
+		tables: TablesRef,
+		address: Address,
+		change: &Operation<K, V>,
+		log: &mut LogWriter,
+		stats: Option<&ColumnStats>,
+		ref_counted: bool,
+	) -> Result<(Option<PlanOutcome>, Option<Address>)> {
+		let tier = address.size_tier() as usize;
+
+		let fetch_size = || -> Result<(u32, u32)> {
+			let (cur_size, compressed) =
+				tables.tables[tier].size(key, address.offset(), log)?.unwrap_or((0, false));
+			Ok(if compressed {
+				// This is very costly.
+				let compressed = tables.tables[tier]
+					.get(key, address.offset(), log)?
+					.expect("Same query as size")
+					.0;
+				let uncompressed = tables.compression.decompress(compressed.as_slice())?;
+
+				(cur_size, uncompressed.len() as u32)
+			} else {
+				(cur_size, cur_size)
+			})
+		};
+
+		match change {
+			Operation::Reference(_) =>
+				if ref_counted {
+					log::trace!(target: "parity-db", "{}: Increment ref {}", tables.col, key);
+					tables.tables[tier].write_inc_ref(address.offset(), log)?;
+					if let Some(stats) = stats {
+						stats.reference_increase();
+					}
+					Ok((Some(PlanOutcome::Written), None))
+				} else {
+					Ok((Some(PlanOutcome::Skipped), None))
+				},
+			Operation::Set(_, val) => {
+				if ref_counted {
+					log::trace!(target: "parity-db", "{}: Increment ref {}", tables.col, key);
+					tables.tables[tier].write_inc_ref(address.offset(), log)?;
+					return Ok((Some(PlanOutcome::Written), None))
+				}
+				if tables.preimage {
+					// Replace is not supported
+					return Ok((Some(PlanOutcome::Skipped), None))
+				}
+
+				let (cval, target_tier) =
+					Column::compress(tables.compression, key, val.as_ref(), tables.tables);
+				let (cval, compressed) = cval
+					.as_ref()
+					.map(|cval| (cval.as_slice(), true))
 					.unwrap_or((val.as_ref(), false));
 
+				if tier == target_tier {
+					// Skip rewriting a value that is already stored as is.
+					if let Some((existing, existing_compressed)) =
+						tables.tables[tier].get(key, address.offset(), log)?
+					{
+						if existing_compressed == compressed && existing == cval {
+							log::trace!(target: "parity-db", "{}: Skipping redundant write {}", tables.col, key);
+							if let Some(stats) = stats {
+								stats.redundant_write();
+							}
+							return Ok((Some(PlanOutcome::Skipped), None))
+						}
+					}
+				}
+
 				if let Some(stats) = stats {
 					let (cur_size, uncompressed) = fetch_size()?;
 					stats.replace_val(
@@ -959,7 +2767,7 @@ impl Column {
 					);
 				}
 				if tier == target_tier {
-					log::trace!(target: "parity-db", "{}: Replacing {}", tables.col, key);
+					log::trace!(target: "parity-db", "{}: Replacing {}{}", tables.col, key, trace_value(&tables, val.as_ref()));
 					tables.tables[target_tier].write_replace_plan(
 						address.offset(),
 						key,
@@ -969,7 +2777,7 @@ impl Column {
 					)?;
 					Ok((Some(PlanOutcome::Written), None))
 				} else {
-					log::trace!(target: "parity-db", "{}: Replacing in a new table {}", tables.col, key);
+					log::trace!(target: "parity-db", "{}: Replacing in a new table {}{}", tables.col, key, trace_value(&tables, val.as_ref()));
 					tables.tables[tier].write_remove_plan(address.offset(), log)?;
 					let new_offset =
 						tables.tables[target_tier].write_insert_plan(key, cval, log, compressed)?;
@@ -1014,7 +2822,7 @@ impl Column {
 		let (cval, compressed) =
 			cval.as_ref().map(|cval| (cval.as_slice(), true)).unwrap_or((val, false));
 
-		log::trace!(target: "parity-db", "{}: Inserting new {}, size = {}", tables.col, key, cval.len());
+		log::trace!(target: "parity-db", "{}: Inserting new {}, size = {}{}", tables.col, key, cval.len(), trace_value(&tables, val));
 		let offset = tables.tables[target_tier].write_insert_plan(key, cval, log, compressed)?;
 		let address = Address::new(offset, target_tier as u8);
 
@@ -1024,6 +2832,330 @@ impl Column {
 		Ok(address)
 	}
 
+	pub fn defrag_index(&self, log: &mut LogWriter) -> Result<DefragIndexStats> {
+		match self {
+			Column::Hash(column) => column.defrag_index(log),
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	pub fn resize_index_in_place(&self, log: &mut LogWriter) -> Result<()> {
+		match self {
+			Column::Hash(column) => column.resize_index_in_place(log),
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	/// Number of index tables waiting to be reindexed. Always zero for tree columns.
+	pub fn reindex_queue_len(&self) -> usize {
+		match self {
+			Column::Hash(column) => column.reindex_queue_len(),
+			Column::Tree(_column) => 0,
+		}
+	}
+
+	pub fn reserve(&self, expected_entries: u64) -> Result<bool> {
+		match self {
+			Column::Hash(column) => column.reserve(expected_entries),
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	pub fn trim_rebalance_queue(&self, log: &mut LogWriter) -> Result<Vec<IndexTableId>> {
+		match self {
+			Column::Hash(column) => column.trim_rebalance_queue(log),
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	pub fn collect_garbage(&self, log: &mut LogWriter) -> Result<GarbageStats> {
+		match self {
+			Column::Hash(column) => column.collect_garbage(log),
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	pub fn check_integrity(&self, log: &impl LogQuery, report: &mut IntegrityReport) -> Result<()> {
+		match self {
+			Column::Hash(column) => column.check_integrity(log, report),
+			// Tree nodes are only checked when read.
+			Column::Tree(_column) => Ok(()),
+		}
+	}
+
+	pub fn defragment(&self, log: &mut LogWriter) -> Result<u64> {
+		match self {
+			Column::Hash(column) => column.defragment(log),
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	pub fn shrink_value_files(&self) -> Result<u64> {
+		match self {
+			Column::Hash(column) => column.shrink_value_files(),
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	pub fn warm_cache(&self, chunks: Option<u64>) -> Result<()> {
+		match self {
+			Column::Hash(column) => column.tables.read().index.warm_cache(chunks),
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	/// Up to `n` most read keys with their estimated read counts.
+	pub fn hot_keys(&self, n: usize) -> Result<Vec<(Key, u64)>> {
+		match self {
+			Column::Hash(column) => match &column.hot_keys {
+				Some(hot_keys) => Ok(hot_keys.lock().top(n)),
+				None => Err(Error::InvalidConfiguration("Hot key tracking is disabled.".into())),
+			},
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	pub fn reset_hot_keys(&self) -> Result<()> {
+		match self {
+			Column::Hash(column) => match &column.hot_keys {
+				Some(hot_keys) => {
+					hot_keys.lock().reset();
+					Ok(())
+				},
+				None => Err(Error::InvalidConfiguration("Hot key tracking is disabled.".into())),
+			},
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	pub fn page_cache_hit_ratio(&self) -> Result<f64> {
+		match self {
+			Column::Hash(column) => column.tables.read().index.page_cache_hit_ratio(),
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	pub fn index_stats(&self, log: &Log) -> Result<CachedStats> {
+		match self {
+			Column::Hash(column) => column.index_stats(log),
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	/// Add space usage of all non-empty value tables to `report`.
+	pub fn fragmentation(
+		&self,
+		col: ColId,
+		report: &mut FragmentationReport,
+		log: &Log,
+	) -> Result<()> {
+		let scan = |tables: &[ValueTable], report: &mut FragmentationReport| -> Result<()> {
+			for (tier, table) in tables.iter().enumerate() {
+				let allocated = table.filled().saturating_sub(1);
+				if allocated == 0 {
+					continue
+				}
+				let entry_size = table.entry_size as u64;
+				let free = table.count_free(log.overlays())?;
+				report.add(TierFragmentation {
+					col,
+					tier: tier as u8,
+					bytes_allocated: allocated * entry_size,
+					bytes_live: (allocated - free) * entry_size,
+					bytes_free: free * entry_size,
+				});
+			}
+			Ok(())
+		};
+		match self {
+			Column::Hash(column) => scan(&column.tables.read().value, report),
+			Column::Tree(column) => column.with_locked(|tables| scan(tables.tables, report)),
+		}
+	}
+
+	/// Number of values stored in each value table, by size tier. For tree columns this
+	/// includes the tree nodes. This reads every value table entry.
+	pub fn entry_count_by_tier(&self, log: &Log) -> Result<Vec<u64>> {
+		let count = |tables: &[ValueTable]| -> Result<Vec<u64>> {
+			tables.iter().map(|table| table.count_values(log.overlays())).collect()
+		};
+		match self {
+			Column::Hash(column) => count(&column.tables.read().value),
+			Column::Tree(column) => column.with_locked(|tables| count(tables.tables)),
+		}
+	}
+
+	/// Number of values stored in the column, see `entry_count_by_tier`.
+	pub fn entry_count(&self, log: &Log) -> Result<u64> {
+		Ok(self.entry_count_by_tier(log)?.iter().sum())
+	}
+
+	/// Release disk blocks taken by removed entries of all value tables, see
+	/// `ValueTable::punch_holes`.
+	pub fn punch_holes(&self, log: &Log) -> Result<u64> {
+		let punch_holes = |tables: &[ValueTable]| -> Result<u64> {
+			let mut released = 0;
+			for table in tables {
+				released += table.punch_holes(log.overlays())?;
+			}
+			Ok(released)
+		};
+		match self {
+			Column::Hash(column) => punch_holes(&column.tables.read().value),
+			Column::Tree(column) => column.with_locked(|tables| punch_holes(tables.tables)),
+		}
+	}
+
+	pub fn purge_tier(&self, tier: usize, log: &mut LogWriter) -> Result<u64> {
+		match self {
+			Column::Hash(column) => column.purge_tier(tier, log),
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	pub fn expire(&self, log: &mut LogWriter) -> Result<u64> {
+		match self {
+			Column::Hash(column) => column.expire(log),
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	/// Check the removed entries list and entry overlaps of all value tables.
+	pub fn fsck(&self, repair: bool, log: &mut LogWriter) -> Result<FsckReport> {
+		let fsck_tables = |tables: &[ValueTable], log: &mut LogWriter| -> Result<FsckReport> {
+			let mut total = FsckReport::default();
+			for table in tables {
+				let report = table.fsck(repair, log)?;
+				table.complete_plan(log)?;
+				total.healthy += report.healthy;
+				total.corrupt += report.corrupt;
+				total.repaired += report.repaired;
+				total.overlapping += report.overlapping;
+			}
+			Ok(total)
+		};
+		match self {
+			Column::Hash(column) => fsck_tables(&column.tables.read().value, log),
+			Column::Tree(column) => column.with_locked(|tables| fsck_tables(tables.tables, log)),
+		}
+	}
+
+	pub fn free_range(
+		&self,
+		tier: usize,
+		start: u64,
+		end: u64,
+		log: &mut LogWriter,
+	) -> Result<u64> {
+		match self {
+			Column::Hash(column) => column.free_range(tier, start, end, log),
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	pub fn hard_reset(&self) -> Result<()> {
+		match self {
+			Column::Hash(column) => column.hard_reset(),
+			Column::Tree(column) => column.hard_reset(),
+		}
+	}
+
+	pub fn scan_prefix<'a>(
+		&'a self,
+		prefix: &[u8],
+		log: &'a Log,
+	) -> Result<impl Iterator<Item = Result<(Key, Value)>> + 'a> {
+		match self {
+			Column::Hash(column) => column.scan_prefix(prefix, log),
+			Column::Tree(_) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	pub fn iter_with_offset<'a>(
+		&'a self,
+		offset: u64,
+		log: &'a Log,
+	) -> Result<PaginatedIterator<'a>> {
+		match self {
+			Column::Hash(column) => column.iter_with_offset(offset, log),
+			Column::Tree(_) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	/// Iterate over all live entries of a hash column, walking value tables in file order.
+	/// Keys are returned hashed, as stored in the index. Index read locks are held by the
+	/// iterator until it is dropped.
+	pub fn iter<'a>(
+		&'a self,
+		log: &'a Log,
+	) -> Result<impl Iterator<Item = Result<(Key, Value)>> + 'a> {
+		match self {
+			Column::Hash(column) => {
+				let mut iter = column.iter_by_size(log)?;
+				Ok(std::iter::from_fn(move || iter.next().transpose()))
+			},
+			Column::Tree(_) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	pub fn index_entries_while(&self, mut f: impl FnMut(Key, Address) -> bool) -> Result<()> {
+		match self {
+			Column::Hash(column) => {
+				let tables = column.tables.read();
+				let reindex = column.reindex.read();
+				for table in std::iter::once(&tables.index).chain(reindex.queue.iter()) {
+					for (key, address) in table.iter_all_entries() {
+						if !f(key, address) {
+							return Ok(())
+						}
+					}
+				}
+				Ok(())
+			},
+			Column::Tree(_) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	pub fn raw_slots_while(
+		&self,
+		size_tier: u8,
+		mut f: impl FnMut(u64, &[u8]) -> bool,
+	) -> Result<()> {
+		let mut iter_table = |table: &ValueTable| -> Result<()> {
+			let mut iter = table.raw_iter();
+			while let Some((index, slot)) = iter.next()? {
+				if !f(index, slot) {
+					break
+				}
+			}
+			Ok(())
+		};
+		match self {
+			Column::Hash(column) => iter_table(&column.tables.read().value[size_tier as usize]),
+			Column::Tree(column) =>
+				column.with_locked(|tables| iter_table(&tables.tables[size_tier as usize])),
+		}
+	}
+
 	pub fn complete_plan(&self, log: &mut LogWriter) -> Result<()> {
 		match self {
 			Column::Hash(column) => column.complete_plan(log),
@@ -1031,6 +3163,43 @@ impl Column {
 		}
 	}
 
+	/// Save the allocation state of all value tables, see `ValueTable::checkpoint`.
+	pub fn checkpoint(&self) -> Vec<TableCheckpoint> {
+		match self {
+			Column::Hash(column) =>
+				column.tables.read().value.iter().map(|t| t.checkpoint()).collect(),
+			Column::Tree(column) => column
+				.with_locked(|tables| Ok(tables.tables.iter().map(|t| t.checkpoint()).collect()))
+				.expect("Collecting checkpoints does not fail; qed"),
+		}
+	}
+
+	/// Restore the state saved by `checkpoint`, after the changes planned since then were
+	/// discarded. Those changes are not in `log`.
+	pub fn rollback(&self, checkpoint: &[TableCheckpoint], log: &impl LogQuery) -> Result<()> {
+		let rollback = |tables: &[ValueTable]| {
+			for (table, checkpoint) in tables.iter().zip(checkpoint) {
+				table.rollback(checkpoint);
+			}
+		};
+		match self {
+			Column::Hash(column) => {
+				rollback(&column.tables.read().value);
+				// Removed keys were taken out of the filter. The log record is not written, so
+				// writes are already blocked.
+				#[cfg(feature = "bloom")]
+				column.rebuild_bloom(log, || ())?;
+				#[cfg(not(feature = "bloom"))]
+				let _ = log;
+				Ok(())
+			},
+			Column::Tree(column) => column.with_locked(|tables| {
+				rollback(tables.tables);
+				Ok(())
+			}),
+		}
+	}
+
 	pub fn validate_plan(&self, action: LogAction, log: &mut LogReader) -> Result<()> {
 		match self {
 			Column::Hash(column) => column.validate_plan(action, log),
@@ -1038,6 +3207,64 @@ impl Column {
 		}
 	}
 
+	/// Reject all further writes with `Error::ReadOnly`. `write_plan_txn`, `write_reindex_plan`,
+	/// `complete_plan` and `enact_plan` fail from then on, while reads, including reads of the
+	/// log overlays, and `validate_plan` are still allowed. Called after the logs are replayed,
+	/// so that a database opened read only can still recover from an unclean shutdown.
+	pub fn set_read_only(&self) {
+		match self {
+			Column::Hash(column) => column.read_only.store(true, Ordering::Relaxed),
+			Column::Tree(column) => column.set_read_only(),
+		}
+	}
+
+	/// Look keys of a hash column up in the index only, without the Bloom filter.
+	#[cfg(feature = "bloom")]
+	pub fn disable_bloom(&mut self) {
+		if let Column::Hash(column) = self {
+			column.bloom = None;
+		}
+	}
+
+	/// Query a value by the original (unhashed) key, bypassing the commit overlay.
+	pub fn get(&self, key: &[u8], log: &RwLock<LogOverlays>) -> Result<Option<Value>> {
+		match self {
+			Column::Hash(column) => column.get(&column.hash_key(key), log),
+			Column::Tree(column) => {
+				let log = log.read();
+				column.with_locked(|btree| BTreeTable::get(key, &*log, btree))
+			},
+		}
+	}
+
+	/// Value table address of the entry for the original (unhashed) key, bypassing the commit
+	/// overlay. Only supported by hash columns.
+	pub fn get_index_entry(
+		&self,
+		key: &[u8],
+		log: &RwLock<LogOverlays>,
+	) -> Result<Option<Address>> {
+		match self {
+			Column::Hash(column) => column.get_index_entry(&column.hash_key(key), log),
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	/// Query a value in this column and then in `fallback` if the key is not found. The key is
+	/// hashed separately for each column, so columns with different settings may be combined.
+	pub fn get_with_fallback(
+		&self,
+		key: &[u8],
+		fallback: &Column,
+		log: &RwLock<LogOverlays>,
+	) -> Result<Option<Value>> {
+		if let Some(value) = self.get(key, log)? {
+			return Ok(Some(value))
+		}
+		fallback.get(key, log)
+	}
+
 	pub fn enact_plan(&self, action: LogAction, log: &mut LogReader) -> Result<()> {
 		match self {
 			Column::Hash(column) => column.enact_plan(action, log),
@@ -1059,6 +3286,56 @@ impl Column {
 		}
 	}
 
+	#[cfg(feature = "bloom")]
+	pub fn rebuild_bloom<T>(
+		&self,
+		log: &impl LogQuery,
+		block_writes: impl FnOnce() -> T,
+	) -> Result<()> {
+		match self {
+			Column::Hash(column) => column.rebuild_bloom(log, block_writes),
+			Column::Tree(_column) => Ok(()),
+		}
+	}
+
+	#[cfg(feature = "bloom")]
+	pub fn is_bloom_full(&self) -> bool {
+		match self {
+			Column::Hash(column) => column.is_bloom_full(),
+			Column::Tree(_column) => false,
+		}
+	}
+
+	/// Change the maximum number of index entries moved in a single reindex batch. Takes effect
+	/// with the next batch.
+	pub fn set_reindex_batch_size(&self, size: u32) -> Result<()> {
+		match self {
+			Column::Hash(column) => {
+				column.reindex_batch_size.store(size, Ordering::Relaxed);
+				Ok(())
+			},
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	pub fn value_cache_stats(&self) -> Result<Option<ValueCacheStats>> {
+		match self {
+			Column::Hash(column) => Ok(column.value_cache_stats()),
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	#[cfg(feature = "bloom")]
+	pub fn bloom_stats(&self) -> Result<Option<BloomStats>> {
+		match self {
+			Column::Hash(column) => Ok(column.bloom_stats()),
+			Column::Tree(_column) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
 	pub fn write_stats_text(&self, writer: &mut impl std::io::Write) -> Result<()> {
 		match self {
 			Column::Hash(column) => column.write_stats_text(writer),
@@ -1087,3 +3364,167 @@ impl Column {
 		}
 	}
 }
+
+/// Plans changes to multiple columns as a single log record. If planning fails for any column,
+/// the changes planned so far are dropped for all columns and an empty record is written in
+/// their place, so none of them become visible. The same happens if the writer is dropped
+/// without calling `commit`. The caller holds the log record lock while the writer exists.
+pub struct MultiColumnWriter<'a> {
+	log: &'a Log,
+	columns: &'a [Column],
+	writer: Option<LogWriter<'a>>,
+	// Allocation state of each written column before its first change.
+	checkpoints: Vec<(ColId, Vec<TableCheckpoint>)>,
+	ops: u64,
+	reindex: bool,
+}
+
+impl<'a> MultiColumnWriter<'a> {
+	pub fn new(log: &'a Log, columns: &'a [Column]) -> Self {
+		MultiColumnWriter {
+			log,
+			columns,
+			writer: Some(log.begin_record()),
+			checkpoints: Vec::new(),
+			ops: 0,
+			reindex: false,
+		}
+	}
+
+	fn writer(&mut self) -> Result<&mut LogWriter<'a>> {
+		self.writer
+			.as_mut()
+			.ok_or_else(|| Error::InvalidInput("Changes were rolled back".into()))
+	}
+
+	fn column(&mut self, col: ColId) -> Result<&'a Column> {
+		let column = self
+			.columns
+			.get(col as usize)
+			.ok_or_else(|| Error::InvalidInput(format!("Invalid column {}", col)))?;
+		if !self.checkpoints.iter().any(|(c, _)| *c == col) {
+			self.checkpoints.push((col, column.checkpoint()));
+		}
+		Ok(column)
+	}
+
+	pub fn record_id(&self) -> Option<u64> {
+		self.writer.as_ref().map(|w| w.record_id())
+	}
+
+	/// Number of planned operations.
+	pub fn ops(&self) -> u64 {
+		self.ops
+	}
+
+	/// Whether any write found the index full and started a reindex.
+	pub fn needs_reindex(&self) -> bool {
+		self.reindex
+	}
+
+	pub fn set_sequence(&mut self, seq: u64) -> Result<()> {
+		self.writer()?.set_sequence(seq);
+		Ok(())
+	}
+
+	/// Plan `changes` to hash column `col`. Changes of a single call are enacted together, see
+	/// `HashColumn::write_plan_txn`.
+	pub fn write(&mut self, col: ColId, changes: &[Operation<Key, Vec<u8>>]) -> Result<()> {
+		let result = self.write_hash(col, changes);
+		if result.is_err() {
+			self.rollback()?;
+		}
+		result
+	}
+
+	fn write_hash(&mut self, col: ColId, changes: &[Operation<Key, Vec<u8>>]) -> Result<()> {
+		let column = match self.column(col)? {
+			Column::Hash(column) => column,
+			Column::Tree(_column) =>
+				return Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		};
+		for outcome in column.write_plan_txn(changes, self.writer()?)? {
+			if let PlanOutcome::NeedReindex = outcome {
+				// Reindex has triggered another reindex.
+				self.reindex = true;
+			}
+			self.ops += 1;
+		}
+		Ok(())
+	}
+
+	/// Plan `changes` to tree column `col`.
+	pub fn write_tree(&mut self, col: ColId, changes: &mut BTreeChangeSet) -> Result<()> {
+		let result = self.write_btree(col, changes);
+		if result.is_err() {
+			self.rollback()?;
+		}
+		result
+	}
+
+	fn write_btree(&mut self, col: ColId, changes: &mut BTreeChangeSet) -> Result<()> {
+		let column = match self.column(col)? {
+			Column::Hash(_column) =>
+				return Err(Error::InvalidConfiguration("Not an indexed column.".to_string())),
+			Column::Tree(column) => column,
+		};
+		let mut ops = self.ops;
+		let result = changes.write_plan(column, self.writer()?, &mut ops);
+		self.ops = ops;
+		result
+	}
+
+	/// Add a checkpoint marker, see `LogWriter::write_barrier`.
+	pub fn write_barrier(&mut self, id: u64) -> Result<()> {
+		self.writer()?.write_barrier(id);
+		Ok(())
+	}
+
+	/// Complete the plan of all columns and write the record to the log. Returns the record
+	/// size in bytes.
+	pub fn commit(mut self) -> Result<u64> {
+		let columns = self.columns;
+		let mut result = Ok(());
+		let writer = self.writer()?;
+		for c in columns.iter() {
+			result = c.complete_plan(writer);
+			if result.is_err() {
+				break
+			}
+		}
+		if let Err(e) = result {
+			self.rollback()?;
+			return Err(e)
+		}
+		let writer = self.writer.take().expect("Checked above; qed");
+		self.log.end_record(writer.drain())
+	}
+
+	// Restore all written columns and end the record without any changes.
+	fn rollback(&mut self) -> Result<()> {
+		let mut writer = match self.writer.take() {
+			Some(writer) => writer,
+			None => return Ok(()),
+		};
+		writer.clear();
+		let record_id = writer.record_id();
+		let mut result = Ok(());
+		for (col, checkpoint) in self.checkpoints.drain(..) {
+			if let Err(e) = self.columns[col as usize].rollback(&checkpoint, self.log.overlays()) {
+				result = Err(e);
+			}
+		}
+		// Records are replayed in order, so the record id can't be skipped.
+		self.log.end_record(writer.drain())?;
+		log::debug!(target: "parity-db", "Rolled back record {}", record_id);
+		result
+	}
+}
+
+impl<'a> Drop for MultiColumnWriter<'a> {
+	fn drop(&mut self) {
+		if let Err(e) = self.rollback() {
+			log::warn!(target: "parity-db", "Error rolling back changes: {:?}", e);
+		}
+	}
+}
@@ -18,29 +18,49 @@
 //! Each background worker is signalled with a conditional variable once
 //! there is some work to be done.
 
+#[cfg(feature = "async-tokio")]
+use crate::column::RebalanceProgress;
 use crate::{
+	audit::AuditLog,
 	btree::{commit_overlay::BTreeChangeSet, BTreeIterator, BTreeTable},
-	column::{hash_key, ColId, Column, IterState, ReindexBatch},
-	error::{try_io, Error, Result},
+	changelog::{self, ChangeEntry, ChangeLog},
+	column::{
+		check_key, hash_key, strip_expired, unix_millis, ColId, Column, DefragIndexStats,
+		GarbageStats, HashColumn, IntegrityReport, IterState, MultiColumnWriter, PaginatedIterator,
+		ReindexBatch, TTL_TIMESTAMP_SIZE,
+	},
+	display::hex,
+	error::{try_io, DatabaseError, Error, Result},
 	hash::IdentityBuildHasher,
 	index::PlanOutcome,
 	log::{Log, LogAction},
 	options::{Options, CURRENT_VERSION},
-	parking_lot::{Condvar, Mutex, RwLock},
-	stats::StatSummary,
-	ColumnOptions, Key,
+	parking_lot::{Condvar, Mutex, MutexGuard, RwLock},
+	stats::{
+		CachedStats, ColumnCounters, DatabaseStats, DbFileStats, EnactStats, FileInfo,
+		FragmentationReport, MaintenanceReport, StatSummary,
+	},
+	ColumnOptions, FsckReport, Key,
 };
 use fs2::FileExt;
 use std::{
-	collections::{BTreeMap, HashMap, VecDeque},
+	collections::{BTreeMap, HashMap, HashSet, VecDeque},
 	ops::Bound,
 	sync::{
 		atomic::{AtomicBool, AtomicU64, Ordering},
-		Arc,
+		Arc, Weak,
 	},
 	thread,
 };
 
+// Number of reindex progress events buffered for slow subscribers.
+#[cfg(feature = "async-tokio")]
+const REBALANCE_EVENTS_CAPACITY: usize = 64;
+// Number of corruption events buffered for each `subscribe_errors` receiver.
+const ERROR_EVENTS_CAPACITY: usize = 256;
+// Size of the version prefix of values in versioned columns.
+const VERSION_SIZE: usize = 8;
+
 // Max size of commit queue. (Keys + Values). If the queue is
 // full `commit` will block.
 // These are in memory, so we use usize
@@ -52,6 +72,14 @@ const MAX_LOG_QUEUE_BYTES: i64 = 128 * 1024 * 1024;
 const MIN_LOG_SIZE_BYTES: u64 = 64 * 1024 * 1024;
 // Number of log files to keep after flush.
 const KEEP_LOGS: usize = 16;
+// Log enactment speed assumed for a slow disk, when no throughput history is available.
+const FALLBACK_ENACT_THROUGHPUT: f64 = 8.0 * 1024.0 * 1024.0;
+// Interval between attempts to acquire the database lock.
+const LOCK_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_millis(10);
+// Number of entries committed to the destination at once by `transfer_column`.
+const TRANSFER_BATCH_SIZE: usize = 10240;
+// Stores the highest enacted sequence number of `write_with_sequence` when logs are cleaned.
+const SEQUENCE_FILE: &str = "sequence";
 
 /// Value is just a vector of bytes. Value sizes up to 4Gb are allowed.
 pub type Value = Vec<u8>;
@@ -80,6 +108,21 @@ struct CommitQueue {
 	commits: VecDeque<Commit>,
 }
 
+// Column being copied by `transfer_column`.
+#[derive(Debug)]
+struct ColumnTransfer {
+	col: ColId,
+	dest: Arc<DbInner>,
+	dest_col: ColId,
+	// Keys written to the destination by commits made during the transfer. Values copied from
+	// the source must not overwrite them.
+	touched: HashSet<Key>,
+	mirrored: u64,
+	// Changes waiting to be committed to the destination, in commit order. They are sent by the
+	// transferring thread without holding the commit queue lock, see `forward_transfer`.
+	forward: VecDeque<CommitChangeSet>,
+}
+
 #[derive(Debug)]
 struct DbInner {
 	columns: Vec<Column>,
@@ -88,6 +131,16 @@ struct DbInner {
 	log: Log,
 	commit_queue: Mutex<CommitQueue>,
 	commit_queue_full_cv: Condvar,
+	// Serializes log record creation between the log worker and maintenance calls.
+	log_record_lock: Mutex<()>,
+	// Held while queued commits are taken and written to the log, so that commits taken by
+	// different threads get their log records in queue order.
+	process_commits_lock: Mutex<()>,
+	// Held while a log record is enacted, so that maintenance calls that write out all logs
+	// don't interleave with the commit worker.
+	enact_lock: Mutex<()>,
+	// Held while log files are cleaned, for the same reason.
+	cleanup_lock: Mutex<()>,
 	log_worker_wait: WaitCondvar<bool>,
 	commit_worker_wait: Arc<WaitCondvar<bool>>,
 	// Overlay of most recent values in the commit queue.
@@ -96,30 +149,69 @@ struct DbInner {
 	log_queue_wait: WaitCondvar<i64>,
 	flush_worker_wait: Arc<WaitCondvar<bool>>,
 	cleanup_worker_wait: WaitCondvar<bool>,
+	stats_worker_wait: WaitCondvar<bool>,
+	stats_refresh_wait: WaitCondvar<bool>,
 	last_enacted: AtomicU64,
+	// Highest sequence number passed to `write_with_sequence`.
+	last_sequence: AtomicU64,
+	// Highest sequence number of the enacted log records.
+	enacted_sequence: AtomicU64,
 	next_reindex: AtomicU64,
 	bg_err: Mutex<Option<Arc<Error>>>,
+	enact_stats: Mutex<EnactStats>,
+	// Per column index metrics computed by `precompute_index_stats`.
+	cached_stats: Vec<RwLock<Option<Arc<CachedStats>>>>,
+	// Columns locked with `set_column_read_only`.
+	read_only_columns: Vec<AtomicBool>,
+	// Set while a column is transferred to another database.
+	transfer: Mutex<Option<ColumnTransfer>>,
+	counters: DatabaseStats,
+	// Snapshots of open read transactions.
+	read_snapshots: Mutex<Vec<Weak<ReadSnapshot>>>,
+	audit_log: Option<AuditLog>,
+	changelog: Option<ChangeLog>,
+	#[cfg(feature = "async-tokio")]
+	rebalance_events: tokio::sync::broadcast::Sender<RebalanceProgress>,
+	error_subscribers: Mutex<Vec<std::sync::mpsc::SyncSender<DatabaseError>>>,
+	// Corruption events reported before the first `subscribe_errors` call, e.g. while the log is
+	// replayed on open. Taken by the first subscriber. Locked after `error_subscribers`.
+	pending_error_events: Mutex<Option<Vec<DatabaseError>>>,
+	// Corruption events dropped because a subscriber's channel was full.
+	dropped_error_events: AtomicU64,
 	db_version: u32,
-	_lock_file: std::fs::File,
+	// Opened with `Db::open_read_only`.
+	read_only: bool,
+	lock_file: std::fs::File,
 }
 
 #[derive(Debug)]
 struct WaitCondvar<S> {
 	cv: Condvar,
 	work: Mutex<S>,
+	// Condvar of the `DbGroup` worker that serves this database, signalled along with this one.
+	group: Option<Arc<WaitCondvar<bool>>>,
 }
 
 impl<S: Default> WaitCondvar<S> {
 	fn new() -> Self {
-		WaitCondvar { cv: Condvar::new(), work: Mutex::new(S::default()) }
+		Self::grouped(None)
+	}
+
+	fn grouped(group: Option<Arc<WaitCondvar<bool>>>) -> Self {
+		WaitCondvar { cv: Condvar::new(), work: Mutex::new(S::default()), group }
 	}
 }
 
 impl WaitCondvar<bool> {
 	fn signal(&self) {
-		let mut work = self.work.lock();
-		*work = true;
-		self.cv.notify_one();
+		{
+			let mut work = self.work.lock();
+			*work = true;
+			self.cv.notify_one();
+		}
+		if let Some(group) = &self.group {
+			group.signal();
+		}
 	}
 
 	pub fn wait(&self) {
@@ -131,8 +223,32 @@ impl WaitCondvar<bool> {
 	}
 }
 
+// Condvars of the background workers shared by a `DbGroup`.
+#[derive(Debug)]
+struct GroupWaits {
+	log: Arc<WaitCondvar<bool>>,
+	commit: Arc<WaitCondvar<bool>>,
+	flush: Arc<WaitCondvar<bool>>,
+	cleanup: Arc<WaitCondvar<bool>>,
+}
+
+impl GroupWaits {
+	fn new() -> Self {
+		GroupWaits {
+			log: Arc::new(WaitCondvar::new()),
+			commit: Arc::new(WaitCondvar::new()),
+			flush: Arc::new(WaitCondvar::new()),
+			cleanup: Arc::new(WaitCondvar::new()),
+		}
+	}
+}
+
 impl DbInner {
-	fn open(options: &Options, opening_mode: OpeningMode) -> Result<DbInner> {
+	fn open(
+		options: &Options,
+		opening_mode: OpeningMode,
+		group: Option<&GroupWaits>,
+	) -> Result<DbInner> {
 		if opening_mode == OpeningMode::Create {
 			try_io!(std::fs::create_dir_all(&options.path));
 		} else if !options.path.is_dir() {
@@ -141,24 +257,43 @@ impl DbInner {
 
 		let mut lock_path: std::path::PathBuf = options.path.clone();
 		lock_path.push("lock");
-		let lock_file = try_io!(std::fs::OpenOptions::new()
-			.create(true)
-			.read(true)
-			.write(true)
-			.open(lock_path.as_path()));
-		lock_file.try_lock_exclusive().map_err(Error::Locked)?;
-
+		let read_only = opening_mode == OpeningMode::ReadOnly;
+		let (lock_file, audit_log, changelog, log) = if read_only {
+			// Another instance may have the database open for writing, so the lock is not taken
+			// here. See `Db::open_read_only`.
+			let lock_file = try_io!(std::fs::File::open(lock_path.as_path()));
+			(lock_file, None, None, Log::open_read_only(options)?)
+		} else {
+			let lock_file = try_io!(std::fs::OpenOptions::new()
+				.create(true)
+				.read(true)
+				.write(true)
+				.open(lock_path.as_path()));
+			Self::lock_exclusive(&lock_file, options.lock_timeout)?;
+			let audit_log = options.audit_log.as_deref().map(AuditLog::open).transpose()?;
+			let changelog = if options.changelog.is_empty() {
+				None
+			} else {
+				Some(ChangeLog::open(&options.path)?)
+			};
+			let log = Log::open(options)?;
+			Self::apply_column_actions(options, &log)?;
+			(lock_file, audit_log, changelog, log)
+		};
 		let metadata = options.load_and_validate_metadata(opening_mode == OpeningMode::Create)?;
 		let mut columns = Vec::with_capacity(metadata.columns.len());
 		let mut commit_overlay = Vec::with_capacity(metadata.columns.len());
-		let log = Log::open(options)?;
 		let last_enacted = log.replay_record_id().unwrap_or(2) - 1;
+		let counters = DatabaseStats::new(metadata.columns.len());
 		for c in 0..metadata.columns.len() {
-			let column = Column::open(c as ColId, options, &metadata)?;
+			let column =
+				Column::open(c as ColId, options, &metadata, counters.column_arc(c as ColId))?;
 			commit_overlay.push(CommitOverlay::new());
 			columns.push(column);
 		}
 		log::debug!(target: "parity-db", "Opened db {:?}, metadata={:?}", options, metadata);
+		let enact_stats = EnactStats::load(&options.path)?;
+		let sequence = load_sequence(&options.path)?;
 		let mut options = options.clone();
 		if options.salt.is_none() {
 			options.salt = Some(metadata.salt);
@@ -171,36 +306,144 @@ impl DbInner {
 			log,
 			commit_queue: Mutex::new(Default::default()),
 			commit_queue_full_cv: Condvar::new(),
-			log_worker_wait: WaitCondvar::new(),
-			commit_worker_wait: Arc::new(WaitCondvar::new()),
+			log_record_lock: Mutex::new(()),
+			process_commits_lock: Mutex::new(()),
+			enact_lock: Mutex::new(()),
+			cleanup_lock: Mutex::new(()),
+			log_worker_wait: WaitCondvar::grouped(group.map(|g| g.log.clone())),
+			commit_worker_wait: Arc::new(WaitCondvar::grouped(group.map(|g| g.commit.clone()))),
 			commit_overlay: RwLock::new(commit_overlay),
 			log_queue_wait: WaitCondvar::new(),
-			flush_worker_wait: Arc::new(WaitCondvar::new()),
-			cleanup_worker_wait: WaitCondvar::new(),
+			flush_worker_wait: Arc::new(WaitCondvar::grouped(group.map(|g| g.flush.clone()))),
+			cleanup_worker_wait: WaitCondvar::grouped(group.map(|g| g.cleanup.clone())),
+			stats_worker_wait: WaitCondvar::new(),
+			stats_refresh_wait: WaitCondvar::new(),
 			next_reindex: AtomicU64::new(1),
 			last_enacted: AtomicU64::new(last_enacted),
+			last_sequence: AtomicU64::new(sequence),
+			enacted_sequence: AtomicU64::new(sequence),
 			bg_err: Mutex::new(None),
+			enact_stats: Mutex::new(enact_stats),
+			cached_stats: (0..metadata.columns.len()).map(|_| RwLock::new(None)).collect(),
+			read_only_columns: (0..metadata.columns.len())
+				.map(|_| AtomicBool::new(false))
+				.collect(),
+			transfer: Mutex::new(None),
+			counters,
+			read_snapshots: Mutex::new(Vec::new()),
+			audit_log,
+			changelog,
+			#[cfg(feature = "async-tokio")]
+			rebalance_events: tokio::sync::broadcast::channel(REBALANCE_EVENTS_CAPACITY).0,
+			error_subscribers: Mutex::new(Vec::new()),
+			pending_error_events: Mutex::new(Some(Vec::new())),
+			dropped_error_events: AtomicU64::new(0),
 			db_version: metadata.version,
-			_lock_file: lock_file,
+			read_only,
+			lock_file,
 		})
 	}
 
+	// Complete column changes that were interrupted. The logged actions are skipped when the log
+	// is replayed.
+	fn apply_column_actions(options: &Options, log: &Log) -> Result<()> {
+		for action in log.pending_column_actions()? {
+			match action {
+				LogAction::CreateColumn { col, options: column_options } => {
+					let meta = match Options::load_metadata(&options.path)? {
+						Some(meta) => meta,
+						None => continue,
+					};
+					if meta.columns.len() == col as usize {
+						log::info!(target: "parity-db", "Completing creation of column {}", col);
+						let mut options = options.clone();
+						options.columns = meta.columns;
+						options.columns.push(column_options);
+						options.write_metadata_with_version(
+							&options.path,
+							&meta.salt,
+							Some(meta.version),
+						)?;
+					}
+				},
+				LogAction::DropColumn { col } => {
+					log::info!(target: "parity-db", "Completing removal of column {}", col);
+					crate::migration::remove_column_files(&options.path, col)?;
+				},
+				_ => (),
+			}
+		}
+		Ok(())
+	}
+
+	fn lock_exclusive(file: &std::fs::File, timeout: Option<std::time::Duration>) -> Result<()> {
+		let start = std::time::Instant::now();
+		loop {
+			match file.try_lock_exclusive() {
+				Ok(()) => return Ok(()),
+				Err(e) if e.kind() == fs2::lock_contended_error().kind() => match timeout {
+					Some(timeout) if start.elapsed() < timeout =>
+						thread::sleep(LOCK_RETRY_INTERVAL),
+					Some(_) => return Err(Error::DatabaseLocked),
+					None => return Err(Error::Locked(e)),
+				},
+				Err(e) => return Err(Error::Locked(e)),
+			}
+		}
+	}
+
 	fn get(&self, col: ColId, key: &[u8]) -> Result<Option<Value>> {
-		match &self.columns[col as usize] {
-			Column::Hash(column) => {
-				let key = column.hash_key(key);
-				let overlay = self.commit_overlay.read();
-				// Check commit overlay first
-				if let Some(v) = overlay.get(col as usize).and_then(|o| o.get(&key)) {
-					return Ok(v)
+		let value = self.get_uncounted(col, key)?;
+		self.count_read(col, value.as_ref());
+		Ok(value)
+	}
+
+	// Same as `get`, but values of columns with a time-to-live are returned without the write
+	// time, and expired values are not returned.
+	fn get_unexpired(&self, col: ColId, key: &[u8]) -> Result<Option<Value>> {
+		let value = self.get(col, key)?;
+		self.strip_ttl(col, value)
+	}
+
+	// Remove the write time of a value of a column with a time-to-live, or drop the value if it
+	// is expired.
+	fn strip_ttl(&self, col: ColId, value: Option<Value>) -> Result<Option<Value>> {
+		match (value, self.options.columns[col as usize].ttl_millis()) {
+			(Some(value), Some(ttl)) => strip_expired(value, ttl, unix_millis()),
+			(value, _) => Ok(value),
+		}
+	}
+
+	// Remove the version or the write time stored in front of values of versioned columns and
+	// columns with a time-to-live, as returned by `get`. Expired values are dropped.
+	fn strip_value_prefix(&self, col: ColId, value: Option<Value>) -> Result<Option<Value>> {
+		match value {
+			Some(mut value) if self.options.columns[col as usize].versioning => {
+				if value.len() < VERSION_SIZE {
+					return Err(Error::Corruption("Missing value version".into()))
 				}
-				// Go into tables and log overlay.
-				let log = self.log.overlays();
-				column.get(&key, log)
+				value.drain(..VERSION_SIZE);
+				Ok(Some(value))
 			},
+			value => self.strip_ttl(col, value),
+		}
+	}
+
+	fn count_read(&self, col: ColId, value: Option<&Value>) {
+		let counters = self.counters.get(col);
+		counters.reads.fetch_add(1, Ordering::Relaxed);
+		if let Some(value) = value {
+			counters.bytes_read.fetch_add(value.len() as u64, Ordering::Relaxed);
+		}
+	}
+
+	fn get_uncounted(&self, col: ColId, key: &[u8]) -> Result<Option<Value>> {
+		match &self.columns[col as usize] {
+			Column::Hash(column) => self.get_hashed(col, column, &column.hash_key(key)),
 			Column::Tree(column) => {
 				let overlay = self.commit_overlay.read();
 				if let Some(l) = overlay.get(col as usize).and_then(|o| o.btree_get(key)) {
+					self.counters.get(col).cache_hits.fetch_add(1, Ordering::Relaxed);
 					return Ok(l.cloned())
 				}
 				// We lock log, if btree structure changed while reading that would be an issue.
@@ -210,6 +453,232 @@ impl DbInner {
 		}
 	}
 
+	fn get_hashed(&self, col: ColId, column: &HashColumn, key: &Key) -> Result<Option<Value>> {
+		let overlay = self.commit_overlay.read();
+		// Check commit overlay first
+		if let Some(v) = overlay.get(col as usize).and_then(|o| o.get(key)) {
+			self.counters.get(col).cache_hits.fetch_add(1, Ordering::Relaxed);
+			return Ok(v)
+		}
+		// Go into tables and log overlay.
+		let log = self.log.overlays();
+		column.get(key, log)
+	}
+
+	// Same as `get_hashed`, but the value is written to `writer`.
+	fn write_hashed(
+		&self,
+		col: ColId,
+		column: &HashColumn,
+		key: &Key,
+		writer: &mut dyn std::io::Write,
+	) -> Result<Option<usize>> {
+		let counters = self.counters.get(col);
+		counters.reads.fetch_add(1, Ordering::Relaxed);
+		let overlay = self.commit_overlay.read();
+		let written = match overlay.get(col as usize).and_then(|o| o.get(key)) {
+			Some(value) => {
+				counters.cache_hits.fetch_add(1, Ordering::Relaxed);
+				match value {
+					Some(value) => {
+						writer.write_all(&value).map_err(Error::Io)?;
+						Some(value.len())
+					},
+					None => None,
+				}
+			},
+			None => column.write_value(key, self.log.overlays(), writer)?,
+		};
+		if let Some(written) = written {
+			counters.bytes_read.fetch_add(written as u64, Ordering::Relaxed);
+		}
+		Ok(written)
+	}
+
+	fn read_snapshot(&self) -> Arc<ReadSnapshot> {
+		// Commits are added to the overlay under the queue lock, so each commit is either
+		// visible to the snapshot or captured by it.
+		let _queue = self.commit_queue.lock();
+		let snapshot = Arc::new(ReadSnapshot::default());
+		let mut snapshots = self.read_snapshots.lock();
+		snapshots.retain(|s| s.strong_count() > 0);
+		snapshots.push(Arc::downgrade(&snapshot));
+		snapshot
+	}
+
+	// Save current values of all keys in the commit to open read snapshots that don't have them
+	// yet. Must be called under the queue lock before the commit is added to the overlay.
+	fn capture_read_snapshots(&self, commit: &CommitChangeSet) -> Result<()> {
+		let snapshots: Vec<_> =
+			self.read_snapshots.lock().iter().filter_map(Weak::upgrade).collect();
+		if snapshots.is_empty() {
+			return Ok(())
+		}
+		let indexed = commit
+			.indexed
+			.iter()
+			.flat_map(|(c, set)| set.changes.iter().map(move |op| (*c, &op.key()[..])));
+		let btree_indexed = commit
+			.btree_indexed
+			.iter()
+			.flat_map(|(c, set)| set.changes.iter().map(move |op| (*c, &op.key()[..])));
+		for (col, key) in indexed.chain(btree_indexed) {
+			let mut value = None;
+			for snapshot in &snapshots {
+				let mut values = snapshot.values.lock();
+				if values.contains_key(&(col, key.to_vec())) {
+					continue
+				}
+				if value.is_none() {
+					value = Some(match &self.columns[col as usize] {
+						Column::Hash(column) =>
+							self.get_hashed(col, column, &key.try_into().unwrap())?,
+						Column::Tree(_) => self.get(col, key)?,
+					});
+				}
+				values.insert((col, key.to_vec()), value.clone().unwrap());
+			}
+		}
+		Ok(())
+	}
+
+	fn get_from_commit_overlay(
+		&self,
+		overlay: &[CommitOverlay],
+		col: ColId,
+		key: &[u8],
+	) -> Option<Option<Value>> {
+		let overlay = overlay.get(col as usize)?;
+		match &self.columns[col as usize] {
+			Column::Hash(column) => overlay.get(&column.hash_key(key)),
+			Column::Tree(_) => overlay.btree_get(key).map(|v| v.cloned()),
+		}
+	}
+
+	fn get_with_fallback(&self, col: ColId, fallback: ColId, key: &[u8]) -> Result<Option<Value>> {
+		let overlay = self.commit_overlay.read();
+		let primary = self.get_from_commit_overlay(&overlay, col, key);
+		let secondary = self.get_from_commit_overlay(&overlay, fallback, key);
+		let log = self.log.overlays();
+		let (col, fallback) = (&self.columns[col as usize], &self.columns[fallback as usize]);
+		match (primary, secondary) {
+			(Some(Some(value)), _) => Ok(Some(value)),
+			(Some(None), Some(value)) => Ok(value),
+			(Some(None), None) => fallback.get(key, log),
+			(None, Some(value)) => Ok(col.get(key, log)?.or(value)),
+			(None, None) => col.get_with_fallback(key, fallback, log),
+		}
+	}
+
+	fn get_many_cross_column(&self, key: &[u8], cols: &[ColId]) -> Result<Vec<Option<Value>>> {
+		let mut sorted = cols.to_vec();
+		sorted.sort_unstable();
+		sorted.dedup();
+		let overlay = self.commit_overlay.read();
+		// Index locks are taken in column order to avoid lock order inversion between callers.
+		let guards: HashMap<ColId, _> = sorted
+			.iter()
+			.filter_map(|col| match &self.columns[*col as usize] {
+				Column::Hash(column) => Some((*col, column.read_lock())),
+				Column::Tree(_) => None,
+			})
+			.collect();
+		let log = self.log.overlays().read();
+		cols.iter()
+			.map(|col| match &self.columns[*col as usize] {
+				Column::Hash(column) => {
+					let key = column.hash_key(key);
+					if let Some(v) = overlay.get(*col as usize).and_then(|o| o.get(&key)) {
+						return Ok(v)
+					}
+					guards[col].get(&key, &*log)
+				},
+				Column::Tree(column) => {
+					if let Some(l) = overlay.get(*col as usize).and_then(|o| o.btree_get(key)) {
+						return Ok(l.cloned())
+					}
+					column.with_locked(|btree| BTreeTable::get(key, &*log, btree))
+				},
+			})
+			.collect()
+	}
+
+	fn get_multi(&self, col: ColId, keys: &[&[u8]]) -> Result<Vec<Option<Value>>> {
+		let column = match &self.columns[col as usize] {
+			Column::Hash(column) => column,
+			Column::Tree(_) => return keys.iter().map(|key| self.get(col, key)).collect(),
+		};
+		let counters = self.counters.get(col);
+		let overlay = self.commit_overlay.read();
+		let mut values = Vec::with_capacity(keys.len());
+		// Positions and hashed keys of values that are not in the commit overlay.
+		let mut missing = Vec::new();
+		let mut missing_keys = Vec::new();
+		for (i, key) in keys.iter().enumerate() {
+			let key = column.hash_key(key);
+			let value = overlay.get(col as usize).and_then(|o| o.get(&key));
+			if value.is_none() {
+				missing.push(i);
+				missing_keys.push(key);
+			} else {
+				counters.cache_hits.fetch_add(1, Ordering::Relaxed);
+			}
+			values.push(value.flatten());
+		}
+		let log = self.log.overlays().read();
+		for (i, value) in missing.into_iter().zip(column.get_multi(&missing_keys, &*log)?) {
+			values[i] = value;
+		}
+		for value in &values {
+			self.count_read(col, value.as_ref());
+		}
+		Ok(values)
+	}
+
+	fn has_key(&self, col: ColId, key: &[u8]) -> Result<bool> {
+		match &self.columns[col as usize] {
+			Column::Hash(column) => {
+				let key = column.hash_key(key);
+				let overlay = self.commit_overlay.read();
+				if let Some(v) = overlay.get(col as usize).and_then(|o| o.get_size(&key)) {
+					return Ok(v.is_some())
+				}
+				column.has_key(&key, self.log.overlays())
+			},
+			Column::Tree(_) => Ok(self.get(col, key)?.is_some()),
+		}
+	}
+
+	fn has_keys(&self, col: ColId, keys: &[&[u8]]) -> Result<Vec<bool>> {
+		let column = match self.columns.get(col as usize) {
+			Some(Column::Hash(column)) => column,
+			Some(Column::Tree(_)) => return keys.iter().map(|key| self.has_key(col, key)).collect(),
+			None => return Err(Error::InvalidInput(format!("Invalid column {}", col))),
+		};
+		let overlay = self.commit_overlay.read();
+		let mut found = Vec::with_capacity(keys.len());
+		// Positions and hashed keys of values that are not in the commit overlay.
+		let mut missing = Vec::new();
+		let mut missing_keys = Vec::new();
+		for (i, key) in keys.iter().enumerate() {
+			let key = column.hash_key(key);
+			match overlay.get(col as usize).and_then(|o| o.get_size(&key)) {
+				Some(size) => found.push(size.is_some()),
+				None => {
+					missing.push(i);
+					missing_keys.push(key);
+					found.push(false);
+				},
+			}
+		}
+		for (i, exists) in
+			missing.into_iter().zip(column.has_keys(&missing_keys, self.log.overlays())?)
+		{
+			found[i] = exists;
+		}
+		Ok(found)
+	}
+
 	fn get_size(&self, col: ColId, key: &[u8]) -> Result<Option<u32>> {
 		match &self.columns[col as usize] {
 			Column::Hash(column) => {
@@ -246,6 +715,24 @@ impl DbInner {
 		}
 	}
 
+	fn get_first(&self, col: ColId) -> Result<Option<(Vec<u8>, Value)>> {
+		if let Column::Hash(_) = &self.columns[col as usize] {
+			return Err(Error::NotOrderedColumn)
+		}
+		let mut iter = self.btree_iter(col)?;
+		iter.seek_to_first()?;
+		iter.next()
+	}
+
+	fn get_last(&self, col: ColId) -> Result<Option<(Vec<u8>, Value)>> {
+		if let Column::Hash(_) = &self.columns[col as usize] {
+			return Err(Error::NotOrderedColumn)
+		}
+		let mut iter = self.btree_iter(col)?;
+		iter.seek_to_last()?;
+		iter.prev()
+	}
+
 	// Commit simply adds the data to the queue and to the overlay and
 	// exits as early as possible.
 	fn commit<I, K>(&self, tx: I) -> Result<()>
@@ -268,8 +755,86 @@ impl DbInner {
 	where
 		I: IntoIterator<Item = (ColId, Operation<Vec<u8>, Vec<u8>>)>,
 	{
-		let mut commit: CommitChangeSet = Default::default();
+		self.commit_changes_with_sequence(tx, None)
+	}
+
+	fn write_with_sequence(
+		&self,
+		col: ColId,
+		entries: &[(Key, Option<Value>)],
+		seq: u64,
+	) -> Result<()> {
+		self.commit_changes_with_sequence(
+			entries.iter().map(|(k, v)| {
+				(
+					col,
+					match v {
+						Some(v) => Operation::Set(k.to_vec(), v.clone()),
+						None => Operation::Dereference(k.to_vec()),
+					},
+				)
+			}),
+			Some(seq),
+		)
+	}
+
+	fn get_changelog(&self, col: ColId, from: u64, to: u64) -> Result<Vec<ChangeEntry>> {
+		use std::collections::hash_map::Entry;
+		match self.columns.get(col as usize) {
+			Some(Column::Hash(_)) => (),
+			Some(_) =>
+				return Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+			None => return Err(Error::InvalidInput(format!("Invalid column {}", col))),
+		}
+		if !self.options.changelog.contains(&col) {
+			return Err(Error::InvalidConfiguration(format!(
+				"Column {} is not in the changelog",
+				col
+			)))
+		}
+		if self.options.columns[col as usize].ref_counted {
+			return Err(Error::InvalidConfiguration(format!("Column {} is reference counted", col)))
+		}
+		let mut result: Vec<ChangeEntry> = Vec::new();
+		// Position in `result` of each change. A commit that was written again after a crash
+		// lost it replaces the earlier entry.
+		let mut positions = HashMap::new();
+		for (c, mut entry) in changelog::read(&self.options.path)? {
+			if c != col || entry.seq < from || entry.seq > to {
+				continue
+			}
+			entry.old_value = self.strip_value_prefix(col, entry.old_value)?;
+			entry.new_value = self.strip_value_prefix(col, entry.new_value)?;
+			match positions.entry((entry.seq, entry.key)) {
+				Entry::Occupied(position) => result[*position.get()] = entry,
+				Entry::Vacant(position) => {
+					position.insert(result.len());
+					result.push(entry);
+				},
+			}
+		}
+		Ok(result)
+	}
+
+	fn commit_changes_with_sequence<I>(&self, tx: I, sequence: Option<u64>) -> Result<()>
+	where
+		I: IntoIterator<Item = (ColId, Operation<Vec<u8>, Vec<u8>>)>,
+	{
+		self.commit_raw(self.make_changeset(tx, sequence)?)
+	}
+
+	fn make_changeset<I>(&self, tx: I, sequence: Option<u64>) -> Result<CommitChangeSet>
+	where
+		I: IntoIterator<Item = (ColId, Operation<Vec<u8>, Vec<u8>>)>,
+	{
+		let mut commit = CommitChangeSet { sequence, ..Default::default() };
 		for (col, change) in tx.into_iter() {
+			if self.options.columns[col as usize].versioning {
+				return Err(Error::InvalidInput(format!(
+					"Column {} is versioned, use put_if_version",
+					col
+				)))
+			}
 			if self.options.columns[col as usize].btree_index {
 				commit
 					.btree_indexed
@@ -284,22 +849,285 @@ impl DbInner {
 				)
 			}
 		}
+		Ok(commit)
+	}
+
+	fn write_batch<K: AsRef<[u8]>>(
+		&self,
+		col: ColId,
+		batch: &[(K, Option<Value>)],
+		duplicates: DuplicateKeys,
+	) -> Result<()> {
+		if col as usize >= self.columns.len() {
+			return Err(Error::InvalidInput(format!("Invalid column {}", col)))
+		}
+		// Walk the batch backwards so that the last value set for a key is kept. Removals are
+		// dereferences and are all kept, since each one drops a reference in reference counted
+		// columns.
+		let mut seen = HashSet::with_capacity(batch.len());
+		let mut changes = Vec::with_capacity(batch.len());
+		for (key, value) in batch.iter().rev() {
+			let key = key.as_ref();
+			if value.is_some() && !seen.insert(key) {
+				match duplicates {
+					DuplicateKeys::LastWins => continue,
+					DuplicateKeys::Reject =>
+						return Err(Error::InvalidInput(format!(
+							"Duplicate key {} in batch",
+							hex(key)
+						))),
+				}
+			}
+			changes.push((
+				col,
+				match value {
+					Some(value) => Operation::Set(key.to_vec(), value.clone()),
+					None => Operation::Dereference(key.to_vec()),
+				},
+			));
+		}
+		changes.reverse();
+		self.commit_changes(changes)
+	}
+
+	// Looks up `key` and, if it is missing, computes and commits a new value. The commit queue lock
+	// is held between the second lookup and queueing the insert, so concurrent callers never
+	// insert twice.
+	fn get_or_compute(
+		&self,
+		col: ColId,
+		key: &[u8],
+		compute_fn: impl FnOnce() -> Value,
+	) -> Result<Value> {
+		let read_at = self.commit_queue.lock().record_id;
+		if let Some(value) = self.get_unexpired(col, key)? {
+			return Ok(value)
+		}
+		let value = compute_fn();
+		self.write_plan_if_absent(col, key, value, read_at)
+	}
+
+	// Queue `value` for `key`, which was missing before the commit queue reached `read_at`.
+	// Commits queued since then are checked in the commit overlay while they are all still
+	// queued. Otherwise the key is read again, without holding the commit queue lock.
+	fn write_plan_if_absent(
+		&self,
+		col: ColId,
+		key: &[u8],
+		value: Value,
+		mut read_at: u64,
+	) -> Result<Value> {
+		let commit = self.make_changeset(
+			std::iter::once((col, Operation::Set(key.to_vec(), value.clone()))),
+			None,
+		)?;
+		loop {
+			let mut queue = self.commit_queue.lock();
+			self.wait_for_queue_space(&mut queue);
+			let queued_since = (queue.record_id - read_at) as usize;
+			if queue.commits.len() >= queued_since {
+				if queued_since > 0 {
+					let existing = self.commit_overlay_get(col, key);
+					if let Some(existing) = self.strip_ttl(col, existing.flatten())? {
+						return Ok(existing)
+					}
+				}
+				self.enqueue_commit(&mut queue, commit)?;
+				return Ok(value)
+			}
+			read_at = queue.record_id;
+			drop(queue);
+			if let Some(existing) = self.get_unexpired(col, key)? {
+				return Ok(existing)
+			}
+		}
+	}
+
+	// Change of `key` that is queued, but not written to the log yet.
+	fn commit_overlay_get(&self, col: ColId, key: &[u8]) -> Option<Option<Value>> {
+		let overlay = self.commit_overlay.read();
+		let overlay = overlay.get(col as usize)?;
+		match &self.columns[col as usize] {
+			Column::Hash(column) => overlay.get(&column.hash_key(key)),
+			Column::Tree(_) => overlay.btree_get(key).map(|value| value.cloned()),
+		}
+	}
+
+	fn get_versioned(&self, col: ColId, key: &[u8]) -> Result<Option<(u64, Value)>> {
+		match self.get(col, key)? {
+			Some(mut value) => {
+				if value.len() < VERSION_SIZE {
+					return Err(Error::Corruption("Missing value version".into()))
+				}
+				let version = u64::from_le_bytes(value[..VERSION_SIZE].try_into().unwrap());
+				value.drain(..VERSION_SIZE);
+				Ok(Some((version, value)))
+			},
+			None => Ok(None),
+		}
+	}
+
+	fn put_if_version(
+		&self,
+		col: ColId,
+		key: &[u8],
+		expected_version: u64,
+		new_value: Option<Value>,
+	) -> Result<PutIfVersionOutcome> {
+		let options = self
+			.options
+			.columns
+			.get(col as usize)
+			.ok_or_else(|| Error::InvalidInput(format!("Invalid column {}", col)))?;
+		if !options.versioning {
+			return Err(Error::InvalidConfiguration(format!("Column {} is not versioned", col)))
+		}
+		let mut queue = self.commit_queue.lock();
+		self.wait_for_queue_space(&mut queue);
+		// Commits are added to the overlay under the queue lock, so the version can't change
+		// until this commit is queued.
+		let version = self.get_versioned(col, key)?.map_or(0, |(version, _)| version);
+		if version != expected_version {
+			return Ok(PutIfVersionOutcome::Conflict(version))
+		}
+		let (change, version) = match new_value {
+			Some(value) => {
+				let version = version + 1;
+				let mut stored = Vec::with_capacity(VERSION_SIZE + value.len());
+				stored.extend_from_slice(&version.to_le_bytes());
+				stored.extend_from_slice(&value);
+				(Operation::Set(key.to_vec(), stored), version)
+			},
+			None => (Operation::Dereference(key.to_vec()), 0),
+		};
+		let mut commit = CommitChangeSet::default();
+		commit.indexed.entry(col).or_insert_with(|| IndexedChangeSet::new(col)).push(
+			change,
+			&self.options,
+			self.db_version,
+		);
+		self.enqueue_commit(&mut queue, commit)?;
+		Ok(PutIfVersionOutcome::Success(version))
+	}
+
+	fn compare_and_swap(
+		&self,
+		col: ColId,
+		key: &[u8],
+		expected: Option<&[u8]>,
+		new_value: Option<Value>,
+	) -> Result<bool> {
+		let options = self
+			.options
+			.columns
+			.get(col as usize)
+			.ok_or_else(|| Error::InvalidInput(format!("Invalid column {}", col)))?;
+		if options.versioning {
+			return Err(Error::InvalidConfiguration(format!(
+				"Column {} is versioned, use `put_if_version`",
+				col
+			)))
+		}
+		if options.ref_counted {
+			return Err(Error::InvalidConfiguration(format!("Column {} is reference counted", col)))
+		}
+		let mut queue = self.commit_queue.lock();
+		self.wait_for_queue_space(&mut queue);
+		// Commits are added to the overlay under the queue lock, so the value can't change until
+		// this commit is queued.
+		if self.get_unexpired(col, key)?.as_deref() != expected {
+			return Ok(false)
+		}
+		let change = match new_value {
+			Some(value) => Operation::Set(key.to_vec(), value),
+			None => Operation::Dereference(key.to_vec()),
+		};
+		let commit = self.make_changeset(std::iter::once((col, change)), None)?;
+		self.enqueue_commit(&mut queue, commit)?;
+		Ok(true)
+	}
+
+	#[cfg(feature = "bloom")]
+	fn rebuild_bloom(&self, col: ColId) -> Result<()> {
+		let column = self
+			.columns
+			.get(col as usize)
+			.ok_or_else(|| Error::InvalidInput(format!("Invalid column {}", col)))?;
+		if column.bloom_stats()?.is_none() {
+			return Err(Error::InvalidConfiguration(format!("Column {} has no Bloom filter", col)))
+		}
+		self.rebuild_column_bloom(column)
+	}
+
+	// Keys planned by the log worker are added to the filter before their log record is
+	// complete. Holding the lock while the rebuild starts makes sure they are in the log overlay
+	// when the index is scanned. Keys planned later are added to the new filter directly.
+	#[cfg(feature = "bloom")]
+	fn rebuild_column_bloom(&self, column: &Column) -> Result<()> {
+		column.rebuild_bloom(self.log.overlays(), || self.log_record_lock.lock())
+	}
+
+	// Filters that hold more keys than they were sized for keep working with a higher false
+	// positive rate until they are rebuilt here, away from the commit path.
+	#[cfg(feature = "bloom")]
+	fn rebuild_full_blooms(&self) -> Result<()> {
+		for column in self.columns.iter().filter(|column| column.is_bloom_full()) {
+			self.rebuild_column_bloom(column)?;
+		}
+		Ok(())
+	}
 
-		self.commit_raw(commit)
+	fn set_column_read_only(&self, col: ColId, read_only: bool) -> Result<()> {
+		let flag = self
+			.read_only_columns
+			.get(col as usize)
+			.ok_or_else(|| Error::InvalidInput(format!("Invalid column {}", col)))?;
+		// Commits are checked under the queue lock.
+		let _queue = self.commit_queue.lock();
+		flag.store(read_only, Ordering::SeqCst);
+		Ok(())
 	}
 
 	fn commit_raw(&self, commit: CommitChangeSet) -> Result<()> {
 		let mut queue = self.commit_queue.lock();
+		self.wait_for_queue_space(&mut queue);
+		self.enqueue_commit(&mut queue, commit)
+	}
 
+	fn wait_for_queue_space(&self, queue: &mut MutexGuard<CommitQueue>) {
 		#[cfg(any(test, feature = "instrumentation"))]
 		let might_wait_because_the_queue_is_full = self.options.with_background_thread;
 		#[cfg(not(any(test, feature = "instrumentation")))]
 		let might_wait_because_the_queue_is_full = true;
 		if might_wait_because_the_queue_is_full && queue.bytes > MAX_COMMIT_QUEUE_BYTES {
 			log::debug!(target: "parity-db", "Waiting, queue size={}", queue.bytes);
-			self.commit_queue_full_cv.wait(&mut queue);
+			self.commit_queue_full_cv.wait(queue);
 		}
+		// Unlike the commit queue, this limit is opt-in, so it also applies without background
+		// threads. Dropped index tables are signalled under the queue lock.
+		while let Some(col) = self.reindex_queue_full() {
+			if self.shutdown.load(Ordering::SeqCst) || self.bg_err.lock().is_some() {
+				break
+			}
+			log::debug!(target: "parity-db", "Waiting for column {} to be reindexed", col);
+			self.commit_queue_full_cv.wait(queue);
+		}
+	}
+
+	// A column with at least `Options::max_reindex_queue_depth` index tables waiting to be
+	// reindexed.
+	fn reindex_queue_full(&self) -> Option<ColId> {
+		self.options.max_reindex_queue_depth.iter().find_map(|(col, max)| {
+			let column = self.columns.get(*col as usize)?;
+			(column.reindex_queue_len() >= *max).then_some(*col)
+		})
+	}
 
+	fn enqueue_commit(
+		&self,
+		queue: &mut MutexGuard<CommitQueue>,
+		commit: CommitChangeSet,
+	) -> Result<()> {
 		{
 			let bg_err = self.bg_err.lock();
 			if let Some(err) = &*bg_err {
@@ -307,6 +1135,29 @@ impl DbInner {
 			}
 		}
 
+		if self.read_only {
+			return Err(Error::ReadOnly)
+		}
+
+		// Checked under the queue lock, so no commit is queued for a column once it is locked.
+		for col in commit.indexed.keys().chain(commit.btree_indexed.keys()) {
+			if self.read_only_columns[*col as usize].load(Ordering::SeqCst) {
+				return Err(Error::ColumnReadOnly(*col))
+			}
+		}
+
+		self.check_index_space(&commit)?;
+
+		if let Some(seq) = commit.sequence {
+			if seq < self.last_sequence.load(Ordering::SeqCst) {
+				return Err(Error::StaleSequence)
+			}
+			self.last_sequence.store(seq, Ordering::SeqCst);
+		}
+
+		self.capture_read_snapshots(&commit)?;
+		self.mirror_transfer(&commit);
+
 		let mut overlay = self.commit_overlay.write();
 
 		queue.record_id += 1;
@@ -345,100 +1196,364 @@ impl DbInner {
 		Ok(())
 	}
 
-	fn process_commits(&self) -> Result<bool> {
-		#[cfg(any(test, feature = "instrumentation"))]
-		let might_wait_because_the_queue_is_full = self.options.with_background_thread;
-		#[cfg(not(any(test, feature = "instrumentation")))]
-		let might_wait_because_the_queue_is_full = true;
-		if might_wait_because_the_queue_is_full {
-			// Wait if the queue is full.
-			let mut queue = self.log_queue_wait.work.lock();
-			if !self.shutdown.load(Ordering::Relaxed) && *queue > MAX_LOG_QUEUE_BYTES {
-				log::debug!(target: "parity-db", "Waiting, log_bytes={}", queue);
-				self.log_queue_wait.cv.wait(&mut queue);
+	// Fail with `Error::IndexFull` if `commit` sets keys that may not fit the index of a column
+	// with `ColumnOptions::max_bits`. Once a commit is queued, such an error would stop the
+	// database, so this is checked before. Called under the queue lock.
+	fn check_index_space(&self, commit: &CommitChangeSet) -> Result<()> {
+		for (col, changes) in &commit.indexed {
+			let column = match &self.columns[*col as usize] {
+				Column::Hash(column) if self.options.columns[*col as usize].max_bits.is_some() =>
+					column,
+				_ => continue,
+			};
+			let keys: Vec<&Key> = changes
+				.changes
+				.iter()
+				.filter_map(|change| match change {
+					Operation::Set(key, _) => Some(key),
+					_ => None,
+				})
+				.collect();
+			if keys.is_empty() {
+				continue
 			}
+			// Commits that are being planned are written to the log overlay and removed from the
+			// commit overlay under this lock, so each of their keys is seen in one of them.
+			let _record_lock = self.log_record_lock.lock();
+			let overlay = self.commit_overlay.read();
+			let pending = overlay[*col as usize]
+				.indexed
+				.iter()
+				.filter_map(|(key, (_, value))| value.as_ref().map(|_| key));
+			column.check_index_space(&keys, pending, self.log.overlays())?;
 		}
-		let commit = {
-			let mut queue = self.commit_queue.lock();
-			if let Some(commit) = queue.commits.pop_front() {
-				queue.bytes -= commit.bytes;
-				log::debug!(
-					target: "parity-db",
-					"Removed {}. Still queued commits {} bytes",
-					commit.bytes,
-					queue.bytes,
-				);
-				if queue.bytes <= MAX_COMMIT_QUEUE_BYTES &&
-					(queue.bytes + commit.bytes) > MAX_COMMIT_QUEUE_BYTES
-				{
-					// Past the waiting threshold.
-					log::debug!(
-						target: "parity-db",
-						"Waking up commit queue worker",
-					);
-					self.commit_queue_full_cv.notify_all();
-				}
-				Some(commit)
-			} else {
-				None
+		Ok(())
+	}
+
+	// Queue changes to a column that is being transferred for the destination. Called under the
+	// queue lock, so the destination receives them in commit order.
+	fn mirror_transfer(&self, commit: &CommitChangeSet) {
+		let mut transfer = self.transfer.lock();
+		let transfer = match &mut *transfer {
+			Some(transfer) => transfer,
+			None => return,
+		};
+		let changes = match commit.indexed.get(&transfer.col) {
+			Some(changes) => changes,
+			None => return,
+		};
+		let mut mirrored = IndexedChangeSet::new(transfer.dest_col);
+		for change in &changes.changes {
+			transfer.touched.insert(*change.key());
+			mirrored.changes.push(match change {
+				Operation::Set(k, v) => Operation::Set(*k, v.clone()),
+				Operation::Dereference(k) => Operation::Dereference(*k),
+				Operation::Reference(k) => Operation::Reference(*k),
+			});
+		}
+		transfer.mirrored += mirrored.changes.len() as u64;
+		let mut commit = CommitChangeSet::default();
+		commit.indexed.insert(transfer.dest_col, mirrored);
+		transfer.forward.push_back(commit);
+	}
+
+	// Commit changes queued for the destination of the running transfer. Only called by the
+	// transferring thread, which keeps them in order. No lock of this database is held while
+	// committing, so databases that transfer columns to each other can't deadlock.
+	fn forward_transfer(&self) -> Result<()> {
+		loop {
+			let (dest, commit) = {
+				let mut transfer = self.transfer.lock();
+				let transfer = transfer.as_mut().expect("Only called during a transfer; qed");
+				match transfer.forward.pop_front() {
+					Some(commit) => (transfer.dest.clone(), commit),
+					None => return Ok(()),
+				}
+			};
+			dest.commit_raw(commit)?;
+		}
+	}
+
+	fn transfer_column(
+		&self,
+		col: ColId,
+		dest: &Arc<DbInner>,
+		dest_col: ColId,
+	) -> Result<TransferStats> {
+		self.check_transfer(col, dest, dest_col)?;
+		// Keys with commits still in the queue may be missing from the tables, so they are
+		// copied separately.
+		let pending: Vec<Key> = {
+			let _queue = self.commit_queue.lock();
+			let mut transfer = self.transfer.lock();
+			if transfer.is_some() {
+				return Err(Error::InvalidInput("A column transfer is already running".into()))
 			}
+			*transfer = Some(ColumnTransfer {
+				col,
+				dest: dest.clone(),
+				dest_col,
+				touched: Default::default(),
+				mirrored: 0,
+				forward: Default::default(),
+			});
+			self.commit_overlay.read()[col as usize].indexed.keys().copied().collect()
 		};
+		let mut stats = TransferStats::default();
+		let copied = self.copy_transfer_entries(col, pending, &mut stats);
+
+		let queue = self.commit_queue.lock();
+		let transfer = self.transfer.lock().take().expect("Transfer is set above; qed");
+		copied?;
+		stats.mirrored = transfer.mirrored;
+		// Commits to the source column are rejected while it is cleared, rather than lost.
+		let read_only = self.read_only_columns[col as usize].swap(true, Ordering::SeqCst);
+		drop(queue);
+		let forwarded = transfer.forward.into_iter().try_for_each(|commit| dest.commit_raw(commit));
+		let cleared = forwarded
+			.and_then(|_| dest.flush_queued_commits())
+			.and_then(|_| self.hard_reset(col));
+		let _queue = self.commit_queue.lock();
+		self.read_only_columns[col as usize].store(read_only, Ordering::SeqCst);
+		cleared?;
+		Ok(stats)
+	}
 
-		if let Some(mut commit) = commit {
-			let mut reindex = false;
-			let mut writer = self.log.begin_record();
-			log::debug!(
-				target: "parity-db",
-				"Processing commit {}, record {}, {} bytes",
-				commit.id,
-				writer.record_id(),
-				commit.bytes,
-			);
-			let mut ops: u64 = 0;
-			for (c, key_values) in commit.changeset.indexed.iter() {
-				key_values.write_plan(
-					&self.columns[*c as usize],
-					&mut writer,
-					&mut ops,
-					&mut reindex,
-				)?;
-			}
-
-			for (c, btree) in commit.changeset.btree_indexed.iter_mut() {
-				match &self.columns[*c as usize] {
-					Column::Hash(_column) =>
-						return Err(Error::InvalidConfiguration(
-							"Not an indexed column.".to_string(),
-						)),
-					Column::Tree(column) => {
-						btree.write_plan(column, &mut writer, &mut ops)?;
-					},
+	fn check_transfer(&self, col: ColId, dest: &Arc<DbInner>, dest_col: ColId) -> Result<()> {
+		if col as usize >= self.columns.len() {
+			return Err(Error::InvalidInput(format!("Invalid column {}", col)))
+		}
+		if dest_col as usize >= dest.columns.len() {
+			return Err(Error::InvalidInput(format!("Invalid column {}", dest_col)))
+		}
+		if std::ptr::eq(self, &**dest) {
+			return Err(Error::InvalidInput("Can't transfer a column to the same database".into()))
+		}
+		if !matches!(self.columns[col as usize], Column::Hash(_)) ||
+			!matches!(dest.columns[dest_col as usize], Column::Hash(_))
+		{
+			return Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string()))
+		}
+		let (source_options, dest_options) =
+			(&self.options.columns[col as usize], &dest.options.columns[dest_col as usize]);
+		if source_options.ref_counted {
+			return Err(Error::InvalidConfiguration(
+				"Reference counted columns can't be transferred.".to_string(),
+			))
+		}
+		// Values are copied with their write time.
+		if source_options.ttl.is_some() != dest_options.ttl.is_some() {
+			return Err(Error::InvalidConfiguration(
+				"Only one of the columns has a time-to-live.".to_string(),
+			))
+		}
+		// Values are copied with their version.
+		if source_options.versioning != dest_options.versioning {
+			return Err(Error::InvalidConfiguration(
+				"Only one of the columns is versioned.".to_string(),
+			))
+		}
+		// Keys are copied hashed.
+		if source_options.uniform != dest_options.uniform ||
+			self.options.salt != dest.options.salt ||
+			self.db_version != dest.db_version
+		{
+			return Err(Error::InvalidConfiguration(
+				"Destination column hashes keys differently.".to_string(),
+			))
+		}
+		Ok(())
+	}
+
+	fn copy_transfer_entries(
+		&self,
+		col: ColId,
+		pending: Vec<Key>,
+		stats: &mut TransferStats,
+	) -> Result<()> {
+		let column = match &self.columns[col as usize] {
+			Column::Hash(column) => column,
+			Column::Tree(_) => unreachable!("Checked in check_transfer"),
+		};
+		let mut batch = Vec::with_capacity(pending.len());
+		for key in pending {
+			let value = self.get_hashed(col, column, &key)?;
+			batch.push((key, value));
+		}
+		// Keys queued before the transfer started are marked as touched, so that possibly
+		// outdated values found in the tables are skipped.
+		self.transfer_batch(&mut batch, true, stats)?;
+
+		let mut result = Ok(());
+		self.iter_column_while(col, |IterState { key, value, .. }| {
+			batch.push((key, Some(value)));
+			if batch.len() >= TRANSFER_BATCH_SIZE {
+				if let Err(e) = self.transfer_batch(&mut batch, false, stats) {
+					result = Err(e);
+					return false
 				}
 			}
+			true
+		})?;
+		result?;
+		self.transfer_batch(&mut batch, false, stats)
+	}
 
-			// Collect final changes to value tables
-			for c in self.columns.iter() {
-				c.complete_plan(&mut writer)?;
+	// Values were read without holding the transfer lock. Any key modified since then is in
+	// `touched` and its value has been mirrored to the destination already.
+	fn transfer_batch(
+		&self,
+		batch: &mut Vec<(Key, Option<Value>)>,
+		mark_touched: bool,
+		stats: &mut TransferStats,
+	) -> Result<()> {
+		{
+			let mut transfer = self.transfer.lock();
+			let transfer =
+				transfer.as_mut().expect("Batches are only copied during a transfer; qed");
+			let mut changes = IndexedChangeSet::new(transfer.dest_col);
+			for (key, value) in batch.drain(..) {
+				if transfer.touched.contains(&key) {
+					continue
+				}
+				if mark_touched {
+					transfer.touched.insert(key);
+				}
+				if let Some(value) = value {
+					stats.entries += 1;
+					stats.bytes += value.len() as u64;
+					changes.changes.push(Operation::Set(key, value));
+				}
 			}
-			let record_id = writer.record_id();
-			let l = writer.drain();
+			if !changes.changes.is_empty() {
+				let mut commit = CommitChangeSet::default();
+				commit.indexed.insert(transfer.dest_col, changes);
+				// Queued behind the changes mirrored so far, so that it can't overwrite them.
+				transfer.forward.push_back(commit);
+			}
+		}
+		self.forward_transfer()
+	}
+
+	// Write all queued commits to the log and flush it.
+	fn flush_queued_commits(&self) -> Result<()> {
+		while self.process_commits()? {}
+		let _record_lock = self.log_record_lock.lock();
+		while self.flush_logs(0)? {}
+		Ok(())
+	}
+
+	fn process_commits(&self) -> Result<bool> {
+		#[cfg(any(test, feature = "instrumentation"))]
+		let might_wait_because_the_queue_is_full = self.options.with_background_thread;
+		#[cfg(not(any(test, feature = "instrumentation")))]
+		let might_wait_because_the_queue_is_full = true;
+		if might_wait_because_the_queue_is_full {
+			// Wait if the queue is full.
+			let mut queue = self.log_queue_wait.work.lock();
+			if !self.shutdown.load(Ordering::Relaxed) && *queue > MAX_LOG_QUEUE_BYTES {
+				log::debug!(target: "parity-db", "Waiting, log_bytes={}", queue);
+				self.log_queue_wait.cv.wait(&mut queue);
+			}
+		}
+		let _process_lock = self.process_commits_lock.lock();
+		let mut commits = Vec::new();
+		{
+			let mut queue = self.commit_queue.lock();
+			let queued_bytes = queue.bytes;
+			// Changes per column of the commits taken so far.
+			let mut buffered = HashMap::new();
+			while let Some(commit) = queue.commits.front() {
+				let fits = self.buffer_changes(&commit.changeset, &mut buffered);
+				if !fits && !commits.is_empty() {
+					break
+				}
+				let commit = queue.commits.pop_front().expect("Checked above; qed");
+				queue.bytes -= commit.bytes;
+				log::debug!(
+					target: "parity-db",
+					"Removed {}. Still queued commits {} bytes",
+					commit.bytes,
+					queue.bytes,
+				);
+				commits.push(commit);
+				if !fits {
+					break
+				}
+			}
+			if queue.bytes <= MAX_COMMIT_QUEUE_BYTES && queued_bytes > MAX_COMMIT_QUEUE_BYTES {
+				// Past the waiting threshold.
+				log::debug!(
+					target: "parity-db",
+					"Waking up commit queue worker",
+				);
+				self.commit_queue_full_cv.notify_all();
+			}
+		}
+
+		if !commits.is_empty() {
+			let _record_lock = self.log_record_lock.lock();
+			let changes = match self.changelog {
+				Some(_) => self.changelog_entries(&commits)?,
+				None => Vec::new(),
+			};
+			// Changes of a commit that fails to be planned are dropped from all columns, along
+			// with the other commits of the record.
+			let mut writer = MultiColumnWriter::new(&self.log, &self.columns);
+			let record_id = writer.record_id().expect("Nothing is written yet; qed");
+			for commit in commits.iter_mut() {
+				if let Some(seq) = commit.changeset.sequence {
+					writer.set_sequence(seq)?;
+				}
+				log::debug!(
+					target: "parity-db",
+					"Processing commit {}, record {}, {} bytes",
+					commit.id,
+					record_id,
+					commit.bytes,
+				);
+				for (c, key_values) in commit.changeset.indexed.iter() {
+					count_writes(self.counters.get(*c), &key_values.changes);
+					key_values.write_plan(&self.columns[*c as usize], &mut writer)?;
+				}
+
+				for (c, btree) in commit.changeset.btree_indexed.iter_mut() {
+					count_writes(self.counters.get(*c), &btree.changes);
+					writer.write_tree(*c, btree)?;
+				}
+
+				writer.write_barrier(commit.id)?;
+			}
+			let ops = writer.ops();
+			let reindex = writer.needs_reindex();
 
 			let bytes = {
-				let bytes = self.log.end_record(l)?;
+				let bytes = writer.commit()?;
 				let mut logged_bytes = self.log_queue_wait.work.lock();
 				*logged_bytes += bytes as i64;
 				self.flush_worker_wait.signal();
 				bytes
 			};
 
+			if let Some(audit_log) = &self.audit_log {
+				for commit in &commits {
+					audit_log.add(record_id, &commit.changeset);
+				}
+			}
+			if let Some(changelog) = &self.changelog {
+				changelog.record(&changes)?;
+			}
+
 			{
 				// Cleanup the commit overlay.
 				let mut overlay = self.commit_overlay.write();
-				for (c, key_values) in commit.changeset.indexed.iter() {
-					key_values.clean_overlay(&mut overlay[*c as usize], commit.id);
-				}
-				for (c, iterset) in commit.changeset.btree_indexed.iter_mut() {
-					iterset.clean_overlay(&mut overlay[*c as usize].btree_indexed, commit.id);
+				for commit in commits.iter_mut() {
+					for (c, key_values) in commit.changeset.indexed.iter() {
+						key_values.clean_overlay(&mut overlay[*c as usize], commit.id);
+					}
+					for (c, iterset) in commit.changeset.btree_indexed.iter_mut() {
+						iterset.clean_overlay(&mut overlay[*c as usize].btree_indexed, commit.id);
+					}
 				}
 			}
 
@@ -448,8 +1563,9 @@ impl DbInner {
 
 			log::debug!(
 				target: "parity-db",
-				"Processed commit {} (record {}), {} ops, {} bytes written",
-				commit.id,
+				"Processed {} commits up to {} (record {}), {} ops, {} bytes written",
+				commits.len(),
+				commits.last().map_or(0, |commit| commit.id),
 				record_id,
 				ops,
 				bytes,
@@ -460,6 +1576,99 @@ impl DbInner {
 		}
 	}
 
+	// Changes of the sequenced commits in `commits` to the columns in `Options::changelog`. The
+	// previous values are read from the tables and the log overlay, which hold all commits
+	// processed before, or from the earlier commits of `commits`. Called with `log_record_lock`
+	// held, so that no other commit is written to the log in between.
+	fn changelog_entries(&self, commits: &[Commit]) -> Result<Vec<(ColId, ChangeEntry)>> {
+		let mut entries = Vec::new();
+		let mut written: HashMap<(ColId, Key), Option<Value>> = HashMap::new();
+		for commit in commits {
+			let seq = match commit.changeset.sequence {
+				Some(seq) => seq,
+				None => continue,
+			};
+			for (c, key_values) in commit.changeset.indexed.iter() {
+				let column = match &self.columns[*c as usize] {
+					Column::Hash(column)
+						if self.options.changelog.contains(c) &&
+							!self.options.columns[*c as usize].ref_counted =>
+						column,
+					_ => continue,
+				};
+				for change in &key_values.changes {
+					let key = *change.key();
+					let old_value = match written.get(&(*c, key)) {
+						Some(value) => value.clone(),
+						None => column.get(&key, self.log.overlays())?,
+					};
+					let new_value = match change {
+						Operation::Set(_, value) => Some(value.clone()),
+						Operation::Dereference(_) | Operation::Reference(_) => None,
+					};
+					written.insert((*c, key), new_value.clone());
+					entries.push((*c, ChangeEntry { seq, key, old_value, new_value }));
+				}
+			}
+		}
+		Ok(entries)
+	}
+
+	// Add the number of changes per column of `changeset` to `buffered`, if the totals stay within
+	// `Options::write_buffer_capacity`. Returns `false` and leaves `buffered` as is otherwise.
+	fn buffer_changes(
+		&self,
+		changeset: &CommitChangeSet,
+		buffered: &mut HashMap<ColId, usize>,
+	) -> bool {
+		let changes = changeset
+			.indexed
+			.iter()
+			.map(|(c, set)| (*c, set.changes.len()))
+			.chain(changeset.btree_indexed.iter().map(|(c, set)| (*c, set.changes.len())));
+		let mut totals = buffered.clone();
+		for (col, count) in changes {
+			let total = totals.entry(col).or_default();
+			*total += count;
+			match self.options.write_buffer_capacity.get(&col) {
+				Some(capacity) if *total <= *capacity => (),
+				_ => return false,
+			}
+		}
+		*buffered = totals;
+		true
+	}
+
+	// Send corruption errors to `subscribe_errors` receivers and pass the error through.
+	fn report_corruption(&self, col: ColId, operation: &'static str, e: Error) -> Error {
+		if let Error::Corruption(msg) = &e {
+			let event = DatabaseError {
+				error: Arc::new(Error::Corruption(msg.clone())),
+				col,
+				operation,
+				timestamp: std::time::SystemTime::now(),
+			};
+			let mut subscribers = self.error_subscribers.lock();
+			if let Some(pending) = &mut *self.pending_error_events.lock() {
+				if pending.len() < ERROR_EVENTS_CAPACITY {
+					pending.push(event);
+				} else {
+					self.dropped_error_events.fetch_add(1, Ordering::Relaxed);
+				}
+				return e
+			}
+			subscribers.retain(|sender| match sender.try_send(event.clone()) {
+				Ok(()) => true,
+				Err(std::sync::mpsc::TrySendError::Full(_)) => {
+					self.dropped_error_events.fetch_add(1, Ordering::Relaxed);
+					true
+				},
+				Err(std::sync::mpsc::TrySendError::Disconnected(_)) => false,
+			});
+		}
+		e
+	}
+
 	fn start_reindex(&self, record_id: u64) {
 		self.next_reindex.store(record_id, Ordering::SeqCst);
 	}
@@ -474,7 +1683,13 @@ impl DbInner {
 			let column = if let Column::Hash(c) = column { c } else { continue };
 			let ReindexBatch { drop_index, batch } = column.reindex(&self.log)?;
 			if !batch.is_empty() || drop_index.is_some() {
+				#[cfg(feature = "async-tokio")]
+				if let Some(progress) = column.reindex_progress() {
+					// Fails only when there are no subscribers.
+					let _ = self.rebalance_events.send(progress);
+				}
 				let mut next_reindex = false;
+				let _record_lock = self.log_record_lock.lock();
 				let mut writer = self.log.begin_record();
 				log::debug!(
 					target: "parity-db",
@@ -514,7 +1729,233 @@ impl DbInner {
 		Ok(false)
 	}
 
+	fn defrag_index(&self, col: ColId) -> Result<DefragIndexStats> {
+		let _record_lock = self.log_record_lock.lock();
+		let mut writer = self.log.begin_record();
+		let stats = self.columns[col as usize].defrag_index(&mut writer)?;
+		let record_id = writer.record_id();
+		let l = writer.drain();
+
+		let mut logged_bytes = self.log_queue_wait.work.lock();
+		let bytes = self.log.end_record(l)?;
+		log::debug!(
+			target: "parity-db",
+			"Created index defragmentation record {}, {} bytes",
+			record_id,
+			bytes,
+		);
+		*logged_bytes += bytes as i64;
+		self.flush_worker_wait.signal();
+		Ok(stats)
+	}
+
+	fn resize_index_in_place(&self, col: ColId) -> Result<()> {
+		let _record_lock = self.log_record_lock.lock();
+		let mut writer = self.log.begin_record();
+		self.columns[col as usize].resize_index_in_place(&mut writer)?;
+		let l = writer.drain();
+
+		let mut logged_bytes = self.log_queue_wait.work.lock();
+		let bytes = self.log.end_record(l)?;
+		*logged_bytes += bytes as i64;
+		self.flush_worker_wait.signal();
+		Ok(())
+	}
+
+	fn reserve(&self, col: ColId, expected_entries: u64) -> Result<()> {
+		let column = self
+			.columns
+			.get(col as usize)
+			.ok_or_else(|| Error::InvalidInput(format!("Invalid column {}", col)))?;
+		let _record_lock = self.log_record_lock.lock();
+		if !column.reserve(expected_entries)? {
+			return Ok(())
+		}
+		// The reindex starts once this empty record is enacted, like one that overflowed an
+		// index chunk.
+		let writer = self.log.begin_record();
+		let record_id = writer.record_id();
+		let l = writer.drain();
+
+		let mut logged_bytes = self.log_queue_wait.work.lock();
+		let bytes = self.log.end_record(l)?;
+		*logged_bytes += bytes as i64;
+		self.start_reindex(record_id);
+		self.flush_worker_wait.signal();
+		Ok(())
+	}
+
+	fn trim_rebalance_queue(&self, col: ColId) -> Result<usize> {
+		let _record_lock = self.log_record_lock.lock();
+		let mut writer = self.log.begin_record();
+		let dropped = self.columns[col as usize].trim_rebalance_queue(&mut writer)?;
+		let l = writer.drain();
+
+		let mut logged_bytes = self.log_queue_wait.work.lock();
+		let bytes = self.log.end_record(l)?;
+		*logged_bytes += bytes as i64;
+		self.flush_worker_wait.signal();
+		Ok(dropped.len())
+	}
+
+	fn collect_garbage(&self, col: ColId) -> Result<GarbageStats> {
+		// Values of commits that are being written are referenced by the index once their
+		// record is complete, and all records are planned under this lock.
+		let _record_lock = self.log_record_lock.lock();
+		let mut writer = self.log.begin_record();
+		let stats = self.columns[col as usize].collect_garbage(&mut writer)?;
+		let l = writer.drain();
+
+		let mut logged_bytes = self.log_queue_wait.work.lock();
+		let bytes = self.log.end_record(l)?;
+		*logged_bytes += bytes as i64;
+		self.flush_worker_wait.signal();
+		Ok(stats)
+	}
+
+	fn expire(&self, col: ColId) -> Result<u64> {
+		let _record_lock = self.log_record_lock.lock();
+		let mut writer = self.log.begin_record();
+		let column = &self.columns[col as usize];
+		let expired = column.expire(&mut writer)?;
+		column.complete_plan(&mut writer)?;
+		let l = writer.drain();
+
+		let mut logged_bytes = self.log_queue_wait.work.lock();
+		let bytes = self.log.end_record(l)?;
+		*logged_bytes += bytes as i64;
+		self.flush_worker_wait.signal();
+		Ok(expired)
+	}
+
+	fn delete_range_by_tier(&self, col: ColId, tier: usize, start: u64, end: u64) -> Result<u64> {
+		let _record_lock = self.log_record_lock.lock();
+		let mut writer = self.log.begin_record();
+		let freed = self.columns[col as usize].free_range(tier, start, end, &mut writer)?;
+		let l = writer.drain();
+
+		let mut logged_bytes = self.log_queue_wait.work.lock();
+		let bytes = self.log.end_record(l)?;
+		*logged_bytes += bytes as i64;
+		self.flush_worker_wait.signal();
+		Ok(freed)
+	}
+
+	fn purge_tier(&self, col: ColId, tier: usize) -> Result<u64> {
+		let _record_lock = self.log_record_lock.lock();
+		let mut writer = self.log.begin_record();
+		let column = &self.columns[col as usize];
+		let purged = column.purge_tier(tier, &mut writer)?;
+		column.complete_plan(&mut writer)?;
+		let l = writer.drain();
+
+		let mut logged_bytes = self.log_queue_wait.work.lock();
+		let bytes = self.log.end_record(l)?;
+		*logged_bytes += bytes as i64;
+		self.flush_worker_wait.signal();
+		Ok(purged)
+	}
+
+	fn punch_holes(&self, col: ColId) -> Result<u64> {
+		// No new log records can be created while this is held, so removed entries without
+		// pending changes in the log can't be reused until we are done.
+		let _record_lock = self.log_record_lock.lock();
+		self.columns[col as usize].punch_holes(&self.log)
+	}
+
+	fn fsck(&self, col: ColId, repair: bool) -> Result<FsckReport> {
+		let _record_lock = self.log_record_lock.lock();
+		let mut writer = self.log.begin_record();
+		let report = self.columns[col as usize].fsck(repair, &mut writer)?;
+		let l = writer.drain();
+
+		let mut logged_bytes = self.log_queue_wait.work.lock();
+		let bytes = self.log.end_record(l)?;
+		*logged_bytes += bytes as i64;
+		self.flush_worker_wait.signal();
+		Ok(report)
+	}
+
+	fn check_integrity(&self) -> Result<IntegrityReport> {
+		// Values of commits that are being written are referenced by the index once their
+		// record is complete, and all records are planned under this lock.
+		let _record_lock = self.log_record_lock.lock();
+		let log = self.log.overlays().read();
+		let mut report = IntegrityReport::default();
+		for column in &self.columns {
+			column.check_integrity(&*log, &mut report)?;
+		}
+		Ok(report)
+	}
+
+	fn hard_reset(&self, col: ColId) -> Result<()> {
+		// Write out everything committed so far, so that no pending log record targets the
+		// files that are about to be deleted.
+		while self.process_commits()? {}
+		let _record_lock = self.log_record_lock.lock();
+		self.enact_all_logs()?;
+		// Old records must not be replayed over the empty column on the next start.
+		self.clean_all_logs()?;
+		self.columns[col as usize].hard_reset()
+	}
+
+	// Write all log records to the database files. The caller holds the record lock, so that no
+	// new records are added meanwhile.
+	fn enact_all_logs(&self) -> Result<()> {
+		while self.flush_logs(0)? {}
+		loop {
+			while self.enact_logs(false)? {}
+			if !self.log.has_log_files_to_read() {
+				break
+			}
+		}
+		Ok(())
+	}
+
+	fn defragment(&self, col: ColId) -> Result<u64> {
+		let column = self
+			.columns
+			.get(col as usize)
+			.ok_or_else(|| Error::InvalidInput(format!("Invalid column {}", col)))?;
+		while self.process_commits()? {}
+		let _record_lock = self.log_record_lock.lock();
+		self.enact_all_logs()?;
+		let mut writer = self.log.begin_record();
+		let moved = column.defragment(&mut writer)?;
+		let l = writer.drain();
+		let bytes = self.log.end_record(l)?;
+		*self.log_queue_wait.work.lock() += bytes as i64;
+		// Moved values must be in the files before the slots they were moved from are dropped.
+		// Cleaning the logs makes sure the old slots are not written again on the next start.
+		self.enact_all_logs()?;
+		self.clean_all_logs()?;
+		let released = column.shrink_value_files()?;
+		log::info!(target: "parity-db", "Defragmented column {}: moved {} values, released {} bytes", col, moved, released);
+		Ok(released)
+	}
+
+	fn backup_column(&self, col: ColId, dest: &std::path::Path) -> Result<()> {
+		if col as usize >= self.columns.len() {
+			return Err(Error::InvalidInput(format!("Invalid column {}", col)))
+		}
+		// Write out everything committed so far. Holding the record lock keeps new records out
+		// of the log, so the column files don't change while they are copied.
+		while self.process_commits()? {}
+		let _record_lock = self.log_record_lock.lock();
+		self.enact_all_logs()?;
+		crate::backup::backup_column(
+			&self.options.path,
+			col,
+			&self.options.columns[col as usize],
+			&self.options.salt.expect("`salt` is always `Some` after opening the DB; qed"),
+			self.db_version,
+			dest,
+		)
+	}
+
 	fn enact_logs(&self, validation_mode: bool) -> Result<bool> {
+		let _enact_lock = self.enact_lock.lock();
+		let start = std::time::Instant::now();
 		let cleared = {
 			let reader = match self.log.read_next(validation_mode) {
 				Ok(reader) => reader,
@@ -562,41 +2003,39 @@ impl DbInner {
 								return Ok(false)
 							},
 							LogAction::EndRecord => break,
-							LogAction::InsertIndex(insertion) => {
-								let col = insertion.table.col() as usize;
-								if let Err(e) = self.columns.get(col).map_or_else(
-									|| Err(Error::Corruption(format!("Invalid column id {}", col))),
-									|col| {
-										col.validate_plan(
-											LogAction::InsertIndex(insertion),
-											&mut reader,
-										)
-									},
-								) {
-									log::warn!(target: "parity-db", "Error replaying log: {:?}. Reverting", e);
-									drop(reader);
-									self.log.clear_replay_logs();
-									return Ok(false)
-								}
-							},
-							LogAction::InsertValue(insertion) => {
-								let col = insertion.table.col() as usize;
-								if let Err(e) = self.columns.get(col).map_or_else(
-									|| Err(Error::Corruption(format!("Invalid column id {}", col))),
-									|col| {
-										col.validate_plan(
-											LogAction::InsertValue(insertion),
-											&mut reader,
-										)
-									},
-								) {
+							action @ (LogAction::InsertIndex(_) |
+							LogAction::InsertValue(_) |
+							LogAction::DeleteIndex { .. } |
+							LogAction::DeleteValue { .. }) => {
+								let col_id = action
+									.table_col()
+									.expect("Index and value actions have a column; qed");
+								let col = col_id as usize;
+								if let Err(e) = self
+									.columns
+									.get(col)
+									.map_or_else(
+										|| {
+											Err(Error::Corruption(format!(
+												"Invalid column id {}",
+												col
+											)))
+										},
+										|col| col.validate_plan(action, &mut reader),
+									)
+									.map_err(|e| self.report_corruption(col_id, "validate_plan", e))
+								{
 									log::warn!(target: "parity-db", "Error replaying log: {:?}. Reverting", e);
 									drop(reader);
 									self.log.clear_replay_logs();
 									return Ok(false)
 								}
 							},
-							LogAction::DropTable(_) => continue,
+							LogAction::DropTable(_) |
+							LogAction::Sequence(_) |
+							LogAction::Barrier(_) |
+							LogAction::CreateColumn { .. } |
+							LogAction::DropColumn { .. } => continue,
 						}
 					}
 					reader.reset()?;
@@ -607,13 +2046,16 @@ impl DbInner {
 						LogAction::BeginRecord =>
 							return Err(Error::Corruption("Bad log record".into())),
 						LogAction::EndRecord => break,
-						LogAction::InsertIndex(insertion) => {
-							self.columns[insertion.table.col() as usize]
-								.enact_plan(LogAction::InsertIndex(insertion), &mut reader)?;
-						},
-						LogAction::InsertValue(insertion) => {
-							self.columns[insertion.table.col() as usize]
-								.enact_plan(LogAction::InsertValue(insertion), &mut reader)?;
+						action @ (LogAction::InsertIndex(_) |
+						LogAction::InsertValue(_) |
+						LogAction::DeleteIndex { .. } |
+						LogAction::DeleteValue { .. }) => {
+							let col = action
+								.table_col()
+								.expect("Index and value actions have a column; qed");
+							self.columns[col as usize]
+								.enact_plan(action, &mut reader)
+								.map_err(|e| self.report_corruption(col, "enact_plan", e))?;
 						},
 						LogAction::DropTable(id) => {
 							log::debug!(
@@ -623,13 +2065,27 @@ impl DbInner {
 							);
 							match &self.columns[id.col() as usize] {
 								Column::Hash(col) => {
-									col.drop_index(id)?;
+									col.drop_index(id, self.log.overlays())?;
 									// Check if there's another reindex on the next iteration
 									self.start_reindex(reader.record_id());
+									if self.options.max_reindex_queue_depth.contains_key(&id.col())
+									{
+										let _queue = self.commit_queue.lock();
+										self.commit_queue_full_cv.notify_all();
+									}
 								},
 								Column::Tree(_) => (),
 							}
 						},
+						LogAction::Sequence(seq) => {
+							self.last_sequence.fetch_max(seq, Ordering::SeqCst);
+							self.enacted_sequence.fetch_max(seq, Ordering::SeqCst);
+						},
+						LogAction::Barrier(id) => {
+							log::trace!(target: "parity-db", "Reached barrier {}", id);
+						},
+						// Completed before the columns are opened, see `apply_column_actions`.
+						LogAction::CreateColumn { .. } | LogAction::DropColumn { .. } => (),
 					}
 				}
 				log::debug!(
@@ -651,6 +2107,10 @@ impl DbInner {
 
 		if let Some((record_id, cleared, bytes)) = cleared {
 			self.log.end_read(cleared, record_id);
+			if let Some(audit_log) = &self.audit_log {
+				audit_log.enacted(record_id);
+			}
+			self.enact_stats.lock().record(bytes, start.elapsed());
 			{
 				if !validation_mode {
 					let mut queue = self.log_queue_wait.work.lock();
@@ -688,6 +2148,9 @@ impl DbInner {
 	}
 
 	fn clean_logs(&self) -> Result<bool> {
+		#[cfg(feature = "bloom")]
+		self.rebuild_full_blooms()?;
+		let _cleanup_lock = self.cleanup_lock.lock();
 		let keep_logs = if self.options.sync_data { 0 } else { KEEP_LOGS };
 		let num_cleanup = self.log.num_dirty_logs();
 		if num_cleanup > keep_logs {
@@ -696,6 +2159,8 @@ impl DbInner {
 					c.flush()?;
 				}
 			}
+			self.enact_stats.lock().save(&self.options.path)?;
+			self.save_sequence()?;
 			self.log.clean_logs(num_cleanup - keep_logs)
 		} else {
 			Ok(false)
@@ -703,14 +2168,30 @@ impl DbInner {
 	}
 
 	fn clean_all_logs(&self) -> Result<()> {
+		let _cleanup_lock = self.cleanup_lock.lock();
 		for c in self.columns.iter() {
 			c.flush()?;
 		}
 		let num_cleanup = self.log.num_dirty_logs();
+		self.save_sequence()?;
 		self.log.clean_logs(num_cleanup)?;
 		Ok(())
 	}
 
+	// Sequence numbers are only stored in log records, so the highest one is kept in a file
+	// before the logs are removed.
+	fn save_sequence(&self) -> Result<()> {
+		let seq = self.enacted_sequence.load(Ordering::SeqCst);
+		if seq == 0 {
+			return Ok(())
+		}
+		let path = self.options.path.join(SEQUENCE_FILE);
+		let tmp = path.with_extension("tmp");
+		try_io!(std::fs::write(&tmp, format!("{}\n", seq)));
+		try_io!(std::fs::rename(&tmp, &path));
+		Ok(())
+	}
+
 	fn replay_all_logs(&mut self) -> Result<()> {
 		while let Some(id) = self.log.replay_next()? {
 			log::debug!(target: "parity-db", "Replaying database log {}", id);
@@ -719,6 +2200,9 @@ impl DbInner {
 		// Re-read any cached metadata
 		for c in self.columns.iter() {
 			c.refresh_metadata()?;
+			// Replayed records don't go through the write plan and are not in the filter.
+			#[cfg(feature = "bloom")]
+			c.rebuild_bloom(self.log.overlays(), || ())?;
 		}
 		log::debug!(target: "parity-db", "Replay is complete.");
 		Ok(())
@@ -731,6 +2215,8 @@ impl DbInner {
 		self.log_worker_wait.signal();
 		self.commit_worker_wait.signal();
 		self.cleanup_worker_wait.signal();
+		self.stats_worker_wait.signal();
+		self.stats_refresh_wait.signal();
 	}
 
 	fn kill_logs(&self) -> Result<()> {
@@ -795,6 +2281,42 @@ impl DbInner {
 		StatSummary { columns: self.columns.iter().map(|c| c.stats()).collect() }
 	}
 
+	fn precompute_index_stats(&self, col: ColId) -> Result<()> {
+		let column = self
+			.columns
+			.get(col as usize)
+			.ok_or_else(|| Error::InvalidInput(format!("Invalid column {}", col)))?;
+		let stats = column.index_stats(&self.log)?;
+		*self.cached_stats[col as usize].write() = Some(Arc::new(stats));
+		Ok(())
+	}
+
+	fn cached_stats(&self, col: ColId) -> Option<Arc<CachedStats>> {
+		self.cached_stats.get(col as usize)?.read().clone()
+	}
+
+	fn stats_summary(&self) -> String {
+		let mut parts = Vec::with_capacity(self.columns.len() + 1);
+		let mut reindexing = Vec::new();
+		for (c, column) in self.columns.iter().enumerate() {
+			match column {
+				Column::Hash(column) => {
+					parts.push(format!("col{}: {}", c, column.summary_line()));
+					if column.is_reindexing() {
+						reindexing.push(format!("col{}", c));
+					}
+				},
+				Column::Tree(_) => parts.push(format!("col{}: ordered", c)),
+			}
+		}
+		if reindexing.is_empty() {
+			parts.push("reindexing: none".into());
+		} else {
+			parts.push(format!("reindexing: {}", reindexing.join(", ")));
+		}
+		format!("[{}]", parts.join(" | "))
+	}
+
 	fn store_err(&self, result: Result<()>) {
 		if let Err(e) = result {
 			log::warn!(target: "parity-db", "Background worker error: {}", e);
@@ -807,56 +2329,261 @@ impl DbInner {
 		}
 	}
 
-	fn iter_column_while(&self, c: ColId, f: impl FnMut(IterState) -> bool) -> Result<()> {
-		match &self.columns[c as usize] {
-			Column::Hash(column) => column.iter_while(&self.log, f),
-			Column::Tree(_) => unimplemented!(),
+	fn query<R>(
+		&self,
+		col: ColId,
+		filter: impl Fn(&Key, &[u8]) -> bool,
+		map: impl Fn(Key, Vec<u8>) -> R,
+	) -> Result<Vec<R>> {
+		match &self.columns[col as usize] {
+			Column::Hash(column) => {
+				let mut result = Vec::new();
+				let mut error = None;
+				column.iter_while(&self.log, |IterState { key, value, .. }| {
+					let value = match self.strip_ttl(col, Some(value)) {
+						Ok(Some(value)) => value,
+						Ok(None) => return true,
+						Err(e) => {
+							error = Some(e);
+							return false
+						},
+					};
+					if filter(&key, &value) {
+						result.push(map(key, value));
+					}
+					true
+				})?;
+				match error {
+					Some(e) => Err(e),
+					None => Ok(result),
+				}
+			},
+			Column::Tree(_) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
 		}
 	}
-}
-
-pub struct Db {
-	inner: Arc<DbInner>,
-	commit_thread: Option<thread::JoinHandle<()>>,
-	flush_thread: Option<thread::JoinHandle<()>>,
-	log_thread: Option<thread::JoinHandle<()>>,
-	cleanup_thread: Option<thread::JoinHandle<()>>,
-	join_on_shutdown: bool,
-}
 
-impl Db {
-	pub fn with_columns(path: &std::path::Path, num_columns: u8) -> Result<Db> {
-		let options = Options::with_columns(path, num_columns);
-		Self::open_inner(&options, OpeningMode::Create)
+	#[cfg(feature = "parquet")]
+	fn export_parquet(&self, col: ColId, path: &std::path::Path) -> Result<crate::ExportStats> {
+		match &self.columns[col as usize] {
+			Column::Hash(column) => crate::export::export_parquet(column, &self.log, path),
+			Column::Tree(_) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
 	}
 
-	/// Open the database with given options.
-	pub fn open(options: &Options) -> Result<Db> {
-		Self::open_inner(options, OpeningMode::Write)
-	}
+	fn dry_run_commit<K: AsRef<[u8]>>(
+		&self,
+		tx: &[(ColId, K, Option<Value>)],
+	) -> Result<DryRunReport> {
+		let mut changes: BTreeMap<ColId, Vec<_>> = BTreeMap::new();
+		for (col, key, value) in tx {
+			if *col as usize >= self.columns.len() {
+				return Err(Error::InvalidInput(format!("Invalid column {}", col)))
+			}
+			changes
+				.entry(*col)
+				.or_default()
+				.push((key.as_ref(), value.as_ref().map(|v| v.len())));
+		}
+		let mut report = DryRunReport::default();
+		for (col, changes) in changes {
+			let (bytes, tiers, reindex) = match &self.columns[col as usize] {
+				Column::Hash(column) => {
+					let changes: Vec<_> =
+						changes.iter().map(|(key, len)| (column.hash_key(key), *len)).collect();
+					column.dry_run(&changes, self.log.overlays())?
+				},
+				Column::Tree(_) => {
+					let mut bytes = 0;
+					let mut tiers = Vec::new();
+					for (key, len) in changes {
+						bytes += key.len() as u64;
+						if let Some(len) = len {
+							bytes += len as u64;
+							let tier = self.columns[col as usize].tier_for_value(len)?;
+							if !tiers.contains(&tier) {
+								tiers.push(tier);
+							}
+						}
+					}
+					tiers.sort_unstable();
+					(bytes, tiers, false)
+				},
+			};
+			report.estimated_bytes += bytes;
+			report.tiers.extend(tiers.into_iter().map(|tier| (col, tier)));
+			report.reindex_needed |= reindex;
+		}
+		report.available_space = try_io!(fs2::available_space(&self.options.path));
+		// Changes are written to the log before they are written to the tables.
+		report.enough_space = report.available_space >= report.estimated_bytes * 2;
+		Ok(report)
+	}
+
+	fn count_keys_by_prefix(&self, col: ColId, prefix: &[u8], prefix_bits: u8) -> Result<u64> {
+		match &self.columns[col as usize] {
+			Column::Hash(column) => column.count_keys_by_prefix(prefix, prefix_bits, &self.log),
+			Column::Tree(_) =>
+				Err(Error::InvalidConfiguration("Not a hash indexed column.".to_string())),
+		}
+	}
+
+	fn iter_column_by_size(&self, col: ColId, mut f: impl FnMut(Key, Value) -> bool) -> Result<()> {
+		for entry in self.columns[col as usize].iter(&self.log)? {
+			let (key, value) = entry?;
+			let value = match self.strip_ttl(col, Some(value))? {
+				Some(value) => value,
+				None => continue,
+			};
+			if !f(key, value) {
+				break
+			}
+		}
+		Ok(())
+	}
+
+	fn iter_column_while(&self, c: ColId, f: impl FnMut(IterState) -> bool) -> Result<()> {
+		match &self.columns[c as usize] {
+			Column::Hash(column) => column.iter_while(&self.log, f),
+			Column::Tree(_) => unimplemented!(),
+		}
+	}
+}
+
+pub struct Db {
+	inner: Arc<DbInner>,
+	commit_thread: Option<thread::JoinHandle<()>>,
+	flush_thread: Option<thread::JoinHandle<()>>,
+	log_thread: Option<thread::JoinHandle<()>>,
+	cleanup_thread: Option<thread::JoinHandle<()>>,
+	stats_thread: Option<thread::JoinHandle<()>>,
+	stats_refresh_thread: Option<thread::JoinHandle<()>>,
+	join_on_shutdown: bool,
+}
+
+impl Db {
+	pub fn with_columns(path: &std::path::Path, num_columns: u8) -> Result<Db> {
+		let options = Options::with_columns(path, num_columns);
+		Self::open_inner(&options, OpeningMode::Create)
+	}
+
+	/// Open the database with given options.
+	pub fn open(options: &Options) -> Result<Db> {
+		Self::open_inner(options, OpeningMode::Write)
+	}
+
+	/// Open the database with given options. Fails with `Error::DatabaseLocked` without
+	/// waiting if the database is in use, regardless of `Options::lock_timeout`.
+	pub fn open_locked(options: &Options) -> Result<Db> {
+		let mut options = options.clone();
+		options.lock_timeout = Some(std::time::Duration::ZERO);
+		Self::open_inner(&options, OpeningMode::Write)
+	}
 
 	/// Create the database using given options.
 	pub fn open_or_create(options: &Options) -> Result<Db> {
 		Self::open_inner(options, OpeningMode::Create)
 	}
 
-	/// Read the database using given options
+	/// Estimate how long opening the database at `path` takes to replay the logs left after an
+	/// unclean shutdown.
+	///
+	/// The estimate is based on the size of non-empty log files and the average log enactment
+	/// throughput recorded by the database. A slow disk is assumed when there is no history.
+	/// The database does not need to be closed, but the estimate is only meaningful when it is.
+	pub fn estimate_recovery_time(path: &std::path::Path) -> Result<std::time::Duration> {
+		let (count, bytes) = Log::pending_logs(path)?;
+		let throughput = EnactStats::load_throughput(path)?.unwrap_or(FALLBACK_ENACT_THROUGHPUT);
+		log::debug!(
+			target: "parity-db",
+			"{} pending logs, {} bytes, {:.0} bytes/s",
+			count,
+			bytes,
+			throughput,
+		);
+		Ok(std::time::Duration::from_secs((bytes as f64 / throughput).ceil() as u64))
+	}
+
+	/// Open the database for reading only. Commits fail with `Error::ReadOnly` and no
+	/// background workers are started.
+	///
+	/// The database may be open for writing by another instance at the same time. Reads then see
+	/// the data the writer had enacted when the database was opened, while later changes may not
+	/// be visible, and the logs are left to the writer. Otherwise the logs left
+	/// after an unclean shutdown are replayed first, which is the only time the files are written
+	/// to, so that a validator can still check the consistency of a database that is not in use.
 	pub fn open_read_only(options: &Options) -> Result<Db> {
 		Self::open_inner(options, OpeningMode::ReadOnly)
 	}
 
-	fn open_inner(options: &Options, opening_mode: OpeningMode) -> Result<Db> {
+	/// Open several databases that share background worker threads. Each database normally
+	/// runs its own commit, log, flush and cleanup threads. In a group there is a single thread
+	/// of each kind that serves all the databases, so the number of threads does not grow with
+	/// the number of databases. Databases are opened like `Db::open`, in order, and opening
+	/// stops at the first error.
+	///
+	/// A background worker error only stops the database that caused it, the others keep
+	/// running. `Options::stats_log_interval` is ignored for the databases of a group.
+	pub fn open_many(options: &[Options]) -> Result<DbGroup> {
+		let waits = GroupWaits::new();
+		let mut dbs = Vec::with_capacity(options.len());
+		for options in options {
+			let inner = Self::open_replayed(options, OpeningMode::Write, Some(&waits))?;
+			dbs.push(Db {
+				inner,
+				commit_thread: None,
+				flush_thread: None,
+				log_thread: None,
+				cleanup_thread: None,
+				stats_thread: None,
+				stats_refresh_thread: None,
+				join_on_shutdown: false,
+			});
+		}
+		Ok(DbGroup::start(dbs, waits))
+	}
+
+	fn open_replayed(
+		options: &Options,
+		opening_mode: OpeningMode,
+		group: Option<&GroupWaits>,
+	) -> Result<Arc<DbInner>> {
 		assert!(options.is_valid());
-		let mut db = DbInner::open(options, opening_mode)?;
+		let mut db = DbInner::open(options, opening_mode, group)?;
+		// A read only database only replays the logs when no other instance has it open, and
+		// holds a shared lock meanwhile.
+		let replay = !db.read_only || db.lock_file.try_lock_shared().is_ok();
 		// This needs to be call before log thread: so first reindexing
 		// will run in correct state.
-		if let Err(e) = db.replay_all_logs() {
-			log::debug!(target: "parity-db", "Error during log replay, doing log cleanup");
-			db.log.clean_logs(db.log.num_dirty_logs())?;
-			db.log.kill_logs()?;
-			return Err(e)
+		if replay {
+			if let Err(e) = db.replay_all_logs() {
+				if !db.read_only {
+					log::debug!(target: "parity-db", "Error during log replay, doing log cleanup");
+					db.log.clean_logs(db.log.num_dirty_logs())?;
+					db.log.kill_logs()?;
+				}
+				return Err(e)
+			}
+		} else {
+			// The Bloom filters are only built when the logs are replayed, and would miss the
+			// keys added by the writer later on anyway.
+			#[cfg(feature = "bloom")]
+			for column in &mut db.columns {
+				column.disable_bloom();
+			}
+		}
+		if db.read_only {
+			try_io!(db.lock_file.unlock());
+			for column in &db.columns {
+				column.set_read_only();
+			}
 		}
-		let db = Arc::new(db);
+		Ok(Arc::new(db))
+	}
+
+	fn open_inner(options: &Options, opening_mode: OpeningMode) -> Result<Db> {
+		let db = Self::open_replayed(options, opening_mode, None)?;
 		#[cfg(any(test, feature = "instrumentation"))]
 		let start_threads = opening_mode != OpeningMode::ReadOnly && options.with_background_thread;
 		#[cfg(not(any(test, feature = "instrumentation")))]
@@ -897,28 +2624,195 @@ impl Db {
 		} else {
 			None
 		};
+		let stats_thread = match options.stats_log_interval {
+			Some(interval) if start_threads => {
+				let stats_worker_db = db.clone();
+				Some(thread::spawn(move || {
+					stats_worker_db.store_err(Self::stats_worker(stats_worker_db.clone(), interval))
+				}))
+			},
+			_ => None,
+		};
 		Ok(Db {
 			inner: db,
 			commit_thread,
 			flush_thread,
 			log_thread,
 			cleanup_thread,
+			stats_thread,
+			stats_refresh_thread: None,
 			join_on_shutdown: start_threads,
 		})
 	}
 
 	pub fn get(&self, col: ColId, key: &[u8]) -> Result<Option<Value>> {
-		self.inner.get(col, key)
+		if self.inner.options.columns[col as usize].versioning {
+			return Ok(self.inner.get_versioned(col, key)?.map(|(_, value)| value))
+		}
+		self.inner.get_unexpired(col, key)
+	}
+
+	/// Write the value of `key` to `writer` instead of returning it. In hash columns without
+	/// compression the value is written part by part as it is read from disk, so that large
+	/// values are never held in memory as a whole. Other columns read the value first. Returns
+	/// the number of bytes written, or `None` if there is no value. With
+	/// `ColumnOptions::checksums`, a mismatch is only detected after the value is written.
+	pub fn read_value_into(
+		&self,
+		col: ColId,
+		key: &[u8],
+		writer: &mut impl std::io::Write,
+	) -> Result<Option<usize>> {
+		let column = self
+			.inner
+			.columns
+			.get(col as usize)
+			.ok_or_else(|| Error::InvalidInput(format!("Invalid column {}", col)))?;
+		let options = &self.inner.options.columns[col as usize];
+		match column {
+			Column::Hash(column) if !options.versioning && options.ttl.is_none() =>
+				self.inner.write_hashed(col, column, &column.hash_key(key), writer),
+			_ => match self.get(col, key)? {
+				Some(value) => {
+					writer.write_all(&value).map_err(Error::Io)?;
+					Ok(Some(value.len()))
+				},
+				None => Ok(None),
+			},
+		}
 	}
 
 	pub fn get_size(&self, col: ColId, key: &[u8]) -> Result<Option<u32>> {
-		self.inner.get_size(col, key)
+		if self.inner.options.columns[col as usize].ttl.is_some() {
+			return Ok(self.get(col, key)?.map(|value| value.len() as u32))
+		}
+		let size = self.inner.get_size(col, key)?;
+		if self.inner.options.columns[col as usize].versioning {
+			return Ok(size.map(|s| s.saturating_sub(VERSION_SIZE as u32)))
+		}
+		Ok(size)
+	}
+
+	/// Check if `key` has a value, without reading the value from a hash column. Values of
+	/// columns with `ColumnOptions::ttl` are read to check their write time.
+	pub fn has_key(&self, col: ColId, key: &[u8]) -> Result<bool> {
+		if self.inner.options.columns[col as usize].ttl.is_some() {
+			return Ok(self.get(col, key)?.is_some())
+		}
+		self.inner.has_key(col, key)
+	}
+
+	/// Check which of a batch of keys have a value, in the order of `keys`. In hash columns,
+	/// keys rejected by the Bloom filter are not looked up in the index, and index locks are
+	/// acquired once for the rest.
+	pub fn has_keys(&self, col: ColId, keys: &[&[u8]]) -> Result<Vec<bool>> {
+		if self.inner.options.columns.get(col as usize).is_some_and(|c| c.ttl.is_some()) {
+			let values = self.get_multi(col, keys)?;
+			return Ok(values.iter().map(Option::is_some).collect())
+		}
+		self.inner.has_keys(col, keys)
+	}
+
+	/// Start a read transaction. All reads in the transaction see the database as it was when
+	/// the transaction was started. Values of keys modified by later commits are kept in memory
+	/// until the transaction is dropped.
+	pub fn read_transaction(&self) -> ReadTx<'_> {
+		ReadTx { db: self, snapshot: self.inner.read_snapshot() }
+	}
+
+	/// Get the value of `key` in a versioned column together with its version.
+	pub fn get_versioned(&self, col: ColId, key: &[u8]) -> Result<Option<(u64, Value)>> {
+		if !self.inner.options.columns[col as usize].versioning {
+			return Err(Error::InvalidConfiguration(format!("Column {} is not versioned", col)))
+		}
+		self.inner.get_versioned(col, key)
+	}
+
+	/// Set or remove the value of `key` in a versioned column, if its current version is
+	/// `expected_version`. A missing key has version 0. Each write increments the version and
+	/// removing the value resets it to 0. Returns the new version on success, or the actual
+	/// version if it does not match.
+	pub fn put_if_version(
+		&self,
+		col: ColId,
+		key: &[u8],
+		expected_version: u64,
+		new_value: Option<Value>,
+	) -> Result<PutIfVersionOutcome> {
+		self.inner.put_if_version(col, key, expected_version, new_value)
+	}
+
+	/// Set or remove the value of `key` if its current value is `expected`, where `None` stands
+	/// for a missing key. Returns `true` if the change was committed and `false` if the value
+	/// differs. The comparison includes commits that are still queued, so two callers that
+	/// expect the same value can't both succeed. Not supported for versioned columns, see
+	/// `put_if_version`, and for reference counted columns.
+	pub fn compare_and_swap(
+		&self,
+		col: ColId,
+		key: &[u8],
+		expected: Option<&[u8]>,
+		new_value: Option<Value>,
+	) -> Result<bool> {
+		self.inner.compare_and_swap(col, key, expected, new_value)
+	}
+
+	/// Get the values of a batch of keys in a column. Index locks are acquired once for the
+	/// whole batch, which is faster than calling `get` for each key. Results are in the order of
+	/// `keys`.
+	pub fn get_multi(&self, col: ColId, keys: &[&[u8]]) -> Result<Vec<Option<Value>>> {
+		if self.inner.options.columns[col as usize].versioning {
+			return keys.iter().map(|key| self.get(col, key)).collect()
+		}
+		let values = self.inner.get_multi(col, keys)?;
+		values.into_iter().map(|value| self.inner.strip_ttl(col, value)).collect()
+	}
+
+	/// Get the value of `key` in each of the given columns. Column locks are acquired once,
+	/// so all values are read from the same database state. Results are in the order of `cols`.
+	pub fn get_many_cross_column(&self, key: &[u8], cols: &[ColId]) -> Result<Vec<Option<Value>>> {
+		let values = self.inner.get_many_cross_column(key, cols)?;
+		cols.iter()
+			.zip(values)
+			.map(|(col, value)| self.inner.strip_value_prefix(*col, value))
+			.collect()
+	}
+
+	/// Get the value of `key` in `col`, or in `fallback` if `col` does not contain the key.
+	pub fn get_with_fallback(
+		&self,
+		col: ColId,
+		fallback: ColId,
+		key: &[u8],
+	) -> Result<Option<Value>> {
+		let options = &self.inner.options.columns;
+		let prefixed =
+			|col: ColId| options[col as usize].ttl.is_some() || options[col as usize].versioning;
+		if prefixed(col) || prefixed(fallback) {
+			// The value has to be stripped according to the column it is found in. An expired
+			// value in `col` does not hide the value in `fallback`.
+			return match self.get(col, key)? {
+				Some(value) => Ok(Some(value)),
+				None => self.get(fallback, key),
+			}
+		}
+		self.inner.get_with_fallback(col, fallback, key)
 	}
 
 	pub fn iter(&self, col: ColId) -> Result<BTreeIterator> {
 		self.inner.btree_iter(col)
 	}
 
+	/// Get the entry with the smallest key in an ordered column.
+	pub fn get_first(&self, col: ColId) -> Result<Option<(Vec<u8>, Value)>> {
+		self.inner.get_first(col)
+	}
+
+	/// Get the entry with the largest key in an ordered column.
+	pub fn get_last(&self, col: ColId) -> Result<Option<(Vec<u8>, Value)>> {
+		self.inner.get_last(col)
+	}
+
 	pub fn commit<I, K>(&self, tx: I) -> Result<()>
 	where
 		I: IntoIterator<Item = (ColId, K, Option<Value>)>,
@@ -938,10 +2832,545 @@ impl Db {
 		self.inner.commit_raw(commit)
 	}
 
+	/// Set or remove the values of a batch of keys in a single column. `duplicates` decides
+	/// whether a key that is set more than once fails the whole batch or takes its last value.
+	/// Removals are never merged, so removing a key twice drops two references in reference
+	/// counted columns.
+	/// Like `commit`, the batch is written as one log record, so its changes become visible
+	/// together and are either all replayed or all discarded after a crash. Hash column changes
+	/// are planned under a single index lock acquisition.
+	pub fn write_batch<K: AsRef<[u8]>>(
+		&self,
+		col: ColId,
+		batch: &[(K, Option<Value>)],
+		duplicates: DuplicateKeys,
+	) -> Result<()> {
+		self.inner.write_batch(col, batch, duplicates)
+	}
+
+	/// Commit a set of changes to a single column, tagged with the sequence number `seq`.
+	/// The sequence number is stored in the log record header, and the highest one is kept in a
+	/// `sequence` file once the log is removed. Fails with `Error::StaleSequence` if `seq` is
+	/// lower than the last sequence number passed to this function, including before a restart.
+	/// Used for replaying writes in a given order, e.g. for replication.
+	pub fn write_with_sequence(
+		&self,
+		col: ColId,
+		entries: &[(Key, Option<Value>)],
+		seq: u64,
+	) -> Result<()> {
+		self.inner.write_with_sequence(col, entries, seq)
+	}
+
+	/// Changes made to the column `col` by `Db::write_with_sequence` with sequence numbers from
+	/// `from` to `to` inclusive, in the order they were committed. Only changes made while `col`
+	/// was in `Options::changelog` are returned. Keys are hashed, as the original key is not
+	/// kept.
+	pub fn get_changelog(&self, col: ColId, from: u64, to: u64) -> Result<Vec<ChangeEntry>> {
+		self.inner.get_changelog(col, from, to)
+	}
+
+	/// Get value from the database, or compute and insert it if the key is missing.
+	///
+	/// `compute_fn` is called outside of any lock, so it may be called by several threads for the
+	/// same key. Only the first computed value is inserted, and all callers get that value back.
+	pub fn get_or_compute(
+		&self,
+		col: ColId,
+		key: &[u8],
+		compute_fn: impl FnOnce() -> Value,
+	) -> Result<Value> {
+		self.inner.get_or_compute(col, key, compute_fn)
+	}
+
 	pub fn num_columns(&self) -> u8 {
 		self.inner.columns.len() as u8
 	}
 
+	/// Compact the index of a hash column, so that all keys are found with minimal probing.
+	/// Only needed after many insert-remove cycles. Changes are written as a single log record.
+	/// Fails while the column is being reindexed. Iterators over the column return
+	/// `Error::ConcurrentModification` afterwards, and cursors taken before may skip or repeat
+	/// entries when resumed.
+	pub fn defrag_index(&self, col: ColId) -> Result<DefragIndexStats> {
+		self.inner.defrag_index(col)
+	}
+
+	/// Double the index capacity of a hash column in place. Unlike the automatic reindex, which
+	/// moves entries to a new index file in the background, this rewrites the index within the
+	/// existing file in a single log record. Meant for small databases, where the record stays
+	/// small. Fails if the column is being reindexed or if index changes are still pending in the
+	/// log, e.g. right after a commit.
+	pub fn resize_index_in_place(&self, col: ColId) -> Result<()> {
+		self.inner.resize_index_in_place(col)
+	}
+
+	/// Number of index tables of a column that are waiting to be reindexed. Lookups check each
+	/// of them, so writers may want to back off while this grows, see
+	/// `Options::max_reindex_queue_depth`. Always zero for tree columns.
+	pub fn reindex_queue_depth(&self, col: ColId) -> Result<usize> {
+		let column = self
+			.inner
+			.columns
+			.get(col as usize)
+			.ok_or_else(|| Error::InvalidInput(format!("Invalid column {}", col)))?;
+		Ok(column.reindex_queue_len())
+	}
+
+	/// Grow the index of a hash column ahead of a bulk load of about `expected_entries` keys.
+	/// Without this, the index is doubled and all entries are moved each time it fills up.
+	/// Existing entries are moved to the larger index in the background. Does nothing if the
+	/// index is already large enough, it is never shrunk.
+	pub fn reserve(&self, col: ColId, expected_entries: u64) -> Result<()> {
+		self.inner.reserve(col, expected_entries)
+	}
+
+	/// Drop index tables waiting to be reindexed that have no entries left to migrate. Tables are
+	/// checked in reindex order, up to the first one that still has entries. Returns the number
+	/// of dropped tables. The tables are removed when the change is written to the database files.
+	pub fn trim_rebalance_queue(&self, col: ColId) -> Result<usize> {
+		self.inner.trim_rebalance_queue(col)
+	}
+
+	/// Free value table entries of a hash column that are not referenced by its index. Such
+	/// entries are left behind if the database is not shut down cleanly. Every value table entry
+	/// is read, so this may take a while on large columns.
+	pub fn collect_garbage(&self, col: ColId) -> Result<GarbageStats> {
+		self.inner.collect_garbage(col)
+	}
+
+	/// Copy each log file to `archive_dir` once it is complete, before it is written to the
+	/// database files and reused. Archived files are named after the first record they contain,
+	/// e.g. `wal_00000000000000000042`. The log file currently being written is archived too.
+	/// A file that can't be copied is logged and skipped, the database keeps running.
+	pub fn enable_wal_archiving(&self, archive_dir: std::path::PathBuf) -> Result<()> {
+		self.inner.log.set_archive_dir(Some(archive_dir))
+	}
+
+	/// Stop archiving log files, including the one currently being written.
+	pub fn disable_wal_archiving(&self) -> Result<()> {
+		self.inner.log.set_archive_dir(None)
+	}
+
+	/// Load the first `chunks` index chunks of a hash column, or the whole index if `None`, into
+	/// the OS page cache, to speed up the first reads after a cold start. On Linux this only
+	/// schedules the reads and returns immediately.
+	pub fn warm_cache(&self, col: ColId, chunks: Option<u64>) -> Result<()> {
+		self.inner.columns[col as usize].warm_cache(chunks)
+	}
+
+	/// Up to `n` most frequently read keys of a hash column since it was opened or since the last
+	/// `reset_hot_key_stats`, most read first. Requires `Options::track_hot_keys`. Keys are
+	/// returned hashed. Read counts are estimates that may be higher than the actual count. At
+	/// most 1024 keys are tracked.
+	pub fn get_hot_keys(&self, col: ColId, n: usize) -> Result<Vec<(Key, u64)>> {
+		self.inner.columns[col as usize].hot_keys(n)
+	}
+
+	/// Clear read counts collected for `get_hot_keys`.
+	pub fn reset_hot_key_stats(&self, col: ColId) -> Result<()> {
+		self.inner.columns[col as usize].reset_hot_keys()
+	}
+
+	/// Value cache statistics of a hash column, or `None` if no cache is configured in
+	/// `Options::value_cache_capacity`.
+	pub fn get_value_cache_stats(&self, col: ColId) -> Result<Option<crate::ValueCacheStats>> {
+		self.inner.columns[col as usize].value_cache_stats()
+	}
+
+	/// Bloom filter statistics of a hash column, or `None` if no filter is configured in
+	/// `Options::bloom_false_positive_rate`. Query counters start at zero when the database is
+	/// opened.
+	#[cfg(feature = "bloom")]
+	pub fn get_bloom_stats(&self, col: ColId) -> Result<Option<crate::BloomStats>> {
+		self.inner.columns[col as usize].bloom_stats()
+	}
+
+	/// Rebuild the Bloom filter of a hash column from the live index entries, sized for their
+	/// current number. Removed keys are never dropped from saturated counters, so this restores
+	/// the false positive rate after many removals. Fails if no filter is configured in
+	/// `Options::bloom_false_positive_rate`.
+	#[cfg(feature = "bloom")]
+	pub fn rebuild_bloom(&self, col: ColId) -> Result<()> {
+		self.inner.rebuild_bloom(col)
+	}
+
+	/// Read and write counters of all columns since the database was opened. Unlike `stats`,
+	/// these are always collected.
+	pub fn counters(&self) -> &DatabaseStats {
+		&self.inner.counters
+	}
+
+	/// Reject further commits to a column with `Error::ColumnReadOnly` while `read_only` is set.
+	/// Commits queued before the call are still written. This is not persisted, columns are
+	/// writable after the database is reopened.
+	pub fn set_column_read_only(&self, col: ColId, read_only: bool) -> Result<()> {
+		self.inner.set_column_read_only(col, read_only)
+	}
+
+	/// Change the number of index entries moved per log record while a hash column is
+	/// reindexed, overriding `Options::reindex_batch_size` until the database is reopened.
+	/// Smaller batches let commits through sooner, larger ones finish the reindex faster.
+	pub fn set_reindex_batch_size(&self, col: ColId, size: u32) -> Result<()> {
+		self.inner
+			.columns
+			.get(col as usize)
+			.ok_or_else(|| Error::InvalidInput(format!("Invalid column {}", col)))?
+			.set_reindex_batch_size(size)
+	}
+
+	/// Fraction of the index pages of a hash column that are currently in the OS page cache,
+	/// between 0.0 and 1.0. Only supported on Linux, `Error::NotSupported` is returned elsewhere.
+	pub fn get_page_cache_hit_ratio(&self, col: ColId) -> Result<f64> {
+		self.inner.columns[col as usize].page_cache_hit_ratio()
+	}
+
+	/// Options the column was opened with.
+	pub fn get_column_options(&self, col: ColId) -> Result<ColumnOptions> {
+		self.inner
+			.options
+			.columns
+			.get(col as usize)
+			.cloned()
+			.ok_or_else(|| Error::InvalidInput(format!("Invalid column {}", col)))
+	}
+
+	/// Paths of all index and value table files that currently exist for the column, including
+	/// index tables that are being reindexed. Value tables are only created once a value of
+	/// their size tier is written. Paths are sorted.
+	pub fn get_column_file_paths(&self, col: ColId) -> Result<Vec<std::path::PathBuf>> {
+		if col as usize >= self.inner.columns.len() {
+			return Err(Error::InvalidInput(format!("Invalid column {}", col)))
+		}
+		let mut paths = Vec::new();
+		for entry in try_io!(std::fs::read_dir(&self.inner.options.path)) {
+			let entry = try_io!(entry);
+			if let Some(file) = entry.file_name().to_str() {
+				if crate::index::TableId::is_file_name(col, file) ||
+					crate::table::TableId::is_file_name(col, file)
+				{
+					paths.push(entry.path());
+				}
+			}
+		}
+		paths.sort();
+		Ok(paths)
+	}
+
+	/// Path of the value table file that holds values of the given size tier. The file is only
+	/// created once a value of that tier is written, so it may not exist yet.
+	pub fn get_tier_file_path(&self, col: ColId, tier: usize) -> Result<std::path::PathBuf> {
+		if col as usize >= self.inner.columns.len() {
+			return Err(Error::InvalidInput(format!("Invalid column {}", col)))
+		}
+		if tier > crate::column::value_sizes(&self.inner.options.columns[col as usize]).len() {
+			return Err(Error::InvalidInput(format!("Invalid size tier {}", tier)))
+		}
+		let id = crate::table::TableId::new(col, tier as u8);
+		Ok(self.inner.options.path.join(id.file_name()))
+	}
+
+	/// Size and modification time of every file in the database directory, including index,
+	/// value table and log files as well as metadata and statistics files. Files are sorted by
+	/// path. Files that are removed while listing are skipped.
+	pub fn get_metadata_stats(&self) -> Result<DbFileStats> {
+		let mut files = Vec::new();
+		for entry in try_io!(std::fs::read_dir(&self.inner.options.path)) {
+			let entry = try_io!(entry);
+			let metadata = match entry.metadata() {
+				Ok(metadata) => metadata,
+				Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+				Err(e) => return Err(Error::Io(e)),
+			};
+			if !metadata.is_file() {
+				continue
+			}
+			files.push(FileInfo {
+				path: entry.path(),
+				size: metadata.len(),
+				last_modified: try_io!(metadata.modified()),
+			});
+		}
+		files.sort_by(|a, b| a.path.cmp(&b.path));
+		Ok(DbFileStats { files })
+	}
+
+	/// Iterate over the entries of a hash column in index order, starting at `offset`. Index
+	/// tables of an unfinished reindex are walked after the main index. The
+	/// offset is either `0` or a cursor returned by `PaginatedIterator::cursor`. Cursors point to
+	/// index slots and stay valid across restarts, so paging can be resumed later. Once the
+	/// column is modified, the iterator returns `Error::ConcurrentModification`. Paging may then
+	/// be resumed from the last cursor, but entries inserted or removed in between may or may not
+	/// be returned. Other offsets fail with `Error::InvalidInput`.
+	pub fn iter_with_offset(&self, col: ColId, offset: u64) -> Result<PaginatedIterator<'_>> {
+		self.inner.columns[col as usize].iter_with_offset(offset, &self.inner.log)
+	}
+
+	/// Subscribe to reindex progress events. An event is sent for each batch of index entries
+	/// moved to a resized index table. Only events sent after subscribing are received.
+	#[cfg(feature = "async-tokio")]
+	pub fn watch_rebalance(&self) -> tokio::sync::broadcast::Receiver<RebalanceProgress> {
+		self.inner.rebalance_events.subscribe()
+	}
+
+	/// Subscribe to corruption errors hit while applying the log. Up to 256 events are buffered
+	/// for the receiver; further events are dropped until it catches up, see
+	/// `dropped_error_events`. The first receiver also gets the events reported before it
+	/// subscribed, including those of the log replay on open.
+	pub fn subscribe_errors(&self) -> std::sync::mpsc::Receiver<DatabaseError> {
+		let (sender, receiver) = std::sync::mpsc::sync_channel(ERROR_EVENTS_CAPACITY);
+		let mut subscribers = self.inner.error_subscribers.lock();
+		// At most `ERROR_EVENTS_CAPACITY` events are kept, so they fit the channel.
+		for event in self.inner.pending_error_events.lock().take().into_iter().flatten() {
+			let _ = sender.try_send(event);
+		}
+		subscribers.push(sender);
+		receiver
+	}
+
+	/// Number of corruption events dropped because a `subscribe_errors` receiver was full.
+	pub fn dropped_error_events(&self) -> u64 {
+		self.inner.dropped_error_events.load(Ordering::Relaxed)
+	}
+
+	/// Start a set of changes spanning multiple columns that are committed atomically.
+	pub fn multi_column_transaction(&self) -> MultiColumnTx<'_> {
+		MultiColumnTx::begin(self)
+	}
+
+	/// Mark value table slots `offset_start..offset_end` of size tier `tier` as free and add them
+	/// to the free list. Slots that are already free are skipped. Returns the number of freed
+	/// slots. Fails for columns with `btree_index`.
+	///
+	/// Keys of values stored in the range are removed from the index, and parts of these values
+	/// outside of the range are freed as well. This is meant for recovery from a corrupt free
+	/// list only, and loses any data stored in the range.
+	pub fn delete_range_by_tier(
+		&self,
+		col: ColId,
+		tier: usize,
+		offset_start: u64,
+		offset_end: u64,
+	) -> Result<u64> {
+		self.inner.delete_range_by_tier(col, tier, offset_start, offset_end)
+	}
+
+	/// Remove all values of a hash column stored in size tier `tier`, together with their
+	/// index entries. Returns the number of removed values. This can't be undone.
+	pub fn purge_tier(&self, col: ColId, tier: usize) -> Result<u64> {
+		self.inner.purge_tier(col, tier)
+	}
+
+	/// Remove all values of a column with `ColumnOptions::ttl` that were written longer than the
+	/// time-to-live ago. Expired values are not returned by reads even before they are removed.
+	/// Returns the number of removed values.
+	pub fn expire(&self, col: ColId) -> Result<u64> {
+		self.inner.expire(col)
+	}
+
+	/// Release disk blocks taken by removed values of a column, so that thin provisioned storage
+	/// can reclaim them. Only applies to size tiers with entries larger than 4 KiB. File sizes do
+	/// not change. Returns the number of released bytes. This is a no-op on platforms other than
+	/// Linux, or if the file system does not support punching holes.
+	pub fn punch_holes(&self, col: ColId) -> Result<u64> {
+		self.inner.punch_holes(col)
+	}
+
+	/// Check value table free lists of a column for out of bounds pointers, pointers to live
+	/// entries and cycles, and for multipart values that share an entry. With `repair` the lists
+	/// are truncated at the first corrupt pointer. Overlapping values are only reported.
+	pub fn fsck(&self, col: ColId, repair: bool) -> Result<FsckReport> {
+		self.inner.fsck(col, repair)
+	}
+
+	/// Check the index and value tables of all hash columns against each other. Every index
+	/// entry must point to an occupied value table entry holding a key that belongs to the index
+	/// entry, no index table may reference an entry twice, and every occupied entry must be
+	/// referenced by the index. Ordered columns are not checked. Each table is read in full, so
+	/// this may take a while on large databases. Nothing is repaired.
+	pub fn check_integrity(&self) -> Result<IntegrityReport> {
+		self.inner.check_integrity()
+	}
+
+	/// Move the content of a hash column to column `dest_col` of another database, then clear
+	/// the source column. Entries are committed to `dest` in batches. Commits made to the source
+	/// column while the transfer runs are also committed to `dest`, so no change is missed.
+	/// Commits to the source column fail with `Error::ColumnReadOnly` while it is cleared.
+	///
+	/// Both columns must hash keys the same way, which requires the same salt and `uniform`
+	/// option. Values are copied as stored, so either both or neither of the columns may be
+	/// versioned or have a time-to-live. Reference counted columns are not supported.
+	pub fn transfer_column(&self, col: ColId, dest: &Db, dest_col: ColId) -> Result<TransferStats> {
+		self.inner.transfer_column(col, &dest.inner, dest_col)
+	}
+
+	/// Delete all data in a column. Changes committed before this call are discarded along
+	/// with the rest of the column content. This is not crash safe: if interrupted, the
+	/// column may be left partially deleted and should be reset again.
+	pub fn hard_reset(&self, col: ColId) -> Result<()> {
+		self.inner.hard_reset(col)
+	}
+
+	/// Move values of a hash column from the end of its value tables into free slots left by
+	/// removed values, and truncate the table files. All changes committed before this call are
+	/// written first, and new commits are held back until this is done. Moving stops at the
+	/// first value that is not referenced by the index, so running `collect_garbage` first may
+	/// release more space. Fails if the column is being reindexed. Returns the number of
+	/// released bytes.
+	pub fn defragment(&self, col: ColId) -> Result<u64> {
+		self.inner.defragment(col)
+	}
+
+	/// Copy the files of a column to directory `dest`, which must be empty or missing. All
+	/// changes committed before this call are written to the column first, and new commits are
+	/// held back until the copy is complete. A manifest with the column configuration and the
+	/// hash of each file is written along with the copies. See `restore_column`.
+	pub fn backup_column(&self, col: ColId, dest: &std::path::Path) -> Result<()> {
+		self.inner.backup_column(col, dest)
+	}
+
+	/// Estimate the effect of committing `tx` without writing anything. Reports the number of
+	/// bytes that would be written, the size tiers receiving values, whether a hash column would
+	/// need to be reindexed and whether there is enough free disk space. Values are assumed
+	/// to be stored uncompressed. Commits that are still queued are not taken into account, so
+	/// the estimate may be off for keys they modify.
+	pub fn dry_run_commit<K: AsRef<[u8]>>(
+		&self,
+		tx: &[(ColId, K, Option<Value>)],
+	) -> Result<DryRunReport> {
+		self.inner.dry_run_commit(tx)
+	}
+
+	/// Get the value table size tier a value of `value_len` bytes would be written to.
+	/// Compression is not taken into account, so a compressible value may end up in a
+	/// lower tier.
+	pub fn get_tier_for_value(&self, col: ColId, value_len: usize) -> Result<usize> {
+		self.inner.columns[col as usize].tier_for_value(value_len)
+	}
+
+	/// Size tier and value table entry index of the value stored for `key` in a hash column,
+	/// as passed to `iter_raw_slots`. The value is not read. Changes that are still in the
+	/// commit queue are not visible.
+	pub fn get_value_address(&self, col: ColId, key: &[u8]) -> Result<Option<(u8, u64)>> {
+		let column = self
+			.inner
+			.columns
+			.get(col as usize)
+			.ok_or_else(|| Error::InvalidInput(format!("Invalid column {}", col)))?;
+		let address = column.get_index_entry(key, self.inner.log.overlays())?;
+		Ok(address.map(|address| (address.size_tier(), address.offset())))
+	}
+
+	/// Number of values stored in the column. Unlike `stats`, this does not depend on
+	/// `Options::stats` and is not reset by `clear_stats`. Every value table entry is read, so
+	/// this may take a while on large columns. Changes that are still in the commit queue are
+	/// not counted. For tree columns the count includes the tree nodes.
+	pub fn entry_count(&self, col: ColId) -> Result<u64> {
+		self.inner
+			.columns
+			.get(col as usize)
+			.ok_or_else(|| Error::InvalidInput(format!("Invalid column {}", col)))?
+			.entry_count(&self.inner.log)
+	}
+
+	/// Number of values stored in each value table of the column, by size tier, see
+	/// `entry_count`.
+	pub fn entry_count_by_tier(&self, col: ColId) -> Result<Vec<u64>> {
+		self.inner
+			.columns
+			.get(col as usize)
+			.ok_or_else(|| Error::InvalidInput(format!("Invalid column {}", col)))?
+			.entry_count_by_tier(&self.inner.log)
+	}
+
+	/// Iterate over raw value table slots of the given size tier in file order, with no key
+	/// verification. Meant for forensic tools. Changes that are still in the log are ignored.
+	pub fn iter_raw_slots(
+		&self,
+		col: ColId,
+		size_tier: u8,
+		f: impl FnMut(u64, &[u8]) -> bool,
+	) -> Result<()> {
+		self.inner.columns[col as usize].raw_slots_while(size_tier, f)
+	}
+
+	/// Iterate over all entries in the index files of a hash column, including tables that are
+	/// being reindexed. `f` gets the leading key bits stored in the index, the value size tier
+	/// and the value table offset. Iteration stops when `f` returns `false`. Meant for forensic
+	/// tools. Changes that are still in the log are ignored.
+	pub fn iter_index_entries(
+		&self,
+		col: ColId,
+		mut f: impl FnMut(Key, u8, u64) -> bool,
+	) -> Result<()> {
+		self.inner.columns[col as usize]
+			.index_entries_while(|key, address| f(key, address.size_tier(), address.offset()))
+	}
+
+	/// Iterate over all entries of a hash column, smallest values first. Keys are the hashed
+	/// keys stored in the index. Iteration stops when `f` returns `false`.
+	pub fn iter_column_by_size(&self, col: ColId, f: impl FnMut(Key, Value) -> bool) -> Result<()> {
+		self.inner.iter_column_by_size(col, f)
+	}
+
+	/// Count keys in a `uniform` hash column that start with the first `prefix_bits` bits of
+	/// `prefix`.
+	///
+	/// Keys are counted in the index, so no values are read. When `prefix_bits` is not larger
+	/// than the column index bits (16 or more), the prefix covers whole index chunks and
+	/// `2^(index_bits - prefix_bits)` chunks are read. Longer prefixes read a single chunk and
+	/// compare the key bits stored in the index entries, which holds up to 50 leading bits.
+	/// The count is exact in both cases. Commits still in the commit queue are not counted.
+	pub fn count_keys_by_prefix(&self, col: ColId, prefix: &[u8], prefix_bits: u8) -> Result<u64> {
+		self.inner.count_keys_by_prefix(col, prefix, prefix_bits)
+	}
+
+	/// Iterate over the entries of a hash column with uniform keys that start with `prefix`.
+	/// Keys are returned as committed. This scans the index chunks that may hold matching keys,
+	/// which is the whole column for an empty prefix, see `Column::scan_prefix`. Commits still in
+	/// the commit queue are not returned.
+	pub fn scan_prefix(
+		&self,
+		col: ColId,
+		prefix: &[u8],
+	) -> Result<impl Iterator<Item = Result<(Key, Value)>> + '_> {
+		let entries = self.inner.columns[col as usize].scan_prefix(prefix, &self.inner.log)?;
+		Ok(entries.filter_map(move |entry| match entry {
+			Ok((key, value)) => self
+				.inner
+				.strip_value_prefix(col, Some(value))
+				.transpose()
+				.map(|value| value.map(|value| (key, value))),
+			Err(e) => Some(Err(e)),
+		}))
+	}
+
+	/// Collect `map` of all entries of a hash column for which `filter` returns `true`.
+	///
+	/// Entries are scanned under the column read lock and only matching ones are passed to
+	/// `map`. Keys are the hashed keys stored in the index. Commits that are still queued are not
+	/// visible.
+	pub fn query<R>(
+		&self,
+		col: ColId,
+		filter: impl Fn(&Key, &[u8]) -> bool,
+		map: impl Fn(Key, Vec<u8>) -> R,
+	) -> Result<Vec<R>> {
+		self.inner.query(col, filter, map)
+	}
+
+	/// Export all entries of a hash column to a Parquet file at `path`.
+	///
+	/// Each row holds the hex encoded hashed key and value, followed by the value size tier and
+	/// the offset in the value table. Rows are written in groups of 10000, in index order. Expired
+	/// values of columns with a time-to-live are skipped. The column is not locked between
+	/// entries, so it can be written during the export. Entries changed meanwhile may or may not
+	/// be exported.
+	#[cfg(feature = "parquet")]
+	pub fn export_parquet(&self, col: ColId, path: &std::path::Path) -> Result<crate::ExportStats> {
+		self.inner.export_parquet(col, path)
+	}
+
 	pub(crate) fn iter_column_while(
 		&self,
 		c: ColId,
@@ -1006,16 +3435,58 @@ impl Db {
 		Ok(())
 	}
 
-	pub fn write_stats_text(
-		&self,
-		writer: &mut impl std::io::Write,
-		column: Option<u8>,
-	) -> Result<()> {
-		self.inner.write_stats_text(writer, column)
-	}
-
-	pub fn clear_stats(&self, column: Option<u8>) -> Result<()> {
-		self.inner.clear_stats(column)
+	fn stats_worker(db: Arc<DbInner>, interval: std::time::Duration) -> Result<()> {
+		loop {
+			{
+				let mut work = db.stats_worker_wait.work.lock();
+				if !*work {
+					db.stats_worker_wait.cv.wait_for(&mut work, interval);
+				}
+				*work = false;
+			}
+			if db.shutdown.load(Ordering::SeqCst) {
+				break
+			}
+			log::info!(target: "parity-db", "Stats: {}", db.stats_summary());
+		}
+		log::debug!(target: "parity-db", "Stats worker shutdown");
+		Ok(())
+	}
+
+	fn stats_refresh_worker(db: Arc<DbInner>, interval: std::time::Duration) -> Result<()> {
+		loop {
+			for (col, column) in db.columns.iter().enumerate() {
+				if let Column::Hash(_) = column {
+					if let Err(e) = db.precompute_index_stats(col as ColId) {
+						log::warn!(target: "parity-db", "Error computing stats for column {}: {}", col, e);
+					}
+				}
+			}
+			{
+				let mut work = db.stats_refresh_wait.work.lock();
+				if !*work {
+					db.stats_refresh_wait.cv.wait_for(&mut work, interval);
+				}
+				*work = false;
+			}
+			if db.shutdown.load(Ordering::SeqCst) {
+				break
+			}
+		}
+		log::debug!(target: "parity-db", "Stats refresh worker shutdown");
+		Ok(())
+	}
+
+	pub fn write_stats_text(
+		&self,
+		writer: &mut impl std::io::Write,
+		column: Option<u8>,
+	) -> Result<()> {
+		self.inner.write_stats_text(writer, column)
+	}
+
+	pub fn clear_stats(&self, column: Option<u8>) -> Result<()> {
+		self.inner.clear_stats(column)
 	}
 
 	pub fn dump(&self, check_param: check::CheckOptions) -> Result<()> {
@@ -1033,6 +3504,72 @@ impl Db {
 		self.inner.stats()
 	}
 
+	/// Compute derived index metrics of a hash column, such as the fill rate and the probe
+	/// distance histogram. This scans the whole index. The result is available from
+	/// `cached_stats` afterwards.
+	pub fn precompute_index_stats(&self, col: ColId) -> Result<()> {
+		self.inner.precompute_index_stats(col)
+	}
+
+	/// Index metrics last computed for the column, if any. This does not do any work.
+	pub fn cached_stats(&self, col: ColId) -> Option<Arc<CachedStats>> {
+		self.inner.cached_stats(col)
+	}
+
+	/// Report space taken by removed entries in value tables of all columns. Removed entries are
+	/// reused by later inserts of the same size tier, but the files never shrink. Tables with at
+	/// least half of their space free are listed in `recommendations`. This reads every value
+	/// table entry, so it may take a while on large databases.
+	pub fn get_fragmentation_report(&self) -> Result<FragmentationReport> {
+		let mut report = FragmentationReport::default();
+		for (col, column) in self.inner.columns.iter().enumerate() {
+			column.fragmentation(col as ColId, &mut report, &self.inner.log)?;
+		}
+		Ok(report)
+	}
+
+	/// Evaluate all columns and list recommended maintenance actions, most severe first. Checks
+	/// value table fragmentation, index fill rates and reindex queues, as well as Bloom filters
+	/// with the `bloom` feature. Every index and value table is read, so this may take a while
+	/// on large databases. With the `serde` feature the report can be serialized, e.g. to JSON.
+	pub fn maintenance_report(&self) -> Result<MaintenanceReport> {
+		let mut report = MaintenanceReport::default();
+		report.check_fragmentation(&self.get_fragmentation_report()?);
+		for (col, column) in self.inner.columns.iter().enumerate() {
+			let column = if let Column::Hash(c) = column { c } else { continue };
+			let stats = column.index_stats(&self.inner.log)?;
+			report.check_index(col as ColId, &stats, column.reindex_queue_len());
+			#[cfg(feature = "bloom")]
+			if let Some(bloom) = column.bloom_stats() {
+				report.check_bloom(col as ColId, &bloom, &stats);
+			}
+		}
+		report.sort();
+		Ok(report)
+	}
+
+	/// Start a background thread that recomputes index metrics of all hash columns with the
+	/// given interval. Requires background threads, so this fails for read-only databases.
+	pub fn start_stats_refresh(&mut self, interval: std::time::Duration) -> Result<()> {
+		if !self.join_on_shutdown {
+			return Err(Error::InvalidConfiguration("Background threads are not running".into()))
+		}
+		if self.stats_refresh_thread.is_some() {
+			return Err(Error::InvalidConfiguration("Stats refresh is already running".into()))
+		}
+		let stats_refresh_db = self.inner.clone();
+		self.stats_refresh_thread = Some(thread::spawn(move || {
+			stats_refresh_db
+				.store_err(Self::stats_refresh_worker(stats_refresh_db.clone(), interval))
+		}));
+		Ok(())
+	}
+
+	/// Get a one-line human readable summary of column statistics.
+	pub fn get_stats_summary(&self) -> String {
+		self.inner.stats_summary()
+	}
+
 	/// Add a new column with options specified by `new_column_options`.
 	pub fn add_column(options: &mut Options, new_column_options: ColumnOptions) -> Result<()> {
 		// We open the DB before to check metadata validity and make sure there are no pending WAL
@@ -1041,12 +3578,48 @@ impl Db {
 		let salt = db.inner.options.salt;
 		drop(db);
 
+		// The new column is logged first, so that it is completed when the database is opened
+		// if it is interrupted.
+		let col = options.columns.len() as ColId;
+		log_column_action(options, |writer| writer.create_column(col, new_column_options.clone()))?;
 		options.columns.push(new_column_options);
 		options.write_metadata_with_version(
 			&options.path,
 			&salt.expect("`salt` is always `Some` after opening the DB; qed"),
 			Some(CURRENT_VERSION),
 		)?;
+		// Opening the database replays and removes the logged action.
+		drop(Db::open(options)?);
+
+		Ok(())
+	}
+
+	/// Remove the last column and delete its files. The database must be closed. Only the last
+	/// column can be removed, since columns are identified by their position. At least one
+	/// column is kept.
+	pub fn drop_last_column(options: &mut Options) -> Result<()> {
+		if options.columns.len() < 2 {
+			return Err(Error::InvalidInput("Can't remove the only column".into()))
+		}
+		// Opening the database enacts all logs, so that no leftover commit writes to the
+		// column after its files are deleted.
+		let db = Db::open(options)?;
+		let salt = db.inner.options.salt;
+		drop(db);
+
+		// The removal is logged first, so that the files are deleted when the database is
+		// opened if it is interrupted.
+		let col = options.columns.len() as ColId - 1;
+		log_column_action(options, |writer| writer.drop_column(col))?;
+		options.columns.pop();
+		options.write_metadata_with_version(
+			&options.path,
+			&salt.expect("`salt` is always `Some` after opening the DB; qed"),
+			Some(CURRENT_VERSION),
+		)?;
+		crate::migration::remove_column_files(&options.path, col)?;
+		// Opening the database replays and removes the logged action.
+		drop(Db::open(options)?);
 
 		Ok(())
 	}
@@ -1106,6 +3679,16 @@ impl Drop for Db {
 					log::warn!(target: "parity-db", "Cleanup thread shutdown error: {:?}", e);
 				}
 			}
+			if let Some(t) = self.stats_thread.take() {
+				if let Err(e) = t.join() {
+					log::warn!(target: "parity-db", "Stats thread shutdown error: {:?}", e);
+				}
+			}
+			if let Some(t) = self.stats_refresh_thread.take() {
+				if let Err(e) = t.join() {
+					log::warn!(target: "parity-db", "Stats refresh thread shutdown error: {:?}", e);
+				}
+			}
 			if let Err(e) = self.inner.kill_logs() {
 				log::warn!(target: "parity-db", "Shutdown error: {:?}", e);
 			}
@@ -1113,6 +3696,129 @@ impl Drop for Db {
 	}
 }
 
+/// Databases opened with `Db::open_many`, sharing background worker threads.
+pub struct DbGroup {
+	dbs: Vec<Db>,
+	waits: Arc<GroupWaits>,
+	threads: Vec<(&'static str, thread::JoinHandle<()>)>,
+}
+
+impl DbGroup {
+	fn start(dbs: Vec<Db>, waits: GroupWaits) -> DbGroup {
+		let waits = Arc::new(waits);
+		let inners: Arc<Vec<Arc<DbInner>>> =
+			Arc::new(dbs.iter().map(|db| db.inner.clone()).collect());
+		#[cfg(any(test, feature = "instrumentation"))]
+		let min_log_size =
+			|db: &Arc<DbInner>| if db.options.always_flush { 0 } else { MIN_LOG_SIZE_BYTES };
+		#[cfg(not(any(test, feature = "instrumentation")))]
+		let min_log_size = |_: &Arc<DbInner>| MIN_LOG_SIZE_BYTES;
+		let min_log_sizes: Vec<u64> = inners.iter().map(min_log_size).collect();
+		let mut group = DbGroup { dbs, waits: waits.clone(), threads: Vec::new() };
+		group.spawn("Commit", &inners, waits.commit.clone(), |db, _| {
+			let more_work = db.enact_logs(false)?;
+			if !more_work {
+				db.cleanup_worker_wait.signal();
+			}
+			Ok(more_work || db.log.has_log_files_to_read())
+		});
+		group.spawn("Flush", &inners, waits.flush.clone(), move |db, i| {
+			// Same as `Db::flush_worker`, pending logs are not flushed on shutdown.
+			if db.shutdown.load(Ordering::SeqCst) {
+				return Ok(false)
+			}
+			db.flush_logs(min_log_sizes[i])
+		});
+		group.spawn("Log", &inners, waits.log.clone(), |db, _| {
+			let more_commits = db.process_commits()?;
+			let more_reindex = db.process_reindex()?;
+			Ok(more_commits || more_reindex)
+		});
+		group.spawn("Cleanup", &inners, waits.cleanup.clone(), |db, _| db.clean_logs());
+		group
+	}
+
+	// Run `work` for each database that has not failed, until it reports no more work for any
+	// of them, then wait for a signal. The first round runs right away, which starts pending
+	// reindexing and log cleanup like the workers of a single database do. Exits once all databases
+	// are shut down and have no work left.
+	fn spawn(
+		&mut self,
+		name: &'static str,
+		dbs: &Arc<Vec<Arc<DbInner>>>,
+		wait: Arc<WaitCondvar<bool>>,
+		work: impl Fn(&DbInner, usize) -> Result<bool> + Send + 'static,
+	) {
+		let dbs = dbs.clone();
+		let handle = thread::spawn(move || {
+			loop {
+				let mut more_work = false;
+				for (i, db) in dbs.iter().enumerate() {
+					if db.bg_err.lock().is_some() {
+						continue
+					}
+					match work(db, i) {
+						Ok(more) => more_work |= more,
+						Err(e) => db.store_err(Err(e)),
+					}
+				}
+				if !more_work {
+					if dbs.iter().all(|db| db.shutdown.load(Ordering::SeqCst)) {
+						break
+					}
+					wait.wait();
+				}
+			}
+			log::debug!(target: "parity-db", "{} group worker shutdown", name);
+		});
+		self.threads.push((name, handle));
+	}
+
+	/// Number of databases in the group.
+	pub fn len(&self) -> usize {
+		self.dbs.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.dbs.is_empty()
+	}
+
+	/// Database at `db_index`, in the order passed to `Db::open_many`.
+	pub fn db(&self, db_index: usize) -> Option<&Db> {
+		self.dbs.get(db_index)
+	}
+
+	/// Get a value from the database at `db_index`, see `Db::get`.
+	pub fn get(&self, db_index: usize, col: ColId, key: &[u8]) -> Result<Option<Value>> {
+		self.db(db_index)
+			.ok_or_else(|| Error::InvalidInput(format!("Invalid database index {}", db_index)))?
+			.get(col, key)
+	}
+}
+
+impl Drop for DbGroup {
+	fn drop(&mut self) {
+		for db in &self.dbs {
+			db.inner.shutdown();
+		}
+		// Wake up workers that might have missed the shutdown of an already failed database.
+		self.waits.commit.signal();
+		self.waits.flush.signal();
+		self.waits.log.signal();
+		self.waits.cleanup.signal();
+		for (name, t) in self.threads.drain(..) {
+			if let Err(e) = t.join() {
+				log::warn!(target: "parity-db", "{} thread shutdown error: {:?}", name, e);
+			}
+		}
+		for db in &self.dbs {
+			if let Err(e) = db.inner.kill_logs() {
+				log::warn!(target: "parity-db", "Shutdown error: {:?}", e);
+			}
+		}
+	}
+}
+
 pub type IndexedCommitOverlay = HashMap<Key, (u64, Option<Value>), IdentityBuildHasher>;
 pub type BTreeCommitOverlay = BTreeMap<Vec<u8>, (u64, Option<Value>)>;
 
@@ -1198,6 +3904,42 @@ impl CommitOverlay {
 	}
 }
 
+/// Builder for a set of changes spanning multiple columns.
+///
+/// All changes are queued as a single commit, which is written as a single log record. On
+/// recovery the record is either replayed completely or discarded.
+pub struct MultiColumnTx<'a> {
+	db: &'a Db,
+	changes: Vec<(ColId, Operation<Vec<u8>, Value>)>,
+}
+
+impl<'a> MultiColumnTx<'a> {
+	pub fn begin(db: &'a Db) -> Self {
+		MultiColumnTx { db, changes: Vec::new() }
+	}
+
+	/// Insert or update the value for `key` in column `col`.
+	pub fn put(&mut self, col: ColId, key: impl AsRef<[u8]>, value: Value) -> &mut Self {
+		self.changes.push((col, Operation::Set(key.as_ref().to_vec(), value)));
+		self
+	}
+
+	/// Remove `key` from column `col`. For reference counted columns this decrements the
+	/// reference count.
+	pub fn delete(&mut self, col: ColId, key: impl AsRef<[u8]>) -> &mut Self {
+		self.changes.push((col, Operation::Dereference(key.as_ref().to_vec())));
+		self
+	}
+
+	/// Queue all changes as a single commit.
+	pub fn commit(self) -> Result<()> {
+		if let Some((col, _)) = self.changes.iter().find(|(col, _)| *col >= self.db.num_columns()) {
+			return Err(Error::InvalidInput(format!("Invalid column {}", col)))
+		}
+		self.db.commit_changes(self.changes)
+	}
+}
+
 /// Different operations allowed for a commit.
 /// Behavior may differs depending on column configuration.
 #[derive(Debug, PartialEq, Eq)]
@@ -1251,10 +3993,121 @@ impl<K: AsRef<[u8]>, Value> Operation<K, Value> {
 	}
 }
 
+// Column and key. Keys of hash columns are hashed.
+type SnapshotKey = (ColId, Vec<u8>);
+
+// Values of keys committed after a read transaction was started, as of the start.
+#[derive(Debug, Default)]
+struct ReadSnapshot {
+	values: Mutex<HashMap<SnapshotKey, Option<Value>>>,
+}
+
+/// Consistent read-only view of the database, see `Db::read_transaction`.
+pub struct ReadTx<'a> {
+	db: &'a Db,
+	snapshot: Arc<ReadSnapshot>,
+}
+
+impl ReadTx<'_> {
+	pub fn get(&self, col: ColId, key: &[u8]) -> Result<Option<Value>> {
+		let inner = &self.db.inner;
+		let column = inner
+			.columns
+			.get(col as usize)
+			.ok_or_else(|| Error::InvalidInput(format!("Invalid column {}", col)))?;
+		let index_key = match column {
+			Column::Hash(column) => column.hash_key(key).to_vec(),
+			Column::Tree(_) => key.to_vec(),
+		};
+		// Hold the lock while reading, so that a concurrent commit can't be partially seen.
+		let values = self.snapshot.values.lock();
+		let value = match values.get(&(col, index_key)) {
+			Some(value) => value.clone(),
+			None => inner.get(col, key)?,
+		};
+		inner.strip_value_prefix(col, value)
+	}
+}
+
+/// Estimated effect of a commit, see `Db::dry_run_commit`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DryRunReport {
+	/// Number of value table and index bytes the commit would write.
+	pub estimated_bytes: u64,
+	/// Columns and size tiers that would receive values, sorted.
+	pub tiers: Vec<(ColId, usize)>,
+	/// An index chunk would overflow, so the column would be reindexed.
+	pub reindex_needed: bool,
+	/// Free space available to the database directory, in bytes.
+	pub available_space: u64,
+	/// The free space is enough to write the commit both to the log and to the tables.
+	pub enough_space: bool,
+}
+
+/// Statistics of `Db::transfer_column`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TransferStats {
+	/// Number of entries copied from the source column.
+	pub entries: u64,
+	/// Size of the copied values in bytes.
+	pub bytes: u64,
+	/// Number of changes committed to the source column during the transfer and forwarded to
+	/// the destination.
+	pub mirrored: u64,
+}
+
+/// How `Db::write_batch` handles keys that are set more than once in a batch.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateKeys {
+	/// The last change of the key is applied.
+	#[default]
+	LastWins,
+	/// The batch fails with `Error::InvalidInput`.
+	Reject,
+}
+
+/// Result of `Db::put_if_version`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PutIfVersionOutcome {
+	/// The change was committed. Contains the new version.
+	Success(u64),
+	/// The key has a different version. Contains the actual version.
+	Conflict(u64),
+}
+
 #[derive(Debug, Default)]
 pub struct CommitChangeSet {
 	pub indexed: HashMap<ColId, IndexedChangeSet>,
 	pub btree_indexed: HashMap<ColId, BTreeChangeSet>,
+	pub sequence: Option<u64>,
+}
+
+// Sequence number stored by `DbInner::save_sequence`, or `0`.
+fn load_sequence(path: &std::path::Path) -> Result<u64> {
+	let path = path.join(SEQUENCE_FILE);
+	if !path.exists() {
+		return Ok(0)
+	}
+	let content = try_io!(std::fs::read_to_string(path));
+	content
+		.trim()
+		.parse()
+		.map_err(|_| Error::Corruption("Bad sequence file".into()))
+}
+
+fn count_writes<K, V: AsRef<[u8]>>(counters: &ColumnCounters, changes: &[Operation<K, V>]) {
+	for change in changes {
+		match change {
+			Operation::Set(_, value) => {
+				counters.writes.fetch_add(1, Ordering::Relaxed);
+				counters.bytes_written.fetch_add(value.as_ref().len() as u64, Ordering::Relaxed);
+			},
+			Operation::Dereference(_) => {
+				counters.deletes.fetch_add(1, Ordering::Relaxed);
+			},
+			Operation::Reference(_) => (),
+		}
+	}
 }
 
 #[derive(Debug)]
@@ -1279,7 +4132,14 @@ impl IndexedChangeSet {
 			hash_key(key, &salt, options.columns[self.col as usize].uniform, db_version)
 		};
 
+		let ttl = options.columns[self.col as usize].ttl.is_some();
 		self.push_change_hashed(match change {
+			Operation::Set(k, v) if ttl => {
+				let mut stored = Vec::with_capacity(TTL_TIMESTAMP_SIZE + v.len());
+				stored.extend_from_slice(&unix_millis().to_le_bytes());
+				stored.extend_from_slice(&v);
+				Operation::Set(hash_key(k.as_ref()), stored)
+			},
 			Operation::Set(k, v) => Operation::Set(hash_key(k.as_ref()), v),
 			Operation::Dereference(k) => Operation::Dereference(hash_key(k.as_ref())),
 			Operation::Reference(k) => Operation::Reference(hash_key(k.as_ref())),
@@ -1298,6 +4158,9 @@ impl IndexedChangeSet {
 		options: &Options,
 	) -> Result<()> {
 		let ref_counted = options.columns[self.col as usize].ref_counted;
+		for change in self.changes.iter() {
+			check_key(change.key())?;
+		}
 		for change in self.changes.iter() {
 			match &change {
 				Operation::Set(k, v) => {
@@ -1323,28 +4186,12 @@ impl IndexedChangeSet {
 		Ok(())
 	}
 
-	fn write_plan(
-		&self,
-		column: &Column,
-		writer: &mut crate::log::LogWriter,
-		ops: &mut u64,
-		reindex: &mut bool,
-	) -> Result<()> {
-		let column = match column {
-			Column::Hash(column) => column,
-			Column::Tree(_) => {
-				log::warn!(target: "parity-db", "Skipping unindex commit in indexed column");
-				return Ok(())
-			},
-		};
-		for change in self.changes.iter() {
-			if let PlanOutcome::NeedReindex = column.write_plan(change, writer)? {
-				// Reindex has triggered another reindex.
-				*reindex = true;
-			}
-			*ops += 1;
+	fn write_plan(&self, column: &Column, writer: &mut MultiColumnWriter) -> Result<()> {
+		if let Column::Tree(_) = column {
+			log::warn!(target: "parity-db", "Skipping unindex commit in indexed column");
+			return Ok(())
 		}
-		Ok(())
+		writer.write(self.col, &self.changes)
 	}
 
 	fn clean_overlay(&self, overlay: &mut CommitOverlay, record_id: u64) {
@@ -1399,6 +4246,20 @@ pub mod check {
 	}
 }
 
+/// Write a log record with column actions to a closed database.
+pub(crate) fn log_column_action(
+	options: &Options,
+	action: impl FnOnce(&mut crate::log::LogWriter),
+) -> Result<()> {
+	let log = Log::open(options)?;
+	let mut writer = log.begin_record();
+	action(&mut writer);
+	let l = writer.drain();
+	log.end_record(l)?;
+	log.flush_one(0)?;
+	Ok(())
+}
+
 #[derive(Eq, PartialEq, Clone, Copy)]
 enum OpeningMode {
 	Create,
@@ -1410,10 +4271,12 @@ enum OpeningMode {
 mod tests {
 	use crate::{ColumnOptions, Value};
 
-	use super::{Db, Options};
+	use super::{check, Db, DuplicateKeys, MultiColumnTx, Options, PutIfVersionOutcome};
 	use crate::{
-		column::ColId,
+		column::{ColId, Column},
 		db::{DbInner, OpeningMode},
+		log::{Log, LogAction},
+		table::{TableId as ValueTableId, SIZE_TIERS},
 	};
 	use rand::Rng;
 	use std::{
@@ -1448,6 +4311,21 @@ mod tests {
 				salt: None,
 				columns: (0..num_columns).map(|_| Default::default()).collect(),
 				compression_threshold: HashMap::new(),
+				lock_timeout: None,
+				stats_log_interval: None,
+				audit_log: None,
+				changelog: HashSet::new(),
+				track_hot_keys: false,
+				#[cfg(feature = "bloom")]
+				bloom_false_positive_rate: HashMap::new(),
+				value_cache_capacity: HashMap::new(),
+				write_buffer_capacity: HashMap::new(),
+				verbose_trace: HashSet::new(),
+				reindex_batch_size: HashMap::new(),
+				tail_map_window: HashMap::new(),
+				max_reindex_queue_depth: HashMap::new(),
+				parallel_reindex: HashSet::new(),
+				max_log_segment_bytes: crate::options::DEFAULT_MAX_LOG_SEGMENT_BYTES,
 				with_background_thread: *self == Self::Standard,
 				always_flush: *self == Self::DbFile,
 			}
@@ -1550,6 +4428,46 @@ mod tests {
 		assert!(Db::open(&options).is_ok(), "Existing database should be reopened");
 	}
 
+	#[test]
+	fn test_db_open_locked() {
+		let tmp = tempdir().unwrap();
+		let mut options = Options::with_columns(tmp.path(), 1);
+		let db = Db::open_or_create(&options).unwrap();
+		assert!(matches!(Db::open_locked(&options), Err(crate::Error::DatabaseLocked)));
+		assert!(matches!(Db::open(&options), Err(crate::Error::Locked(_))));
+		options.lock_timeout = Some(std::time::Duration::from_millis(50));
+		assert!(matches!(Db::open(&options), Err(crate::Error::DatabaseLocked)));
+
+		options.lock_timeout = Some(std::time::Duration::from_secs(60));
+		let release = std::thread::spawn(move || {
+			std::thread::sleep(std::time::Duration::from_millis(50));
+			drop(db);
+		});
+		assert!(Db::open(&options).is_ok(), "Open should wait for the lock to be released");
+		release.join().unwrap();
+	}
+
+	#[test]
+	fn test_stats_summary() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		assert_eq!(
+			db.get_stats_summary(),
+			"[col0: 0 keys 0% full | col1: ordered | reindexing: none]"
+		);
+
+		db.commit((0u32..1500).map(|i| (0, i.to_le_bytes(), Some(vec![1; 40]))))
+			.unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(
+			db.get_stats_summary(),
+			"[col0: 1.5K keys 0% full | col1: ordered | reindexing: none]"
+		);
+	}
+
 	#[test]
 	fn test_indexed_keyvalues() {
 		test_indexed_keyvalues_inner(EnableCommitPipelineStages::CommitOverlay);
@@ -1645,65 +4563,460 @@ mod tests {
 	}
 
 	#[test]
-	fn test_add_column() {
+	fn test_write_with_sequence() {
 		let tmp = tempdir().unwrap();
-		let db_test = EnableCommitPipelineStages::DbFile;
-		let mut options = db_test.options(tmp.path(), 1);
-		options.salt = Some(options.salt.unwrap_or_default());
-
-		let old_col_id = 0;
-		let new_col_id = 1;
-		let new_col_indexed_id = 2;
+		let db_test = EnableCommitPipelineStages::LogOverlay;
+		let options = db_test.options(tmp.path(), 1);
+		let col_nb = 0;
 
-		let key1 = b"key1".to_vec();
-		let key2 = b"key2".to_vec();
-		let key3 = b"key3".to_vec();
+		let key1 = [1u8; 32];
+		let key2 = [2u8; 32];
 
 		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
-
-		db.commit(vec![
-			(old_col_id, key1.clone(), Some(b"value1".to_vec())),
-			(old_col_id, key2.clone(), Some(b"value2".to_vec())),
-			(old_col_id, key3.clone(), Some(b"value3".to_vec())),
-		])
-		.unwrap();
+		db.write_with_sequence(col_nb, &[(key1, Some(b"value1".to_vec()))], 5).unwrap();
+		assert!(matches!(
+			db.write_with_sequence(col_nb, &[(key1, None)], 4),
+			Err(crate::Error::StaleSequence)
+		));
+		db.write_with_sequence(col_nb, &[(key2, Some(b"value2".to_vec()))], 5).unwrap();
 		db_test.run_stages(&db);
-
+		db.inner.log.flush_one(0).unwrap();
+		assert_eq!(db.get(col_nb, &key1).unwrap(), Some(b"value1".to_vec()));
+		assert_eq!(db.get(col_nb, &key2).unwrap(), Some(b"value2".to_vec()));
 		drop(db);
 
-		Db::add_column(&mut options, ColumnOptions { btree_index: false, ..Default::default() })
-			.unwrap();
-
-		Db::add_column(&mut options, ColumnOptions { btree_index: true, ..Default::default() })
-			.unwrap();
+		// Sequence number is recovered when replaying the log.
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert_eq!(db.get(col_nb, &key1).unwrap(), Some(b"value1".to_vec()));
+		assert!(matches!(
+			db.write_with_sequence(col_nb, &[(key1, None)], 4),
+			Err(crate::Error::StaleSequence)
+		));
+		db.write_with_sequence(col_nb, &[(key1, None)], 6).unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(db.get(col_nb, &key1).unwrap(), None);
 
-		let mut options = db_test.options(tmp.path(), 3);
-		options.columns[new_col_indexed_id as usize].btree_index = true;
+		// Sequence number is kept after the logs are enacted and removed.
+		EnableCommitPipelineStages::DbFile.run_stages(&db);
+		db.inner.clean_all_logs().unwrap();
+		drop(db);
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert!(!db.inner.log.has_log_files_to_read());
+		assert!(matches!(
+			db.write_with_sequence(col_nb, &[(key1, None)], 5),
+			Err(crate::Error::StaleSequence)
+		));
+		db.write_with_sequence(col_nb, &[(key1, None)], 6).unwrap();
+	}
 
+	#[test]
+	fn test_get_changelog() {
+		let tmp = tempdir().unwrap();
 		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.changelog.insert(0);
+		options.write_buffer_capacity.insert(0, 10);
+		let key1 = [1u8; 32];
+		let key2 = [2u8; 32];
+
 		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.write_with_sequence(0, &[(key1, Some(b"a".to_vec()))], 1).unwrap();
+		db.commit(vec![(0, key2, Some(b"b".to_vec()))]).unwrap();
+		db.write_with_sequence(1, &[(key1, Some(b"x".to_vec()))], 1).unwrap();
+		db_test.run_stages(&db);
+		// Previous values are taken from earlier commits of the same record.
+		db.write_with_sequence(0, &[(key1, Some(b"c".to_vec()))], 2).unwrap();
+		db.write_with_sequence(0, &[(key2, Some(b"d".to_vec()))], 2).unwrap();
+		db.write_with_sequence(0, &[(key1, None)], 3).unwrap();
+		assert!(db.inner.process_commits().unwrap());
+		assert_eq!(db.inner.commit_queue.lock().commits.len(), 0);
+		drop(db);
 
-		// Expected number of columns
-		assert_eq!(db.num_columns(), 3);
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		let (hashed1, hashed2) = match &db.inner.columns[0] {
+			Column::Hash(column) => (column.hash_key(&key1), column.hash_key(&key2)),
+			Column::Tree(_) => unreachable!(),
+		};
+		let entry = |seq, key, old: Option<&[u8]>, new: Option<&[u8]>| crate::ChangeEntry {
+			seq,
+			key,
+			old_value: old.map(|v| v.to_vec()),
+			new_value: new.map(|v| v.to_vec()),
+		};
+		assert_eq!(
+			db.get_changelog(0, 0, 10).unwrap(),
+			vec![
+				entry(1, hashed1, None, Some(b"a")),
+				entry(2, hashed1, Some(b"a"), Some(b"c")),
+				entry(2, hashed2, Some(b"b"), Some(b"d")),
+				entry(3, hashed1, Some(b"c"), None),
+			]
+		);
+		assert_eq!(db.get_changelog(0, 2, 2).unwrap().len(), 2);
+		assert!(db.get_changelog(0, 4, 10).unwrap().is_empty());
+		assert!(matches!(db.get_changelog(1, 0, 10), Err(crate::Error::InvalidConfiguration(_))));
+		assert!(matches!(db.get_changelog(2, 0, 10), Err(crate::Error::InvalidInput(_))));
+	}
 
-		let new_key1 = b"abcdef".to_vec();
-		let new_key2 = b"123456".to_vec();
+	#[test]
+	fn test_zero_key_rejected() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::CommitOverlay;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.columns[0].uniform = true;
+		options.salt = Some(Default::default());
+		let col_nb = 0;
 
-		// Write to new columns.
-		db.commit(vec![
-			(new_col_id, new_key1.clone(), Some(new_key1.to_vec())),
-			(new_col_id, new_key2.clone(), Some(new_key2.to_vec())),
-			(new_col_indexed_id, new_key1.clone(), Some(new_key1.to_vec())),
-			(new_col_indexed_id, new_key2.clone(), Some(new_key2.to_vec())),
-		])
-		.unwrap();
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		assert!(matches!(
+			db.commit(vec![(col_nb, [0u8; 32], Some(b"value".to_vec()))]),
+			Err(crate::Error::InvalidKey(_))
+		));
+		assert_eq!(db.get(col_nb, &[0u8; 32]).unwrap(), None);
+		db.commit(vec![(col_nb, [1u8; 32], Some(b"value".to_vec()))]).unwrap();
 		db_test.run_stages(&db);
+		assert_eq!(db.get(col_nb, &[1u8; 32]).unwrap(), Some(b"value".to_vec()));
+	}
 
-		drop(db);
-
-		// Reopen DB and fetch all keys we inserted.
+	#[test]
+	fn test_log_barrier() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::LogOverlay;
+		let options = db_test.options(tmp.path(), 1);
 		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
-
+		db.commit(vec![(0, [1u8; 32], Some(b"value".to_vec()))]).unwrap();
+		db_test.run_stages(&db);
+		db.inner.log.flush_one(0).unwrap();
+
+		let mut reader = db.inner.log.read_next(false).unwrap().unwrap();
+		let mut last = None;
+		loop {
+			match reader.next().unwrap() {
+				LogAction::EndRecord => break,
+				LogAction::InsertIndex(insertion) => {
+					let col = insertion.table.col() as usize;
+					db.inner.columns[col]
+						.enact_plan(LogAction::InsertIndex(insertion), &mut reader)
+						.unwrap();
+				},
+				LogAction::InsertValue(insertion) => {
+					let col = insertion.table.col() as usize;
+					db.inner.columns[col]
+						.enact_plan(LogAction::InsertValue(insertion), &mut reader)
+						.unwrap();
+				},
+				action => last = Some(action),
+			}
+		}
+		// The barrier is written last.
+		assert!(matches!(last, Some(LogAction::Barrier(_))));
+	}
+
+	#[test]
+	fn test_delete_actions_replay() {
+		let tmp = tempdir().unwrap();
+		let live = tmp.path().join("live");
+		let replayed = tmp.path().join("replayed");
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(&live, 2);
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let large = vec![7u8; 40000];
+		db.commit((0..20u8).map(|i| (0, vec![i], Some(vec![i; 100])))).unwrap();
+		db.commit(vec![(0, vec![20], Some(large.clone())), (1, vec![0], Some(vec![0; 100]))])
+			.unwrap();
+		db_test.run_stages(&db);
+
+		db.commit((0..10u8).chain([20]).map(|i| (0, vec![i], None))).unwrap();
+		db.commit(vec![(1, vec![0], None)]).unwrap();
+		while db.inner.process_commits().unwrap() {}
+		db.inner.log.flush_one(0).unwrap();
+		// The copy has to replay the removals when it is opened.
+		std::fs::create_dir(&replayed).unwrap();
+		for entry in std::fs::read_dir(&live).unwrap() {
+			let entry = entry.unwrap();
+			std::fs::copy(entry.path(), replayed.join(entry.file_name())).unwrap();
+		}
+
+		let mut deleted_index = 0;
+		let mut deleted_values = 0;
+		while let Some(mut reader) = db.inner.log.read_next(false).unwrap() {
+			loop {
+				match reader.next().unwrap() {
+					LogAction::EndRecord => break,
+					action @ (LogAction::InsertIndex(_) |
+					LogAction::InsertValue(_) |
+					LogAction::DeleteIndex { .. } |
+					LogAction::DeleteValue { .. }) => {
+						match action {
+							LogAction::DeleteIndex { .. } => deleted_index += 1,
+							LogAction::DeleteValue { .. } => deleted_values += 1,
+							_ => (),
+						}
+						let col = action.table_col().unwrap();
+						db.inner.columns[col as usize].enact_plan(action, &mut reader).unwrap();
+					},
+					_ => (),
+				}
+			}
+			let record_id = reader.record_id();
+			let cleared = reader.drain();
+			db.inner.log.end_read(cleared, record_id);
+		}
+		assert!(deleted_index > 0);
+		// Ten single entries and at least two parts of the large value.
+		assert!(deleted_values >= 12);
+		drop(db);
+
+		for path in [&live, &replayed] {
+			options.path = path.clone();
+			let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+			for i in 0..10u8 {
+				assert_eq!(db.get(0, &[i]).unwrap(), None);
+			}
+			for i in 10..20u8 {
+				assert_eq!(db.get(0, &[i]).unwrap(), Some(vec![i; 100]));
+			}
+			assert_eq!(db.get(0, &[20]).unwrap(), None);
+			assert_eq!(db.get(1, &[0]).unwrap(), None);
+			let mut entries = 0;
+			db.iter_column_while(0, |_| {
+				entries += 1;
+				true
+			})
+			.unwrap();
+			assert_eq!(entries, 10);
+		}
+		for entry in std::fs::read_dir(&live).unwrap() {
+			let name = entry.unwrap().file_name();
+			let name = name.to_str().unwrap();
+			if name.starts_with("index_") || name.starts_with("table_") {
+				assert_eq!(
+					std::fs::read(live.join(name)).unwrap(),
+					std::fs::read(replayed.join(name)).unwrap(),
+					"{}",
+					name
+				);
+			}
+		}
+	}
+
+	#[test]
+	fn test_defrag_index() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.columns[0].uniform = true;
+		let col_nb = 0;
+
+		// Uniform keys with the same prefix all land in the first index chunk.
+		let key = |i: u8| {
+			let mut key = [0u8; 32];
+			key[2] = i;
+			key
+		};
+
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit((0..16).map(|i| (col_nb, key(i), Some(vec![i; 40])))).unwrap();
+		db_test.run_stages(&db);
+		db.commit((0..16).filter(|i| i % 2 == 0).map(|i| (col_nb, key(i), None)))
+			.unwrap();
+		db_test.run_stages(&db);
+
+		// Entries are moved within the chunk, which stops iterators.
+		let mut iter = db.iter_with_offset(col_nb, 0).unwrap();
+		assert!(iter.next().unwrap().is_some());
+		let stats = db.defrag_index(col_nb).unwrap();
+		db_test.run_stages(&db);
+		assert!(matches!(iter.next(), Err(crate::Error::ConcurrentModification)));
+
+		assert_eq!(stats.entries, 8);
+		assert_eq!(stats.avg_probe_before, 9.0);
+		assert_eq!(stats.avg_probe_after, 4.5);
+		assert_eq!(stats.max_probe_before, 16);
+		assert_eq!(stats.max_probe_after, 8);
+		for i in 0..16 {
+			let expected = if i % 2 == 0 { None } else { Some(vec![i; 40]) };
+			assert_eq!(db.get(col_nb, &key(i)).unwrap(), expected);
+		}
+
+		// Overflow the chunk. The index can't be defragmented until the reindex is done.
+		db.commit((16..73).map(|i| (col_nb, key(i), Some(vec![i; 40])))).unwrap();
+		while db.inner.process_commits().unwrap() {}
+		assert_eq!(db.inner.columns[col_nb as usize].reindex_queue_len(), 1);
+		assert!(matches!(db.defrag_index(col_nb), Err(crate::Error::InvalidInput(_))));
+		loop {
+			db.inner.log.flush_one(0).unwrap();
+			while db.inner.enact_logs(false).unwrap() {}
+			if !db.inner.process_reindex().unwrap() {
+				break
+			}
+		}
+		assert_eq!(db.inner.columns[col_nb as usize].reindex_queue_len(), 0);
+		assert_eq!(db.defrag_index(col_nb).unwrap().entries, 65);
+	}
+
+	#[test]
+	fn test_dry_run_commit() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[0].uniform = true;
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+
+		// Uniform keys with the same prefix all land in the first index chunk.
+		let key = |i: u8| {
+			let mut key = [0u8; 32];
+			key[2] = i;
+			key
+		};
+		db.commit((0..60).map(|i| (0, key(i), Some(vec![i; 40])))).unwrap();
+		db_test.run_stages(&db);
+
+		let tier = db.get_tier_for_value(0, 40).unwrap();
+		let report = db.dry_run_commit(&[(0, key(60), Some(vec![0; 40]))]).unwrap();
+		assert!(report.estimated_bytes > 40);
+		assert_eq!(report.tiers, vec![(0, tier)]);
+		assert!(!report.reindex_needed);
+		assert!(report.enough_space);
+
+		// Four entries are left in the chunk. Updates and removals don't take new entries.
+		let mut tx: Vec<_> = (60..64).map(|i| (0, key(i), Some(vec![i; 40]))).collect();
+		tx.push((0, key(0), Some(vec![0; 40])));
+		tx.push((0, key(1), None));
+		assert!(!db.dry_run_commit(&tx).unwrap().reindex_needed);
+		tx.push((0, key(64), Some(vec![0; 40])));
+		assert!(db.dry_run_commit(&tx).unwrap().reindex_needed);
+
+		let report = db
+			.dry_run_commit(&[(1, key(0), Some(vec![0; 40])), (0, key(0), Some(vec![0; 1000]))])
+			.unwrap();
+		assert_eq!(report.tiers.len(), 2);
+		assert_eq!(report.tiers[1], (1, db.get_tier_for_value(1, 40).unwrap()));
+		assert!(db.dry_run_commit(&[(2, key(0), None)]).is_err());
+
+		// Nothing is written.
+		db_test.run_stages(&db);
+		assert_eq!(db.get(0, &key(60)).unwrap(), None);
+		assert_eq!(db.get(0, &key(0)).unwrap(), Some(vec![0; 40]));
+		assert_eq!(db.get(1, &key(0)).unwrap(), None);
+	}
+
+	#[test]
+	fn test_drop_last_column() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 3);
+		options.columns[2].btree_index = true;
+		options.salt = Some(options.salt.unwrap_or_default());
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = |i: u32| i.to_le_bytes().to_vec();
+		for col in 0..3 {
+			db.commit((0..100).map(|i| (col, key(i), Some(key(i))))).unwrap();
+		}
+		db_test.run_stages(&db);
+		drop(db);
+		// Logs of column actions are only removed on shutdown by the background workers.
+		options.with_background_thread = true;
+
+		let column_files = |col: ColId| {
+			std::fs::read_dir(tmp.path())
+				.unwrap()
+				.filter(|entry| {
+					let name = entry.as_ref().unwrap().file_name();
+					let name = name.to_str().unwrap();
+					crate::index::TableId::is_file_name(col, name) ||
+						crate::table::TableId::is_file_name(col, name)
+				})
+				.count()
+		};
+		assert!(column_files(2) > 0);
+		Db::drop_last_column(&mut options).unwrap();
+		assert_eq!(options.columns.len(), 2);
+		assert_eq!(column_files(2), 0);
+		assert_eq!(Options::load_metadata(tmp.path()).unwrap().unwrap().columns.len(), 2);
+
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert_eq!(db.num_columns(), 2);
+		for col in 0..2 {
+			for i in 0..100 {
+				assert_eq!(db.get(col, &key(i)).unwrap(), Some(key(i)));
+			}
+		}
+		drop(db);
+
+		// A column added in its place is empty.
+		Db::add_column(&mut options, ColumnOptions::default()).unwrap();
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert_eq!(db.get(2, &key(0)).unwrap(), None);
+		drop(db);
+
+		Db::drop_last_column(&mut options).unwrap();
+		Db::drop_last_column(&mut options).unwrap();
+		assert!(matches!(Db::drop_last_column(&mut options), Err(crate::Error::InvalidInput(_))));
+	}
+
+	#[test]
+	fn test_add_column() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.salt = Some(options.salt.unwrap_or_default());
+
+		let old_col_id = 0;
+		let new_col_id = 1;
+		let new_col_indexed_id = 2;
+
+		let key1 = b"key1".to_vec();
+		let key2 = b"key2".to_vec();
+		let key3 = b"key3".to_vec();
+
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+
+		db.commit(vec![
+			(old_col_id, key1.clone(), Some(b"value1".to_vec())),
+			(old_col_id, key2.clone(), Some(b"value2".to_vec())),
+			(old_col_id, key3.clone(), Some(b"value3".to_vec())),
+		])
+		.unwrap();
+		db_test.run_stages(&db);
+
+		drop(db);
+
+		Db::add_column(&mut options, ColumnOptions { btree_index: false, ..Default::default() })
+			.unwrap();
+
+		Db::add_column(&mut options, ColumnOptions { btree_index: true, ..Default::default() })
+			.unwrap();
+
+		let mut options = db_test.options(tmp.path(), 3);
+		options.columns[new_col_indexed_id as usize].btree_index = true;
+
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+
+		// Expected number of columns
+		assert_eq!(db.num_columns(), 3);
+
+		let new_key1 = b"abcdef".to_vec();
+		let new_key2 = b"123456".to_vec();
+
+		// Write to new columns.
+		db.commit(vec![
+			(new_col_id, new_key1.clone(), Some(new_key1.to_vec())),
+			(new_col_id, new_key2.clone(), Some(new_key2.to_vec())),
+			(new_col_indexed_id, new_key1.clone(), Some(new_key1.to_vec())),
+			(new_col_indexed_id, new_key2.clone(), Some(new_key2.to_vec())),
+		])
+		.unwrap();
+		db_test.run_stages(&db);
+
+		drop(db);
+
+		// Reopen DB and fetch all keys we inserted.
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+
 		assert_eq!(db.get(old_col_id, key1.as_slice()).unwrap(), Some(b"value1".to_vec()));
 		assert_eq!(db.get(old_col_id, key2.as_slice()).unwrap(), Some(b"value2".to_vec()));
 		assert_eq!(db.get(old_col_id, key3.as_slice()).unwrap(), Some(b"value3".to_vec()));
@@ -2265,6 +5578,3379 @@ mod tests {
 		]);
 	}
 
+	#[test]
+	fn test_get_first_last() {
+		for db_test in [
+			EnableCommitPipelineStages::CommitOverlay,
+			EnableCommitPipelineStages::LogOverlay,
+			EnableCommitPipelineStages::DbFile,
+			EnableCommitPipelineStages::Standard,
+		] {
+			let tmp = tempdir().unwrap();
+			let mut options = db_test.options(tmp.path(), 2);
+			options.columns[1].btree_index = true;
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+
+			assert!(matches!(db.get_first(0), Err(crate::Error::NotOrderedColumn)));
+			assert!(matches!(db.get_last(0), Err(crate::Error::NotOrderedColumn)));
+			assert_eq!(db.get_first(1).unwrap(), None);
+			assert_eq!(db.get_last(1).unwrap(), None);
+
+			db.commit(vec![
+				(1, b"key2".to_vec(), Some(b"value2".to_vec())),
+				(1, b"key1".to_vec(), Some(b"value1".to_vec())),
+				(1, b"key3".to_vec(), Some(b"value3".to_vec())),
+			])
+			.unwrap();
+			db_test.run_stages(&db);
+			assert_eq!(db.get_first(1).unwrap(), Some((b"key1".to_vec(), b"value1".to_vec())));
+			assert_eq!(db.get_last(1).unwrap(), Some((b"key3".to_vec(), b"value3".to_vec())));
+
+			db.commit(vec![(1, b"key1".to_vec(), None), (1, b"key3".to_vec(), None)])
+				.unwrap();
+			db_test.run_stages(&db);
+			assert_eq!(db.get_first(1).unwrap(), Some((b"key2".to_vec(), b"value2".to_vec())));
+			assert_eq!(db.get_last(1).unwrap(), Some((b"key2".to_vec(), b"value2".to_vec())));
+		}
+	}
+
+	#[test]
+	fn test_get_or_compute() {
+		for db_test in [
+			EnableCommitPipelineStages::CommitOverlay,
+			EnableCommitPipelineStages::LogOverlay,
+			EnableCommitPipelineStages::DbFile,
+			EnableCommitPipelineStages::Standard,
+		] {
+			let tmp = tempdir().unwrap();
+			let mut options = db_test.options(tmp.path(), 2);
+			options.columns[1].btree_index = true;
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+
+			for col in 0..2 {
+				let key = [col + 1; 32];
+				assert_eq!(db.get_or_compute(col, &key, || b"first".to_vec()).unwrap(), b"first");
+				db_test.run_stages(&db);
+				assert_eq!(
+					db.get_or_compute(col, &key, || panic!("Value is present")).unwrap(),
+					b"first"
+				);
+				// A value computed after a concurrent insert is discarded.
+				assert_eq!(
+					db.inner.write_plan_if_absent(col, &key, b"second".to_vec(), 0).unwrap(),
+					b"first"
+				);
+				db_test.run_stages(&db);
+				assert_eq!(db.get(col, &key).unwrap(), Some(b"first".to_vec()));
+			}
+		}
+	}
+
+	#[test]
+	fn test_get_or_compute_concurrent() {
+		for db_test in
+			[EnableCommitPipelineStages::CommitOverlay, EnableCommitPipelineStages::Standard]
+		{
+			let tmp = tempdir().unwrap();
+			let options = db_test.options(tmp.path(), 1);
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+
+			// Both threads find the key missing and compute a value, only one is inserted.
+			let barrier = std::sync::Barrier::new(2);
+			let values: Vec<_> = std::thread::scope(|s| {
+				let threads: Vec<_> = (0..2u8)
+					.map(|i| {
+						let (db, barrier) = (&db, &barrier);
+						s.spawn(move || {
+							db.get_or_compute(0, b"key", || {
+								barrier.wait();
+								vec![i]
+							})
+							.unwrap()
+						})
+					})
+					.collect();
+				threads.into_iter().map(|t| t.join().unwrap()).collect()
+			});
+			assert_eq!(values[0], values[1]);
+			db_test.run_stages(&db);
+			assert_eq!(db.get(0, b"key").unwrap(), Some(values[0].clone()));
+
+			// A value committed by another thread while computing is returned instead.
+			let value = db
+				.get_or_compute(0, b"other", || {
+					std::thread::scope(|s| {
+						s.spawn(|| {
+							db.commit(vec![(0, b"other", Some(b"first".to_vec()))]).unwrap()
+						});
+					});
+					db_test.run_stages(&db);
+					b"second".to_vec()
+				})
+				.unwrap();
+			assert_eq!(value, b"first");
+			db_test.run_stages(&db);
+			assert_eq!(db.get(0, b"other").unwrap(), Some(b"first".to_vec()));
+		}
+	}
+
+	#[test]
+	fn test_get_many_cross_column() {
+		for db_test in [
+			EnableCommitPipelineStages::CommitOverlay,
+			EnableCommitPipelineStages::LogOverlay,
+			EnableCommitPipelineStages::DbFile,
+			EnableCommitPipelineStages::Standard,
+		] {
+			let tmp = tempdir().unwrap();
+			let mut options = db_test.options(tmp.path(), 3);
+			options.columns[2].btree_index = true;
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+			let key = b"entity".to_vec();
+
+			db.commit(vec![
+				(0, key.clone(), Some(b"header".to_vec())),
+				(2, key.clone(), Some(b"receipts".to_vec())),
+			])
+			.unwrap();
+			db_test.run_stages(&db);
+			assert_eq!(
+				db.get_many_cross_column(&key, &[2, 1, 0, 2]).unwrap(),
+				vec![
+					Some(b"receipts".to_vec()),
+					None,
+					Some(b"header".to_vec()),
+					Some(b"receipts".to_vec())
+				],
+			);
+			assert_eq!(db.get_many_cross_column(&key, &[]).unwrap(), Vec::<Option<Value>>::new());
+		}
+	}
+
+	#[test]
+	fn test_iter_column_by_size() {
+		for db_test in [EnableCommitPipelineStages::LogOverlay, EnableCommitPipelineStages::DbFile]
+		{
+			let tmp = tempdir().unwrap();
+			let mut options = db_test.options(tmp.path(), 2);
+			options.columns[1].btree_index = true;
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+
+			let sizes = [300usize, 1, 60, 5000, 1, 60, 20];
+			db.commit(
+				sizes
+					.iter()
+					.enumerate()
+					.map(|(i, size)| (0, vec![i as u8], Some(vec![i as u8; *size]))),
+			)
+			.unwrap();
+			db_test.run_stages(&db);
+
+			let mut values = Vec::new();
+			db.iter_column_by_size(0, |_key, value| {
+				values.push(value);
+				true
+			})
+			.unwrap();
+			let tiers: Vec<usize> =
+				values.iter().map(|v| db.get_tier_for_value(0, v.len()).unwrap()).collect();
+			assert!(tiers.windows(2).all(|w| w[0] <= w[1]));
+			values.sort();
+			let mut expected: Vec<Vec<u8>> =
+				sizes.iter().enumerate().map(|(i, size)| vec![i as u8; *size]).collect();
+			expected.sort();
+			assert_eq!(values, expected);
+
+			assert!(db.iter_column_by_size(1, |_, _| true).is_err());
+		}
+	}
+
+	#[test]
+	fn test_hard_reset() {
+		for db_test in [
+			EnableCommitPipelineStages::CommitOverlay,
+			EnableCommitPipelineStages::LogOverlay,
+			EnableCommitPipelineStages::DbFile,
+			EnableCommitPipelineStages::Standard,
+		] {
+			let tmp = tempdir().unwrap();
+			let mut options = db_test.options(tmp.path(), 3);
+			options.columns[1].btree_index = true;
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+
+			let commit = |db: &Db, value: &[u8]| {
+				db.commit((0..3).map(|col| (col, b"key".to_vec(), Some(value.to_vec()))))
+					.unwrap();
+				db_test.run_stages(db);
+			};
+			commit(&db, b"old");
+			db.hard_reset(0).unwrap();
+			db.hard_reset(1).unwrap();
+			assert_eq!(db.get(0, b"key").unwrap(), None);
+			assert_eq!(db.get(1, b"key").unwrap(), None);
+			assert_eq!(db.get(2, b"key").unwrap(), Some(b"old".to_vec()));
+			assert_eq!(db.get_first(1).unwrap(), None);
+
+			db.commit(vec![(0, b"new".to_vec(), Some(b"new".to_vec()))]).unwrap();
+			db.commit(vec![(1, b"new".to_vec(), Some(b"new".to_vec()))]).unwrap();
+			EnableCommitPipelineStages::DbFile.run_stages(&db);
+			drop(db);
+
+			let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+			for col in 0..2 {
+				assert_eq!(db.get(col, b"key").unwrap(), None);
+				assert_eq!(db.get(col, b"new").unwrap(), Some(b"new".to_vec()));
+			}
+			assert_eq!(db.get(2, b"key").unwrap(), Some(b"old".to_vec()));
+		}
+	}
+
+	#[test]
+	fn test_hard_reset_concurrent_commits() {
+		const COMMITS: u32 = 2000;
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::Standard;
+		let options = db_test.options(tmp.path(), 2);
+		let db = std::sync::Arc::new(Db::open_inner(&options, OpeningMode::Create).unwrap());
+
+		// Commits taken from the queue by `hard_reset` and the log worker are written in order.
+		let writer = {
+			let db = db.clone();
+			std::thread::spawn(move || {
+				for i in 0..COMMITS {
+					db.commit(vec![(0, b"key".to_vec(), Some(i.to_le_bytes().to_vec()))]).unwrap();
+				}
+			})
+		};
+		while !writer.is_finished() {
+			db.hard_reset(1).unwrap();
+		}
+		writer.join().unwrap();
+		drop(db);
+
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert_eq!(db.get(0, b"key").unwrap(), Some((COMMITS - 1).to_le_bytes().to_vec()));
+	}
+
+	#[test]
+	fn test_referential_integrity() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let options = db_test.options(tmp.path(), 1);
+		let corrupt = |db: &Db| match &db.inner.columns[0] {
+			Column::Hash(column) => column.check_referential_integrity(&db.inner.log).unwrap(),
+			Column::Tree(_) => unreachable!(),
+		};
+
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		assert_eq!(db.get_tier_for_value(0, 1).unwrap(), 0);
+		db.commit(vec![(0, b"a".to_vec(), Some(b"a".to_vec()))]).unwrap();
+		db.commit(vec![(0, b"b".to_vec(), Some(b"b".to_vec()))]).unwrap();
+		db_test.run_stages(&db);
+		assert!(corrupt(&db).is_empty());
+		drop(db);
+
+		// Turn the second value into a tombstone. Tier 0 entries are 32 bytes.
+		use std::io::{Seek, SeekFrom, Write};
+		let mut file = std::fs::OpenOptions::new()
+			.write(true)
+			.open(tmp.path().join("table_00_00"))
+			.unwrap();
+		file.seek(SeekFrom::Start(2 * 32)).unwrap();
+		file.write_all(&[0xff, 0xff, 0, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+		drop(file);
+
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		let found = corrupt(&db);
+		assert_eq!(found.len(), 1);
+		assert_eq!(found[0].address, crate::index::Address::new(2, 0));
+		db.dump(check::CheckOptions::new(None, None, None, false, None)).unwrap();
+	}
+
+	#[test]
+	fn test_count_keys_by_prefix() {
+		for db_test in [EnableCommitPipelineStages::LogOverlay, EnableCommitPipelineStages::DbFile]
+		{
+			let tmp = tempdir().unwrap();
+			let mut options = db_test.options(tmp.path(), 2);
+			options.columns[0].uniform = true;
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+
+			let key = |prefix: &[u8]| {
+				let mut key = [0xaa; 32];
+				key[..prefix.len()].copy_from_slice(prefix);
+				key
+			};
+			db.commit(
+				[&[0x12, 0x34, 0x56, 0, 0, 0, 0][..], &[0x12, 0x34, 0x57], &[0x12, 0x35], &[0x13]]
+					.iter()
+					.map(|prefix| (0, key(prefix), Some(b"value".to_vec()))),
+			)
+			.unwrap();
+			db_test.run_stages(&db);
+
+			assert_eq!(db.count_keys_by_prefix(0, &[], 0).unwrap(), 4);
+			assert_eq!(db.count_keys_by_prefix(0, &[0x12], 8).unwrap(), 3);
+			assert_eq!(db.count_keys_by_prefix(0, &[0x12, 0x34], 16).unwrap(), 2);
+			assert_eq!(db.count_keys_by_prefix(0, &[0x12, 0x34, 0x50], 20).unwrap(), 2);
+			assert_eq!(db.count_keys_by_prefix(0, &[0x12, 0x34, 0x56], 24).unwrap(), 1);
+			assert_eq!(db.count_keys_by_prefix(0, &[0x12, 0x34, 0x56, 0, 0, 0, 0], 50).unwrap(), 1);
+			assert_eq!(db.count_keys_by_prefix(0, &[0x14], 8).unwrap(), 0);
+			assert!(db.count_keys_by_prefix(0, &[0x12; 8], 51).is_err());
+			assert!(db.count_keys_by_prefix(0, &[0x12], 9).is_err());
+			assert!(db.count_keys_by_prefix(1, &[0x12], 8).is_err());
+		}
+	}
+
+	#[test]
+	fn test_scan_prefix() {
+		for db_test in [EnableCommitPipelineStages::LogOverlay, EnableCommitPipelineStages::DbFile]
+		{
+			let tmp = tempdir().unwrap();
+			let mut options = db_test.options(tmp.path(), 2);
+			options.columns[0].uniform = true;
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+
+			let key = |prefix: &[u8]| {
+				let mut key = [0xaa; 32];
+				key[..prefix.len()].copy_from_slice(prefix);
+				key
+			};
+			let prefixes = [&[0x12, 0x34, 0x56][..], &[0x12, 0x34, 0x57], &[0x12, 0x35], &[0x13]];
+			db.commit(prefixes.iter().map(|prefix| (0, key(prefix), Some(prefix.to_vec()))))
+				.unwrap();
+			db_test.run_stages(&db);
+
+			let scan = |prefix: &[u8]| {
+				let mut found: Vec<_> =
+					db.scan_prefix(0, prefix).unwrap().map(|e| e.unwrap()).collect();
+				found.sort();
+				found
+			};
+			let expected = |prefixes: &[&[u8]]| {
+				let mut entries: Vec<_> = prefixes.iter().map(|p| (key(p), p.to_vec())).collect();
+				entries.sort();
+				entries
+			};
+			assert_eq!(scan(&[]), expected(&prefixes));
+			assert_eq!(scan(&[0x12]), expected(&prefixes[..3]));
+			assert_eq!(scan(&[0x12, 0x34]), expected(&prefixes[..2]));
+			assert_eq!(scan(&[0x12, 0x34, 0x57]), expected(&prefixes[1..2]));
+			assert_eq!(scan(&key(&[0x13])), expected(&prefixes[3..]));
+			assert_eq!(scan(&[0x12, 0x34, 0x57, 0xab]), expected(&[]));
+			assert_eq!(scan(&[0x14]), expected(&[]));
+			assert!(db.scan_prefix(0, &[0; 33]).is_err());
+			assert!(db.scan_prefix(1, &[0x12]).is_err());
+		}
+	}
+
+	#[cfg(feature = "parquet")]
+	#[test]
+	fn test_export_parquet() {
+		use parquet::file::reader::{FileReader, SerializedFileReader};
+
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit((0..10_005u32).map(|i| (0, i.to_le_bytes(), Some(vec![1; i as usize % 100]))))
+			.unwrap();
+		db_test.run_stages(&db);
+
+		let path = tmp.path().join("export.parquet");
+		let stats = db.export_parquet(0, &path).unwrap();
+		assert_eq!(stats, crate::ExportStats { rows: 10_005, row_groups: 2 });
+		let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+		assert_eq!(reader.metadata().file_metadata().num_rows(), 10_005);
+		assert_eq!(reader.metadata().num_row_groups(), 2);
+		assert_eq!(reader.metadata().file_metadata().schema_descr().num_columns(), 4);
+		assert!(db.export_parquet(1, &path).is_err());
+	}
+
+	#[test]
+	fn test_query() {
+		for db_test in [EnableCommitPipelineStages::LogOverlay, EnableCommitPipelineStages::DbFile]
+		{
+			let tmp = tempdir().unwrap();
+			let mut options = db_test.options(tmp.path(), 2);
+			options.columns[1].btree_index = true;
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+			db.commit((0..100u8).map(|i| (0, [i], Some(vec![i; 1 + i as usize])))).unwrap();
+			db_test.run_stages(&db);
+
+			let mut lens = db.query(0, |_, v| v[0] % 10 == 0, |_, v| v.len()).unwrap();
+			lens.sort_unstable();
+			assert_eq!(lens, (0..10).map(|i| 1 + i * 10).collect::<Vec<_>>());
+			let key = match &db.inner.columns[0] {
+				Column::Hash(column) => column.hash_key(&[42]),
+				Column::Tree(_) => unreachable!(),
+			};
+			assert_eq!(db.query(0, |k, _| *k == key, |_, v| v).unwrap(), vec![vec![42; 43]]);
+			assert!(db.query(1, |_, _| true, |k, _| k).is_err());
+		}
+	}
+
+	#[test]
+	fn test_iter_index_entries() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit((0..200u32).map(|i| (0, i.to_le_bytes(), Some(vec![1; 1 + i as usize % 50]))))
+			.unwrap();
+		db_test.run_stages(&db);
+
+		let hash = |i: u32| match &db.inner.columns[0] {
+			Column::Hash(column) => column.hash_key(&i.to_le_bytes()),
+			Column::Tree(_) => unreachable!(),
+		};
+		let mut expected: HashSet<_> = (0..200).map(|i| hash(i)[..6].to_vec()).collect();
+		let mut addresses = HashSet::new();
+		db.iter_index_entries(0, |key, tier, offset| {
+			assert!(expected.remove(&key[..6]));
+			assert!(addresses.insert((tier, offset)));
+			true
+		})
+		.unwrap();
+		assert!(expected.is_empty());
+
+		let mut count = 0;
+		db.iter_index_entries(0, |_, _, _| {
+			count += 1;
+			count < 10
+		})
+		.unwrap();
+		assert_eq!(count, 10);
+		assert!(db.iter_index_entries(1, |_, _, _| true).is_err());
+	}
+
+	#[test]
+	fn test_estimate_recovery_time() {
+		use crate::stats::EnactStats;
+		use std::time::Duration;
+
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::LogOverlay;
+		let options = db_test.options(tmp.path(), 1);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit((0..100u32).map(|i| (0, i.to_le_bytes(), Some(vec![1; 1000]))))
+			.unwrap();
+		db_test.run_stages(&db);
+		drop(db);
+
+		let (count, bytes) = Log::pending_logs(tmp.path()).unwrap();
+		assert_eq!(count, 1);
+		assert!(bytes > 100_000);
+		// No history, assume a slow disk.
+		assert_eq!(Db::estimate_recovery_time(tmp.path()).unwrap(), Duration::from_secs(1));
+
+		let mut stats = EnactStats::default();
+		stats.record(100, Duration::from_millis(500));
+		stats.save(tmp.path()).unwrap();
+		assert_eq!(EnactStats::load_throughput(tmp.path()).unwrap(), None);
+		stats.record(900, Duration::from_millis(500));
+		stats.save(tmp.path()).unwrap();
+		assert_eq!(EnactStats::load_throughput(tmp.path()).unwrap(), Some(1000.0));
+		stats.record(3000, Duration::from_secs(1));
+		stats.save(tmp.path()).unwrap();
+		assert_eq!(EnactStats::load_throughput(tmp.path()).unwrap(), Some(1400.0));
+		assert_eq!(
+			Db::estimate_recovery_time(tmp.path()).unwrap(),
+			Duration::from_secs((bytes as f64 / 1400.0).ceil() as u64)
+		);
+
+		// Replaying the log empties it.
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert_eq!(db.get(0, &1u32.to_le_bytes()).unwrap(), Some(vec![1; 1000]));
+		EnableCommitPipelineStages::DbFile.run_stages(&db);
+		drop(db);
+		assert_eq!(Db::estimate_recovery_time(tmp.path()).unwrap(), Duration::ZERO);
+	}
+
+	#[test]
+	fn test_purge_tier() {
+		for db_test in [EnableCommitPipelineStages::LogOverlay, EnableCommitPipelineStages::DbFile]
+		{
+			let tmp = tempdir().unwrap();
+			let mut options = db_test.options(tmp.path(), 2);
+			options.columns[1].btree_index = true;
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+			let small = db.get_tier_for_value(0, 10).unwrap();
+			assert_ne!(small, db.get_tier_for_value(0, 1000).unwrap());
+
+			db.commit(
+				(0..50u32).map(|i| {
+					(0, i.to_le_bytes(), Some(vec![1; if i % 2 == 0 { 10 } else { 1000 }]))
+				}),
+			)
+			.unwrap();
+			db_test.run_stages(&db);
+			assert_eq!(db.purge_tier(0, small).unwrap(), 25);
+			db_test.run_stages(&db);
+			assert_eq!(db.purge_tier(0, small).unwrap(), 0);
+			assert!(db.purge_tier(1, small).is_err());
+			for i in 0..50u32 {
+				let expected = if i % 2 == 0 { None } else { Some(vec![1; 1000]) };
+				assert_eq!(db.get(0, &i.to_le_bytes()).unwrap(), expected);
+			}
+
+			db.commit(vec![(0, 0u32.to_le_bytes(), Some(vec![2; 10]))]).unwrap();
+			EnableCommitPipelineStages::DbFile.run_stages(&db);
+			drop(db);
+			let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+			assert_eq!(db.get(0, &0u32.to_le_bytes()).unwrap(), Some(vec![2; 10]));
+			assert_eq!(db.get(0, &2u32.to_le_bytes()).unwrap(), None);
+			assert_eq!(db.get(0, &1u32.to_le_bytes()).unwrap(), Some(vec![1; 1000]));
+		}
+	}
+
+	#[test]
+	fn test_ttl() {
+		for db_test in [EnableCommitPipelineStages::LogOverlay, EnableCommitPipelineStages::DbFile]
+		{
+			let tmp = tempdir().unwrap();
+			let mut options = db_test.options(tmp.path(), 2);
+			// Long enough for the values to be checked before they expire on a slow machine.
+			options.columns[0].ttl = Some(std::time::Duration::from_secs(5));
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+
+			db.commit(vec![(0, b"old", Some(vec![1; 10])), (1, b"old", Some(vec![1; 10]))])
+				.unwrap();
+			db_test.run_stages(&db);
+			assert_eq!(db.get(0, b"old").unwrap(), Some(vec![1; 10]));
+			assert_eq!(db.get_size(0, b"old").unwrap(), Some(10));
+			assert!(db.has_key(0, b"old").unwrap());
+
+			std::thread::sleep(std::time::Duration::from_secs(6));
+			db.commit(vec![(0, b"new", Some(vec![2; 10]))]).unwrap();
+			db_test.run_stages(&db);
+			assert_eq!(db.get(0, b"old").unwrap(), None);
+			assert!(!db.has_key(0, b"old").unwrap());
+			assert_eq!(db.get(1, b"old").unwrap(), Some(vec![1; 10]));
+			assert_eq!(db.get_multi(0, &[b"old", b"new"]).unwrap(), vec![None, Some(vec![2; 10])]);
+			let mut values = Vec::new();
+			db.iter_column_by_size(0, |_, value| {
+				values.push(value);
+				true
+			})
+			.unwrap();
+			assert_eq!(values, vec![vec![2; 10]]);
+			let mut iter = db.iter_with_offset(0, 0).unwrap();
+			assert_eq!(iter.next().unwrap().map(|(_, value)| value), Some(vec![2; 10]));
+			assert_eq!(iter.next().unwrap(), None);
+			#[cfg(feature = "parquet")]
+			{
+				let path = tmp.path().join("export.parquet");
+				assert_eq!(db.export_parquet(0, &path).unwrap().rows, 1);
+			}
+
+			assert!(db.get_value_address(0, b"old").unwrap().is_some());
+			assert_eq!(db.expire(0).unwrap(), 1);
+			db_test.run_stages(&db);
+			assert!(db.get_value_address(0, b"old").unwrap().is_none());
+			assert_eq!(db.expire(0).unwrap(), 0);
+			assert!(db.expire(1).is_err());
+			assert_eq!(db.get(0, b"new").unwrap(), Some(vec![2; 10]));
+		}
+	}
+
+	#[test]
+	fn test_write_buffer() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.write_buffer_capacity.insert(0, 10);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+
+		for i in 0..8u8 {
+			db.commit(vec![(0, [i], Some(vec![i; 4])), (0, [i + 1], None)]).unwrap();
+		}
+		// Queued commits are visible before they are written.
+		for i in 0..8u8 {
+			assert_eq!(db.get(0, &[i]).unwrap(), Some(vec![i; 4]));
+		}
+		assert_eq!(db.get(0, &[8]).unwrap(), None);
+		// Five commits of two changes fit a record.
+		assert!(db.inner.process_commits().unwrap());
+		assert_eq!(db.inner.commit_queue.lock().commits.len(), 3);
+		assert_eq!(db.get(0, &[0]).unwrap(), Some(vec![0; 4]));
+		assert_eq!(db.get(0, &[7]).unwrap(), Some(vec![7; 4]));
+		assert!(db.inner.process_commits().unwrap());
+		assert!(!db.inner.process_commits().unwrap());
+
+		// Commits to a column without a capacity are written on their own.
+		db.commit(vec![(0, [10], Some(vec![10]))]).unwrap();
+		db.commit(vec![(1, [10], Some(vec![10]))]).unwrap();
+		db.commit(vec![(0, [11], Some(vec![11]))]).unwrap();
+		assert!(db.inner.process_commits().unwrap());
+		assert_eq!(db.inner.commit_queue.lock().commits.len(), 2);
+		assert!(db.inner.process_commits().unwrap());
+		assert_eq!(db.inner.commit_queue.lock().commits.len(), 1);
+		db_test.run_stages(&db);
+
+		drop(db);
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		for i in 0..8u8 {
+			assert_eq!(db.get(0, &[i]).unwrap(), Some(vec![i; 4]));
+		}
+		assert_eq!(db.get(0, &[8]).unwrap(), None);
+		assert_eq!(db.get(0, &[11]).unwrap(), Some(vec![11]));
+		assert_eq!(db.get(1, &[10]).unwrap(), Some(vec![10]));
+	}
+
+	#[test]
+	fn test_multi_column_transaction() {
+		for db_test in [
+			EnableCommitPipelineStages::CommitOverlay,
+			EnableCommitPipelineStages::LogOverlay,
+			EnableCommitPipelineStages::DbFile,
+			EnableCommitPipelineStages::Standard,
+		] {
+			let tmp = tempdir().unwrap();
+			let mut options = db_test.options(tmp.path(), 2);
+			options.columns[1].btree_index = true;
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+			db.commit(vec![(0, b"key", Some(b"value".to_vec()))]).unwrap();
+			db_test.run_stages(&db);
+
+			let mut tx = db.multi_column_transaction();
+			tx.delete(0, b"key").put(1, b"key", b"value".to_vec()).put(0, b"other", vec![1]);
+			tx.commit().unwrap();
+			db_test.run_stages(&db);
+			assert_eq!(db.get(0, b"key").unwrap(), None);
+			assert_eq!(db.get(1, b"key").unwrap(), Some(b"value".to_vec()));
+			assert_eq!(db.get(0, b"other").unwrap(), Some(vec![1]));
+
+			let mut tx = MultiColumnTx::begin(&db);
+			tx.put(0, b"key", vec![2]).put(2, b"key", vec![2]);
+			assert!(tx.commit().is_err());
+			db_test.run_stages(&db);
+			assert_eq!(db.get(0, b"key").unwrap(), None);
+		}
+	}
+
+	#[test]
+	fn test_multi_column_writer_rollback() {
+		use crate::{column::MultiColumnWriter, db::Operation};
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 3);
+		options.columns[2].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit(vec![(0, b"key".to_vec(), Some(b"value".to_vec()))]).unwrap();
+		db_test.run_stages(&db);
+
+		let hash = |col: usize, key: &[u8]| match &db.inner.columns[col] {
+			Column::Hash(column) => column.hash_key(key),
+			Column::Tree(_) => unreachable!(),
+		};
+		let state = |db: &Db| format!("{:?}", db.inner.columns[0].checkpoint());
+		let before = state(&db);
+		{
+			let _lock = db.inner.log_record_lock.lock();
+			let mut writer = MultiColumnWriter::new(&db.inner.log, &db.inner.columns);
+			writer
+				.write(
+					0,
+					&[
+						Operation::Set(hash(0, b"key"), vec![1; 100]),
+						Operation::Set(hash(0, b"key2"), vec![2; 100]),
+					],
+				)
+				.unwrap();
+			assert_ne!(state(&db), before);
+			// All-zero keys are rejected.
+			assert!(writer.write(1, &[Operation::Set([0; 32], vec![3; 100])]).is_err());
+			assert_eq!(state(&db), before);
+			assert!(writer.write(0, &[Operation::Dereference(hash(0, b"key"))]).is_err());
+			assert!(writer.commit().is_err());
+
+			let mut writer = MultiColumnWriter::new(&db.inner.log, &db.inner.columns);
+			writer.write(0, &[Operation::Dereference(hash(0, b"key"))]).unwrap();
+			assert!(writer.write(2, &[Operation::Set(hash(0, b"key"), vec![])]).is_err());
+			assert_eq!(state(&db), before);
+
+			// Dropping the writer without committing drops its changes as well.
+			let mut writer = MultiColumnWriter::new(&db.inner.log, &db.inner.columns);
+			writer.write(0, &[Operation::Set(hash(0, b"key2"), vec![2; 100])]).unwrap();
+			drop(writer);
+			assert_eq!(state(&db), before);
+		}
+		db_test.run_stages(&db);
+		assert_eq!(state(&db), before);
+		assert_eq!(db.get(0, b"key").unwrap(), Some(b"value".to_vec()));
+		assert_eq!(db.get(0, b"key2").unwrap(), None);
+
+		db.commit(vec![(1, b"key".to_vec(), Some(b"value".to_vec()))]).unwrap();
+		db_test.run_stages(&db);
+		drop(db);
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert_eq!(db.get(0, b"key").unwrap(), Some(b"value".to_vec()));
+		assert_eq!(db.get(0, b"key2").unwrap(), None);
+		assert_eq!(db.get(1, b"key").unwrap(), Some(b"value".to_vec()));
+	}
+
+	#[test]
+	fn test_probe_distance_histogram() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let options = db_test.options(tmp.path(), 1);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let histogram = |db: &Db| match &db.inner.columns[0] {
+			Column::Hash(column) => column.probe_distance_histogram(&db.inner.log).unwrap(),
+			Column::Tree(_) => unreachable!(),
+		};
+		assert_eq!(histogram(&db), vec![0; 64]);
+
+		db.commit((0..1000u32).map(|i| (0, i.to_le_bytes(), Some(vec![1])))).unwrap();
+		db_test.run_stages(&db);
+		let before = histogram(&db);
+		assert_eq!(before.len(), 64);
+		assert_eq!(before.iter().sum::<u64>(), 1000);
+		// With 2^16 chunks most keys are alone in their chunk.
+		assert!(before[0] > 900);
+
+		db.commit((0..1000u32).step_by(2).map(|i| (0, i.to_le_bytes(), None))).unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(histogram(&db).iter().sum::<u64>(), 500);
+		db.dump(check::CheckOptions::new(None, None, None, false, None)).unwrap();
+	}
+
+	#[test]
+	fn test_probe_depth_histogram() {
+		use crate::index::IndexTable;
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.columns[0].uniform = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let histogram = |db: &Db| match &db.inner.columns[0] {
+			Column::Hash(column) => column.probe_depth_histogram(&db.inner.log).unwrap(),
+			Column::Tree(_) => unreachable!(),
+		};
+		assert_eq!(IndexTable::max_probe_depth(&histogram(&db)), 0);
+		assert_eq!(IndexTable::mean_probe_depth(&histogram(&db)), 0.0);
+
+		// Keys that only differ past the bits stored in the index collide.
+		let key = |prefix: u8, i: u8| {
+			let mut key = [prefix; 32];
+			key[31] = i;
+			key
+		};
+		db.commit((0..3).map(|i| (0, key(1, i), Some(vec![i])))).unwrap();
+		db.commit((2..9).map(|prefix| (0, key(prefix, 0), Some(vec![prefix])))).unwrap();
+		db_test.run_stages(&db);
+		let depths = histogram(&db);
+		assert_eq!(depths.len(), 64);
+		assert_eq!(depths[..4], [8, 1, 1, 0]);
+		assert_eq!(IndexTable::max_probe_depth(&depths), 3);
+		assert_eq!(IndexTable::mean_probe_depth(&depths), 13.0 / 10.0);
+
+		db.commit(vec![(0, key(1, 0), None)]).unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(histogram(&db)[..3], [8, 1, 0]);
+	}
+
+	#[test]
+	fn test_get_column_options() {
+		let tmp = tempdir().unwrap();
+		let mut options = Options::with_columns(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		options.columns[1].compression = crate::CompressionType::Lz4;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		assert_eq!(db.get_column_options(0).unwrap(), options.columns[0]);
+		assert_eq!(db.get_column_options(1).unwrap(), options.columns[1]);
+		assert!(db.get_column_options(2).is_err());
+	}
+
+	#[test]
+	fn test_warm_cache() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		// No index file yet.
+		db.warm_cache(0, None).unwrap();
+		db.commit((0..100u32).map(|i| (0, i.to_le_bytes(), Some(vec![1])))).unwrap();
+		db_test.run_stages(&db);
+		db.warm_cache(0, Some(16)).unwrap();
+		db.warm_cache(0, Some(u64::MAX)).unwrap();
+		db.warm_cache(0, None).unwrap();
+		assert!(db.warm_cache(1, None).is_err());
+		assert_eq!(db.get(0, &1u32.to_le_bytes()).unwrap(), Some(vec![1]));
+	}
+
+	#[test]
+	fn test_delete_range_by_tier() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let options = db_test.options(tmp.path(), 1);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let tier = db.get_tier_for_value(0, 10).unwrap();
+		db.commit((0..10u32).map(|i| (0, i.to_le_bytes(), Some(vec![1; 10])))).unwrap();
+		db_test.run_stages(&db);
+		db.commit(vec![(0, 0u32.to_le_bytes(), None)]).unwrap();
+		db_test.run_stages(&db);
+
+		// Keys of freed values are removed, so removing them again doesn't free their slot twice.
+		assert_eq!(db.delete_range_by_tier(0, tier, 3, 5).unwrap(), 2);
+		db_test.run_stages(&db);
+		let missing = (1..10u32).filter(|i| db.get(0, &i.to_le_bytes()).unwrap().is_none());
+		assert_eq!(missing.count(), 2);
+		db.commit((1..5u32).map(|i| (0, i.to_le_bytes(), None))).unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(db.fsck(0, false).unwrap().corrupt, 0);
+
+		// Slot 1 is free already, slots past the end are ignored.
+		let live = (5..10u32).filter(|i| db.get(0, &i.to_le_bytes()).unwrap().is_some()).count();
+		assert_eq!(db.delete_range_by_tier(0, tier, 0, 100).unwrap(), live as u64);
+		db_test.run_stages(&db);
+		assert_eq!(db.delete_range_by_tier(0, tier, 0, 100).unwrap(), 0);
+		assert!(db.delete_range_by_tier(0, 256, 0, 100).is_err());
+		assert_eq!(db.fsck(0, false).unwrap().corrupt, 0);
+		assert_eq!(db.fsck(0, false).unwrap().healthy, 10);
+		assert_eq!(db.get(0, &9u32.to_le_bytes()).unwrap(), None);
+
+		// Freed slots are reused.
+		db.commit((100..110u32).map(|i| (0, i.to_le_bytes(), Some(vec![2; 10]))))
+			.unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(db.fsck(0, false).unwrap().healthy, 0);
+		assert_eq!(db.get(0, &105u32.to_le_bytes()).unwrap(), Some(vec![2; 10]));
+
+		// All parts of a value are freed if any of them is in the range.
+		let tier = SIZE_TIERS - 1;
+		db.commit(vec![(0, b"big".to_vec(), Some(vec![3; 40000]))]).unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(db.delete_range_by_tier(0, tier, 2, 3).unwrap(), 1);
+		db_test.run_stages(&db);
+		assert_eq!(db.get(0, b"big").unwrap(), None);
+		assert_eq!(db.delete_range_by_tier(0, tier, 0, 100).unwrap(), 0);
+		assert_eq!(db.fsck(0, false).unwrap().corrupt, 0);
+	}
+
+	#[test]
+	fn test_enact_oversized_entry() {
+		// Logged entries larger than the table entries are rejected instead of overflowing the
+		// entry buffer or the slot.
+		for (tier, size) in [(SIZE_TIERS - 1, 0x7fffu16), (0, 100)] {
+			let tmp = tempdir().unwrap();
+			let db_test = EnableCommitPipelineStages::DbFile;
+			let options = db_test.options(tmp.path(), 1);
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+			db.commit(vec![(0, b"key".to_vec(), Some(b"value".to_vec()))]).unwrap();
+			db_test.run_stages(&db);
+
+			let mut writer = db.inner.log.begin_record();
+			let mut data = vec![0u8; 2 + size as usize];
+			data[0..2].copy_from_slice(&size.to_le_bytes());
+			writer.insert_value(ValueTableId::new(0, tier as u8), 1, data);
+			let l = writer.drain();
+			db.inner.log.end_record(l).unwrap();
+			let _ = db.inner.log.flush_one(0).unwrap();
+			assert!(matches!(db.inner.enact_logs(false), Err(crate::Error::Corruption(_))));
+
+			// The database is still usable.
+			assert_eq!(db.get(0, b"key").unwrap(), Some(b"value".to_vec()));
+			db.commit(vec![(0, b"key2".to_vec(), Some(b"value2".to_vec()))]).unwrap();
+			db.inner.process_commits().unwrap();
+			assert_eq!(db.get(0, b"key2").unwrap(), Some(b"value2".to_vec()));
+		}
+	}
+
+	#[test]
+	fn test_subscribe_errors() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.columns[0].start_bits = 8;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let errors = db.subscribe_errors();
+
+		// A chunk past the end of the index table fails validation on replay.
+		let mut writer = db.inner.log.begin_record();
+		let table = crate::index::TableId::new(0, 8);
+		writer.insert_index(table, table.total_entries(), 0, &[0u8; 512]);
+		// Replay the record as if it was found in the log on startup.
+		db.inner
+			.last_enacted
+			.store(writer.record_id() - 1, std::sync::atomic::Ordering::Relaxed);
+		let l = writer.drain();
+		db.inner.log.end_record(l).unwrap();
+		let _ = db.inner.log.flush_one(0).unwrap();
+		assert!(!db.inner.enact_logs(true).unwrap());
+		let event = errors.try_recv().unwrap();
+		assert_eq!(event.col, 0);
+		assert_eq!(event.operation, "validate_plan");
+		assert!(matches!(*event.error, crate::Error::Corruption(_)));
+		assert!(errors.try_recv().is_err());
+
+		// Other errors are not reported.
+		db.inner.report_corruption(0, "enact_plan", crate::Error::InvalidValueData);
+		assert!(errors.try_recv().is_err());
+
+		// Events are dropped once the channel is full.
+		for _ in 0..super::ERROR_EVENTS_CAPACITY + 1 {
+			db.inner
+				.report_corruption(0, "enact_plan", crate::Error::Corruption("test".into()));
+		}
+		assert_eq!(db.dropped_error_events(), 1);
+		assert_eq!(errors.try_iter().count(), super::ERROR_EVENTS_CAPACITY);
+	}
+
+	#[test]
+	fn test_subscribe_errors_on_open() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.columns[0].start_bits = 8;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+
+		// Leave a record that fails validation in the log, to be replayed on the next open.
+		let mut writer = db.inner.log.begin_record();
+		let table = crate::index::TableId::new(0, 8);
+		writer.insert_index(table, table.total_entries(), 0, &[0u8; 512]);
+		let l = writer.drain();
+		db.inner.log.end_record(l).unwrap();
+		let _ = db.inner.log.flush_one(0).unwrap();
+		drop(db);
+
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		let errors = db.subscribe_errors();
+		let event = errors.try_recv().unwrap();
+		assert_eq!(event.col, 0);
+		assert_eq!(event.operation, "validate_plan");
+		assert!(errors.try_recv().is_err());
+		// Later subscribers only get new events.
+		assert!(db.subscribe_errors().try_recv().is_err());
+	}
+
+	#[test]
+	fn test_max_log_segment_bytes() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::LogOverlay;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.max_log_segment_bytes = 1024 * 1024;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let log_sizes = || -> Vec<u64> {
+			std::fs::read_dir(tmp.path())
+				.unwrap()
+				.map(|e| e.unwrap())
+				.filter(|e| e.file_name().to_str().unwrap().starts_with("log"))
+				.map(|e| e.metadata().unwrap().len())
+				.collect()
+		};
+		let value = |i: u32| vec![i as u8; 100_000];
+		for i in 0..64u32 {
+			db.commit(vec![(0, i.to_le_bytes().to_vec(), Some(value(i)))]).unwrap();
+			db_test.run_stages(&db);
+			assert!(log_sizes().iter().all(|size| *size <= options.max_log_segment_bytes));
+		}
+		assert!(log_sizes().len() > 6);
+		// A commit larger than the limit gets a file of its own.
+		db.commit(vec![(0, b"large".to_vec(), Some(vec![1; 2 * 1024 * 1024]))]).unwrap();
+		db_test.run_stages(&db);
+		drop(db);
+
+		// Commits are replayed from all files.
+		let db = Db::open(&options).unwrap();
+		for i in 0..64u32 {
+			assert_eq!(db.get(0, &i.to_le_bytes()).unwrap(), Some(value(i)));
+		}
+		assert_eq!(db.get(0, b"large").unwrap(), Some(vec![1; 2 * 1024 * 1024]));
+	}
+
+	#[test]
+	fn test_entry_count() {
+		for db_test in [EnableCommitPipelineStages::LogOverlay, EnableCommitPipelineStages::DbFile]
+		{
+			let tmp = tempdir().unwrap();
+			let options = db_test.options(tmp.path(), 1);
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+			let value = |i: u32| vec![i as u8; i as usize * 1000];
+			db.commit((0..40u32).map(|i| (0, i.to_le_bytes().to_vec(), Some(value(i)))))
+				.unwrap();
+			db_test.run_stages(&db);
+			assert_eq!(db.entry_count(0).unwrap(), 40);
+
+			db.commit((0..40u32).step_by(3).map(|i| (0, i.to_le_bytes().to_vec(), None)))
+				.unwrap();
+			db_test.run_stages(&db);
+			assert_eq!(db.entry_count(0).unwrap(), 26);
+			let by_tier = db.entry_count_by_tier(0).unwrap();
+			assert_eq!(by_tier.len(), SIZE_TIERS);
+			assert_eq!(by_tier.iter().sum::<u64>(), 26);
+			// Values over 32 KiB are stored in the multipart table.
+			assert_eq!(by_tier[SIZE_TIERS - 1], (33..40).filter(|i| i % 3 != 0).count() as u64);
+			assert!(matches!(db.entry_count(1), Err(crate::Error::InvalidInput(_))));
+		}
+	}
+
+	#[test]
+	fn test_read_value_into() {
+		// Records the largest single write.
+		#[derive(Default)]
+		struct Sink {
+			data: Vec<u8>,
+			max_write: usize,
+		}
+		impl std::io::Write for Sink {
+			fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+				self.max_write = self.max_write.max(buf.len());
+				self.data.extend_from_slice(buf);
+				Ok(buf.len())
+			}
+			fn flush(&mut self) -> std::io::Result<()> {
+				Ok(())
+			}
+		}
+
+		let large: Vec<u8> = (0..16 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+		for db_test in
+			[EnableCommitPipelineStages::CommitOverlay, EnableCommitPipelineStages::DbFile]
+		{
+			let tmp = tempdir().unwrap();
+			let mut options = db_test.options(tmp.path(), 2);
+			options.columns[1].compression = crate::CompressionType::Lz4;
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+			db.commit(vec![
+				(0, b"large".to_vec(), Some(large.clone())),
+				(0, b"small".to_vec(), Some(b"value".to_vec())),
+				(1, b"large".to_vec(), Some(large.clone())),
+			])
+			.unwrap();
+			db_test.run_stages(&db);
+
+			for col in 0..2 {
+				let mut sink = Sink::default();
+				assert_eq!(
+					db.read_value_into(col, b"large", &mut sink).unwrap(),
+					Some(large.len())
+				);
+				assert!(sink.data == large);
+				if col == 0 && db_test == EnableCommitPipelineStages::DbFile {
+					assert!(sink.max_write <= 4096);
+				}
+			}
+			let mut sink = Sink::default();
+			assert_eq!(db.read_value_into(0, b"small", &mut sink).unwrap(), Some(5));
+			assert_eq!(sink.data, b"value");
+			assert_eq!(db.read_value_into(0, b"missing", &mut sink).unwrap(), None);
+			assert_eq!(sink.data, b"value");
+
+			// Write errors are returned.
+			let mut full = [0u8; 1024];
+			assert!(matches!(
+				db.read_value_into(0, b"large", &mut &mut full[..]),
+				Err(crate::Error::Io(_))
+			));
+		}
+	}
+
+	#[test]
+	fn test_get_with_fallback() {
+		for db_test in
+			[EnableCommitPipelineStages::CommitOverlay, EnableCommitPipelineStages::DbFile]
+		{
+			let tmp = tempdir().unwrap();
+			let mut options = db_test.options(tmp.path(), 2);
+			options.columns[1].uniform = true;
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+			let (hot, cold, both) = ([1u8; 32], [2u8; 32], [3u8; 32]);
+			db.commit(vec![
+				(0, hot.to_vec(), Some(b"hot".to_vec())),
+				(1, cold.to_vec(), Some(b"cold".to_vec())),
+				(0, both.to_vec(), Some(b"new".to_vec())),
+				(1, both.to_vec(), Some(b"old".to_vec())),
+			])
+			.unwrap();
+			db_test.run_stages(&db);
+
+			assert_eq!(db.get_with_fallback(0, 1, &hot).unwrap(), Some(b"hot".to_vec()));
+			assert_eq!(db.get_with_fallback(0, 1, &cold).unwrap(), Some(b"cold".to_vec()));
+			assert_eq!(db.get_with_fallback(0, 1, &both).unwrap(), Some(b"new".to_vec()));
+			assert_eq!(db.get_with_fallback(1, 0, &hot).unwrap(), Some(b"hot".to_vec()));
+			assert_eq!(db.get_with_fallback(0, 1, &[4u8; 32]).unwrap(), None);
+
+			db.commit(vec![(0, both.to_vec(), None)]).unwrap();
+			db_test.run_stages(&db);
+			assert_eq!(db.get_with_fallback(0, 1, &both).unwrap(), Some(b"old".to_vec()));
+		}
+	}
+
+	#[test]
+	fn test_precompute_index_stats() {
+		let tmp = tempdir().unwrap();
+		let mut options = EnableCommitPipelineStages::Standard.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		let mut db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let timeout = std::time::Duration::from_secs(10);
+		db.commit((0..100u32).map(|i| (0, i.to_le_bytes(), Some(vec![1; 10])))).unwrap();
+
+		assert!(db.cached_stats(0).is_none());
+		// Wait for the commit to reach the log overlay.
+		let start = std::time::Instant::now();
+		loop {
+			db.precompute_index_stats(0).unwrap();
+			if db.cached_stats(0).unwrap().index_entries == 100 {
+				break
+			}
+			assert!(start.elapsed() < timeout);
+			std::thread::sleep(std::time::Duration::from_millis(10));
+		}
+		let stats = db.cached_stats(0).unwrap();
+		assert_eq!(stats.index_entries, 100);
+		assert_eq!(stats.probe_distance_histogram.iter().sum::<u64>(), 100);
+		assert_eq!(stats.fill_rate, 100.0 / stats.index_capacity as f64);
+		assert!(db.precompute_index_stats(1).is_err());
+		assert!(db.precompute_index_stats(2).is_err());
+
+		db.commit((100..200u32).map(|i| (0, i.to_le_bytes(), Some(vec![1; 10]))))
+			.unwrap();
+		db.start_stats_refresh(std::time::Duration::from_millis(10)).unwrap();
+		assert!(db.start_stats_refresh(std::time::Duration::from_millis(10)).is_err());
+		let start = std::time::Instant::now();
+		while db.cached_stats(0).unwrap().index_entries != 200 {
+			assert!(start.elapsed() < timeout);
+			std::thread::sleep(std::time::Duration::from_millis(10));
+		}
+		assert!(db.cached_stats(1).is_none());
+	}
+
+	#[test]
+	fn test_get_column_file_paths() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let options = db_test.options(tmp.path(), 2);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit(vec![
+			(0, b"key1".to_vec(), Some(vec![1; 10])),
+			(0, b"key2".to_vec(), Some(vec![2; 1000])),
+		])
+		.unwrap();
+		db_test.run_stages(&db);
+
+		let names = |col| -> Vec<String> {
+			db.get_column_file_paths(col)
+				.unwrap()
+				.iter()
+				.map(|p| {
+					assert_eq!(p.parent(), Some(tmp.path()));
+					p.file_name().unwrap().to_str().unwrap().to_string()
+				})
+				.collect()
+		};
+		let tier = |size| db.get_tier_for_value(0, size).unwrap() as u8;
+		let mut expected = vec![
+			"index_00_16".to_string(),
+			format!("table_00_{}", hex::encode([tier(10)])),
+			format!("table_00_{}", hex::encode([tier(1000)])),
+		];
+		expected.sort();
+		assert_eq!(names(0), expected);
+		assert!(names(1).is_empty());
+		assert!(db.get_column_file_paths(2).is_err());
+	}
+
+	#[test]
+	fn test_put_if_version() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[0].versioning = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = b"key";
+
+		assert_eq!(db.get_versioned(0, key).unwrap(), None);
+		assert_eq!(
+			db.put_if_version(0, key, 1, Some(b"v1".to_vec())).unwrap(),
+			PutIfVersionOutcome::Conflict(0)
+		);
+		assert_eq!(
+			db.put_if_version(0, key, 0, Some(b"v1".to_vec())).unwrap(),
+			PutIfVersionOutcome::Success(1)
+		);
+		// The second of two writers that read version 1 fails.
+		assert_eq!(
+			db.put_if_version(0, key, 1, Some(b"v2".to_vec())).unwrap(),
+			PutIfVersionOutcome::Success(2)
+		);
+		assert_eq!(
+			db.put_if_version(0, key, 1, Some(b"v2'".to_vec())).unwrap(),
+			PutIfVersionOutcome::Conflict(2)
+		);
+		db_test.run_stages(&db);
+		assert_eq!(db.get(0, key).unwrap(), Some(b"v2".to_vec()));
+		assert_eq!(db.get_size(0, key).unwrap(), Some(2));
+		assert_eq!(db.get_versioned(0, key).unwrap(), Some((2, b"v2".to_vec())));
+
+		assert!(db.commit(vec![(0, key.to_vec(), Some(b"v3".to_vec()))]).is_err());
+		assert!(db.put_if_version(1, key, 0, Some(b"v1".to_vec())).is_err());
+		assert!(db.get_versioned(1, key).is_err());
+
+		assert_eq!(db.put_if_version(0, key, 2, None).unwrap(), PutIfVersionOutcome::Success(0));
+		db_test.run_stages(&db);
+		assert_eq!(db.get(0, key).unwrap(), None);
+		drop(db);
+
+		// Versions survive reopening.
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert_eq!(
+			db.put_if_version(0, key, 0, Some(b"v1".to_vec())).unwrap(),
+			PutIfVersionOutcome::Success(1)
+		);
+		db_test.run_stages(&db);
+		drop(db);
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert_eq!(db.get_versioned(0, key).unwrap(), Some((1, b"v1".to_vec())));
+	}
+
+	#[test]
+	fn test_verbose_trace() {
+		use std::cell::RefCell;
+
+		thread_local! {
+			static TRACE: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+		}
+		// Captures parity-db log lines of the threads that enabled capturing.
+		struct TraceCapture;
+		impl log::Log for TraceCapture {
+			fn enabled(&self, metadata: &log::Metadata) -> bool {
+				metadata.target() == "parity-db" && TRACE.with(|t| t.borrow().is_some())
+			}
+			fn log(&self, record: &log::Record) {
+				if self.enabled(record.metadata()) {
+					let line = record.args().to_string();
+					TRACE.with(|t| t.borrow_mut().as_mut().unwrap().push(line));
+				}
+			}
+			fn flush(&self) {}
+		}
+		static LOGGER: TraceCapture = TraceCapture;
+		log::set_logger(&LOGGER).unwrap();
+		log::set_max_level(log::LevelFilter::Trace);
+		TRACE.with(|t| *t.borrow_mut() = Some(Vec::new()));
+
+		let tmp = tempdir().unwrap();
+		// Plans are made on this thread.
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.verbose_trace.insert(0);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let value: Vec<u8> = (0..100).collect();
+		db.commit(vec![
+			(0, b"key".to_vec(), Some(value.clone())),
+			(1, b"key".to_vec(), Some(value.clone())),
+		])
+		.unwrap();
+		db_test.run_stages(&db);
+		let trace = TRACE.with(|t| t.borrow_mut().take().unwrap());
+
+		let logged = format!("value = {}", crate::display::hex(&value[..64]));
+		let inserts: Vec<_> = trace.iter().filter(|line| line.contains("Inserting new")).collect();
+		assert_eq!(inserts.len(), 2);
+		assert!(inserts.iter().any(|line| line.starts_with("0: ") && line.ends_with(&logged)));
+		assert!(inserts.iter().any(|line| line.starts_with("1: ") && !line.contains("value =")));
+	}
+
+	#[test]
+	fn test_versioned_reads() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[0].versioning = true;
+		options.columns[0].uniform = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = [0x12; 32];
+		let other = [0x34; 32];
+
+		db.put_if_version(0, &key, 0, Some(b"v1".to_vec())).unwrap();
+		db.put_if_version(0, &key, 1, Some(b"v2".to_vec())).unwrap();
+		db.commit(vec![(1, other, Some(b"plain".to_vec()))]).unwrap();
+		db_test.run_stages(&db);
+
+		assert_eq!(
+			db.get_many_cross_column(&key, &[0, 1]).unwrap(),
+			vec![Some(b"v2".to_vec()), None]
+		);
+		assert_eq!(db.get_with_fallback(0, 1, &key).unwrap(), Some(b"v2".to_vec()));
+		assert_eq!(db.get_with_fallback(1, 0, &key).unwrap(), Some(b"v2".to_vec()));
+		assert_eq!(db.get_with_fallback(0, 1, &other).unwrap(), Some(b"plain".to_vec()));
+		let found: Vec<_> = db.scan_prefix(0, &[0x12]).unwrap().map(|e| e.unwrap()).collect();
+		assert_eq!(found, vec![(key, b"v2".to_vec())]);
+		assert_eq!(db.read_transaction().get(0, &key).unwrap(), Some(b"v2".to_vec()));
+	}
+
+	#[test]
+	fn test_write_batch() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::LogOverlay;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = |i: u32| i.to_le_bytes().to_vec();
+
+		let mut batch: Vec<_> = (0..1000).map(|i| (key(i), Some(key(i)))).collect();
+		batch.push((key(0), None));
+		batch.push((key(1), Some(b"last".to_vec())));
+		for col in 0..2 {
+			assert!(matches!(
+				db.write_batch(col, &batch, DuplicateKeys::Reject),
+				Err(crate::Error::InvalidInput(_))
+			));
+			db.write_batch(col, &batch, DuplicateKeys::LastWins).unwrap();
+		}
+		assert!(db.write_batch(2, &batch, DuplicateKeys::LastWins).is_err());
+		assert_eq!(db.inner.commit_queue.lock().commits.len(), 2);
+		db_test.run_stages(&db);
+		// Logged but not enacted.
+		drop(db);
+
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		for col in 0..2 {
+			assert_eq!(db.get(col, &key(0)).unwrap(), None);
+			assert_eq!(db.get(col, &key(1)).unwrap(), Some(b"last".to_vec()));
+			for i in 2..1000 {
+				assert_eq!(db.get(col, &key(i)).unwrap(), Some(key(i)));
+			}
+		}
+	}
+
+	#[test]
+	fn test_write_batch_ref_counted() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.columns[0].ref_counted = true;
+		options.columns[0].preimage = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = b"key".to_vec();
+		let value = Some(b"value".to_vec());
+
+		for _ in 0..3 {
+			db.commit::<_, Vec<u8>>(vec![(0, key.clone(), value.clone())]).unwrap();
+		}
+		db_test.run_stages(&db);
+		// Each removal drops a reference, with either policy.
+		db.write_batch(0, &[(&key, None), (&key, None)], DuplicateKeys::Reject).unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(db.get(0, &key).unwrap(), value);
+		db.write_batch(
+			0,
+			&[(&key, None), (&key, value.clone()), (&key, value.clone())],
+			DuplicateKeys::LastWins,
+		)
+		.unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(db.get(0, &key).unwrap(), value);
+		db.write_batch(0, &[(&key, None), (&key, None)], DuplicateKeys::LastWins)
+			.unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(db.get(0, &key).unwrap(), None);
+	}
+
+	#[test]
+	fn test_compare_and_swap() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 3);
+		options.columns[1].btree_index = true;
+		options.columns[2].ref_counted = true;
+		options.columns[2].preimage = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = b"key";
+
+		for col in 0..2 {
+			assert!(!db.compare_and_swap(col, key, Some(b"v0"), Some(b"v1".to_vec())).unwrap());
+			assert!(db.compare_and_swap(col, key, None, Some(b"v1".to_vec())).unwrap());
+			assert!(!db.compare_and_swap(col, key, None, Some(b"v2".to_vec())).unwrap());
+			// Compared against the queued commit.
+			assert!(db.compare_and_swap(col, key, Some(b"v1"), Some(b"v2".to_vec())).unwrap());
+			db_test.run_stages(&db);
+			assert_eq!(db.get(col, key).unwrap(), Some(b"v2".to_vec()));
+			assert!(!db.compare_and_swap(col, key, Some(b"v1"), None).unwrap());
+			assert!(db.compare_and_swap(col, key, Some(b"v2"), None).unwrap());
+			db_test.run_stages(&db);
+			assert_eq!(db.get(col, key).unwrap(), None);
+		}
+		assert!(matches!(
+			db.compare_and_swap(2, key, None, Some(b"v1".to_vec())),
+			Err(crate::Error::InvalidConfiguration(_))
+		));
+		assert!(matches!(
+			db.compare_and_swap(3, key, None, None),
+			Err(crate::Error::InvalidInput(_))
+		));
+
+		// Concurrent increments of a counter, each retried until its swap succeeds.
+		let db = std::sync::Arc::new(db);
+		let threads: Vec<_> = (0..4)
+			.map(|_| {
+				let db = db.clone();
+				std::thread::spawn(move || {
+					for _ in 0..100 {
+						loop {
+							let current = db.get(0, key).unwrap();
+							let count = current
+								.as_ref()
+								.map_or(0, |v| u32::from_le_bytes(v[..].try_into().unwrap()));
+							let next = (count + 1).to_le_bytes().to_vec();
+							if db.compare_and_swap(0, key, current.as_deref(), Some(next)).unwrap()
+							{
+								break
+							}
+						}
+					}
+				})
+			})
+			.collect();
+		for thread in threads {
+			thread.join().unwrap();
+		}
+		assert_eq!(db.get(0, key).unwrap(), Some(400u32.to_le_bytes().to_vec()));
+		db_test.run_stages(&db);
+		assert_eq!(db.get(0, key).unwrap(), Some(400u32.to_le_bytes().to_vec()));
+	}
+
+	#[test]
+	fn test_iter_with_offset() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit((0..100u32).map(|i| (0, i.to_le_bytes(), Some(i.to_le_bytes().to_vec()))))
+			.unwrap();
+		db_test.run_stages(&db);
+
+		let mut values = HashSet::new();
+		let mut cursor = 0;
+		loop {
+			let mut iter = db.iter_with_offset(0, cursor).unwrap();
+			let mut page = 0;
+			while page < 30 {
+				match iter.next().unwrap() {
+					Some((_, value)) => assert!(values.insert(value)),
+					None => break,
+				}
+				page += 1;
+			}
+			if page < 30 {
+				break
+			}
+			cursor = iter.cursor();
+		}
+		assert_eq!(values.len(), 100);
+
+		// Cursors stay valid after reopening.
+		let mut iter = db.iter_with_offset(0, 0).unwrap();
+		let first = iter.next().unwrap().unwrap();
+		let cursor = iter.cursor();
+		let second = iter.next().unwrap().unwrap();
+		drop(db);
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		let mut iter = db.iter_with_offset(0, cursor).unwrap();
+		assert_eq!(iter.next().unwrap().unwrap(), second);
+		assert_ne!(first, second);
+		assert!(db.iter_with_offset(1, 0).is_err());
+
+		// Cursors with out of range index bits are rejected.
+		for bits in [1u64, 7, 255] {
+			assert!(matches!(
+				db.iter_with_offset(0, bits << 56),
+				Err(crate::Error::InvalidInput(_))
+			));
+			assert!(matches!(
+				db.iter_with_offset(0, (bits << 56) | (1 << 55) | 1),
+				Err(crate::Error::InvalidInput(_))
+			));
+		}
+	}
+
+	#[test]
+	fn test_iter_with_offset_reindex() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.columns[0].uniform = true;
+		options.columns[0].start_bits = 8;
+		options.salt = Some(Default::default());
+		options.reindex_batch_size.insert(0, 1);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = |chunk: u8, i: u8| {
+			let mut key = [0u8; 32];
+			key[0] = chunk;
+			key[1] = i;
+			key[31] = 1;
+			key
+		};
+		let page = |db: &Db, cursor: u64, values: &mut HashSet<Vec<u8>>| {
+			let mut iter = db.iter_with_offset(0, cursor).unwrap();
+			for _ in 0..7 {
+				match iter.next().unwrap() {
+					Some((_, value)) => assert!(values.insert(value)),
+					None => return None,
+				}
+			}
+			Some(iter.cursor())
+		};
+
+		// Overflow chunk 0 to start a reindex and move the first chunk of the old index.
+		let mut keys: Vec<_> = (1..=16).map(|chunk| key(chunk, 0)).collect();
+		db.commit(keys.iter().map(|k| (0, *k, Some(k.to_vec())))).unwrap();
+		db_test.run_stages(&db);
+		keys.extend((0..65).map(|i| key(0, i * 2)));
+		db.commit(keys[16..].iter().map(|k| (0, *k, Some(k.to_vec())))).unwrap();
+		let enact = |db: &Db| {
+			db.inner.log.flush_one(0).unwrap();
+			while db.inner.enact_logs(false).unwrap() {}
+		};
+		while db.inner.process_commits().unwrap() {}
+		enact(&db);
+		assert!(db.inner.process_reindex().unwrap());
+		enact(&db);
+		assert_eq!(db.inner.columns[0].reindex_queue_len(), 1);
+
+		// Each key is returned once, from the main index or the queued one.
+		let mut values = HashSet::new();
+		let mut cursor = 0;
+		let mut queued = None;
+		while let Some(next) = page(&db, cursor, &mut values) {
+			// Cursors hold the index bits of the walked table.
+			if next >> 56 == 8 {
+				queued.get_or_insert(next);
+			}
+			cursor = next;
+		}
+		assert_eq!(values.len(), keys.len());
+		assert!(keys.iter().all(|k| values.contains(k.as_slice())));
+
+		// Once the queued index is dropped, its cursors start over at the main index.
+		while db.inner.process_reindex().unwrap() {
+			enact(&db);
+		}
+		db_test.run_stages(&db);
+		assert_eq!(db.inner.columns[0].reindex_queue_len(), 0);
+		let mut values = HashSet::new();
+		let mut cursor = queued.unwrap();
+		while let Some(next) = page(&db, cursor, &mut values) {
+			cursor = next;
+		}
+		assert_eq!(values.len(), keys.len());
+	}
+
+	#[test]
+	fn test_iter_concurrent_modification() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let options = db_test.options(tmp.path(), 1);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit((0..10u32).map(|i| (0, i.to_le_bytes(), Some(i.to_le_bytes().to_vec()))))
+			.unwrap();
+		db_test.run_stages(&db);
+
+		let mut iter = db.iter_with_offset(0, 0).unwrap();
+		let mut values = HashSet::new();
+		values.insert(iter.next().unwrap().unwrap().1);
+		db.commit(vec![(0, 10u32.to_le_bytes(), Some(10u32.to_le_bytes().to_vec()))])
+			.unwrap();
+		db_test.run_stages(&db);
+		assert!(matches!(iter.next(), Err(crate::Error::ConcurrentModification)));
+
+		let mut iter = db.iter_with_offset(0, iter.cursor()).unwrap();
+		while let Some((_, value)) = iter.next().unwrap() {
+			values.insert(value);
+		}
+		assert!(values.len() >= 10);
+	}
+
+	#[cfg(feature = "async-tokio")]
+	#[test]
+	fn test_watch_rebalance() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.columns[0].uniform = true;
+		options.salt = Some(Default::default());
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let mut events = db.watch_rebalance();
+
+		// Keys with the same prefix go to the same index chunk, so this overflows it.
+		let key = |i: u8| {
+			let mut key = [0u8; 32];
+			key[0] = 1;
+			key[31] = i;
+			key
+		};
+		db.commit((0..65u8).map(|i| (0, key(i), Some(vec![i])))).unwrap();
+		db_test.run_stages(&db);
+
+		let mut last = None;
+		while let Ok(progress) = events.try_recv() {
+			assert_eq!(progress.col, 0);
+			last = Some(progress);
+		}
+		let last = last.unwrap();
+		assert_eq!(last.processed_chunks, last.total_chunks);
+		assert_eq!(db.get(0, &key(64)).unwrap(), Some(vec![64]));
+	}
+
+	#[test]
+	fn test_column_actions_replayed() {
+		let tmp = tempdir().unwrap();
+		let mut options = EnableCommitPipelineStages::Standard.options(tmp.path(), 1);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit(vec![(0, b"key".to_vec(), Some(b"value".to_vec()))]).unwrap();
+		drop(db);
+
+		// Column creation interrupted before the metadata was written.
+		super::log_column_action(&options, |writer| {
+			writer.create_column(1, ColumnOptions::default())
+		})
+		.unwrap();
+		options.columns.push(ColumnOptions::default());
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		db.commit(vec![(1, b"key".to_vec(), Some(b"value".to_vec()))]).unwrap();
+		drop(db);
+		assert_eq!(Options::load_metadata(tmp.path()).unwrap().unwrap().columns.len(), 2);
+
+		// Column removal interrupted before all files were deleted.
+		super::log_column_action(&options, |writer| writer.drop_column(1)).unwrap();
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert!(db.get_column_file_paths(1).unwrap().is_empty());
+		assert_eq!(db.get(0, b"key").unwrap(), Some(b"value".to_vec()));
+		assert_eq!(db.get(1, b"key").unwrap(), None);
+		db.commit(vec![(1, b"key".to_vec(), Some(b"value".to_vec()))]).unwrap();
+		drop(db);
+
+		// Completed actions are not applied again.
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert_eq!(db.get(1, b"key").unwrap(), Some(b"value".to_vec()));
+		drop(db);
+		crate::clear_column(tmp.path(), 1).unwrap();
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert_eq!(db.get(1, b"key").unwrap(), None);
+		assert_eq!(db.get(0, b"key").unwrap(), Some(b"value".to_vec()));
+	}
+
+	#[test]
+	fn test_fragmentation_report() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let options = db_test.options(tmp.path(), 2);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = |i: u32| i.to_le_bytes().to_vec();
+		db.commit((0..2000).map(|i| (0, key(i), Some(vec![1; 100])))).unwrap();
+		db.commit((0..1500).map(|i| (0, key(i), None))).unwrap();
+		db_test.run_stages(&db);
+
+		let report = db.get_fragmentation_report().unwrap();
+		let tier = db.get_tier_for_value(0, 100).unwrap() as u8;
+		assert_eq!(report.tiers.len(), 1);
+		let table = &report.tiers[0];
+		assert_eq!((table.col, table.tier), (0, tier));
+		assert_eq!(table.bytes_allocated, table.bytes_live + table.bytes_free);
+		assert_eq!(table.bytes_free, table.bytes_allocated / 2000 * 1500);
+		assert_eq!(report.total_bytes_allocated, table.bytes_allocated);
+		assert_eq!(report.total_bytes_free, table.bytes_free);
+		assert_eq!(
+			report.recommendations,
+			vec![format!("Column 0 tier {} is 75% fragmented; consider compacting.", tier)]
+		);
+	}
+
+	#[test]
+	fn test_maintenance_report() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		#[cfg(feature = "bloom")]
+		options.bloom_false_positive_rate.insert(0, 0.01);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		assert_eq!(db.maintenance_report().unwrap(), crate::MaintenanceReport::default());
+
+		let key = |i: u32| i.to_le_bytes().to_vec();
+		db.commit((0..2000).map(|i| (0, key(i), Some(vec![1; 100])))).unwrap();
+		db.commit((0..2000).map(|i| (1, key(i), Some(vec![1; 100])))).unwrap();
+		db.commit((0..1500).map(|i| (0, key(i), None))).unwrap();
+		db_test.run_stages(&db);
+
+		let report = db.maintenance_report().unwrap();
+		let compact = &report.actions[0];
+		assert_eq!(compact.severity, crate::MaintenanceSeverity::High);
+		assert_eq!((compact.col, compact.action), (0, crate::MaintenanceKind::Compact));
+		assert!(compact.estimated_duration > std::time::Duration::ZERO);
+		#[cfg(feature = "bloom")]
+		{
+			let rebuild = &report.actions[1];
+			assert_eq!(rebuild.severity, crate::MaintenanceSeverity::Medium);
+			assert_eq!((rebuild.col, rebuild.action), (0, crate::MaintenanceKind::RebuildBloom));
+			db.rebuild_bloom(0).unwrap();
+		}
+		assert_eq!(report.actions.len(), if cfg!(feature = "bloom") { 2 } else { 1 });
+
+		#[cfg(feature = "serde")]
+		{
+			let json = serde_json::to_string(&report).unwrap();
+			assert!(json.contains(r#""action":"Compact""#));
+			let parsed: crate::MaintenanceReport = serde_json::from_str(&json).unwrap();
+			assert_eq!(parsed, report);
+		}
+
+		// Removed entries are reused.
+		db.commit((0..1500).map(|i| (0, key(i), Some(vec![1; 100])))).unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(db.maintenance_report().unwrap(), crate::MaintenanceReport::default());
+	}
+
+	#[test]
+	fn test_read_transaction() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit(vec![
+			(0, b"key1".to_vec(), Some(b"value1".to_vec())),
+			(0, b"key2".to_vec(), Some(b"value2".to_vec())),
+			(1, b"key1".to_vec(), Some(b"value1".to_vec())),
+		])
+		.unwrap();
+		db_test.run_stages(&db);
+
+		let tx = db.read_transaction();
+		assert_eq!(tx.get(0, b"key1").unwrap(), Some(b"value1".to_vec()));
+		db.commit(vec![
+			(0, b"key1".to_vec(), Some(b"new".to_vec())),
+			(0, b"key2".to_vec(), None),
+			(0, b"key3".to_vec(), Some(b"value3".to_vec())),
+			(1, b"key1".to_vec(), Some(b"new".to_vec())),
+		])
+		.unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(tx.get(0, b"key1").unwrap(), Some(b"value1".to_vec()));
+		assert_eq!(tx.get(0, b"key2").unwrap(), Some(b"value2".to_vec()));
+		assert_eq!(tx.get(0, b"key3").unwrap(), None);
+		assert_eq!(tx.get(1, b"key1").unwrap(), Some(b"value1".to_vec()));
+		assert_eq!(db.get(0, b"key1").unwrap(), Some(b"new".to_vec()));
+		assert_eq!(db.get(1, b"key1").unwrap(), Some(b"new".to_vec()));
+		assert!(tx.get(2, b"key1").is_err());
+
+		drop(tx);
+		let tx = db.read_transaction();
+		assert_eq!(db.inner.read_snapshots.lock().len(), 1);
+		assert_eq!(tx.get(0, b"key1").unwrap(), Some(b"new".to_vec()));
+	}
+
+	#[test]
+	fn test_punch_holes() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let options = db_test.options(tmp.path(), 1);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = |i: u8| vec![i];
+		db.commit((0..8).map(|i| (0, key(i), Some(vec![i; 20000])))).unwrap();
+		db_test.run_stages(&db);
+		db.commit((0..8).step_by(2).map(|i| (0, key(i), None))).unwrap();
+		// Pending removals are not released.
+		assert_eq!(db.punch_holes(0).unwrap(), 0);
+		db_test.run_stages(&db);
+
+		let released = db.punch_holes(0).unwrap();
+		assert_eq!(released % 4096, 0);
+		#[cfg(target_os = "linux")]
+		assert!(released >= 4 * 8192);
+		for i in 0..8 {
+			let expected = if i % 2 == 0 { None } else { Some(vec![i; 20000]) };
+			assert_eq!(db.get(0, &key(i)).unwrap(), expected);
+		}
+		// Released entries are reused.
+		db.commit((0..8).step_by(2).map(|i| (0, key(i), Some(vec![i + 1; 20000]))))
+			.unwrap();
+		db_test.run_stages(&db);
+		for i in (0..8).step_by(2) {
+			assert_eq!(db.get(0, &key(i)).unwrap(), Some(vec![i + 1; 20000]));
+		}
+		assert_eq!(db.get_fragmentation_report().unwrap().total_bytes_free, 0);
+	}
+
+	#[test]
+	fn test_defragment_concurrent_commits() {
+		const COMMITS: u32 = 2000;
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::Standard;
+		let options = db_test.options(tmp.path(), 2);
+		let db = std::sync::Arc::new(Db::open_inner(&options, OpeningMode::Create).unwrap());
+		db.commit((0..100u32).map(|i| (1, i.to_le_bytes(), Some(vec![1; 100]))))
+			.unwrap();
+
+		// Logs written out and cleaned by `defragment` are handled in order along with the
+		// workers.
+		let writer = {
+			let db = db.clone();
+			std::thread::spawn(move || {
+				for i in 0..COMMITS {
+					db.commit(vec![(0, b"key".to_vec(), Some(i.to_le_bytes().to_vec()))]).unwrap();
+				}
+			})
+		};
+		while !writer.is_finished() {
+			db.defragment(1).unwrap();
+		}
+		writer.join().unwrap();
+		drop(db);
+
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert_eq!(db.get(0, b"key").unwrap(), Some((COMMITS - 1).to_le_bytes().to_vec()));
+		assert_eq!(db.get(1, &99u32.to_le_bytes()).unwrap(), Some(vec![1; 100]));
+	}
+
+	#[test]
+	fn test_defragment() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let tier = db.get_tier_for_value(0, 100).unwrap();
+		let file = tmp.path().join(crate::table::TableId::new(0, tier as u8).file_name());
+		let key = |i: u32| i.to_le_bytes();
+		let value = |i: u32| vec![i as u8; 100];
+		db.commit((0..10_000).map(|i| (0, key(i), Some(value(i))))).unwrap();
+		db_test.run_stages(&db);
+		db.commit((0..10_000).step_by(2).map(|i| (0, key(i), None))).unwrap();
+		db_test.run_stages(&db);
+		let len = std::fs::metadata(&file).unwrap().len();
+
+		let released = db.defragment(0).unwrap();
+		let new_len = std::fs::metadata(&file).unwrap().len();
+		assert_eq!(released, len - new_len);
+		assert!(new_len * 2 <= len + 4096, "{} -> {}", len, new_len);
+		assert!(db.defragment(1).is_err());
+		for i in 0..10_000 {
+			let expected = if i % 2 == 0 { None } else { Some(value(i)) };
+			assert_eq!(db.get(0, &key(i)).unwrap(), expected);
+		}
+		assert_eq!(db.defragment(0).unwrap(), 0);
+
+		db.commit(vec![(0, key(0), Some(value(0)))]).unwrap();
+		db_test.run_stages(&db);
+		drop(db);
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert_eq!(db.get(0, &key(0)).unwrap(), Some(value(0)));
+		assert_eq!(db.get(0, &key(2)).unwrap(), None);
+		assert_eq!(db.get(0, &key(9_999)).unwrap(), Some(value(9_999)));
+		assert_eq!(db.get_fragmentation_report().unwrap().total_bytes_free, 0);
+	}
+
+	#[test]
+	fn test_audit_log() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		let audit_path = tmp.path().join("audit.log");
+		options.audit_log = Some(audit_path.clone());
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit(vec![
+			(0, b"key1".to_vec(), Some(vec![1; 256])),
+			(1, b"key1".to_vec(), Some(vec![1; 10])),
+		])
+		.unwrap();
+		db.commit(vec![(1, b"key1".to_vec(), None)]).unwrap();
+		// Nothing is recorded until commits are enacted.
+		while db.inner.process_commits().unwrap() {}
+		db.inner.log.flush_one(0).unwrap();
+		assert_eq!(std::fs::read_to_string(&audit_path).unwrap(), "");
+		db_test.run_stages(&db);
+
+		let audit = std::fs::read_to_string(&audit_path).unwrap();
+		let lines: Vec<_> = audit.lines().collect();
+		assert_eq!(lines.len(), 3);
+		let hashed_key = match &db.inner.columns[0] {
+			Column::Hash(column) => column.hash_key(b"key1"),
+			Column::Tree(_) => unreachable!(),
+		};
+		let without_ts = |line: &str| line.split_once(",\"col\"").unwrap().1.to_string();
+		assert!(lines[0].starts_with("{\"ts\":"));
+		let mut first: Vec<_> = lines[..2].iter().map(|l| without_ts(l)).collect();
+		first.sort();
+		assert_eq!(
+			first,
+			vec![
+				format!(
+					":0,\"key\":\"0x{}\",\"op\":\"insert\",\"len\":256}}",
+					hex::encode(hashed_key)
+				),
+				format!(":1,\"key\":\"0x{}\",\"op\":\"insert\",\"len\":10}}", hex::encode(b"key1")),
+			]
+		);
+		assert_eq!(
+			without_ts(lines[2]),
+			format!(":1,\"key\":\"0x{}\",\"op\":\"remove\",\"len\":0}}", hex::encode(b"key1"))
+		);
+		drop(db);
+
+		// The audit log must be writable.
+		options.audit_log = Some(tmp.path().to_path_buf());
+		assert!(Db::open_inner(&options, OpeningMode::Write).is_err());
+	}
+
+	#[test]
+	fn test_resize_index_in_place() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = |i: u32| i.to_le_bytes();
+		db.commit((0..1000).map(|i| (0, key(i), Some(key(i).to_vec())))).unwrap();
+		db.commit((0..334).map(|i| (0, key(i), None))).unwrap();
+		db_test.run_stages(&db);
+
+		db.resize_index_in_place(0).unwrap();
+		let expected = |i: u32| (i >= 334).then(|| key(i).to_vec());
+		for i in 0..1000 {
+			assert_eq!(db.get(0, &key(i)).unwrap(), expected(i));
+		}
+		// The resize must be enacted first.
+		assert!(db.resize_index_in_place(0).is_err());
+		assert!(db.resize_index_in_place(1).is_err());
+		db_test.run_stages(&db);
+		assert!(tmp.path().join("index_00_17").exists());
+		assert!(!tmp.path().join("index_00_16").exists());
+		for i in 0..1000 {
+			assert_eq!(db.get(0, &key(i)).unwrap(), expected(i));
+		}
+
+		db.resize_index_in_place(0).unwrap();
+		db.commit((1000..1100).map(|i| (0, key(i), Some(key(i).to_vec())))).unwrap();
+		db_test.run_stages(&db);
+		drop(db);
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert!(tmp.path().join("index_00_18").exists());
+		for i in 0..1100 {
+			let value = if i < 1000 { expected(i) } else { Some(key(i).to_vec()) };
+			assert_eq!(db.get(0, &key(i)).unwrap(), value);
+		}
+		let mut entries = 0;
+		db.iter_index_entries(0, |_, _, _| {
+			entries += 1;
+			true
+		})
+		.unwrap();
+		assert_eq!(entries, 766);
+	}
+
+	#[test]
+	fn test_wal_archiving() {
+		let tmp = tempdir().unwrap();
+		let archive = tmp.path().join("archive");
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let options = db_test.options(&tmp.path().join("db"), 1);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let archived = || -> Vec<String> {
+			let mut names: Vec<_> = std::fs::read_dir(&archive)
+				.unwrap()
+				.map(|e| e.unwrap().file_name().into_string().unwrap())
+				.collect();
+			names.sort();
+			names
+		};
+
+		db.enable_wal_archiving(archive.clone()).unwrap();
+		db.commit(vec![(0, b"key1".to_vec(), Some(b"value1".to_vec()))]).unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(archived(), vec!["wal_00000000000000000001".to_string()]);
+		let contents = std::fs::read(archive.join("wal_00000000000000000001")).unwrap();
+		assert!(contents.windows(6).any(|w| w == b"value1"));
+
+		db.disable_wal_archiving().unwrap();
+		db.commit(vec![(0, b"key2".to_vec(), Some(b"value2".to_vec()))]).unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(archived().len(), 1);
+
+		// A commit that is already in the current log file is archived too.
+		db.commit(vec![(0, b"key3".to_vec(), Some(b"value3".to_vec()))]).unwrap();
+		db.inner.process_commits().unwrap();
+		db.enable_wal_archiving(archive.clone()).unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(archived().len(), 2);
+		let contents = std::fs::read(archive.join(&archived()[1])).unwrap();
+		assert!(contents.windows(6).any(|w| w == b"value3"));
+
+		// Archiving errors don't stop the log from being enacted.
+		std::fs::remove_dir_all(&archive).unwrap();
+		db.commit(vec![(0, b"key4".to_vec(), Some(b"value4".to_vec()))]).unwrap();
+		db_test.run_stages(&db);
+		db.commit(vec![(0, b"key5".to_vec(), Some(b"value5".to_vec()))]).unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(db.get(0, b"key5").unwrap(), Some(b"value5".to_vec()));
+		assert!(db_test.check_empty_overlay(&db.inner, 0));
+	}
+
+	#[test]
+	fn test_trim_rebalance_queue() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.columns[0].uniform = true;
+		options.salt = Some(Default::default());
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = |i: u8| {
+			let mut key = [0u8; 32];
+			key[0] = 1;
+			key[31] = i;
+			key
+		};
+		// The last key overflows the index chunk and starts a reindex.
+		db.commit((0..65u8).map(|i| (0, key(i), Some(vec![i])))).unwrap();
+		while db.inner.process_commits().unwrap() {}
+		assert_eq!(db.trim_rebalance_queue(0).unwrap(), 0);
+
+		// Remove all keys that are still in the old index.
+		db.commit((0..64u8).map(|i| (0, key(i), None))).unwrap();
+		while db.inner.process_commits().unwrap() {}
+		assert_eq!(db.trim_rebalance_queue(0).unwrap(), 1);
+		db.inner.log.flush_one(0).unwrap();
+		while db.inner.enact_logs(false).unwrap() {}
+		assert!(!tmp.path().join("index_00_16").exists());
+		assert!(tmp.path().join("index_00_17").exists());
+		assert_eq!(db.trim_rebalance_queue(0).unwrap(), 0);
+		db_test.run_stages(&db);
+		assert_eq!(db.get(0, &key(0)).unwrap(), None);
+		assert_eq!(db.get(0, &key(64)).unwrap(), Some(vec![64]));
+	}
+
+	#[test]
+	fn test_reindex_batch_size() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[0].uniform = true;
+		options.columns[0].start_bits = 8;
+		options.columns[1].btree_index = true;
+		options.salt = Some(Default::default());
+		options.reindex_batch_size.insert(0, 1);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = |chunk: u8, i: u8| {
+			let mut key = [0u8; 32];
+			key[0] = chunk;
+			key[1] = i;
+			key[31] = 1;
+			key
+		};
+		let reindex_batches = |db: &Db| {
+			while db.inner.process_commits().unwrap() {}
+			// Each batch is created once the previous one is enacted.
+			let mut batches = 0;
+			loop {
+				db.inner.log.flush_one(0).unwrap();
+				while db.inner.enact_logs(false).unwrap() {}
+				if !db.inner.process_reindex().unwrap() {
+					break
+				}
+				batches += 1;
+			}
+			db_test.run_stages(db);
+			batches
+		};
+		let check = |db: &Db, keys: &[[u8; 32]]| {
+			for key in keys {
+				assert_eq!(db.get(0, key).unwrap(), Some(key.to_vec()));
+			}
+		};
+
+		// One key in each of 16 index chunks, followed by a chunk that overflows and starts a
+		// reindex. Each batch moves a single non-empty chunk, the last one drops the old index.
+		let mut keys: Vec<_> = (1..=16).map(|chunk| key(chunk, 0)).collect();
+		db.commit(keys.iter().map(|k| (0, *k, Some(k.to_vec())))).unwrap();
+		db_test.run_stages(&db);
+		keys.extend((0..65).map(|i| key(0, i * 2)));
+		db.commit(keys[16..].iter().map(|k| (0, *k, Some(k.to_vec())))).unwrap();
+		assert_eq!(reindex_batches(&db), 18);
+		assert!(tmp.path().join("index_00_9").exists());
+		check(&db, &keys);
+
+		// Everything is moved at once.
+		db.set_reindex_batch_size(0, u32::MAX).unwrap();
+		keys.extend((0..8).map(|i| key(0, i * 2 + 1)));
+		db.commit(keys[81..].iter().map(|k| (0, *k, Some(k.to_vec())))).unwrap();
+		assert_eq!(reindex_batches(&db), 1);
+		assert!(tmp.path().join("index_00_10").exists());
+		check(&db, &keys);
+
+		assert!(matches!(
+			db.set_reindex_batch_size(1, 1),
+			Err(crate::Error::InvalidConfiguration(_))
+		));
+		assert!(matches!(db.set_reindex_batch_size(2, 1), Err(crate::Error::InvalidInput(_))));
+	}
+
+	#[test]
+	fn test_reserve() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[0].start_bits = 8;
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit((0..100u32).map(|i| (0, i.to_le_bytes(), Some(vec![1; 10])))).unwrap();
+		db_test.run_stages(&db);
+
+		// Half of 1024 chunks of 64 entries.
+		db.reserve(0, 32 * 1024).unwrap();
+		// Entries are moved once the reserve record is enacted.
+		db_test.run_stages(&db);
+		db_test.run_stages(&db);
+		assert!(!tmp.path().join("index_00_8").exists());
+		assert!(tmp.path().join("index_00_10").exists());
+		for i in 0..100u32 {
+			assert_eq!(db.get(0, &i.to_le_bytes()).unwrap(), Some(vec![1; 10]));
+		}
+
+		// The index is never shrunk.
+		db.reserve(0, 1000).unwrap();
+		db.commit(vec![(0, 100u32.to_le_bytes(), Some(vec![1; 10]))]).unwrap();
+		db_test.run_stages(&db);
+		assert!(tmp.path().join("index_00_10").exists());
+		assert!(!tmp.path().join("index_00_11").exists());
+		assert!(matches!(db.reserve(1, 1000), Err(crate::Error::InvalidConfiguration(_))));
+		// More entries than the index entry format can address.
+		assert!(matches!(db.reserve(0, u64::MAX), Err(crate::Error::InvalidInput(_))));
+		assert!(matches!(db.reserve(0, 1 << 56), Err(crate::Error::InvalidInput(_))));
+	}
+
+	#[cfg(feature = "parallel-reindex")]
+	#[test]
+	fn test_parallel_reindex() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.columns[0].uniform = true;
+		options.columns[0].start_bits = 8;
+		options.salt = Some(Default::default());
+		options.reindex_batch_size.insert(0, 128);
+		options.parallel_reindex.insert(0);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = |chunk: u8, i: u8| {
+			let mut key = [0u8; 32];
+			key[0] = chunk;
+			key[1] = i;
+			key[31] = 1;
+			key
+		};
+
+		// 40 keys in each of 16 index chunks, followed by a chunk that overflows and starts a
+		// reindex.
+		let mut keys: Vec<_> =
+			(0..16).flat_map(|chunk| (0..40).map(move |i| key(chunk, i * 4))).collect();
+		db.commit(keys.iter().map(|k| (0, *k, Some(k.to_vec())))).unwrap();
+		db_test.run_stages(&db);
+		keys.extend((0..65).map(|i| key(16, i * 2)));
+		db.commit(keys[640..].iter().map(|k| (0, *k, Some(k.to_vec())))).unwrap();
+		while db.inner.process_commits().unwrap() {}
+
+		// Batches are cut at the same chunks as when reading them one at a time: four batches
+		// of four chunks, then the overflowed chunk along with the empty rest of the table.
+		let mut batches = 0;
+		loop {
+			db.inner.log.flush_one(0).unwrap();
+			while db.inner.enact_logs(false).unwrap() {}
+			if !db.inner.process_reindex().unwrap() {
+				break
+			}
+			batches += 1;
+		}
+		db_test.run_stages(&db);
+		assert_eq!(batches, 5);
+		assert!(!tmp.path().join("index_00_8").exists());
+		for key in &keys {
+			assert_eq!(db.get(0, key).unwrap(), Some(key.to_vec()));
+		}
+	}
+
+	#[test]
+	fn test_reindex_sized_for_entries() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.columns[0].uniform = true;
+		options.columns[0].start_bits = 8;
+		options.salt = Some(Default::default());
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		// Key number `i` of commit `n` in index chunk `chunk` of an index with `bits` bits.
+		let key = |n: u8, bits: u8, chunk: u64, i: u8| {
+			let mut key = [0u8; 32];
+			let prefix = (chunk << (64 - bits)) | ((i as u64) << (56 - bits));
+			key[0..8].copy_from_slice(&prefix.to_be_bytes());
+			key[8] = n;
+			key[31] = 1;
+			key
+		};
+		// Values are spread over several size tiers, so that value table addresses fit the
+		// index entries.
+		let value = |i: u8| vec![i; 10 + (i as usize % 4) * 100];
+		let fill = |n: u8, bits: u8, per_chunk: u8| {
+			db.commit((0..1u64 << bits).flat_map(|chunk| {
+				(0..per_chunk).map(move |i| (0, key(n, bits, chunk, i), Some(value(i))))
+			}))
+			.unwrap();
+			while db.inner.process_commits().unwrap() {}
+		};
+		let overflow = |n: u8, bits: u8, count: u8| {
+			db.commit((0..count).map(|i| (0, key(n, bits, 1, i), Some(value(i))))).unwrap();
+			while db.inner.process_commits().unwrap() {}
+		};
+		fill(0, 8, 63);
+		db_test.run_stages(&db);
+
+		// Each index fills up before any entries are moved out of the previous one. The first
+		// two are doubled, as that is enough for the values in the column.
+		overflow(1, 8, 2);
+		fill(2, 9, 63);
+		overflow(3, 9, 2);
+		assert_eq!(db.reindex_queue_depth(0).unwrap(), 2);
+		// 98580 values take more than three quarters of an index with 11 bits.
+		fill(4, 10, 49);
+		overflow(5, 10, 16);
+		assert_eq!(db.reindex_queue_depth(0).unwrap(), 3);
+		db_test.run_stages(&db);
+		assert!(tmp.path().join("index_00_12").exists());
+		assert!(!tmp.path().join("index_00_11").exists());
+	}
+
+	#[test]
+	fn test_backup_column() {
+		let tmp = tempdir().unwrap();
+		let backup = tmp.path().join("backup");
+		let path = tmp.path().join("db");
+		let db_test = EnableCommitPipelineStages::CommitOverlay;
+		let mut options = db_test.options(&path, 2);
+		options.columns[0].start_bits = 8;
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit((0..100u32).map(|i| (0, i.to_le_bytes(), Some(vec![i as u8; 100]))))
+			.unwrap();
+		db.commit(vec![(1, b"key".to_vec(), Some(b"value".to_vec()))]).unwrap();
+		// Commits that are still queued are written to the column first.
+		db.backup_column(0, &backup).unwrap();
+		assert!(backup.join("manifest").exists());
+		assert!(matches!(db.backup_column(0, &backup), Err(crate::Error::InvalidInput(_))));
+
+		db.commit(vec![
+			(0, 0u32.to_le_bytes(), Some(vec![5; 100])),
+			(0, 100u32.to_le_bytes(), None),
+		])
+		.unwrap();
+		db.commit(vec![(0, 100u32.to_le_bytes(), Some(vec![1; 100]))]).unwrap();
+		db_test.run_stages(&db);
+		drop(db);
+
+		// A damaged backup is not restored.
+		let damaged = tmp.path().join("damaged");
+		std::fs::create_dir(&damaged).unwrap();
+		for entry in std::fs::read_dir(&backup).unwrap() {
+			let entry = entry.unwrap();
+			std::fs::copy(entry.path(), damaged.join(entry.file_name())).unwrap();
+		}
+		let index = std::fs::read_dir(&damaged)
+			.unwrap()
+			.map(|e| e.unwrap().path())
+			.find(|p| p.file_name().unwrap().to_str().unwrap().starts_with("index_00_"))
+			.unwrap();
+		let mut content = std::fs::read(&index).unwrap();
+		content[0] ^= 1;
+		std::fs::write(&index, content).unwrap();
+		assert!(matches!(
+			crate::restore_column(&path, 0, &damaged),
+			Err(crate::Error::Corruption(_))
+		));
+		assert!(matches!(
+			crate::restore_column(&path, 1, &backup),
+			Err(crate::Error::InvalidConfiguration(_))
+		));
+
+		crate::restore_column(&path, 0, &backup).unwrap();
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		for i in 0..100u32 {
+			assert_eq!(db.get(0, &i.to_le_bytes()).unwrap(), Some(vec![i as u8; 100]));
+		}
+		assert_eq!(db.get(0, &100u32.to_le_bytes()).unwrap(), None);
+		assert_eq!(db.get(1, b"key").unwrap(), Some(b"value".to_vec()));
+	}
+
+	#[test]
+	fn test_backup_column_concurrent_commits() {
+		const COMMITS: u32 = 2000;
+		let tmp = tempdir().unwrap();
+		let path = tmp.path().join("db");
+		let db_test = EnableCommitPipelineStages::Standard;
+		let options = db_test.options(&path, 2);
+		let db = std::sync::Arc::new(Db::open_inner(&options, OpeningMode::Create).unwrap());
+
+		// Logs written out by the backup are enacted in order along with the commit worker.
+		let writer = {
+			let db = db.clone();
+			std::thread::spawn(move || {
+				for i in 0..COMMITS {
+					db.commit(vec![(0, b"key".to_vec(), Some(i.to_le_bytes().to_vec()))]).unwrap();
+				}
+			})
+		};
+		let mut backups = 0;
+		while !writer.is_finished() {
+			db.backup_column(1, &tmp.path().join(format!("backup{}", backups))).unwrap();
+			backups += 1;
+		}
+		writer.join().unwrap();
+		drop(db);
+
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert_eq!(db.get(0, b"key").unwrap(), Some((COMMITS - 1).to_le_bytes().to_vec()));
+	}
+
+	#[test]
+	fn test_drop_index_out_of_order() {
+		use crate::index::TableId as IndexTableId;
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.columns[0].start_bits = 8;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit((0..100u32).map(|i| (0, i.to_le_bytes(), Some(vec![1; 10])))).unwrap();
+		db_test.run_stages(&db);
+		db.reserve(0, 32 * 1024).unwrap();
+		let column = match &db.inner.columns[0] {
+			Column::Hash(column) => column,
+			Column::Tree(_) => unreachable!(),
+		};
+		let log = db.inner.log.overlays();
+
+		// The current index is not in the reindex queue.
+		match column.drop_index(IndexTableId::new(0, 10), log) {
+			Err(crate::Error::Corruption(message)) => {
+				assert!(message.contains("00-10"));
+				assert!(message.contains("00-08"));
+			},
+			r => panic!("Unexpected result {:?}", r),
+		}
+		assert_eq!(db.reindex_queue_depth(0).unwrap(), 1);
+
+		column.drop_index(IndexTableId::new(0, 8), log).unwrap();
+		assert_eq!(db.reindex_queue_depth(0).unwrap(), 0);
+		// Dropping the same table again is a replayed log record.
+		column.drop_index(IndexTableId::new(0, 8), log).unwrap();
+		assert!(matches!(
+			column.drop_index(IndexTableId::new(0, 10), log),
+			Err(crate::Error::Corruption(_))
+		));
+	}
+
+	#[test]
+	fn test_max_reindex_queue_depth() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.columns[0].uniform = true;
+		options.columns[0].start_bits = 8;
+		options.salt = Some(Default::default());
+		options.max_reindex_queue_depth.insert(0, 1);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = |chunk: u8, i: u8| {
+			let mut key = [0u8; 32];
+			key[0] = chunk;
+			key[1] = i;
+			key[31] = 1;
+			key
+		};
+
+		// The last key overflows the index chunk and starts a reindex.
+		db.commit((0..65).map(|i| (0, key(0, i * 2), Some(vec![i])))).unwrap();
+		while db.inner.process_commits().unwrap() {}
+		assert_eq!(db.reindex_queue_depth(0).unwrap(), 1);
+
+		std::thread::scope(|s| {
+			let writer = s.spawn(|| db.commit(vec![(0, key(1, 0), Some(vec![1]))]).unwrap());
+			std::thread::sleep(std::time::Duration::from_millis(100));
+			assert!(!writer.is_finished());
+			// Enact the commit, then move the entries and drop the old index.
+			db_test.run_stages(&db);
+			db_test.run_stages(&db);
+			writer.join().unwrap();
+		});
+		assert_eq!(db.reindex_queue_depth(0).unwrap(), 0);
+		db_test.run_stages(&db);
+		assert_eq!(db.get(0, &key(1, 0)).unwrap(), Some(vec![1]));
+		assert_eq!(db.get(0, &key(0, 128)).unwrap(), Some(vec![64]));
+	}
+
+	#[test]
+	fn test_collect_garbage() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit((0..4u8).map(|i| (0, vec![i], Some(vec![i; 100])))).unwrap();
+		db.commit(vec![(0, vec![4], Some(vec![4; 40000]))]).unwrap();
+		db_test.run_stages(&db);
+		drop(db);
+
+		// Losing the index leaves all values unreferenced.
+		for entry in std::fs::read_dir(tmp.path()).unwrap() {
+			let path = entry.unwrap().path();
+			if path.file_name().unwrap().to_str().unwrap().starts_with("index_00_") {
+				std::fs::remove_file(path).unwrap();
+			}
+		}
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		db.commit(vec![(0, vec![5], Some(vec![5; 100])), (0, vec![6], Some(vec![6; 40000]))])
+			.unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(db.get_fragmentation_report().unwrap().total_bytes_free, 0);
+
+		let stats = db.collect_garbage(0).unwrap();
+		// Four small values and at least two parts of the large one.
+		assert!(stats.orphaned_slots >= 6);
+		db_test.run_stages(&db);
+		assert_eq!(db.get_fragmentation_report().unwrap().total_bytes_free, stats.bytes_reclaimed);
+		assert_eq!(db.get(0, &[5]).unwrap(), Some(vec![5; 100]));
+		assert_eq!(db.get(0, &[6]).unwrap(), Some(vec![6; 40000]));
+		assert_eq!(db.collect_garbage(0).unwrap(), crate::GarbageStats::default());
+		assert!(matches!(db.collect_garbage(1), Err(crate::Error::InvalidConfiguration(_))));
+	}
+
+	#[test]
+	fn test_check_integrity() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		options.salt = Some(Default::default());
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit((0..20u8).map(|i| (0, vec![i], Some(vec![i; 100])))).unwrap();
+		db.commit(vec![(0, vec![20], Some(vec![20; 40000])), (1, vec![1], Some(vec![1; 100]))])
+			.unwrap();
+		db_test.run_stages(&db);
+		let report = db.check_integrity().unwrap();
+		assert_eq!(report.index_entries, 21);
+		assert!(report.value_slots > 21);
+		assert_eq!(report.errors, 0);
+		assert!(report.corruptions.is_empty());
+		drop(db);
+
+		// Entries of the index file follow a 16 KiB header, in chunks of 64.
+		let path = tmp.path().join("index_00_16");
+		let mut file = std::fs::read(&path).unwrap();
+		let mut entries: Vec<u64> = file[16 * 1024..]
+			.chunks(8)
+			.map(|e| u64::from_le_bytes(e.try_into().unwrap()))
+			.collect();
+		// The multipart value is skipped, as each of its parts would be reported when orphaned.
+		let used: Vec<usize> = (0..entries.len())
+			.filter(|i| entries[*i] != 0 && entries[*i] & 0xff != SIZE_TIERS as u64 - 1)
+			.take(3)
+			.collect();
+		// Reference the first value twice, from a free slot of the same chunk.
+		let chunk = used[0] / 64 * 64;
+		let free = (chunk..chunk + 64).find(|i| entries[*i] == 0).unwrap();
+		entries[free] = entries[used[0]];
+		// Point the second entry past the end of the value table, orphaning its value.
+		entries[used[1]] |= 1 << 29;
+		// Change a key bit of the third entry that is also stored with the value.
+		entries[used[2]] ^= 1 << 30;
+		for (i, entry) in entries.iter().enumerate() {
+			file[16 * 1024 + i * 8..16 * 1024 + i * 8 + 8].copy_from_slice(&entry.to_le_bytes());
+		}
+		std::fs::write(&path, file).unwrap();
+
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		let report = db.check_integrity().unwrap();
+		assert_eq!(report.index_entries, 22);
+		assert_eq!(report.errors, 4);
+		let messages: Vec<_> = report.corruptions.iter().map(|c| c.message.as_str()).collect();
+		for expected in ["referenced twice", "out of bounds", "does not match", "not referenced"] {
+			assert!(messages.iter().any(|m| m.contains(expected)), "{:?}", messages);
+		}
+		assert!(report.corruptions.iter().all(|c| c.col == 0));
+	}
+
+	#[test]
+	fn test_get_metadata_stats() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let options = db_test.options(tmp.path(), 1);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit(vec![(0, vec![1], Some(vec![1; 100]))]).unwrap();
+		db_test.run_stages(&db);
+
+		let stats = db.get_metadata_stats().unwrap();
+		let names: Vec<_> = stats
+			.files
+			.iter()
+			.map(|f| f.path.file_name().unwrap().to_str().unwrap().to_owned())
+			.collect();
+		let mut sorted = names.clone();
+		sorted.sort();
+		assert_eq!(names, sorted);
+		assert!(names.contains(&"metadata".to_owned()));
+		for path in db.get_column_file_paths(0).unwrap() {
+			let info = stats.files.iter().find(|f| f.path == path).unwrap();
+			assert_eq!(info.size, std::fs::metadata(&path).unwrap().len());
+		}
+		assert!(stats.total_size() > 0);
+	}
+
+	#[test]
+	fn test_get_tier_file_path() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let options = db_test.options(tmp.path(), 1);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit(vec![(0, vec![1], Some(vec![1; 100]))]).unwrap();
+		db_test.run_stages(&db);
+
+		let tier = db.get_tier_for_value(0, 100).unwrap();
+		let path = db.get_tier_file_path(0, tier).unwrap();
+		assert_eq!(db.get_column_file_paths(0).unwrap().iter().filter(|p| **p == path).count(), 1);
+		assert_eq!(
+			db.get_tier_file_path(0, crate::table::SIZE_TIERS - 1).unwrap(),
+			tmp.path().join("table_00_ff")
+		);
+		assert!(db.get_tier_file_path(0, crate::table::SIZE_TIERS).is_err());
+		assert!(db.get_tier_file_path(1, 0).is_err());
+	}
+
+	#[test]
+	fn test_column_iter() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let column = &db.inner.columns[0];
+		let hash = |i: u32| match column {
+			Column::Hash(column) => column.hash_key(&i.to_le_bytes()),
+			_ => unreachable!(),
+		};
+		let entries = |db: &Db| -> std::collections::BTreeMap<crate::Key, Value> {
+			db.inner.columns[0].iter(&db.inner.log).unwrap().map(|e| e.unwrap()).collect()
+		};
+		let value = |i: u32| vec![i as u8; 10 + 100 * i as usize];
+		db.commit((0..50u32).map(|i| (0, i.to_le_bytes().to_vec(), Some(value(i)))))
+			.unwrap();
+		db_test.run_stages(&db);
+		let expected: std::collections::BTreeMap<_, _> =
+			(0..50).map(|i| (hash(i), value(i))).collect();
+		assert_eq!(entries(&db), expected);
+
+		db.commit(vec![(0, 7u32.to_le_bytes().to_vec(), None)]).unwrap();
+		db_test.run_stages(&db);
+		let entries = entries(&db);
+		assert_eq!(entries.len(), 49);
+		assert!(!entries.contains_key(&hash(7)));
+		assert!(db.inner.columns[1].iter(&db.inner.log).is_err());
+	}
+
+	#[test]
+	fn test_get_multi() {
+		for db_test in [
+			EnableCommitPipelineStages::CommitOverlay,
+			EnableCommitPipelineStages::LogOverlay,
+			EnableCommitPipelineStages::DbFile,
+			EnableCommitPipelineStages::Standard,
+		] {
+			let tmp = tempdir().unwrap();
+			let mut options = db_test.options(tmp.path(), 2);
+			options.columns[1].btree_index = true;
+			#[cfg(feature = "bloom")]
+			options.bloom_false_positive_rate.insert(0, 0.01);
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+			let key = |i: u32| i.to_le_bytes().to_vec();
+			for col in 0..2 {
+				db.commit((0..100).step_by(2).map(|i| (col, key(i), Some(key(i + 1000)))))
+					.unwrap();
+			}
+			db_test.run_stages(&db);
+			// Overlay entries are mixed with values read from the tables.
+			db.commit(vec![(0, key(1), Some(b"new".to_vec())), (0, key(2), None)]).unwrap();
+
+			let keys: Vec<Vec<u8>> = (0..100).rev().map(key).collect();
+			let keys: Vec<&[u8]> = keys.iter().map(|k| k.as_slice()).collect();
+			for col in 0..2 {
+				let expected: Vec<_> = keys.iter().map(|k| db.get(col, k).unwrap()).collect();
+				assert_eq!(db.get_multi(col, &keys).unwrap(), expected);
+				let expected: Vec<_> = expected.iter().map(Option::is_some).collect();
+				assert_eq!(db.has_keys(col, &keys).unwrap(), expected);
+			}
+			let values = db.get_multi(0, &keys).unwrap();
+			assert_eq!(values[98], Some(b"new".to_vec()));
+			assert_eq!(values[97], None);
+			assert_eq!(values[99], Some(key(1000)));
+			assert_eq!(db.get_multi(0, &[]).unwrap(), Vec::<Option<Value>>::new());
+			assert_eq!(db.has_keys(0, &[]).unwrap(), Vec::<bool>::new());
+			assert!(db.has_keys(2, &keys).is_err());
+		}
+	}
+
+	#[test]
+	fn test_page_cache_hit_ratio() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit(vec![(0, b"key".to_vec(), Some(b"value".to_vec()))]).unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(db.get(0, b"key").unwrap(), Some(b"value".to_vec()));
+
+		#[cfg(target_os = "linux")]
+		{
+			// The lookup has read at least the page holding the key's chunk.
+			let ratio = db.get_page_cache_hit_ratio(0).unwrap();
+			assert!(ratio > 0.0 && ratio <= 1.0);
+		}
+		#[cfg(not(target_os = "linux"))]
+		assert!(matches!(db.get_page_cache_hit_ratio(0), Err(crate::Error::NotSupported)));
+		assert!(db.get_page_cache_hit_ratio(1).is_err());
+	}
+
+	#[test]
+	fn test_has_key() {
+		for db_test in [
+			EnableCommitPipelineStages::CommitOverlay,
+			EnableCommitPipelineStages::LogOverlay,
+			EnableCommitPipelineStages::DbFile,
+			EnableCommitPipelineStages::Standard,
+		] {
+			let tmp = tempdir().unwrap();
+			let mut options = db_test.options(tmp.path(), 2);
+			options.columns[1].btree_index = true;
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+			for col in 0..2 {
+				db.commit(vec![
+					(col, b"small".to_vec(), Some(vec![1; 10])),
+					(col, b"large".to_vec(), Some(vec![2; 40000])),
+				])
+				.unwrap();
+			}
+			db_test.run_stages(&db);
+			for col in 0..2 {
+				assert!(db.has_key(col, b"small").unwrap());
+				assert!(db.has_key(col, b"large").unwrap());
+				assert!(!db.has_key(col, b"missing").unwrap());
+				db.commit(vec![(col, b"large".to_vec(), None)]).unwrap();
+				assert!(!db.has_key(col, b"large").unwrap());
+			}
+			db_test.run_stages(&db);
+			for col in 0..2 {
+				assert!(db.has_key(col, b"small").unwrap());
+				assert!(!db.has_key(col, b"large").unwrap());
+			}
+		}
+	}
+
+	#[test]
+	fn test_index_partial_key_collisions() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.columns[0].uniform = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		// Keys that differ only in the upper 8 bytes, which are stored in the index entry.
+		let upper = |i: u8| {
+			let mut key = [7u8; 32];
+			key[0] = i + 1;
+			key[7] = i;
+			key
+		};
+		// Keys that share the upper 8 bytes, so their index entries are identical and only the
+		// partial key stored with the value tells them apart.
+		let lower = |i: u8| {
+			let mut key = [9u8; 32];
+			key[31] = i;
+			key
+		};
+		db.commit((0..8).flat_map(|i| {
+			[
+				(0, upper(i).to_vec(), Some(vec![i; 10])),
+				(0, lower(i).to_vec(), Some(vec![i + 100; 10])),
+			]
+		}))
+		.unwrap();
+		db_test.run_stages(&db);
+		for i in 0..8 {
+			assert_eq!(db.get(0, &upper(i)).unwrap(), Some(vec![i; 10]));
+			assert_eq!(db.get(0, &lower(i)).unwrap(), Some(vec![i + 100; 10]));
+		}
+		assert_eq!(db.get(0, &upper(8)).unwrap(), None);
+		assert_eq!(db.get(0, &lower(8)).unwrap(), None);
+	}
+
+	#[test]
+	fn test_custom_value_sizes() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[0].value_sizes = Some(vec![32, 64, 528]);
+		options.columns[1].value_sizes = Some(vec![256]);
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let sizes = [1usize, 40, 500, 600, 40000];
+		for col in 0..2 {
+			db.commit(sizes.iter().map(|s| (col, s.to_le_bytes().to_vec(), Some(vec![7; *s]))))
+				.unwrap();
+		}
+		db_test.run_stages(&db);
+		for col in 0..2 {
+			for s in sizes {
+				assert_eq!(db.get(col, &s.to_le_bytes()).unwrap(), Some(vec![7; s]));
+			}
+		}
+		assert_eq!(db.get_tier_for_value(0, 1).unwrap(), 0);
+		assert_eq!(db.get_tier_for_value(0, 500).unwrap(), 2);
+		assert_eq!(db.get_tier_for_value(0, 600).unwrap(), 3);
+		assert!(db.get_tier_file_path(0, 3).unwrap().exists());
+		assert!(db.get_tier_file_path(0, 4).is_err());
+		assert_eq!(db.get_tier_for_value(1, 1 << 20).unwrap(), 1);
+		drop(db);
+
+		// Sizes are stored in the metadata and checked on open.
+		let mut default_sizes = options.clone();
+		default_sizes.columns[0].value_sizes = None;
+		assert!(matches!(
+			Db::open_inner(&default_sizes, OpeningMode::Write),
+			Err(crate::Error::IncompatibleColumnConfig { id: 0, .. })
+		));
+		let metadata = Options::load_metadata(tmp.path()).unwrap().unwrap();
+		assert_eq!(metadata.columns, options.columns);
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert_eq!(db.get(0, &40000usize.to_le_bytes()).unwrap(), Some(vec![7; 40000]));
+
+		for invalid in [vec![], vec![64, 32], vec![16], vec![40000]] {
+			let options = ColumnOptions { value_sizes: Some(invalid), ..Default::default() };
+			assert!(!options.is_valid());
+		}
+	}
+
+	#[test]
+	fn test_index_start_bits() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.columns[0].uniform = true;
+		options.columns[0].start_bits = 8;
+		options.columns[0].max_bits = Some(9);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+
+		// Uniform keys with the same prefix all land in the same index chunk.
+		let key = |i: u8| {
+			let mut key = [0u8; 32];
+			key[2] = i;
+			key
+		};
+		db.commit((0..64).map(|i| (0, key(i), Some(vec![i; 40])))).unwrap();
+		db_test.run_stages(&db);
+		assert!(tmp.path().join("index_00_8").exists());
+		// The keys would still share a chunk once the index has grown to `max_bits`.
+		assert!(matches!(
+			db.commit(vec![(0, key(64), Some(vec![64; 40]))]),
+			Err(crate::Error::IndexFull(0))
+		));
+		db.resize_index_in_place(0).unwrap();
+		db_test.run_stages(&db);
+		assert!(tmp.path().join("index_00_9").exists());
+		assert!(matches!(db.resize_index_in_place(0), Err(crate::Error::IndexFull(0))));
+
+		// The chunk is full and the index can't grow. The commit is rejected before it is queued.
+		assert!(matches!(
+			db.commit(vec![(0, key(64), Some(vec![64; 40]))]),
+			Err(crate::Error::IndexFull(0))
+		));
+		// Existing keys can still be changed.
+		db.commit(vec![(0, key(0), Some(vec![1; 40])), (0, key(63), None)]).unwrap();
+		db_test.run_stages(&db);
+		// The freed entry is taken by the queued commit.
+		db.commit(vec![(0, key(64), Some(vec![64; 40]))]).unwrap();
+		db.commit(vec![(0, key(64), Some(vec![65; 40]))]).unwrap();
+		assert!(matches!(
+			db.commit(vec![(0, key(65), Some(vec![65; 40]))]),
+			Err(crate::Error::IndexFull(0))
+		));
+		db_test.run_stages(&db);
+		assert_eq!(db.get(0, &key(64)).unwrap(), Some(vec![65; 40]));
+		// Removals only free entries once they are written.
+		assert!(db.commit(vec![(0, key(63), Some(vec![63; 40])), (0, key(64), None)]).is_err());
+		db.commit(vec![(0, key(64), None)]).unwrap();
+		db_test.run_stages(&db);
+		db.commit(vec![(0, key(63), Some(vec![63; 40]))]).unwrap();
+		db_test.run_stages(&db);
+		drop(db);
+
+		// Index bits are stored in the metadata and checked on open.
+		let mut default_bits = options.clone();
+		default_bits.columns[0].start_bits = 16;
+		default_bits.columns[0].max_bits = None;
+		assert!(matches!(
+			Db::open_inner(&default_bits, OpeningMode::Write),
+			Err(crate::Error::IncompatibleColumnConfig { id: 0, .. })
+		));
+		let metadata = Options::load_metadata(tmp.path()).unwrap().unwrap();
+		assert_eq!(metadata.columns, options.columns);
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert_eq!(db.get(0, &key(63)).unwrap(), Some(vec![63; 40]));
+
+		for (start_bits, max_bits) in [(7, None), (33, None), (16, Some(15)), (16, Some(50))] {
+			let options = ColumnOptions { start_bits, max_bits, ..Default::default() };
+			assert!(!options.is_valid());
+		}
+	}
+
+	#[test]
+	fn test_hot_keys() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		options.track_hot_keys = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = |i: u32| i.to_le_bytes().to_vec();
+		db.commit((0..100).map(|i| (0, key(i), Some(key(i))))).unwrap();
+		db_test.run_stages(&db);
+		let hash = |i: u32| match &db.inner.columns[0] {
+			Column::Hash(column) => column.hash_key(&key(i)),
+			_ => unreachable!(),
+		};
+
+		for i in 0..100 {
+			db.get(0, &key(i)).unwrap();
+		}
+		for _ in 0..50 {
+			db.get(0, &key(5)).unwrap();
+		}
+		for _ in 0..20 {
+			db.get(0, &key(7)).unwrap();
+		}
+		let hot = db.get_hot_keys(0, 2).unwrap();
+		assert_eq!(hot.iter().map(|(k, _)| *k).collect::<Vec<_>>(), vec![hash(5), hash(7)]);
+		// Estimates are never too low.
+		assert!(hot[0].1 >= 51 && hot[1].1 >= 21);
+		assert_eq!(db.get_hot_keys(0, 1000).unwrap().len(), 100);
+
+		db.reset_hot_key_stats(0).unwrap();
+		assert!(db.get_hot_keys(0, 10).unwrap().is_empty());
+		assert!(db.get_hot_keys(1, 10).is_err());
+		drop(db);
+
+		options.track_hot_keys = false;
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert!(matches!(db.get_hot_keys(0, 10), Err(crate::Error::InvalidConfiguration(_))));
+	}
+
+	#[test]
+	fn test_transfer_column() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(&tmp.path().join("source"), 2);
+		options.columns[1].btree_index = true;
+		options.salt = Some(Default::default());
+		let source = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let mut dest_options = db_test.options(&tmp.path().join("dest"), 2);
+		dest_options.salt = Some(Default::default());
+		let dest = Db::open_inner(&dest_options, OpeningMode::Create).unwrap();
+		let key = |i: u32| i.to_le_bytes().to_vec();
+
+		source.commit((0..1000).map(|i| (0, key(i), Some(key(i))))).unwrap();
+		db_test.run_stages(&source);
+		// Changes still in the commit queue.
+		source
+			.commit(vec![(0, key(0), Some(b"updated".to_vec())), (0, key(1), None)])
+			.unwrap();
+		source.commit(vec![(0, key(1000), Some(key(1000)))]).unwrap();
+
+		assert!(matches!(
+			source.transfer_column(1, &dest, 1),
+			Err(crate::Error::InvalidConfiguration(_))
+		));
+		assert!(matches!(
+			source.transfer_column(0, &source, 1),
+			Err(crate::Error::InvalidInput(_))
+		));
+		let stats = source.transfer_column(0, &dest, 1).unwrap();
+		assert_eq!(stats.entries, 1000);
+		assert_eq!(stats.bytes, 4 * 999 + 7);
+		assert_eq!(stats.mirrored, 0);
+
+		assert_eq!(dest.get(1, &key(0)).unwrap(), Some(b"updated".to_vec()));
+		assert_eq!(dest.get(1, &key(1)).unwrap(), None);
+		for i in 2..=1000 {
+			assert_eq!(dest.get(1, &key(i)).unwrap(), Some(key(i)));
+			assert_eq!(source.get(0, &key(i)).unwrap(), None);
+		}
+		// The source column remains writable.
+		source.commit(vec![(0, key(0), Some(key(0)))]).unwrap();
+		assert_eq!(source.get(0, &key(0)).unwrap(), Some(key(0)));
+		drop(dest);
+
+		let mut versioned_options = db_test.options(&tmp.path().join("versioned"), 1);
+		versioned_options.salt = Some(Default::default());
+		versioned_options.columns[0].versioning = true;
+		let versioned = Db::open_inner(&versioned_options, OpeningMode::Create).unwrap();
+		assert!(matches!(
+			source.transfer_column(0, &versioned, 0),
+			Err(crate::Error::InvalidConfiguration(_))
+		));
+	}
+
+	#[test]
+	fn test_mutual_transfer_mirror() {
+		use super::ColumnTransfer;
+
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let open = |name: &str| {
+			let mut options = db_test.options(&tmp.path().join(name), 2);
+			options.salt = Some(Default::default());
+			Db::open_inner(&options, OpeningMode::Create).unwrap()
+		};
+		let (first, second) = (open("first"), open("second"));
+		// Column 0 of each database is being transferred to column 1 of the other one.
+		for (source, dest) in [(&first, &second), (&second, &first)] {
+			*source.inner.transfer.lock() = Some(ColumnTransfer {
+				col: 0,
+				dest: dest.inner.clone(),
+				dest_col: 1,
+				touched: Default::default(),
+				mirrored: 0,
+				forward: Default::default(),
+			});
+		}
+
+		// Mirrored changes are committed to the destination after the source commit is queued,
+		// so concurrent commits to both databases can't wait for each other.
+		std::thread::scope(|scope| {
+			for (db, value) in [(&first, &b"first"[..]), (&second, &b"second"[..])] {
+				scope.spawn(move || {
+					for i in 0..100u32 {
+						db.commit(vec![(0, i.to_le_bytes().to_vec(), Some(value.to_vec()))])
+							.unwrap();
+					}
+				});
+			}
+		});
+		first.commit(vec![(0, b"key", Some(b"first".to_vec()))]).unwrap();
+		second.commit(vec![(0, b"key", Some(b"second".to_vec()))]).unwrap();
+		first.inner.forward_transfer().unwrap();
+		second.inner.forward_transfer().unwrap();
+		db_test.run_stages(&first);
+		db_test.run_stages(&second);
+		assert_eq!(first.get(1, b"key").unwrap(), Some(b"second".to_vec()));
+		assert_eq!(second.get(1, b"key").unwrap(), Some(b"first".to_vec()));
+		assert_eq!(first.get(1, &99u32.to_le_bytes()).unwrap(), Some(b"second".to_vec()));
+	}
+
+	#[test]
+	fn test_open_read_only() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = |i: u32| i.to_le_bytes().to_vec();
+		db.commit((0..100).flat_map(|i| [(0, key(i), Some(key(i))), (1, key(i), Some(key(i)))]))
+			.unwrap();
+		db_test.run_stages(&db);
+
+		// Opened while the database is open for writing.
+		let reader = Db::open_read_only(&options).unwrap();
+		for col in 0..2 {
+			for i in 0..100 {
+				assert_eq!(reader.get(col, &key(i)).unwrap(), Some(key(i)));
+			}
+		}
+		assert!(matches!(reader.commit(vec![(0, key(0), None)]), Err(crate::Error::ReadOnly)));
+		let mut writer = reader.inner.log.begin_record();
+		for column in &reader.inner.columns {
+			assert!(matches!(column.complete_plan(&mut writer), Err(crate::Error::ReadOnly)));
+		}
+		drop(writer);
+
+		// The writer is not affected by the reader.
+		db.commit(vec![(0, key(0), Some(b"updated".to_vec()))]).unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(reader.get(1, &key(99)).unwrap(), Some(key(99)));
+		drop(reader);
+		assert_eq!(db.get(0, &key(0)).unwrap(), Some(b"updated".to_vec()));
+		drop(db);
+
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert_eq!(db.get(0, &key(0)).unwrap(), Some(b"updated".to_vec()));
+		assert_eq!(db.get(1, &key(99)).unwrap(), Some(key(99)));
+	}
+
+	#[test]
+	fn test_open_many() {
+		let tmp = tempdir().unwrap();
+		let options: Vec<_> = (0..3)
+			.map(|i| Options::with_columns(&tmp.path().join(i.to_string()), 2))
+			.collect();
+		for options in &options {
+			Db::open_or_create(options).unwrap();
+		}
+		let key = |i: u32| i.to_le_bytes().to_vec();
+
+		let group = Db::open_many(&options).unwrap();
+		assert_eq!(group.len(), 3);
+		for (i, db) in group.dbs.iter().enumerate() {
+			db.commit((0..1000).map(|k| (1, key(k), Some(key(k + i as u32))))).unwrap();
+		}
+		// The shared log worker processes the commits of all databases.
+		for db in &group.dbs {
+			let start = std::time::Instant::now();
+			while !db.inner.commit_queue.lock().commits.is_empty() {
+				assert!(start.elapsed() < std::time::Duration::from_secs(10));
+				std::thread::sleep(std::time::Duration::from_millis(10));
+			}
+		}
+		for i in 0..3 {
+			assert_eq!(group.get(i, 1, &key(10)).unwrap(), Some(key(10 + i as u32)));
+		}
+		assert!(group.db(3).is_none());
+		assert!(matches!(group.get(3, 1, &key(10)), Err(crate::Error::InvalidInput(_))));
+		drop(group);
+
+		for (i, options) in options.iter().enumerate() {
+			let db = Db::open(options).unwrap();
+			for k in 0..1000 {
+				assert_eq!(db.get(1, &key(k)).unwrap(), Some(key(k + i as u32)));
+			}
+		}
+	}
+
+	#[test]
+	fn test_counters() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = |i: u32| i.to_le_bytes().to_vec();
+		for col in 0..2 {
+			db.commit((0..10_000).map(|i| (col, key(i), Some(key(i))))).unwrap();
+			// Read from the commit queue.
+			assert_eq!(db.get(col, &key(0)).unwrap(), Some(key(0)));
+		}
+		db_test.run_stages(&db);
+		for col in 0..2 {
+			for i in 0..10_000 {
+				assert_eq!(db.get(col, &key(i)).unwrap(), Some(key(i)));
+			}
+			db.commit((0..100).map(|i| (col, key(i), None))).unwrap();
+		}
+		db_test.run_stages(&db);
+
+		let snapshot = db.counters().snapshot();
+		assert_eq!(snapshot.columns.len(), 2);
+		for counters in &snapshot.columns {
+			assert_eq!(counters.reads, 10_001);
+			assert_eq!(counters.writes, 10_000);
+			assert_eq!(counters.deletes, 100);
+			assert_eq!(counters.cache_hits, 1);
+			assert_eq!(counters.bytes_read, 4 * 10_001);
+			assert_eq!(counters.bytes_written, 4 * 10_000);
+		}
+		assert_eq!(db.counters().column(0).unwrap().snapshot(), snapshot.columns[0]);
+		assert!(db.counters().column(2).is_none());
+	}
+
+	#[test]
+	fn test_incompressible_values_stored_uncompressed() {
+		use rand::RngCore;
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.columns[0].compression = crate::CompressionType::Lz4;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let mut random = vec![0; 10_000];
+		rand::thread_rng().fill_bytes(&mut random);
+		let zeros = vec![0; 10_000];
+		db.commit(vec![
+			(0, b"random".to_vec(), Some(random.clone())),
+			(0, b"zeros".to_vec(), Some(zeros.clone())),
+		])
+		.unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(db.get(0, b"random").unwrap(), Some(random));
+		assert_eq!(db.get(0, b"zeros").unwrap(), Some(zeros));
+
+		// Entries of each tier, with their compression flag.
+		let entries = |tier: u8| {
+			let mut entries = Vec::new();
+			db.iter_raw_slots(0, tier, |_, slot| {
+				let size = u16::from_le_bytes(slot[0..2].try_into().unwrap());
+				if size != 0xffff {
+					entries.push(size & crate::table::COMPRESSED_MASK != 0);
+				}
+				true
+			})
+			.unwrap();
+			entries
+		};
+		// Compressing random data would grow it, so it is stored as is in the tier for its
+		// uncompressed size. Zeros are compressed and moved to a smaller tier.
+		let tier = db.get_tier_for_value(0, 10_000).unwrap() as u8;
+		assert_eq!(entries(tier), vec![false]);
+		let compressed: Vec<_> = (0..tier).flat_map(entries).collect();
+		assert_eq!(compressed, vec![true]);
+	}
+
+	#[test]
+	fn test_column_read_only() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		db.commit(vec![(0, b"a".to_vec(), Some(vec![1])), (1, b"a".to_vec(), Some(vec![1]))])
+			.unwrap();
+
+		db.set_column_read_only(0, true).unwrap();
+		db.set_column_read_only(1, true).unwrap();
+		for col in 0..2 {
+			assert!(matches!(
+				db.commit(vec![(col, b"b".to_vec(), Some(vec![2]))]),
+				Err(crate::Error::ColumnReadOnly(c)) if c == col
+			));
+		}
+		// A transaction touching a locked column is rejected as a whole.
+		db.set_column_read_only(1, false).unwrap();
+		assert!(db
+			.commit(vec![(1, b"c".to_vec(), Some(vec![3])), (0, b"c".to_vec(), Some(vec![3]))])
+			.is_err());
+		db.commit(vec![(1, b"b".to_vec(), Some(vec![2]))]).unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(db.get(0, b"a").unwrap(), Some(vec![1]));
+		assert_eq!(db.get(0, b"b").unwrap(), None);
+		assert_eq!(db.get(1, b"b").unwrap(), Some(vec![2]));
+		assert_eq!(db.get(1, b"c").unwrap(), None);
+		assert!(db.set_column_read_only(2, true).is_err());
+		drop(db);
+
+		// Not persisted.
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		db.commit(vec![(0, b"b".to_vec(), Some(vec![2]))]).unwrap();
+	}
+
+	#[test]
+	fn test_value_cache() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		options.value_cache_capacity.insert(0, 16);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = |i: u32| i.to_le_bytes().to_vec();
+		db.commit((0..100).map(|i| (0, key(i), Some(key(i))))).unwrap();
+		db_test.run_stages(&db);
+
+		assert_eq!(db.get(0, &key(1)).unwrap(), Some(key(1)));
+		assert_eq!(db.get(0, &key(1)).unwrap(), Some(key(1)));
+		assert_eq!(db.get(0, &key(2)).unwrap(), Some(key(2)));
+		let stats = db.get_value_cache_stats(0).unwrap().unwrap();
+		assert_eq!((stats.entries, stats.hits, stats.misses), (2, 1, 2));
+
+		// Overwritten and removed entries are not served from the cache.
+		db.commit(vec![(0, key(1), Some(vec![1; 3])), (0, key(2), None)]).unwrap();
+		db_test.run_stages(&db);
+		assert_eq!(db.get(0, &key(1)).unwrap(), Some(vec![1; 3]));
+		assert_eq!(db.get(0, &key(2)).unwrap(), None);
+
+		for i in 0..100 {
+			assert_eq!(db.get(0, &key(i)).unwrap().is_some(), i != 2);
+		}
+		assert!(db.get_value_cache_stats(0).unwrap().unwrap().entries <= 16);
+		assert!(db.get_value_cache_stats(1).is_err());
+	}
+
+	#[cfg(feature = "bloom")]
+	#[test]
+	fn test_bloom_filter_read_only() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.bloom_false_positive_rate.insert(0, 0.01);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = |i: u32| i.to_le_bytes().to_vec();
+		db.commit((0..100).map(|i| (0, key(i), Some(key(i))))).unwrap();
+		db_test.run_stages(&db);
+
+		// Opened while the database is open for writing, so the logs are not replayed.
+		let reader = Db::open_read_only(&options).unwrap();
+		assert!(reader.get_bloom_stats(0).unwrap().is_none());
+		for i in 0..100 {
+			assert_eq!(reader.get(0, &key(i)).unwrap(), Some(key(i)));
+		}
+		assert!(reader.has_key(0, &key(0)).unwrap());
+		assert!(!reader.has_key(0, &key(100)).unwrap());
+		// Keys added by the writer are found as well.
+		db.commit(vec![(0, key(100), Some(key(100)))]).unwrap();
+		db_test.run_stages(&db);
+		assert!(reader.has_key(0, &key(100)).unwrap());
+		drop(reader);
+		drop(db);
+
+		// Without a writer, the filter is built when the logs are replayed.
+		let reader = Db::open_read_only(&options).unwrap();
+		assert_eq!(reader.get_bloom_stats(0).unwrap().unwrap().entries, 101);
+		assert_eq!(reader.get(0, &key(100)).unwrap(), Some(key(100)));
+	}
+
+	#[cfg(feature = "bloom")]
+	#[test]
+	fn test_bloom_filter() {
+		const ENTRIES: u32 = 1_000_000;
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		options.bloom_false_positive_rate.insert(0, 0.01);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let key = |i: u32| i.to_le_bytes().to_vec();
+		for batch in 0..ENTRIES / 100_000 {
+			let start = batch * 100_000;
+			db.commit((start..start + 100_000).map(|i| (0, key(i), Some(key(i))))).unwrap();
+			db_test.run_stages(&db);
+		}
+		// Removed keys are dropped from the filter.
+		db.commit((0..1000).map(|i| (0, key(i), None))).unwrap();
+		db_test.run_stages(&db);
+
+		for i in ENTRIES..2 * ENTRIES {
+			assert_eq!(db.get(0, &key(i)).unwrap(), None);
+		}
+		let stats = db.get_bloom_stats(0).unwrap().unwrap();
+		assert_eq!(stats.queries, ENTRIES as u64);
+		assert!(stats.rejected as f64 >= ENTRIES as f64 * 0.99, "{:?}", stats);
+		assert!(db.get_bloom_stats(1).is_err());
+
+		// Rebuilding keeps all live keys.
+		db.rebuild_bloom(0).unwrap();
+		assert_eq!(db.get_bloom_stats(0).unwrap().unwrap().entries, ENTRIES as u64 - 1000);
+		for i in (1000..ENTRIES).step_by(97) {
+			assert_eq!(db.get(0, &key(i)).unwrap(), Some(key(i)));
+		}
+		assert!(matches!(db.rebuild_bloom(1), Err(crate::Error::InvalidConfiguration(_))));
+		drop(db);
+
+		// The filter is rebuilt on open and never rejects existing keys.
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		for i in (1000..ENTRIES).step_by(97) {
+			assert_eq!(db.get(0, &key(i)).unwrap(), Some(key(i)));
+		}
+		assert_eq!(db.get(0, &key(0)).unwrap(), None);
+		let stats = db.get_bloom_stats(0).unwrap().unwrap();
+		assert_eq!(stats.entries, ENTRIES as u64 - 1000);
+		assert_eq!(stats.rejected, 1);
+		drop(db);
+
+		options.bloom_false_positive_rate.clear();
+		let db = Db::open_inner(&options, OpeningMode::Write).unwrap();
+		assert_eq!(db.get_bloom_stats(0).unwrap(), None);
+	}
+
+	#[cfg(feature = "bloom")]
+	#[test]
+	fn test_bloom_filter_rebuild_full() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 1);
+		options.bloom_false_positive_rate.insert(0, 0.01);
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+		let size = db.get_bloom_stats(0).unwrap().unwrap().size;
+		let key = |i: u32| i.to_le_bytes().to_vec();
+		db.commit((0..100_000).map(|i| (0, key(i), Some(key(i))))).unwrap();
+
+		// A full filter is not rebuilt while the commit is written.
+		while db.inner.process_commits().unwrap() {}
+		assert_eq!(db.get_bloom_stats(0).unwrap().unwrap().size, size);
+		for i in (0..100_000).step_by(97) {
+			assert_eq!(db.get(0, &key(i)).unwrap(), Some(key(i)));
+		}
+
+		// It is rebuilt with the log cleanup.
+		db_test.run_stages(&db);
+		let stats = db.get_bloom_stats(0).unwrap().unwrap();
+		assert!(stats.size > size, "{:?}", stats);
+		assert_eq!(stats.entries, 100_000);
+		for i in (0..100_000).step_by(97) {
+			assert_eq!(db.get(0, &key(i)).unwrap(), Some(key(i)));
+		}
+	}
+
+	#[test]
+	fn test_get_tier_for_value() {
+		let tmp = tempdir().unwrap();
+		let db_test = EnableCommitPipelineStages::DbFile;
+		let mut options = db_test.options(tmp.path(), 2);
+		options.columns[1].btree_index = true;
+		let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+
+		// Hash column entries carry a 2 byte size and a 26 byte partial key.
+		assert_eq!(db.get_tier_for_value(0, 0).unwrap(), 0);
+		assert_eq!(db.get_tier_for_value(0, 4).unwrap(), 0);
+		assert_eq!(db.get_tier_for_value(0, 5).unwrap(), 1);
+		assert_eq!(db.get_tier_for_value(0, 1 << 20).unwrap(), crate::table::SIZE_TIERS - 1);
+		assert_eq!(db.get_tier_for_value(1, 30).unwrap(), 0);
+		assert_eq!(db.get_tier_for_value(1, 31).unwrap(), 1);
+
+		let tier = db.get_tier_for_value(0, 100).unwrap();
+		db.commit(vec![(0, [1u8; 32], Some(vec![1u8; 100]))]).unwrap();
+		db_test.run_stages(&db);
+		let mut slots = Vec::new();
+		db.iter_raw_slots(0, tier as u8, |index, _| {
+			slots.push(index);
+			true
+		})
+		.unwrap();
+		assert_eq!(slots.len(), 1);
+
+		assert_eq!(db.get_value_address(0, &[1u8; 32]).unwrap(), Some((tier as u8, slots[0])));
+		assert_eq!(db.get_value_address(0, &[2u8; 32]).unwrap(), None);
+		assert!(matches!(
+			db.get_value_address(1, &[1u8; 32]),
+			Err(crate::Error::InvalidConfiguration(_))
+		));
+	}
+
+	#[test]
+	fn test_redundant_write_skipped() {
+		for db_test in [EnableCommitPipelineStages::LogOverlay, EnableCommitPipelineStages::DbFile]
+		{
+			let tmp = tempdir().unwrap();
+			let options = db_test.options(tmp.path(), 1);
+			let db = Db::open_inner(&options, OpeningMode::Create).unwrap();
+			let key = [1u8; 32];
+
+			db.commit(vec![(0, key, Some(b"value".to_vec()))]).unwrap();
+			db_test.run_stages(&db);
+			db.commit(vec![(0, key, Some(b"value".to_vec()))]).unwrap();
+			db_test.run_stages(&db);
+			let stats = db.stats().columns[0].take().unwrap();
+			assert_eq!(stats.redundant_writes, 1);
+			assert_eq!(stats.total_values, 1);
+
+			db.commit(vec![(0, key, Some(b"other".to_vec()))]).unwrap();
+			db_test.run_stages(&db);
+			assert_eq!(db.stats().columns[0].take().unwrap().redundant_writes, 1);
+			assert_eq!(db.get(0, &key).unwrap(), Some(b"other".to_vec()));
+		}
+	}
+
 	#[test]
 	fn test_btree_iter() {
 		let col_nb = 0;
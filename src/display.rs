@@ -28,6 +28,16 @@ impl<'a> std::fmt::Debug for HexDisplay<'a> {
 	}
 }
 
+/// Format a count with a metric suffix, e.g. `1.2M`.
+pub fn human_count(n: u64) -> String {
+	match n {
+		0..=999 => format!("{}", n),
+		1_000..=999_999 => format!("{:.1}K", n as f64 / 1e3),
+		1_000_000..=999_999_999 => format!("{:.1}M", n as f64 / 1e6),
+		_ => format!("{:.1}G", n as f64 / 1e9),
+	}
+}
+
 pub fn hex<R: AsRef<[u8]> + ?Sized>(r: &R) -> HexDisplay<'_> {
 	HexDisplay::from(r)
 }
@@ -4,7 +4,7 @@
 use crate::column::ColId;
 #[cfg(feature = "instrumentation")]
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::{fmt, io, sync::Arc};
+use std::{fmt, io, sync::Arc, time::SystemTime};
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -15,12 +15,22 @@ pub enum Error {
 	InvalidConfiguration(String),
 	IncompatibleColumnConfig { id: ColId, reason: String },
 	InvalidInput(String),
+	InvalidKey(String),
 	InvalidValueData,
 	Background(Arc<Error>),
 	Locked(io::Error),
 	Migration(String),
 	Compression,
 	DatabaseNotFound,
+	DatabaseLocked,
+	NotOrderedColumn,
+	StaleSequence,
+	ConcurrentModification,
+	NotSupported,
+	ColumnReadOnly(ColId),
+	IndexFull(ColId),
+	ChecksumMismatch { table: String, offset: u64, stored: u32, computed: u32 },
+	ReadOnly,
 }
 
 impl fmt::Display for Error {
@@ -32,12 +42,26 @@ impl fmt::Display for Error {
 			Error::IncompatibleColumnConfig { id, reason } =>
 				write!(f, "Invalid column {} configuration : {}", id, reason),
 			Error::InvalidInput(e) => write!(f, "Invalid input: {}", e),
+			Error::InvalidKey(e) => write!(f, "Invalid key: {}", e),
 			Error::InvalidValueData => write!(f, "Invalid data in value table"),
 			Error::Background(e) => write!(f, "Background worker error: {}", e),
 			Error::Locked(e) => write!(f, "Database file is in use. ({})", e),
 			Error::Migration(e) => write!(f, "Migration error: {}", e),
 			Error::Compression => write!(f, "Compression error"),
 			Error::DatabaseNotFound => write!(f, "Database does not exist"),
+			Error::DatabaseLocked => write!(f, "Database is locked by another process"),
+			Error::NotOrderedColumn => write!(f, "Column is not ordered"),
+			Error::StaleSequence => write!(f, "Sequence number is behind the committed sequence"),
+			Error::ConcurrentModification => write!(f, "Column was modified during iteration"),
+			Error::NotSupported => write!(f, "Not supported on this platform"),
+			Error::ColumnReadOnly(col) => write!(f, "Column {} is read only", col),
+			Error::IndexFull(col) => write!(f, "Index of column {} reached `max_bits`", col),
+			Error::ChecksumMismatch { table, offset, stored, computed } => write!(
+				f,
+				"Checksum mismatch in {} at offset {}: stored {:08x}, computed {:08x}",
+				table, offset, stored, computed
+			),
+			Error::ReadOnly => write!(f, "Database is opened read only"),
 		}
 	}
 }
@@ -53,6 +77,23 @@ impl std::error::Error for Error {
 	}
 }
 
+/// An error reported to `Db::subscribe_errors` subscribers.
+#[derive(Debug, Clone)]
+pub struct DatabaseError {
+	pub error: Arc<Error>,
+	/// Column the failed operation was applied to.
+	pub col: ColId,
+	/// Name of the failed operation.
+	pub operation: &'static str,
+	pub timestamp: SystemTime,
+}
+
+impl fmt::Display for DatabaseError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "{} failed for column {}: {}", self.operation, self.col, self.error)
+	}
+}
+
 #[cfg(feature = "instrumentation")]
 pub static IO_COUNTER_BEFORE_ERROR: AtomicUsize = AtomicUsize::new(usize::MAX);
 
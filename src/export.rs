@@ -0,0 +1,113 @@
+// Copyright 2021-2022 Parity Technologies (UK) Ltd.
+// This file is dual-licensed as Apache-2.0 or MIT.
+
+//! Column export to Apache Parquet files.
+
+use crate::{
+	column::HashColumn,
+	error::{try_io, Error, Result},
+	log::Log,
+};
+use parquet::{
+	data_type::{ByteArray, ByteArrayType, Int32Type, Int64Type},
+	file::{
+		properties::WriterProperties,
+		writer::{SerializedFileWriter, SerializedRowGroupWriter},
+	},
+	schema::parser::parse_message_type,
+};
+use std::{fs::File, io::BufWriter, path::Path, sync::Arc};
+
+const ROW_GROUP_SIZE: usize = 10_000;
+
+const SCHEMA: &str = "
+	message entry {
+		REQUIRED BYTE_ARRAY key_hex (UTF8);
+		REQUIRED BYTE_ARRAY value_hex (UTF8);
+		REQUIRED INT32 tier;
+		REQUIRED INT64 offset;
+	}
+";
+
+/// Parquet export statistics.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ExportStats {
+	/// Number of exported entries.
+	pub rows: u64,
+	/// Number of written row groups.
+	pub row_groups: u64,
+}
+
+#[derive(Default)]
+struct Rows {
+	keys: Vec<ByteArray>,
+	values: Vec<ByteArray>,
+	tiers: Vec<i32>,
+	offsets: Vec<i64>,
+}
+
+impl Rows {
+	fn len(&self) -> usize {
+		self.keys.len()
+	}
+
+	fn write(&mut self, mut row_group: SerializedRowGroupWriter<BufWriter<File>>) -> Result<()> {
+		let mut index = 0;
+		while let Some(mut column) = row_group.next_column().map_err(parquet_err)? {
+			let written = match index {
+				0 => column.typed::<ByteArrayType>().write_batch(&self.keys, None, None),
+				1 => column.typed::<ByteArrayType>().write_batch(&self.values, None, None),
+				2 => column.typed::<Int32Type>().write_batch(&self.tiers, None, None),
+				_ => column.typed::<Int64Type>().write_batch(&self.offsets, None, None),
+			};
+			written.map_err(parquet_err)?;
+			column.close().map_err(parquet_err)?;
+			index += 1;
+		}
+		row_group.close().map_err(parquet_err)?;
+		*self = Default::default();
+		Ok(())
+	}
+}
+
+fn parquet_err(e: parquet::errors::ParquetError) -> Error {
+	Error::Io(std::io::Error::other(e))
+}
+
+pub fn export_parquet(column: &HashColumn, log: &Log, path: &Path) -> Result<ExportStats> {
+	let schema = Arc::new(parse_message_type(SCHEMA).map_err(parquet_err)?);
+	let props = Arc::new(WriterProperties::builder().build());
+	let file = BufWriter::new(try_io!(File::create(path)));
+	let mut writer = SerializedFileWriter::new(file, schema, props).map_err(parquet_err)?;
+	let mut stats = ExportStats::default();
+	let mut rows = Rows::default();
+	// The index is only locked while an entry is read, so that the column stays writable.
+	let mut iter = column.iter_with_offset(0, log)?;
+	loop {
+		let next = match iter.next_with_address() {
+			// Entries changed from here on may or may not be exported.
+			Err(Error::ConcurrentModification) => {
+				iter = column.iter_with_offset(iter.cursor(), log)?;
+				continue
+			},
+			next => next?,
+		};
+		let done = next.is_none();
+		if let Some((address, key, value)) = next {
+			rows.keys.push(hex::encode(key).into_bytes().into());
+			rows.values.push(hex::encode(value).into_bytes().into());
+			rows.tiers.push(address.size_tier() as i32);
+			rows.offsets.push(address.offset() as i64);
+		}
+		if rows.len() == ROW_GROUP_SIZE || (done && rows.len() > 0) {
+			stats.rows += rows.len() as u64;
+			stats.row_groups += 1;
+			rows.write(writer.next_row_group().map_err(parquet_err)?)?;
+		}
+		if done {
+			break
+		}
+	}
+	writer.close().map_err(parquet_err)?;
+	Ok(stats)
+}
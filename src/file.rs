@@ -36,6 +36,63 @@ fn disable_read_ahead(_file: &std::fs::File) -> std::io::Result<()> {
 	Ok(())
 }
 
+/// Ask the OS to start reading a file range into the page cache in the background.
+#[cfg(target_os = "linux")]
+pub fn will_need(file: &std::fs::File, offset: u64, len: u64) -> std::io::Result<()> {
+	use std::os::unix::io::AsRawFd;
+	let err = unsafe {
+		libc::posix_fadvise(
+			file.as_raw_fd(),
+			offset as libc::off_t,
+			len as libc::off_t,
+			libc::POSIX_FADV_WILLNEED,
+		)
+	};
+	if err != 0 {
+		Err(std::io::Error::from_raw_os_error(err))
+	} else {
+		Ok(())
+	}
+}
+
+/// Fraction of the pages of a memory mapped range that are resident in the page cache.
+/// `data` must start at a page boundary.
+#[cfg(target_os = "linux")]
+pub fn resident_fraction(data: &[u8]) -> std::io::Result<f64> {
+	if data.is_empty() {
+		return Ok(0.0)
+	}
+	let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+	let mut pages = vec![0u8; data.len().div_ceil(page_size)];
+	if unsafe { libc::mincore(data.as_ptr() as *mut libc::c_void, data.len(), pages.as_mut_ptr()) } !=
+		0
+	{
+		return Err(std::io::Error::last_os_error())
+	}
+	let resident = pages.iter().filter(|p| *p & 1 != 0).count();
+	Ok(resident as f64 / pages.len() as f64)
+}
+
+/// Deallocate disk blocks of a file range. The file size does not change and the range reads
+/// back as zeroes.
+#[cfg(target_os = "linux")]
+fn punch_hole(file: &std::fs::File, offset: u64, len: u64) -> std::io::Result<()> {
+	use std::os::unix::io::AsRawFd;
+	if unsafe {
+		libc::fallocate(
+			file.as_raw_fd(),
+			libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+			offset as libc::off_t,
+			len as libc::off_t,
+		)
+	} != 0
+	{
+		Err(std::io::Error::last_os_error())
+	} else {
+		Ok(())
+	}
+}
+
 // `File::sync_data` uses F_FULLSYNC fcntl on MacOS. It it supposed to be
 // the safest way to make sure data is fully persisted. However starting from
 // MacOS 11.0 it severely degrades parallel write performance, even when writing to
@@ -59,6 +116,24 @@ fn fsync(file: &std::fs::File) -> std::io::Result<()> {
 }
 
 const GROW_SIZE_BYTES: u64 = 256 * 1024;
+// Tail maps start at a multiple of this, which is a multiple of the page size and of the
+// Windows allocation granularity.
+const TAIL_MAP_ALIGN: u64 = 64 * 1024;
+
+// Read-only map of the end of a table file. Writes go through the file and are visible in the
+// map, as both share the page cache.
+#[derive(Debug)]
+struct TailMap {
+	start: u64,
+	map: memmap2::Mmap,
+}
+
+impl TailMap {
+	fn get(&self, offset: u64, len: usize) -> Option<&[u8]> {
+		let start = offset.checked_sub(self.start)? as usize;
+		self.map.get(start..start.checked_add(len)?)
+	}
+}
 
 #[derive(Debug)]
 pub struct TableFile {
@@ -67,6 +142,9 @@ pub struct TableFile {
 	pub capacity: AtomicU64,
 	pub dirty: AtomicBool,
 	pub id: TableId,
+	// Size of the tail map in bytes, disabled if zero. See `map_tail`.
+	tail_window: AtomicU64,
+	tail: RwLock<Option<TailMap>>,
 }
 
 impl TableFile {
@@ -96,9 +174,55 @@ impl TableFile {
 			capacity: AtomicU64::new(capacity),
 			dirty: AtomicBool::new(false),
 			id,
+			tail_window: AtomicU64::new(0),
+			tail: RwLock::new(None),
 		})
 	}
 
+	/// Map the last `window` bytes of data before `data_end` into memory, along with the
+	/// preallocated space after it, and serve reads within them from the map. The map is moved
+	/// each time the file grows, so that it covers the most recently written data. Other reads
+	/// use system calls. The map may start up to 64 KiB earlier for alignment. Disabled if
+	/// `window` is zero.
+	pub fn map_tail(&self, window: u64, data_end: u64) -> Result<()> {
+		self.tail_window.store(window, Ordering::Relaxed);
+		match &*self.file.read() {
+			Some(file) => self.remap_tail(file, data_end, try_io!(file.metadata()).len()),
+			None => Ok(()),
+		}
+	}
+
+	fn remap_tail(&self, file: &std::fs::File, data_end: u64, len: u64) -> Result<()> {
+		let window = self.tail_window.load(Ordering::Relaxed);
+		let mut tail = self.tail.write();
+		// Drop the old map first, so that only one is ever held.
+		*tail = None;
+		if window == 0 || len == 0 {
+			return Ok(())
+		}
+		let start = data_end.min(len).saturating_sub(window) / TAIL_MAP_ALIGN * TAIL_MAP_ALIGN;
+		let map = try_io!(unsafe {
+			memmap2::MmapOptions::new().offset(start).len((len - start) as usize).map(file)
+		});
+		*tail = Some(TailMap { start, map });
+		Ok(())
+	}
+
+	// Serve a read from the tail map, if it covers the whole range.
+	fn read_tail(&self, buf: &mut [u8], offset: u64) -> bool {
+		if let Some(data) = self.tail.read().as_ref().and_then(|t| t.get(offset, buf.len())) {
+			buf.copy_from_slice(data);
+			return true
+		}
+		false
+	}
+
+	/// Bytes of the file that are currently mapped by `map_tail`.
+	#[cfg(test)]
+	pub fn tail_mapped_bytes(&self) -> u64 {
+		self.tail.read().as_ref().map_or(0, |tail| tail.map.len() as u64)
+	}
+
 	fn create_file(&self) -> Result<std::fs::File> {
 		log::debug!(target: "parity-db", "Created value table {}", self.id);
 		let file = try_io!(std::fs::OpenOptions::new()
@@ -113,6 +237,9 @@ impl TableFile {
 	#[cfg(unix)]
 	pub fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<()> {
 		use std::os::unix::fs::FileExt;
+		if self.read_tail(buf, offset) {
+			return Ok(())
+		}
 		try_io!(self.file.read().as_ref().unwrap().read_exact_at(buf, offset));
 		Ok(())
 	}
@@ -130,6 +257,9 @@ impl TableFile {
 		use crate::error::Error;
 		use std::{io, os::windows::fs::FileExt};
 
+		if self.read_tail(buf, offset) {
+			return Ok(())
+		}
 		let file = self.file.read();
 		let file = file.as_ref().unwrap();
 
@@ -187,8 +317,23 @@ impl TableFile {
 		Ok(())
 	}
 
+	/// Deallocate disk blocks of a file range. Returns `false` if the file system does not
+	/// support this.
+	#[cfg(target_os = "linux")]
+	pub fn punch_hole(&self, offset: u64, len: u64) -> Result<bool> {
+		if let Some(file) = &*self.file.read() {
+			match punch_hole(file, offset, len) {
+				Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => return Ok(false),
+				result => try_io!(result),
+			}
+		}
+		Ok(true)
+	}
+
 	pub fn grow(&self, entry_size: u16) -> Result<()> {
 		let mut capacity = self.capacity.load(Ordering::Relaxed);
+		// The file is only grown once it is full.
+		let data_end = capacity * entry_size as u64;
 		capacity += GROW_SIZE_BYTES / entry_size as u64;
 
 		self.capacity.store(capacity, Ordering::Relaxed);
@@ -199,7 +344,29 @@ impl TableFile {
 			file = RwLockWriteGuard::downgrade_to_upgradable(wfile);
 		}
 		try_io!(file.as_ref().unwrap().set_len(capacity * entry_size as u64));
-		Ok(())
+		self.remap_tail(file.as_ref().unwrap(), data_end, capacity * entry_size as u64)
+	}
+
+	/// Truncate the file to `capacity` entries, if it is larger. Returns the number of released
+	/// bytes.
+	pub fn shrink(&self, capacity: u64, entry_size: u16) -> Result<u64> {
+		let file = self.file.read();
+		let file = match file.as_ref() {
+			Some(file) => file,
+			None => return Ok(0),
+		};
+		let len = try_io!(file.metadata()).len();
+		let new_len = capacity * entry_size as u64;
+		if new_len >= len {
+			return Ok(0)
+		}
+		// Accessing a map past the end of the file is an error.
+		*self.tail.write() = None;
+		try_io!(file.set_len(new_len));
+		self.capacity.store(capacity, Ordering::Relaxed);
+		self.dirty.store(true, Ordering::Relaxed);
+		self.remap_tail(file, new_len, new_len)?;
+		Ok(len - new_len)
 	}
 
 	pub fn flush(&self) -> Result<()> {
@@ -215,6 +382,7 @@ impl TableFile {
 
 	pub fn remove(&self) -> Result<()> {
 		let mut file = self.file.write();
+		*self.tail.write() = None;
 		if let Some(file) = file.take() {
 			drop(file);
 			try_io!(std::fs::remove_file(&self.path));
@@ -6,21 +6,29 @@ use crate::{
 	display::hex,
 	error::{try_io, Error, Result},
 	log::{LogQuery, LogReader, LogWriter},
-	parking_lot::{RwLock, RwLockUpgradableReadGuard, RwLockWriteGuard},
+	parking_lot::{Mutex, RwLock, RwLockReadGuard, RwLockUpgradableReadGuard, RwLockWriteGuard},
 	stats::{self, ColumnStats},
-	table::{key::TableKey, SIZE_TIERS_BITS},
+	table::{key::TableKey, ValueTable, SIZE_TIERS_BITS},
 	Key,
 };
-use std::convert::TryInto;
+use std::{
+	convert::TryInto,
+	sync::atomic::{AtomicU64, Ordering},
+};
 
 // Index chunk consists of 8 64-bit entries.
 const CHUNK_LEN: usize = CHUNK_ENTRIES * ENTRY_BYTES; // 512 bytes
-const CHUNK_ENTRIES: usize = 1 << CHUNK_ENTRIES_BITS;
+pub const CHUNK_ENTRIES: usize = 1 << CHUNK_ENTRIES_BITS;
 const CHUNK_ENTRIES_BITS: u8 = 6;
 const HEADER_SIZE: usize = 512;
 const META_SIZE: usize = 16 * 1024; // Contains header and column stats
 const ENTRY_LEN: u8 = 64;
 pub const ENTRY_BYTES: usize = ENTRY_LEN as usize / 8;
+/// Number of leading key bits that can be recovered from an index entry.
+pub const KEY_PREFIX_BITS: u8 = ENTRY_LEN - CHUNK_ENTRIES_BITS - SIZE_TIERS_BITS;
+/// Largest number of index bits. Entries of larger indexes would have no room for the value
+/// address.
+pub const MAX_INDEX_BITS: u8 = KEY_PREFIX_BITS - 1;
 
 const EMPTY_CHUNK: Chunk = [0u8; CHUNK_LEN];
 
@@ -113,17 +121,77 @@ impl std::fmt::Display for Address {
 	}
 }
 
+/// Iterator over index file entries. Holds a read lock on the index map.
+pub struct IndexEntryIter<'a> {
+	table: &'a IndexTable,
+	map: RwLockReadGuard<'a, Option<memmap2::MmapMut>>,
+	chunk_index: u64,
+	sub_index: usize,
+}
+
+impl<'a> Iterator for IndexEntryIter<'a> {
+	type Item = (Key, Address);
+
+	fn next(&mut self) -> Option<Self::Item> {
+		let map = self.map.as_ref()?;
+		let index_bits = self.table.id.index_bits();
+		while self.chunk_index < self.table.id.total_chunks() {
+			let chunk_index = self.chunk_index;
+			let chunk = IndexTable::chunk_at(chunk_index, map).ok()?;
+			while self.sub_index < CHUNK_ENTRIES {
+				let entry = IndexTable::read_entry(chunk, self.sub_index);
+				self.sub_index += 1;
+				if !entry.is_empty() {
+					let key = self.table.recover_key_prefix(chunk_index, entry);
+					return Some((key, entry.address(index_bits)))
+				}
+			}
+			self.chunk_index += 1;
+			self.sub_index = 0;
+		}
+		None
+	}
+}
+
+/// Index entry that does not point to a live value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CorruptAddress {
+	pub chunk_index: u64,
+	pub sub_index: usize,
+	pub address: Address,
+}
+
 pub enum PlanOutcome {
 	Written,
 	NeedReindex,
 	Skipped,
 }
 
+/// Probe chain statistics of `IndexTable::compact_chains`. A chain is the number of chunk
+/// entries scanned to find a key.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct CompactChainStats {
+	/// Number of live index entries.
+	pub entries: u64,
+	/// Average chain length before compaction.
+	pub avg_chain_before: f64,
+	/// Average chain length after compaction.
+	pub avg_chain_after: f64,
+	/// Longest chain before compaction.
+	pub max_chain_before: u64,
+	/// Longest chain after compaction.
+	pub max_chain_after: u64,
+}
+
 #[derive(Debug)]
 pub struct IndexTable {
 	pub id: TableId,
 	map: RwLock<Option<memmap2::MmapMut>>,
 	path: std::path::PathBuf,
+	// Incremented on each planned insert or removal. Not persisted.
+	iteration_version: AtomicU64,
+	// File of the table this one was resized from in place. It is renamed on the first write.
+	rename_from: Mutex<Option<std::path::PathBuf>>,
 }
 
 fn total_entries(index_bits: u8) -> u64 {
@@ -199,13 +267,31 @@ impl IndexTable {
 		try_io!(file.set_len(file_size(id.index_bits())));
 		let map = try_io!(unsafe { memmap2::MmapMut::map_mut(&file) });
 		log::debug!(target: "parity-db", "Opened existing index {}", id);
-		Ok(Some(IndexTable { id, path, map: RwLock::new(Some(map)) }))
+		Ok(Some(IndexTable {
+			id,
+			path,
+			map: RwLock::new(Some(map)),
+			iteration_version: AtomicU64::new(0),
+			rename_from: Mutex::new(None),
+		}))
 	}
 
 	pub fn create_new(path: &std::path::Path, id: TableId) -> IndexTable {
 		let mut path: std::path::PathBuf = path.into();
 		path.push(id.file_name());
-		IndexTable { id, path, map: RwLock::new(None) }
+		IndexTable {
+			id,
+			path,
+			map: RwLock::new(None),
+			iteration_version: AtomicU64::new(0),
+			rename_from: Mutex::new(None),
+		}
+	}
+
+	/// Number of inserts and removals planned since the table was opened. Iterators use this to
+	/// detect modifications.
+	pub fn iteration_version(&self) -> u64 {
+		self.iteration_version.load(Ordering::Acquire)
 	}
 
 	pub fn load_stats(&self) -> Result<ColumnStats> {
@@ -274,6 +360,14 @@ impl IndexTable {
 		Ok((Entry::empty(), 0))
 	}
 
+	/// Check that `entry` at `chunk_index` matches the leading bits of `key`.
+	pub fn is_entry_of(&self, chunk_index: u64, entry: Entry, key: &Key) -> bool {
+		let key = TableKey::index_from_partial(key);
+		self.chunk_index(key) == chunk_index &&
+			Entry::extract_key(key, self.id.index_bits()) ==
+				entry.partial_key(self.id.index_bits())
+	}
+
 	pub fn entries(&self, chunk_index: u64, log: &impl LogQuery) -> Result<[Entry; CHUNK_ENTRIES]> {
 		let mut chunk = [0; CHUNK_LEN];
 		if let Some(entry) =
@@ -289,6 +383,204 @@ impl IndexTable {
 		Ok(Self::transmute_chunk(EMPTY_CHUNK))
 	}
 
+	/// Count the entries of this table that belong in chunk `chunk_index` of an index with
+	/// `index_bits` bits, which must not be fewer than the bits of this table. Entries in chunks
+	/// of this table before `from_chunk` are not counted.
+	pub fn count_entries_for(
+		&self,
+		chunk_index: u64,
+		index_bits: u8,
+		from_chunk: u64,
+		log: &impl LogQuery,
+	) -> Result<usize> {
+		let own_chunk = chunk_index >> (index_bits - self.id.index_bits());
+		if own_chunk < from_chunk {
+			return Ok(0)
+		}
+		let entries = self.entries(own_chunk, log)?;
+		Ok(entries
+			.iter()
+			.filter(|entry| !entry.is_empty())
+			.filter(|entry| {
+				let key = self.recover_key_prefix(own_chunk, **entry);
+				TableKey::index_from_partial(&key) >> (ENTRY_LEN - index_bits) == chunk_index
+			})
+			.count())
+	}
+
+	/// Iterate over all non-empty entries in the index file. Keys only have the leading bits that
+	/// are stored in the index. Pending log changes are ignored.
+	pub fn iter_all_entries(&self) -> IndexEntryIter<'_> {
+		IndexEntryIter { table: self, map: self.map.read(), chunk_index: 0, sub_index: 0 }
+	}
+
+	/// Count entries with keys starting with the high `prefix_bits` bits of `prefix`, skipping
+	/// chunks before `from_chunk`. `prefix_bits` must not exceed `KEY_PREFIX_BITS`.
+	pub fn count_prefix(
+		&self,
+		prefix: u64,
+		prefix_bits: u8,
+		from_chunk: u64,
+		log: &impl LogQuery,
+	) -> Result<u64> {
+		let shift = 64 - self.id.index_bits();
+		let mask = if prefix_bits == 0 { 0 } else { u64::MAX << (64 - prefix_bits) };
+		let first = (prefix & mask) >> shift;
+		let last = (prefix | !mask) >> shift;
+		let mut count = 0;
+		for chunk_index in std::cmp::max(first, from_chunk)..=last {
+			for entry in self.entries(chunk_index, log)?.iter() {
+				if entry.is_empty() {
+					continue
+				}
+				let key = self.recover_key_prefix(chunk_index, *entry);
+				let key = u64::from_be_bytes(key[0..8].try_into().unwrap());
+				if (key ^ prefix) & mask == 0 {
+					count += 1;
+				}
+			}
+		}
+		Ok(count)
+	}
+
+	/// Start loading the first `chunks` index chunks, or the whole index if `None`, into the page
+	/// cache. On Linux the kernel reads the file in the background. Elsewhere pages are read
+	/// before returning.
+	pub fn warm_cache(&self, chunks: Option<u64>) -> Result<()> {
+		let map = self.map.read();
+		let map = match &*map {
+			Some(map) => map,
+			None => return Ok(()),
+		};
+		let total = self.id.total_chunks();
+		let chunks = chunks.map_or(total, |chunks| std::cmp::min(chunks, total));
+		let len = std::cmp::min(META_SIZE as u64 + chunks * CHUNK_LEN as u64, map.len() as u64);
+		log::debug!(target: "parity-db", "{}: Warming up {} chunks", self.id, chunks);
+		#[cfg(target_os = "linux")]
+		{
+			let file = try_io!(std::fs::File::open(&self.path));
+			try_io!(crate::file::will_need(&file, 0, len));
+		}
+		#[cfg(not(target_os = "linux"))]
+		{
+			// Touch every page.
+			let mut sum = 0u8;
+			for offset in (0..len as usize).step_by(4096) {
+				sum = sum.wrapping_add(map[offset]);
+			}
+			std::hint::black_box(sum);
+		}
+		Ok(())
+	}
+
+	/// Fraction of the index file pages that are in the OS page cache.
+	#[cfg(target_os = "linux")]
+	pub fn page_cache_hit_ratio(&self) -> Result<f64> {
+		match &*self.map.read() {
+			Some(map) => Ok(try_io!(crate::file::resident_fraction(map))),
+			None => Ok(0.0),
+		}
+	}
+
+	#[cfg(not(target_os = "linux"))]
+	pub fn page_cache_hit_ratio(&self) -> Result<f64> {
+		Err(Error::NotSupported)
+	}
+
+	/// Index of the chunk `key` belongs to, along with the number of empty entries in it.
+	pub fn chunk_free_entries(&self, key: &Key, log: &impl LogQuery) -> Result<(u64, usize)> {
+		let chunk_index = self.chunk_index(TableKey::index_from_partial(key));
+		let free = self.entries(chunk_index, log)?.iter().filter(|e| e.is_empty()).count();
+		Ok((chunk_index, free))
+	}
+
+	/// Count entries by the number of probes needed to find them. Item `i` is the number of
+	/// entries found after `i + 1` probes. Lookups scan chunk entries in order, so this is the
+	/// entry position in the chunk.
+	pub fn probe_distance_histogram(&self, log: &impl LogQuery) -> Result<Vec<u64>> {
+		let mut histogram = vec![0; CHUNK_ENTRIES];
+		for chunk_index in 0..self.id.total_chunks() {
+			for (i, entry) in self.entries(chunk_index, log)?.iter().enumerate() {
+				if !entry.is_empty() {
+					histogram[i] += 1;
+				}
+			}
+		}
+		Ok(histogram)
+	}
+
+	/// Count entries by the number of slots matching their partial key that a lookup checks
+	/// before finding them, including their own. Item `i` is the number of entries found after
+	/// checking `i + 1` candidates. All candidates but the last are partial key collisions, each
+	/// costing a value table read. Lookups are simulated with `get`.
+	pub fn probe_depth_histogram(&self, log: &impl LogQuery) -> Result<Vec<u64>> {
+		let mut histogram = vec![0; CHUNK_ENTRIES];
+		for chunk_index in 0..self.id.total_chunks() {
+			for (i, entry) in self.entries(chunk_index, log)?.iter().enumerate() {
+				if entry.is_empty() {
+					continue
+				}
+				let key = self.recover_key_prefix(chunk_index, *entry);
+				let mut depth = 0;
+				let mut sub_index = 0;
+				loop {
+					let (found, found_index) = self.get(&key, sub_index, log)?;
+					depth += 1;
+					if found.is_empty() || found_index >= i {
+						break
+					}
+					sub_index = found_index + 1;
+				}
+				histogram[depth - 1] += 1;
+			}
+		}
+		Ok(histogram)
+	}
+
+	/// Largest probe depth in a `probe_depth_histogram`, or zero if there are no entries.
+	pub fn max_probe_depth(histogram: &[u64]) -> usize {
+		histogram.iter().rposition(|count| *count != 0).map_or(0, |i| i + 1)
+	}
+
+	/// Average probe depth in a `probe_depth_histogram`, or zero if there are no entries.
+	pub fn mean_probe_depth(histogram: &[u64]) -> f64 {
+		let entries: u64 = histogram.iter().sum();
+		if entries == 0 {
+			return 0.0
+		}
+		let probes: u64 =
+			histogram.iter().enumerate().map(|(i, count)| (i as u64 + 1) * count).sum();
+		probes as f64 / entries as f64
+	}
+
+	/// Check that every index entry points to a live entry in `value_tables`.
+	pub fn check_referential_integrity(
+		&self,
+		value_tables: &[ValueTable],
+		log: &impl LogQuery,
+	) -> Result<Vec<CorruptAddress>> {
+		let mut corrupt = Vec::new();
+		for chunk_index in 0..self.id.total_chunks() {
+			for (sub_index, entry) in self.entries(chunk_index, log)?.iter().enumerate() {
+				if entry.is_empty() {
+					continue
+				}
+				let address = entry.address(self.id.index_bits());
+				let live = match value_tables.get(address.size_tier() as usize) {
+					Some(table) =>
+						address.offset() != 0 &&
+							address.offset() < table.filled() &&
+							table.has_key_at(address.offset(), &TableKey::NoHash, log)?,
+					None => false,
+				};
+				if !live {
+					corrupt.push(CorruptAddress { chunk_index, sub_index, address });
+				}
+			}
+		}
+		Ok(corrupt)
+	}
+
 	#[inline(always)]
 	fn transmute_chunk(chunk: [u8; CHUNK_LEN]) -> [Entry; CHUNK_ENTRIES] {
 		let mut result: [Entry; CHUNK_ENTRIES] = unsafe { std::mem::transmute(chunk) };
@@ -365,6 +657,7 @@ impl IndexTable {
 		log: &mut LogWriter,
 	) -> Result<PlanOutcome> {
 		log::trace!(target: "parity-db", "{}: Inserting {} -> {}", self.id, hex(key), address);
+		self.iteration_version.fetch_add(1, Ordering::AcqRel);
 		let key_prefix = TableKey::index_from_partial(key);
 		let chunk_index = self.chunk_index(key_prefix);
 
@@ -412,6 +705,7 @@ impl IndexTable {
 		log: &mut LogWriter,
 	) -> Result<PlanOutcome> {
 		log::trace!(target: "parity-db", "{}: Removing {}", self.id, hex(key));
+		self.iteration_version.fetch_add(1, Ordering::AcqRel);
 		let key_prefix = TableKey::index_from_partial(key);
 
 		let chunk_index = self.chunk_index(key_prefix);
@@ -428,7 +722,170 @@ impl IndexTable {
 		Ok(PlanOutcome::Skipped)
 	}
 
+	/// Rewrite all entries so that each key is found with the shortest possible probe chain.
+	///
+	/// Robin Hood insertion places entries with a longer distance from their home slot first.
+	/// Here every key is confined to a single chunk and all entries of a chunk share the same
+	/// home slot, so this amounts to moving all live entries of each chunk to the front,
+	/// preserving their relative order. The longest chain is then the number of live entries in
+	/// the fullest chunk.
+	pub fn compact_chains(&self, log: &mut LogWriter) -> Result<CompactChainStats> {
+		let mut entries = 0;
+		let mut probe_before = 0;
+		let mut probe_after = 0;
+		let mut stats = CompactChainStats::default();
+		for chunk_index in 0..self.id.total_chunks() {
+			let (live, before, longest) = self.write_compact_chunk_plan(chunk_index, log)?;
+			entries += live;
+			probe_before += before;
+			probe_after += live * (live + 1) / 2;
+			stats.max_chain_before = stats.max_chain_before.max(longest);
+			stats.max_chain_after = stats.max_chain_after.max(live);
+		}
+		let avg = |probe: u64| if entries == 0 { 0.0 } else { probe as f64 / entries as f64 };
+		stats.entries = entries;
+		stats.avg_chain_before = avg(probe_before);
+		stats.avg_chain_after = avg(probe_after);
+		Ok(stats)
+	}
+
+	// Move all live entries of a chunk to the front, preserving their relative order.
+	// Returns the number of live entries, the sum of their probe lengths and the longest
+	// probe length before compaction.
+	fn write_compact_chunk_plan(
+		&self,
+		chunk_index: u64,
+		log: &mut LogWriter,
+	) -> Result<(u64, u64, u64)> {
+		let entries = self.entries(chunk_index, &*log)?;
+		let mut chunk = [0; CHUNK_LEN];
+		let mut modified = Vec::new();
+		let mut live = 0;
+		let mut probe_before = 0;
+		let mut longest = 0;
+		for (i, entry) in entries.iter().enumerate() {
+			if entry.is_empty() {
+				continue
+			}
+			Self::write_entry(entry, live, &mut chunk);
+			if i != live {
+				modified.push(i);
+				modified.push(live);
+			}
+			live += 1;
+			probe_before += i as u64 + 1;
+			longest = i as u64 + 1;
+		}
+		if !modified.is_empty() {
+			log::trace!(target: "parity-db", "{}: Compacted chunk {}", self.id, chunk_index);
+			// Entries change position, so iterators over this table can't resume.
+			self.iteration_version.fetch_add(1, Ordering::AcqRel);
+		}
+		for i in modified {
+			log.insert_index(self.id, chunk_index, i as u8, &chunk);
+		}
+		Ok((live as u64, probe_before, longest))
+	}
+
+	/// Plan doubling the number of chunks, reusing the table file. Entries of chunk `c` are split
+	/// between chunks `2c` and `2c + 1` of the returned table, which replaces this one. The file
+	/// is renamed when the plan is enacted. All changes to this table must be enacted first.
+	pub fn resize_in_place(&self, log: &mut LogWriter) -> Result<IndexTable> {
+		if log.has_pending_index_changes(self.id) {
+			return Err(Error::InvalidInput(format!("Index {} has pending changes", self.id)))
+		}
+		let index_bits = self.id.index_bits();
+		let id = TableId::new(self.id.col(), index_bits + 1);
+		let dir = self.path.parent().unwrap_or(std::path::Path::new(""));
+		let mut table = IndexTable::create_new(dir, id);
+		if self.map.read().is_none() {
+			return Ok(table)
+		}
+		let file = try_io!(std::fs::OpenOptions::new().read(true).write(true).open(&self.path));
+		try_io!(file.set_len(file_size(id.index_bits())));
+		let mut map = try_io!(unsafe { memmap2::MmapMut::map_mut(&file) });
+		table.madvise_random(&mut map);
+		// Number of partial key bits in the resized table. The bit above them selects the chunk.
+		let key_bits = 64 - Entry::address_bits(id.index_bits());
+		for chunk_index in 0..self.id.total_chunks() {
+			let entries = self.entries(chunk_index, &*log)?;
+			// First pass: mark entries that move to the upper chunk.
+			let mut upper = 0u64;
+			for (i, entry) in entries.iter().enumerate() {
+				if !entry.is_empty() && (entry.partial_key(index_bits) >> key_bits) & 1 == 1 {
+					upper |= 1 << i;
+				}
+			}
+			// Second pass: relocate entries, preserving their order.
+			let mut chunks = [EMPTY_CHUNK; 2];
+			let mut len = [0; 2];
+			for (i, entry) in entries.iter().enumerate() {
+				if entry.is_empty() {
+					continue
+				}
+				let half = ((upper >> i) & 1) as usize;
+				let partial_key = entry.partial_key(index_bits) & ((1 << key_bits) - 1);
+				let entry = Entry::new(entry.address(index_bits), partial_key, id.index_bits());
+				Self::write_entry(&entry, len[half], &mut chunks[half]);
+				len[half] += 1;
+			}
+			for (half, chunk) in chunks.iter().enumerate() {
+				let target = chunk_index * 2 + half as u64;
+				let current = Self::chunk_at(target, &map)?;
+				for i in 0..CHUNK_ENTRIES {
+					if Self::read_entry(current, i) != Self::read_entry(chunk, i) {
+						log.insert_index(id, target, i as u8, chunk);
+					}
+				}
+			}
+		}
+		table.map = RwLock::new(Some(map));
+		table.rename_from = Mutex::new(Some(self.path.clone()));
+		Ok(table)
+	}
+
 	pub fn enact_plan(&self, index: u64, log: &mut LogReader) -> Result<()> {
+		self.enact_chunk(index, |chunk| {
+			let mut mask_buf = [0u8; 8];
+			log.read(&mut mask_buf)?;
+			let mut mask = u64::from_le_bytes(mask_buf);
+			while mask != 0 {
+				let i = mask.trailing_zeros();
+				mask &= !(1 << i);
+				log.read(try_io!(Ok(
+					&mut chunk[i as usize * ENTRY_BYTES..(i as usize + 1) * ENTRY_BYTES]
+				)))?;
+			}
+			Ok(())
+		})?;
+		log::trace!(target: "parity-db", "{}: Enacted chunk {}", self.id, index);
+		Ok(())
+	}
+
+	pub fn enact_delete_plan(&self, index: u64, log: &mut LogReader) -> Result<()> {
+		self.enact_chunk(index, |chunk| {
+			let mut mask_buf = [0u8; 8];
+			log.read(&mut mask_buf)?;
+			let mut mask = u64::from_le_bytes(mask_buf);
+			while mask != 0 {
+				let i = mask.trailing_zeros();
+				mask &= !(1 << i);
+				chunk[i as usize * ENTRY_BYTES..(i as usize + 1) * ENTRY_BYTES].fill(0);
+			}
+			Ok(())
+		})?;
+		log::trace!(target: "parity-db", "{}: Enacted deletion in chunk {}", self.id, index);
+		Ok(())
+	}
+
+	fn enact_chunk(&self, index: u64, f: impl FnOnce(&mut [u8]) -> Result<()>) -> Result<()> {
+		if index >= self.id.total_chunks() {
+			return Err(Error::Corruption(format!("{}: Bad chunk index {}", self.id, index)))
+		}
+		if let Some(from) = self.rename_from.lock().take() {
+			try_io!(std::fs::rename(&from, &self.path));
+			log::debug!(target: "parity-db", "Renamed resized index {}", self.id);
+		}
 		let mut map = self.map.upgradable_read();
 		if map.is_none() {
 			let mut wmap = RwLockUpgradableReadGuard::upgrade(map);
@@ -455,18 +912,7 @@ impl IndexTable {
 			let ptr = ptr.add(offset);
 			std::slice::from_raw_parts_mut(ptr, CHUNK_LEN)
 		};
-		let mut mask_buf = [0u8; 8];
-		log.read(&mut mask_buf)?;
-		let mut mask = u64::from_le_bytes(mask_buf);
-		while mask != 0 {
-			let i = mask.trailing_zeros();
-			mask &= !(1 << i);
-			log.read(try_io!(Ok(
-				&mut chunk[i as usize * ENTRY_BYTES..(i as usize + 1) * ENTRY_BYTES]
-			)))?;
-		}
-		log::trace!(target: "parity-db", "{}: Enacted chunk {}", self.id, index);
-		Ok(())
+		f(chunk)
 	}
 
 	pub fn validate_plan(&self, index: u64, log: &mut LogReader) -> Result<()> {
@@ -485,6 +931,21 @@ impl IndexTable {
 		Ok(())
 	}
 
+	pub fn validate_delete_plan(&self, index: u64, log: &mut LogReader) -> Result<()> {
+		if index >= self.id.total_entries() {
+			return Err(Error::Corruption("Bad index".into()))
+		}
+		let mut buf = [0u8; 8];
+		log.read(&mut buf)?;
+		log::trace!(target: "parity-db", "{}: Validated deletion in chunk {}", self.id, index);
+		Ok(())
+	}
+
+	pub fn skip_delete_plan(log: &mut LogReader) -> Result<()> {
+		let mut buf = [0u8; 8];
+		log.read(&mut buf)
+	}
+
 	pub fn skip_plan(log: &mut LogReader) -> Result<()> {
 		let mut buf = [0u8; 8];
 		log.read(&mut buf)?;
@@ -498,8 +959,12 @@ impl IndexTable {
 	}
 
 	pub fn drop_file(self) -> Result<()> {
+		let exists = self.map.read().is_some();
+		let path = self.rename_from.lock().take().unwrap_or(self.path);
 		drop(self.map);
-		try_io!(std::fs::remove_file(self.path.as_path()));
+		if exists {
+			try_io!(std::fs::remove_file(path.as_path()));
+		}
 		log::debug!(target: "parity-db", "{}: Dropped table", self.id);
 		Ok(())
 	}
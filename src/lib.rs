@@ -1,12 +1,20 @@
 // Copyright 2021-2022 Parity Technologies (UK) Ltd.
 // This file is dual-licensed as Apache-2.0 or MIT.
 
+mod audit;
+mod backup;
+#[cfg(feature = "bloom")]
+mod bloom;
 mod btree;
+mod cache;
+mod changelog;
 mod column;
 mod compress;
 mod db;
 mod display;
 mod error;
+#[cfg(feature = "parquet")]
+mod export;
 mod file;
 mod hash;
 mod index;
@@ -16,16 +24,38 @@ mod options;
 mod parking_lot;
 mod stats;
 mod table;
+pub mod types;
 
+pub use backup::restore_column;
+#[cfg(feature = "bloom")]
+pub use bloom::BloomStats;
 pub use btree::BTreeIterator;
+pub use cache::ValueCacheStats;
+pub use changelog::ChangeEntry;
+#[cfg(feature = "async-tokio")]
+pub use column::RebalanceProgress;
+pub use column::{
+	CorruptionError, DefragIndexStats, GarbageStats, IntegrityReport, PaginatedIterator,
+	MAX_REPORTED_CORRUPTIONS,
+};
 pub use compress::CompressionType;
-pub use db::{check::CheckOptions, Db, Operation, Value};
+pub use db::{
+	check::CheckOptions, Db, DbGroup, DryRunReport, DuplicateKeys, MultiColumnTx, Operation,
+	PutIfVersionOutcome, ReadTx, TransferStats, Value,
+};
 #[cfg(feature = "instrumentation")]
 pub use error::set_number_of_allowed_io_operations;
-pub use error::{Error, Result};
-pub use migration::{clear_column, migrate};
+pub use error::{DatabaseError, Error, Result};
+#[cfg(feature = "parquet")]
+pub use export::ExportStats;
+pub use migration::{clear_column, migrate, migrate_add_checksums};
 pub use options::{ColumnOptions, Options};
-pub use stats::{ColumnStatSummary, StatSummary};
+pub use stats::{
+	CachedStats, ColumnCounters, ColumnCountersSnapshot, ColumnStatSummary, DatabaseStats,
+	DbFileStats, FileInfo, FragmentationReport, MaintenanceAction, MaintenanceKind,
+	MaintenanceReport, MaintenanceSeverity, StatSummary, StatsSnapshot, TierFragmentation,
+};
+pub use table::FsckReport;
 
 pub const KEY_SIZE: usize = 32;
 pub type Key = [u8; KEY_SIZE];
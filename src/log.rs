@@ -5,8 +5,8 @@ use crate::{
 	column::ColId,
 	error::{try_io, Error, Result},
 	index::{Chunk as IndexChunk, TableId as IndexTableId, ENTRY_BYTES},
-	options::Options,
-	parking_lot::{RwLock, RwLockWriteGuard},
+	options::{ColumnOptions, Options},
+	parking_lot::{Mutex, RwLock, RwLockWriteGuard},
 	table::TableId as ValueTableId,
 };
 use std::{
@@ -23,6 +23,12 @@ const INSERT_INDEX: u8 = 2;
 const INSERT_VALUE: u8 = 3;
 const END_RECORD: u8 = 4;
 const DROP_TABLE: u8 = 5;
+const SEQUENCE: u8 = 6;
+const BARRIER: u8 = 7;
+const CREATE_COLUMN: u8 = 8;
+const DROP_COLUMN: u8 = 9;
+const DELETE_INDEX: u8 = 11;
+const DELETE_VALUE: u8 = 12;
 
 #[derive(Debug)]
 pub struct InsertIndexAction {
@@ -42,9 +48,67 @@ pub enum LogAction {
 	InsertIndex(InsertIndexAction),
 	InsertValue(InsertValueAction),
 	DropTable(IndexTableId),
+	Sequence(u64),
+	Barrier(u64),
+	CreateColumn {
+		col: ColId,
+		options: ColumnOptions,
+	},
+	DropColumn {
+		col: ColId,
+	},
+	/// Clears entries of an index chunk. Followed by the mask of cleared entries.
+	DeleteIndex {
+		table: IndexTableId,
+		index: u64,
+	},
+	/// Frees a value table entry. Followed by the next entry of the free list.
+	DeleteValue {
+		table: ValueTableId,
+		index: u64,
+	},
 	EndRecord,
 }
 
+impl LogAction {
+	/// Column of an index or value table change.
+	pub fn table_col(&self) -> Option<ColId> {
+		match self {
+			LogAction::InsertIndex(InsertIndexAction { table, .. }) |
+			LogAction::DeleteIndex { table, .. } => Some(table.col()),
+			LogAction::InsertValue(InsertValueAction { table, .. }) |
+			LogAction::DeleteValue { table, .. } => Some(table.col()),
+			_ => None,
+		}
+	}
+}
+
+// Entries of a modified index chunk that are cleared. Only written entries are logged with
+// `INSERT_INDEX`.
+fn deleted_entries(modified_entries_mask: u64, chunk: &IndexChunk) -> u64 {
+	let mut deleted = 0;
+	let mut mask = modified_entries_mask;
+	while mask != 0 {
+		let i = mask.trailing_zeros();
+		mask &= !(1 << i);
+		if chunk[i as usize * ENTRY_BYTES..(i as usize + 1) * ENTRY_BYTES]
+			.iter()
+			.all(|b| *b == 0)
+		{
+			deleted |= 1 << i;
+		}
+	}
+	deleted
+}
+
+// Free list link of a value table change that frees the entry. Entry 0 holds the table header.
+fn deleted_value_link(index: u64, value: &[u8]) -> Option<&[u8]> {
+	if index == 0 {
+		return None
+	}
+	crate::table::removed_entry_link(value)
+}
+
 pub trait LogQuery {
 	fn with_index<R, F: FnOnce(&IndexChunk) -> R>(
 		&self,
@@ -216,6 +280,49 @@ impl<'a> LogReader<'a> {
 					IndexTableId::from_u16(u16::from_le_bytes(buf[0..2].try_into().unwrap()));
 				Ok(LogAction::DropTable(table))
 			},
+			SEQUENCE => {
+				read_buf(8, &mut buf)?;
+				Ok(LogAction::Sequence(u64::from_le_bytes(buf)))
+			},
+			BARRIER => {
+				read_buf(8, &mut buf)?;
+				Ok(LogAction::Barrier(u64::from_le_bytes(buf)))
+			},
+			CREATE_COLUMN => {
+				read_buf(1, &mut buf)?;
+				let col = buf[0];
+				read_buf(2, &mut buf)?;
+				let mut options =
+					vec![0; u16::from_le_bytes(buf[0..2].try_into().unwrap()) as usize];
+				self.read(&mut options)?;
+				let options = std::str::from_utf8(&options)
+					.ok()
+					.and_then(ColumnOptions::from_string)
+					.ok_or_else(|| Error::Corruption("Bad column options in log".into()))?;
+				Ok(LogAction::CreateColumn { col, options })
+			},
+			DROP_COLUMN => {
+				read_buf(1, &mut buf)?;
+				Ok(LogAction::DropColumn { col: buf[0] })
+			},
+			DELETE_INDEX => {
+				read_buf(2, &mut buf)?;
+				let table =
+					IndexTableId::from_u16(u16::from_le_bytes(buf[0..2].try_into().unwrap()));
+				read_buf(8, &mut buf)?;
+				let index = u64::from_le_bytes(buf);
+				self.cleared.index.push((table, index));
+				Ok(LogAction::DeleteIndex { table, index })
+			},
+			DELETE_VALUE => {
+				read_buf(2, &mut buf)?;
+				let table =
+					ValueTableId::from_u16(u16::from_le_bytes(buf[0..2].try_into().unwrap()));
+				read_buf(8, &mut buf)?;
+				let index = u64::from_le_bytes(buf);
+				self.cleared.values.push((table, index));
+				Ok(LogAction::DeleteValue { table, index })
+			},
 			_ => Err(Error::Corruption("Bad log entry type".into())),
 		}
 	}
@@ -244,6 +351,9 @@ pub struct LogChange {
 	local_values: HashMap<ValueTableId, ValueLogOverlay>,
 	record_id: u64,
 	dropped_tables: Vec<IndexTableId>,
+	sequence: Option<u64>,
+	barriers: Vec<u64>,
+	column_actions: Vec<LogAction>,
 }
 
 impl LogChange {
@@ -253,6 +363,9 @@ impl LogChange {
 			local_values: Default::default(),
 			dropped_tables: Default::default(),
 			record_id,
+			sequence: None,
+			barriers: Default::default(),
+			column_actions: Default::default(),
 		}
 	}
 
@@ -260,6 +373,43 @@ impl LogChange {
 		self.local_values.get(&id)
 	}
 
+	// Number of bytes written by `flush_to_file`.
+	fn encoded_size(&self) -> u64 {
+		// Begin, end and checksum.
+		let mut size = 1 + 8 + 1 + 4;
+		if self.sequence.is_some() {
+			size += 1 + 8;
+		}
+		for action in self.column_actions.iter() {
+			match action {
+				LogAction::CreateColumn { options, .. } =>
+					size += 1 + 1 + 2 + options.as_string().len(),
+				LogAction::DropColumn { .. } => size += 1 + 1,
+				_ => (),
+			}
+		}
+		for overlay in self.local_index.values() {
+			for (_, modified_entries_mask, chunk) in overlay.map.values() {
+				let deleted = deleted_entries(*modified_entries_mask, chunk);
+				let inserted = modified_entries_mask & !deleted;
+				if inserted != 0 {
+					size += 1 + 2 + 8 + 8 + inserted.count_ones() as usize * ENTRY_BYTES;
+				}
+				if deleted != 0 {
+					size += 1 + 2 + 8 + 8;
+				}
+			}
+		}
+		for overlay in self.local_values.values() {
+			for (index, (_, value)) in overlay.map.iter() {
+				size += 1 + 2 + 8 + deleted_value_link(*index, value).unwrap_or(value).len();
+			}
+		}
+		size += self.dropped_tables.len() * (1 + 2);
+		size += self.barriers.len() * (1 + 8);
+		size as u64
+	}
+
 	fn flush_to_file(self, file: &mut std::io::BufWriter<std::fs::File>) -> Result<FlushedLog> {
 		let mut crc32 = crc32fast::Hasher::new();
 		let mut bytes: u64 = 0;
@@ -273,23 +423,60 @@ impl LogChange {
 
 		write(&BEGIN_RECORD.to_le_bytes())?;
 		write(&self.record_id.to_le_bytes())?;
+		if let Some(seq) = self.sequence {
+			write(&SEQUENCE.to_le_bytes())?;
+			write(&seq.to_le_bytes())?;
+		}
+		for action in self.column_actions.iter() {
+			match action {
+				LogAction::CreateColumn { col, options } => {
+					let options = options.as_string();
+					write(&CREATE_COLUMN.to_le_bytes())?;
+					write(&[*col])?;
+					write(&(options.len() as u16).to_le_bytes())?;
+					write(options.as_bytes())?;
+				},
+				LogAction::DropColumn { col } => {
+					write(&DROP_COLUMN.to_le_bytes())?;
+					write(&[*col])?;
+				},
+				_ => (),
+			}
+		}
 
 		for (id, overlay) in self.local_index.iter() {
 			for (index, (_, modified_entries_mask, chunk)) in overlay.map.iter() {
-				write(INSERT_INDEX.to_le_bytes().as_ref())?;
-				write(&id.as_u16().to_le_bytes())?;
-				write(&index.to_le_bytes())?;
-				write(&modified_entries_mask.to_le_bytes())?;
-				let mut mask = *modified_entries_mask;
-				while mask != 0 {
-					let i = mask.trailing_zeros();
-					mask &= !(1 << i);
-					write(&chunk[i as usize * ENTRY_BYTES..(i as usize + 1) * ENTRY_BYTES])?;
+				let deleted = deleted_entries(*modified_entries_mask, chunk);
+				let inserted = modified_entries_mask & !deleted;
+				if inserted != 0 {
+					write(INSERT_INDEX.to_le_bytes().as_ref())?;
+					write(&id.as_u16().to_le_bytes())?;
+					write(&index.to_le_bytes())?;
+					write(&inserted.to_le_bytes())?;
+					let mut mask = inserted;
+					while mask != 0 {
+						let i = mask.trailing_zeros();
+						mask &= !(1 << i);
+						write(&chunk[i as usize * ENTRY_BYTES..(i as usize + 1) * ENTRY_BYTES])?;
+					}
+				}
+				if deleted != 0 {
+					write(DELETE_INDEX.to_le_bytes().as_ref())?;
+					write(&id.as_u16().to_le_bytes())?;
+					write(&index.to_le_bytes())?;
+					write(&deleted.to_le_bytes())?;
 				}
 			}
 		}
 		for (id, overlay) in self.local_values.iter() {
 			for (index, (_, value)) in overlay.map.iter() {
+				if let Some(next) = deleted_value_link(*index, value) {
+					write(DELETE_VALUE.to_le_bytes().as_ref())?;
+					write(&id.as_u16().to_le_bytes())?;
+					write(&index.to_le_bytes())?;
+					write(next)?;
+					continue
+				}
 				write(INSERT_VALUE.to_le_bytes().as_ref())?;
 				write(&id.as_u16().to_le_bytes())?;
 				write(&index.to_le_bytes())?;
@@ -301,6 +488,10 @@ impl LogChange {
 			write(DROP_TABLE.to_le_bytes().as_ref())?;
 			write(&id.as_u16().to_le_bytes())?;
 		}
+		for id in self.barriers.iter() {
+			write(BARRIER.to_le_bytes().as_ref())?;
+			write(&id.to_le_bytes())?;
+		}
 		write(&END_RECORD.to_le_bytes())?;
 		let checksum: u32 = crc32.finalize();
 		try_io!(file.write_all(&checksum.to_le_bytes()));
@@ -356,6 +547,38 @@ impl<'a> LogWriter<'a> {
 		self.log.dropped_tables.push(id);
 	}
 
+	pub fn set_sequence(&mut self, seq: u64) {
+		self.log.sequence = Some(seq);
+	}
+
+	/// Add a checkpoint marker to the record. Barriers are written after all other actions of
+	/// the record, so a reader that reaches one has seen everything that precedes it.
+	pub fn write_barrier(&mut self, id: u64) {
+		self.log.barriers.push(id);
+	}
+
+	/// Record that column `col` is added with the given options. Column actions are written
+	/// before any other changes of the record, see `Log::pending_column_actions`.
+	pub fn create_column(&mut self, col: ColId, options: ColumnOptions) {
+		self.log.column_actions.push(LogAction::CreateColumn { col, options });
+	}
+
+	/// Record that all files of column `col` are removed.
+	pub fn drop_column(&mut self, col: ColId) {
+		self.log.column_actions.push(LogAction::DropColumn { col });
+	}
+
+	/// Drop all changes added so far. The record id is kept, so the record can still be ended.
+	pub fn clear(&mut self) {
+		self.log = LogChange::new(self.log.record_id);
+	}
+
+	/// Check if there are changes to an index table that are not enacted yet.
+	pub fn has_pending_index_changes(&self, table: IndexTableId) -> bool {
+		self.log.local_index.get(&table).is_some_and(|o| !o.map.is_empty()) ||
+			self.overlays.read().index.get(&table).is_some_and(|o| !o.map.is_empty())
+	}
+
 	pub fn drain(self) -> LogChange {
 		self.log
 	}
@@ -455,6 +678,7 @@ struct Appending {
 	id: u32,
 	file: std::io::BufWriter<std::fs::File>,
 	size: u64,
+	first_record_id: u64,
 }
 
 #[derive(Debug)]
@@ -467,8 +691,15 @@ struct Reading {
 pub struct Log {
 	overlays: RwLock<LogOverlays>,
 	appending: RwLock<Option<Appending>>,
+	// Log files that reached `max_segment_bytes`, waiting to be flushed. Guarded by the
+	// `appending` lock, so that files are flushed in the order they were written.
+	full: RwLock<VecDeque<Appending>>,
+	max_segment_bytes: u64,
 	reading: RwLock<Option<Reading>>,
 	read_queue: RwLock<VecDeque<(u32, std::fs::File)>>,
+	// Held while log files are flushed, so that they are queued for reading in the order they
+	// were taken, even if `flush_one` is called from several threads.
+	flushing: Mutex<()>,
 	next_record_id: AtomicU64,
 	dirty: AtomicBool,
 	log_pool: RwLock<VecDeque<(u32, std::fs::File)>>,
@@ -477,10 +708,22 @@ pub struct Log {
 	path: std::path::PathBuf,
 	next_log_id: AtomicU32,
 	sync: bool,
+	// Directory to copy completed log files to. Guarded by the `appending` lock.
+	archive_dir: RwLock<Option<std::path::PathBuf>>,
 }
 
 impl Log {
 	pub fn open(options: &Options) -> Result<Log> {
+		Self::open_logs(options, false)
+	}
+
+	/// Open the logs of a database that is opened read only. Empty log files are left in place,
+	/// since they may belong to the log pool of another instance that has the database open.
+	pub fn open_read_only(options: &Options) -> Result<Log> {
+		Self::open_logs(options, true)
+	}
+
+	fn open_logs(options: &Options, read_only: bool) -> Result<Log> {
 		let path = options.path.clone();
 		let mut logs = VecDeque::new();
 		let mut max_log_id = 0;
@@ -497,7 +740,7 @@ impl Log {
 							if nlog > max_log_id {
 								max_log_id = nlog
 							}
-						} else {
+						} else if !read_only {
 							log::debug!(target: "parity-db", "Removing log {}", nlog);
 							drop(file);
 							try_io!(std::fs::remove_file(&path));
@@ -512,8 +755,11 @@ impl Log {
 		Ok(Log {
 			overlays: Default::default(),
 			appending: RwLock::new(None),
+			full: RwLock::default(),
+			max_segment_bytes: options.max_log_segment_bytes,
 			reading: RwLock::new(None),
 			read_queue: RwLock::default(),
+			flushing: Mutex::new(()),
 			next_record_id: AtomicU64::new(1),
 			next_log_id: AtomicU32::new(next_log_id),
 			dirty: AtomicBool::new(true),
@@ -522,6 +768,7 @@ impl Log {
 			cleanup_queue: RwLock::default(),
 			log_pool: RwLock::default(),
 			path,
+			archive_dir: RwLock::new(None),
 		})
 	}
 
@@ -531,10 +778,70 @@ impl Log {
 		path
 	}
 
+	/// Column actions in the logs that are pending replay, which have to be completed before
+	/// the columns are opened. Column changes are only logged when there is nothing else to
+	/// replay. If the logs contain any other changes, the database has been opened since and the
+	/// column actions are already complete, so nothing is returned.
+	pub fn pending_column_actions(&self) -> Result<Vec<LogAction>> {
+		let mut actions = Vec::new();
+		for (id, _, file) in self.replay_queue.read().iter() {
+			try_io!((&*file).seek(std::io::SeekFrom::Start(0)));
+			let reading = RwLock::new(Some(Reading {
+				id: *id,
+				file: std::io::BufReader::new(try_io!(file.try_clone())),
+			}));
+			let mut reader = LogReader::new(reading.write(), true);
+			let only_column_actions = 'records: loop {
+				match reader.next() {
+					Ok(LogAction::BeginRecord) => (),
+					Err(Error::Io(e)) if e.kind() == ErrorKind::UnexpectedEof => break true,
+					_ => break false,
+				}
+				loop {
+					// Incomplete or corrupted records are discarded on replay.
+					match reader.next() {
+						Ok(action @ LogAction::CreateColumn { .. }) |
+						Ok(action @ LogAction::DropColumn { .. }) => actions.push(action),
+						Ok(LogAction::Sequence(_)) => (),
+						Ok(LogAction::EndRecord) => break,
+						_ => break 'records false,
+					}
+				}
+			};
+			drop(reader);
+			try_io!((&*file).seek(std::io::SeekFrom::Start(0)));
+			if !only_column_actions {
+				return Ok(Vec::new())
+			}
+		}
+		Ok(actions)
+	}
+
 	pub fn replay_record_id(&self) -> Option<u64> {
 		self.replay_queue.read().front().map(|(_id, record_id, _)| *record_id)
 	}
 
+	/// Count non-empty log files in the database directory and their total size. These are
+	/// replayed when the database is opened. Files are only opened for reading.
+	pub fn pending_logs(path: &std::path::Path) -> Result<(usize, u64)> {
+		let mut count = 0;
+		let mut bytes = 0;
+		for entry in try_io!(std::fs::read_dir(path)) {
+			let entry = try_io!(entry);
+			if let Some(name) = entry.file_name().as_os_str().to_str() {
+				if name.starts_with("log") && name[3..].parse::<u32>().is_ok() {
+					let file = try_io!(std::fs::File::open(entry.path()));
+					let metadata = try_io!(file.metadata());
+					if metadata.is_file() && metadata.len() > 0 {
+						count += 1;
+						bytes += metadata.len();
+					}
+				}
+			}
+		}
+		Ok((count, bytes))
+	}
+
 	pub fn open_log_file(path: &std::path::Path) -> Result<(std::fs::File, Option<u64>)> {
 		let mut file = try_io!(std::fs::OpenOptions::new().read(true).write(true).open(path));
 		if try_io!(file.metadata()).len() == 0 {
@@ -589,7 +896,16 @@ impl Log {
 	pub fn end_record(&self, log: LogChange) -> Result<u64> {
 		assert_eq!(log.record_id + 1, self.next_record_id.load(Ordering::Relaxed));
 		let record_id = log.record_id;
+		let record_size = log.encoded_size();
 		let mut appending = self.appending.write();
+		if appending
+			.as_ref()
+			.is_some_and(|a| a.size > 0 && a.size + record_size > self.max_segment_bytes)
+		{
+			let full = appending.take().expect("Checked above; qed");
+			log::debug!(target: "parity-db", "Flush: Log {} is full, {} bytes", full.id, full.size);
+			self.full.write().push_back(full);
+		}
 		if appending.is_none() {
 			// Find a log file in the pool or create a new one
 			let (id, file) = if let Some((id, file)) = self.log_pool.write().pop_front() {
@@ -607,10 +923,16 @@ impl Log {
 				log::debug!(target: "parity-db", "Flush: Activated new writer {}", id);
 				(id, file)
 			};
-			*appending = Some(Appending { size: 0, file: std::io::BufWriter::new(file), id });
+			*appending = Some(Appending {
+				size: 0,
+				file: std::io::BufWriter::new(file),
+				id,
+				first_record_id: record_id,
+			});
 		}
 		let appending = appending.as_mut().unwrap();
 		let FlushedLog { index, values, bytes } = log.flush_to_file(&mut appending.file)?;
+		debug_assert_eq!(bytes, record_size);
 		let mut overlays = self.overlays.write();
 		let mut total_index = 0;
 		for (id, overlay) in index.into_iter() {
@@ -664,16 +986,38 @@ impl Log {
 	}
 
 	pub fn flush_one(&self, min_size: u64) -> Result<bool> {
-		// If it exists take the writer and flush it
+		// If it exists take the writer and flush it, along with any full log files before it.
+		let _flushing = self.flushing.lock();
 		let cur_size = self.appending.read().as_ref().map_or(0, |r| r.size);
-		if cur_size > min_size {
-			if let Some(to_flush) = self.appending.write().take() {
+		if cur_size > min_size || !self.full.read().is_empty() {
+			let (to_flush, archive_dir) = {
+				let mut appending = self.appending.write();
+				let mut to_flush: Vec<_> = self.full.write().drain(..).collect();
+				if cur_size > min_size {
+					to_flush.extend(appending.take());
+				}
+				(to_flush, self.archive_dir.read().clone())
+			};
+			for to_flush in to_flush {
 				let file = try_io!(to_flush.file.into_inner().map_err(|e| e.into_error()));
 				if self.sync {
 					log::debug!(target: "parity-db", "Flush: Flushing log to disk");
 					try_io!(file.sync_data());
 					log::debug!(target: "parity-db", "Flush: Flushing log completed");
 				}
+				if let Some(dir) = &archive_dir {
+					// Must complete before the file is enacted and reused. A failed copy only
+					// leaves a gap in the archive, the file is still enacted.
+					let target = dir.join(format!("wal_{:020}", to_flush.first_record_id));
+					match std::fs::copy(Self::log_path(&self.path, to_flush.id), &target) {
+						Ok(_) => {
+							log::debug!(target: "parity-db", "Flush: Archived log {} to {}", to_flush.id, target.display());
+						},
+						Err(e) => {
+							log::warn!(target: "parity-db", "Flush: Error archiving log {} to {}: {:?}", to_flush.id, target.display(), e);
+						},
+					}
+				}
 				self.read_queue.write().push_back((to_flush.id, file));
 			}
 			return Ok(true)
@@ -756,6 +1100,18 @@ impl Log {
 		}
 	}
 
+	/// Start or stop copying completed log files to `dir`. This applies to the log file that is
+	/// currently being written as well.
+	pub fn set_archive_dir(&self, dir: Option<std::path::PathBuf>) -> Result<()> {
+		if let Some(dir) = &dir {
+			try_io!(std::fs::create_dir_all(dir));
+		}
+		// Log files are taken for flushing under this lock.
+		let _appending = self.appending.write();
+		*self.archive_dir.write() = dir;
+		Ok(())
+	}
+
 	pub fn overlays(&self) -> &RwLock<LogOverlays> {
 		&self.overlays
 	}
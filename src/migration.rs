@@ -13,6 +13,7 @@ use std::path::{Path, PathBuf};
 
 const COMMIT_SIZE: usize = 10240;
 const OVERWRITE_TMP_PATH: &str = "to_revert_overwrite";
+const CHECKSUMS_TMP_PATH: &str = "to_add_checksums";
 
 /// Attempt to migrate a database to a new configuration with different column settings.
 /// `from` Source database path
@@ -169,6 +170,44 @@ pub fn clear_column(path: &Path, column: ColId) -> Result<()> {
 	let _db = Db::open(&options)?;
 	drop(_db);
 
+	// The removal is logged first, so that it is completed when the database is opened if it is
+	// interrupted.
+	crate::db::log_column_action(&options, |writer| writer.drop_column(column))?;
+	remove_column_files(path, column)?;
+	// Opening the database replays and removes the logged action.
+	drop(Db::open(&options)?);
+	Ok(())
+}
+
+/// Enable `ColumnOptions::checksums` for an existing hash indexed column. All values of the
+/// column are rewritten with checksums, and the column files are replaced in place as with
+/// `migrate` with `overwrite`. Database must be closed before calling this.
+pub fn migrate_add_checksums(path: &Path, column: ColId) -> Result<()> {
+	let meta = Options::load_metadata(path)?
+		.ok_or_else(|| Error::Migration("Error loading source metadata".into()))?;
+
+	if (column as usize) >= meta.columns.len() {
+		return Err(Error::Migration("Invalid column index".into()))
+	}
+	if meta.columns[column as usize].checksums {
+		return Ok(())
+	}
+
+	let mut tmp_dir = path.to_path_buf();
+	tmp_dir.push(CHECKSUMS_TMP_PATH);
+	if std::fs::metadata(&tmp_dir).is_ok() {
+		try_io!(std::fs::remove_dir_all(&tmp_dir));
+	}
+	let mut to = Options::with_columns(&tmp_dir, meta.columns.len() as u8);
+	to.columns = meta.columns;
+	to.columns[column as usize].checksums = true;
+	migrate(path, to, true, &[column])?;
+	try_io!(std::fs::remove_dir_all(&tmp_dir));
+	Ok(())
+}
+
+/// Delete all index and value table files of a column.
+pub(crate) fn remove_column_files(path: &Path, column: ColId) -> Result<()> {
 	// It is not specified how read_dir behaves when deleting and iterating in the same loop
 	// We collect a list of paths to be deleted first.
 	let mut to_delete = Vec::new();
@@ -265,4 +304,32 @@ mod test {
 		assert_eq!(db.get(1, b"1").unwrap(), None);
 		assert_eq!(db.get(2, b"2").unwrap(), Some("value2".as_bytes().to_vec()));
 	}
+
+	#[test]
+	fn migrate_add_checksums() {
+		let dir = tempdir().unwrap();
+		let mut options = Options::with_columns(dir.path(), 2);
+		let large = vec![7u8; 10000];
+		{
+			let db = Db::open_or_create(&options).unwrap();
+			db.commit(vec![
+				(0, b"0".to_vec(), Some(b"value0".to_vec())),
+				(1, b"1".to_vec(), Some(b"value1".to_vec())),
+				(1, b"large".to_vec(), Some(large.clone())),
+			])
+			.unwrap();
+		}
+
+		migration::migrate_add_checksums(dir.path(), 1).unwrap();
+		let meta = Options::load_metadata(dir.path()).unwrap().unwrap();
+		assert!(!meta.columns[0].checksums);
+		assert!(meta.columns[1].checksums);
+		assert!(!dir.path().join(super::CHECKSUMS_TMP_PATH).exists());
+
+		options.columns[1].checksums = true;
+		let db = Db::open(&options).unwrap();
+		assert_eq!(db.get(0, b"0").unwrap(), Some(b"value0".to_vec()));
+		assert_eq!(db.get(1, b"1").unwrap(), Some(b"value1".to_vec()));
+		assert_eq!(db.get(1, b"large").unwrap(), Some(large));
+	}
 }
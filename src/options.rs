@@ -5,9 +5,15 @@ use crate::{
 	column::{ColId, Salt},
 	compress::CompressionType,
 	error::{try_io, Error, Result},
+	index::MAX_INDEX_BITS,
+	table::{MAX_ENTRY_SIZE, MIN_ENTRY_SIZE, SIZE_TIERS},
 };
 use rand::Rng;
-use std::{collections::HashMap, path::Path};
+use std::{
+	collections::{HashMap, HashSet},
+	path::Path,
+	time::Duration,
+};
 
 pub const CURRENT_VERSION: u32 = 7;
 // TODO on last supported 5, remove MULTIHEAD_V4 and MULTIPART_V4
@@ -15,6 +21,12 @@ const LAST_SUPPORTED_VERSION: u32 = 4;
 
 pub const DEFAULT_COMPRESSION_THRESHOLD: u32 = 4096;
 
+pub const DEFAULT_MAX_LOG_SEGMENT_BYTES: u64 = 64 * 1024 * 1024;
+
+pub const DEFAULT_START_BITS: u8 = 16;
+pub const MIN_START_BITS: u8 = 8;
+pub const MAX_START_BITS: u8 = 32;
+
 /// Database configuration.
 #[derive(Clone, Debug)]
 pub struct Options {
@@ -37,6 +49,77 @@ pub struct Options {
 	///
 	/// Optional. A sensible default is used if nothing is set for a given column.
 	pub compression_threshold: HashMap<ColId, u32>,
+	/// How long `Db::open` waits for the database lock to be released by another
+	/// process. If `None` is specified opening fails immediately with `Error::Locked`, otherwise
+	/// with `Error::DatabaseLocked` once the timeout elapses.
+	pub lock_timeout: Option<Duration>,
+	/// Log a one-line statistics summary with this interval. Disabled if `None` is specified.
+	pub stats_log_interval: Option<Duration>,
+	/// Append a JSON line to this file for each key written. Records are written once a commit
+	/// is enacted, so writes that are still in the write-ahead log when the process stops are
+	/// not recorded. Opening fails if the file can't be opened for writing.
+	pub audit_log: Option<std::path::PathBuf>,
+	/// Hash columns whose changes made with `Db::write_with_sequence` are archived to a
+	/// `changelog` file in the database directory, along with the previous value of each key,
+	/// see `Db::get_changelog`. Each archived change reads the previous value when the commit is
+	/// written to the log. Reference counted columns are not archived.
+	pub changelog: HashSet<ColId>,
+	/// Track the most frequently read keys of hash columns, see `Db::get_hot_keys`. Adds a
+	/// lock to every read.
+	pub track_hot_keys: bool,
+	/// Keep a Bloom filter with this false positive rate for the keys of a hash column, so that
+	/// most lookups of missing keys don't touch the index. The filter takes about
+	/// `1.44 * log2(1 / rate)` bytes per key and is rebuilt from the index on open. Not used by
+	/// a `Db::open_read_only` handle opened while the database is open for writing.
+	///
+	/// Optional. No filter is kept if nothing is set for a given column.
+	#[cfg(feature = "bloom")]
+	pub bloom_false_positive_rate: HashMap<ColId, f64>,
+	/// Number of recently read values of a hash column to keep in memory.
+	///
+	/// Optional. Values are not cached if nothing is set for a given column.
+	pub value_cache_capacity: HashMap<ColId, usize>,
+	/// Number of queued changes to a column that may be written to the log in a single record.
+	/// Commits queued back to back are combined into one record as long as each column they
+	/// change has a capacity set and the combined changes to each column stay within it. This
+	/// saves the per-record work of many small commits. Queued commits are visible to reads
+	/// either way.
+	///
+	/// Optional. Commits that change a column with nothing set are written to a record of their
+	/// own.
+	pub write_buffer_capacity: HashMap<ColId, usize>,
+	/// Columns for which trace logs of written values include the first 64 bytes of the value,
+	/// in addition to the key. Only takes effect when trace logging is enabled.
+	pub verbose_trace: HashSet<ColId>,
+	/// Maximum number of index entries moved to a new index table in a single log record while
+	/// a hash column is reindexed. Entries are moved a whole index chunk at a time, so a batch
+	/// may hold up to a chunk more than this. Can be changed with `Db::set_reindex_batch_size`.
+	///
+	/// Optional. 8192 entries are moved at a time if nothing is set for a given column.
+	pub reindex_batch_size: HashMap<ColId, u32>,
+	/// Map the most recently written this many bytes of each value table of a column into
+	/// memory, along with the space preallocated after them. Reads of recently appended values
+	/// are then served from the map instead of a system call, while address space is only taken
+	/// for the end of each file.
+	///
+	/// Optional. Value tables are only accessed with system calls if nothing is set for a given
+	/// column.
+	pub tail_map_window: HashMap<ColId, usize>,
+	/// Hold back commits while this many index tables of a hash column are waiting to be
+	/// reindexed, until the reindex catches up. Commits to all columns are held back, like when
+	/// the commit queue is full. See `Db::reindex_queue_depth`.
+	///
+	/// Optional. The queue is not limited if nothing is set for a given column.
+	pub max_reindex_queue_depth: HashMap<ColId, usize>,
+	/// Hash columns whose index chunks are read on the rayon thread pool while the column is
+	/// reindexed. Entries of a batch are still written to the log in chunk order, so this
+	/// only speeds up reindexing of large tables on machines with several cores. Ignored
+	/// unless the `parallel-reindex` feature is enabled.
+	pub parallel_reindex: HashSet<ColId>,
+	/// Maximum size of a write-ahead log file. A new file is started when a commit would
+	/// make the current one larger. A single commit larger than this is written to a file of
+	/// its own. 64 MiB by default.
+	pub max_log_segment_bytes: u64,
 	#[cfg(any(test, feature = "instrumentation"))]
 	/// Always starts background threads.
 	pub with_background_thread: bool,
@@ -54,6 +137,10 @@ pub struct ColumnOptions {
 	/// Indicates that the keys are at least 32 bytes and
 	/// the first 32 bytes have uniform distribution.
 	/// Allows for skipping additional key hashing.
+	///
+	/// Keys are only mixed with the salt, so callers must make sure this does not produce
+	/// an all-zero key. Such keys are rejected with `Error::InvalidKey`. Leave this off to have
+	/// keys pre-hashed if that can't be guaranteed.
 	pub uniform: bool,
 	/// Use reference counting for values.
 	///
@@ -64,6 +151,34 @@ pub struct ColumnOptions {
 	pub compression: CompressionType,
 	/// Column is using a btree indexing.
 	pub btree_index: bool,
+	/// Keep a version number with each value that is incremented on every update, see
+	/// `Db::put_if_version`. Versioned columns may only be modified with `put_if_version`.
+	/// Values returned by iterators include the version as an 8-byte little endian prefix.
+	pub versioning: bool,
+	/// Entry sizes of the value tables, in ascending order. Each size must be between 32 and
+	/// 32760 bytes. Values that don't fit the largest entry are split over multiple entries of
+	/// an extra table. If `None` is specified, 255 sizes are used, spread between 32 bytes and
+	/// 32 KiB. Sizes can't be changed once the column is created.
+	pub value_sizes: Option<Vec<u16>>,
+	/// Number of index bits of a new hash column, between 8 and 32. The index starts with
+	/// `2^start_bits` chunks of 64 entries and doubles each time a chunk is full. Small values
+	/// save space in small databases, large ones avoid reindexing when many entries are
+	/// expected. Can't be changed once the column is created.
+	pub start_bits: u8,
+	/// Maximum number of index bits of a hash column, up to 49. If `None`, the index grows up to
+	/// that size. A commit that inserts a key into a full index chunk at this size fails with
+	/// `Error::IndexFull`. Keys of commits that are still queued count as inserted.
+	pub max_bits: Option<u8>,
+	/// Store a CRC32 checksum of the key and value with each value. A value that does not
+	/// match its checksum fails to read with `Error::ChecksumMismatch`. Can't be changed once
+	/// the column is created, use `migrate_add_checksums` to enable it for an existing column.
+	pub checksums: bool,
+	/// Time after which values expire, with millisecond precision. Each value is stored with
+	/// the time it was written, in milliseconds since the Unix epoch, as an 8-byte little endian
+	/// prefix. Expired values are not returned by reads or iteration and are removed from disk
+	/// by `Db::expire`. Not supported for ordered, reference counted or versioned columns.
+	/// Can't be changed once the column is created.
+	pub ttl: Option<Duration>,
 }
 
 /// Database metadata.
@@ -78,11 +193,38 @@ pub struct Metadata {
 }
 
 impl ColumnOptions {
-	fn as_string(&self) -> String {
-		format!(
-			"preimage: {}, uniform: {}, refc: {}, compression: {}, ordered: {}",
-			self.preimage, self.uniform, self.ref_counted, self.compression as u8, self.btree_index,
-		)
+	pub(crate) fn as_string(&self) -> String {
+		let mut s = format!(
+			"preimage: {}, uniform: {}, refc: {}, compression: {}, ordered: {}, versioning: {}",
+			self.preimage,
+			self.uniform,
+			self.ref_counted,
+			self.compression as u8,
+			self.btree_index,
+			self.versioning,
+		);
+		if let Some(sizes) = &self.value_sizes {
+			let sizes: Vec<_> = sizes.iter().map(|s| s.to_string()).collect();
+			s.push_str(&format!(", tiers: {}", sizes.join("/")));
+		}
+		if self.start_bits != DEFAULT_START_BITS {
+			s.push_str(&format!(", start_bits: {}", self.start_bits));
+		}
+		if let Some(max_bits) = self.max_bits {
+			s.push_str(&format!(", max_bits: {}", max_bits));
+		}
+		if self.checksums {
+			s.push_str(", checksums: true");
+		}
+		if let Some(ttl) = self.ttl {
+			s.push_str(&format!(", ttl: {}", ttl.as_millis()));
+		}
+		s
+	}
+
+	// Time-to-live in milliseconds, as compared to value write times.
+	pub(crate) fn ttl_millis(&self) -> Option<u64> {
+		self.ttl.map(|ttl| ttl.as_millis().min(u64::MAX as u128) as u64)
 	}
 
 	pub fn is_valid(&self) -> bool {
@@ -90,10 +232,47 @@ impl ColumnOptions {
 			log::error!(target: "parity-db", "Using `ref_counted` option without `preimage` enabled is not supported");
 			return false
 		}
+		if self.versioning && (self.btree_index || self.ref_counted) {
+			log::error!(target: "parity-db", "Using `versioning` with `btree_index` or `ref_counted` is not supported");
+			return false
+		}
+		if let Some(sizes) = &self.value_sizes {
+			if sizes.is_empty() || sizes.len() >= SIZE_TIERS {
+				log::error!(target: "parity-db", "`value_sizes` must have between 1 and {} sizes", SIZE_TIERS - 1);
+				return false
+			}
+			if sizes.windows(2).any(|w| w[0] >= w[1]) ||
+				sizes.iter().any(|s| *s < MIN_ENTRY_SIZE as u16 || *s > MAX_ENTRY_SIZE as u16)
+			{
+				log::error!(target: "parity-db", "`value_sizes` must be ascending and between {} and {}", MIN_ENTRY_SIZE, MAX_ENTRY_SIZE);
+				return false
+			}
+		}
+		if !(MIN_START_BITS..=MAX_START_BITS).contains(&self.start_bits) {
+			log::error!(target: "parity-db", "`start_bits` must be between {} and {}", MIN_START_BITS, MAX_START_BITS);
+			return false
+		}
+		if self
+			.max_bits
+			.is_some_and(|max_bits| max_bits < self.start_bits || max_bits > MAX_INDEX_BITS)
+		{
+			log::error!(target: "parity-db", "`max_bits` must be between `start_bits` and {}", MAX_INDEX_BITS);
+			return false
+		}
+		if let Some(ttl) = self.ttl {
+			if self.btree_index || self.ref_counted || self.versioning {
+				log::error!(target: "parity-db", "Using `ttl` with `btree_index`, `ref_counted` or `versioning` is not supported");
+				return false
+			}
+			if ttl.as_millis() == 0 {
+				log::error!(target: "parity-db", "`ttl` must be at least 1 millisecond");
+				return false
+			}
+		}
 		true
 	}
 
-	fn from_string(s: &str) -> Option<Self> {
+	pub(crate) fn from_string(s: &str) -> Option<Self> {
 		let mut split = s.split("sizes: ");
 		let vals = split.next()?;
 
@@ -110,6 +289,24 @@ impl ColumnOptions {
 		let ref_counted = vals.get("refc")?.parse().ok()?;
 		let compression: u8 = vals.get("compression").and_then(|c| c.parse().ok()).unwrap_or(0);
 		let btree_index = vals.get("ordered").and_then(|c| c.parse().ok()).unwrap_or(false);
+		let versioning = vals.get("versioning").and_then(|c| c.parse().ok()).unwrap_or(false);
+		let value_sizes = match vals.get("tiers") {
+			Some(sizes) => Some(sizes.split('/').map(|s| s.parse().ok()).collect::<Option<_>>()?),
+			None => None,
+		};
+		let start_bits = match vals.get("start_bits") {
+			Some(bits) => bits.parse().ok()?,
+			None => DEFAULT_START_BITS,
+		};
+		let max_bits = match vals.get("max_bits") {
+			Some(bits) => Some(bits.parse().ok()?),
+			None => None,
+		};
+		let checksums = vals.get("checksums").and_then(|c| c.parse().ok()).unwrap_or(false);
+		let ttl = match vals.get("ttl") {
+			Some(ttl) => Some(Duration::from_millis(ttl.parse().ok()?)),
+			None => None,
+		};
 
 		Some(ColumnOptions {
 			preimage,
@@ -117,6 +314,12 @@ impl ColumnOptions {
 			ref_counted,
 			compression: compression.into(),
 			btree_index,
+			versioning,
+			value_sizes,
+			start_bits,
+			max_bits,
+			checksums,
+			ttl,
 		})
 	}
 }
@@ -129,6 +332,12 @@ impl Default for ColumnOptions {
 			ref_counted: false,
 			compression: CompressionType::NoCompression,
 			btree_index: false,
+			versioning: false,
+			value_sizes: None,
+			start_bits: DEFAULT_START_BITS,
+			max_bits: None,
+			checksums: false,
+			ttl: None,
 		}
 	}
 }
@@ -143,6 +352,21 @@ impl Options {
 			salt: None,
 			columns: (0..num_columns).map(|_| Default::default()).collect(),
 			compression_threshold: HashMap::new(),
+			lock_timeout: None,
+			stats_log_interval: None,
+			audit_log: None,
+			changelog: HashSet::new(),
+			track_hot_keys: false,
+			#[cfg(feature = "bloom")]
+			bloom_false_positive_rate: HashMap::new(),
+			value_cache_capacity: HashMap::new(),
+			write_buffer_capacity: HashMap::new(),
+			verbose_trace: HashSet::new(),
+			reindex_batch_size: HashMap::new(),
+			tail_map_window: HashMap::new(),
+			max_reindex_queue_depth: HashMap::new(),
+			parallel_reindex: HashSet::new(),
+			max_log_segment_bytes: DEFAULT_MAX_LOG_SEGMENT_BYTES,
 			#[cfg(any(test, feature = "instrumentation"))]
 			with_background_thread: true,
 			#[cfg(any(test, feature = "instrumentation"))]
@@ -274,6 +498,13 @@ impl Options {
 				return false
 			}
 		}
+		#[cfg(feature = "bloom")]
+		for (col, rate) in &self.bloom_false_positive_rate {
+			if !(*rate > 0.0 && *rate < 1.0) {
+				log::error!(target: "parity-db", "Invalid bloom false positive rate {} for column {}", rate, col);
+				return false
+			}
+		}
 		true
 	}
 }
@@ -1,31 +1,50 @@
-#[cfg(feature = "loom")]
-pub use self::with_loom::*;
-
-#[cfg(not(feature = "loom"))]
+//! Synchronization primitives.
+//!
+//! `parking_lot` is used by default. The `loom` feature swaps in `loom` primitives for
+//! concurrency testing, and the `std-sync` feature swaps in `std::sync` ones for targets where
+//! `parking_lot` is not available. Such targets should build with `--no-default-features`, so
+//! that the `parking_lot` dependency is left out. `std::sync` is also used if neither
+//! `parking_lot` nor `loom` is enabled. The replacements expose the subset of the `parking_lot`
+//! API used by the database, with two differences:
+//!
+//! - Locks are still poisoned when a thread panics while holding them, but the poisoning is ignored
+//!   and the lock is handed out as is, as `parking_lot` would do.
+//! - `RwLock::upgradable_read` takes the write lock, so it excludes readers as well.
+
+#[cfg(any(feature = "loom", feature = "std-sync", not(feature = "parking_lot")))]
+pub use self::wrapped::*;
+
+#[cfg(not(any(feature = "loom", feature = "std-sync", not(feature = "parking_lot"))))]
 pub use parking_lot::*;
 
-#[cfg(feature = "loom")]
-mod with_loom {
+#[cfg(any(feature = "loom", feature = "std-sync", not(feature = "parking_lot")))]
+mod wrapped {
+	#[cfg(feature = "loom")]
+	use loom::sync;
+	#[cfg(not(feature = "loom"))]
+	use std::sync;
 	use std::{
 		fmt,
 		ops::{Deref, DerefMut},
+		sync::PoisonError,
+		time::Duration,
 	};
 
 	#[derive(Debug, Default)]
-	pub struct Mutex<T>(loom::sync::Mutex<T>);
+	pub struct Mutex<T>(sync::Mutex<T>);
 
 	impl<T> Mutex<T> {
 		pub fn new(val: T) -> Self {
-			Self(loom::sync::Mutex::new(val))
+			Self(sync::Mutex::new(val))
 		}
 
 		pub fn lock(&self) -> MutexGuard<'_, T> {
-			MutexGuard(Some(self.0.lock().unwrap()))
+			MutexGuard(Some(self.0.lock().unwrap_or_else(PoisonError::into_inner)))
 		}
 	}
 
 	#[derive(Debug)]
-	pub struct MutexGuard<'a, T>(Option<loom::sync::MutexGuard<'a, T>>);
+	pub struct MutexGuard<'a, T>(Option<sync::MutexGuard<'a, T>>);
 
 	impl<'a, T> Deref for MutexGuard<'a, T> {
 		type Target = T;
@@ -48,11 +67,11 @@ mod with_loom {
 	}
 
 	#[derive(Debug, Default)]
-	pub struct Condvar(loom::sync::Condvar);
+	pub struct Condvar(sync::Condvar);
 
 	impl Condvar {
 		pub fn new() -> Self {
-			Self(loom::sync::Condvar::new())
+			Self(sync::Condvar::new())
 		}
 
 		pub fn notify_one(&self) {
@@ -64,33 +83,45 @@ mod with_loom {
 		}
 
 		pub fn wait<T>(&self, mutex_guard: &mut MutexGuard<'_, T>) {
-			mutex_guard.0 = Some(self.0.wait(mutex_guard.0.take().unwrap()).unwrap())
+			mutex_guard.0 = Some(
+				self.0
+					.wait(mutex_guard.0.take().unwrap())
+					.unwrap_or_else(PoisonError::into_inner),
+			)
+		}
+
+		pub fn wait_for<T>(&self, mutex_guard: &mut MutexGuard<'_, T>, timeout: Duration) {
+			let (guard, _) = self
+				.0
+				.wait_timeout(mutex_guard.0.take().unwrap(), timeout)
+				.unwrap_or_else(PoisonError::into_inner);
+			mutex_guard.0 = Some(guard)
 		}
 	}
 
 	#[derive(Debug, Default)]
-	pub struct RwLock<T>(loom::sync::RwLock<T>);
+	pub struct RwLock<T>(sync::RwLock<T>);
 
 	impl<T> RwLock<T> {
 		pub fn new(val: T) -> Self {
-			Self(loom::sync::RwLock::new(val))
+			Self(sync::RwLock::new(val))
 		}
 
 		pub fn read(&self) -> RwLockReadGuard<'_, T> {
-			RwLockReadGuard(self.0.read().unwrap())
+			RwLockReadGuard(self.0.read().unwrap_or_else(PoisonError::into_inner))
 		}
 
 		pub fn upgradable_read(&self) -> RwLockUpgradableReadGuard<'_, T> {
-			RwLockUpgradableReadGuard(self.0.write().unwrap())
+			RwLockUpgradableReadGuard(self.0.write().unwrap_or_else(PoisonError::into_inner))
 		}
 
 		pub fn write(&self) -> RwLockWriteGuard<'_, T> {
-			RwLockWriteGuard(self.0.write().unwrap())
+			RwLockWriteGuard(self.0.write().unwrap_or_else(PoisonError::into_inner))
 		}
 	}
 
 	#[derive(Debug)]
-	pub struct RwLockReadGuard<'a, T>(loom::sync::RwLockReadGuard<'a, T>);
+	pub struct RwLockReadGuard<'a, T>(sync::RwLockReadGuard<'a, T>);
 
 	impl<'a, T> Deref for RwLockReadGuard<'a, T> {
 		type Target = T;
@@ -107,7 +138,7 @@ mod with_loom {
 	/// read gard first, allowing an other thread to grab a write gard at this time and change the
 	/// protected value state.
 	#[derive(Debug)]
-	pub struct RwLockUpgradableReadGuard<'a, T>(loom::sync::RwLockWriteGuard<'a, T>);
+	pub struct RwLockUpgradableReadGuard<'a, T>(sync::RwLockWriteGuard<'a, T>);
 
 	impl<'a, T> RwLockUpgradableReadGuard<'a, T> {
 		pub fn upgrade(s: Self) -> RwLockWriteGuard<'a, T> {
@@ -124,7 +155,7 @@ mod with_loom {
 	}
 
 	#[derive(Debug)]
-	pub struct RwLockWriteGuard<'a, T>(loom::sync::RwLockWriteGuard<'a, T>);
+	pub struct RwLockWriteGuard<'a, T>(sync::RwLockWriteGuard<'a, T>);
 
 	impl<'a, T> RwLockWriteGuard<'a, T> {
 		pub fn downgrade_to_upgradable(s: Self) -> RwLockUpgradableReadGuard<'a, T> {
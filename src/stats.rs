@@ -1,12 +1,22 @@
 // Copyright 2021-2022 Parity Technologies (UK) Ltd.
 // This file is dual-licensed as Apache-2.0 or MIT.
 
-use crate::{column::ColId, table::SIZE_TIERS};
+use crate::{
+	column::ColId,
+	error::{try_io, Result},
+	table::SIZE_TIERS,
+	Key,
+};
 /// Database statistics.
 use std::sync::atomic::{AtomicI64, AtomicU32, AtomicU64, Ordering};
 use std::{
+	collections::HashMap,
+	convert::TryInto,
 	io::{Cursor, Read, Write},
 	iter,
+	path::Path,
+	sync::Arc,
+	time::{Duration, SystemTime},
 };
 
 // store up to value of size HISTOGRAM_BUCKETS * 2 ^ HISTOGRAM_BUCKET_BITS,
@@ -15,7 +25,31 @@ const HISTOGRAM_BUCKETS: usize = 1024;
 const HISTOGRAM_BUCKET_BITS: u8 = 5;
 
 pub const TOTAL_SIZE: usize =
-	4 * HISTOGRAM_BUCKETS + 8 * HISTOGRAM_BUCKETS + 8 * SIZE_TIERS + 8 * 13;
+	4 * HISTOGRAM_BUCKETS + 8 * HISTOGRAM_BUCKETS + 8 * SIZE_TIERS + 8 * 14;
+
+// Database wide statistics file.
+const STATS_FILE: &str = "stats";
+// Weight of the latest sample in the enactment throughput moving average.
+const THROUGHPUT_SMOOTHING: f64 = 0.2;
+// Enactment time to collect before taking a throughput sample.
+const MIN_SAMPLE_TIME: Duration = Duration::from_secs(1);
+// Value tables smaller than this are never reported as fragmented.
+const FRAGMENTATION_MIN_BYTES: u64 = 64 * 1024;
+// Share of removed entries, in percent, to recommend compacting a value table.
+const FRAGMENTATION_WARN_PERCENT: u64 = 50;
+const FRAGMENTATION_HIGH_PERCENT: u64 = 75;
+// Index fill rates to recommend growing the index.
+const INDEX_FILL_WARN: f64 = 0.75;
+const INDEX_FILL_HIGH: f64 = 0.9;
+// Index tables waiting to be reindexed to recommend trimming the queue.
+const REBALANCE_QUEUE_WARN: usize = 2;
+// Share of removed keys, in percent, to recommend rebuilding a Bloom filter.
+#[cfg(feature = "bloom")]
+const BLOOM_STALE_WARN_PERCENT: u64 = 25;
+#[cfg(feature = "bloom")]
+const BLOOM_STALE_HIGH_PERCENT: u64 = 50;
+// Read rate assumed for maintenance duration estimates.
+const MAINTENANCE_BYTES_PER_SEC: u64 = 256 * 1024 * 1024;
 
 // TODO: get rid of the struct and use index meta directly.
 #[derive(Debug)]
@@ -36,6 +70,7 @@ pub struct ColumnStats {
 	queries_miss: AtomicU64,
 	uncompressed_bytes: AtomicU64,
 	compression_delta: Vec<AtomicI64>,
+	redundant_writes: AtomicU64,
 }
 
 /// Database statistics summary.
@@ -55,6 +90,8 @@ pub struct ColumnStatSummary {
 	/// Total size of values in the column before compression. This does not include key size and
 	/// any other overhead.
 	pub uncompressed_bytes: u64,
+	/// Number of writes that were skipped because the stored value was identical.
+	pub redundant_writes: u64,
 }
 
 fn read_u32(cursor: &mut Cursor<&[u8]>) -> AtomicU32 {
@@ -125,6 +162,7 @@ impl ColumnStats {
 			iter::repeat_with(|| read_i64(cursor)).take(HISTOGRAM_BUCKETS).collect();
 		let reference_increase_hit = read_u64(cursor);
 		let reference_increase_miss = read_u64(cursor);
+		let redundant_writes = read_u64(cursor);
 
 		ColumnStats {
 			value_histogram,
@@ -143,6 +181,7 @@ impl ColumnStats {
 			queries_miss,
 			uncompressed_bytes,
 			compression_delta,
+			redundant_writes,
 		}
 	}
 
@@ -166,6 +205,7 @@ impl ColumnStats {
 			compression_delta: iter::repeat_with(Default::default)
 				.take(HISTOGRAM_BUCKETS)
 				.collect(),
+			redundant_writes: Default::default(),
 		}
 	}
 
@@ -193,6 +233,7 @@ impl ColumnStats {
 		for v in &self.compression_delta {
 			v.store(0, Ordering::Relaxed)
 		}
+		self.redundant_writes.store(0, Ordering::Relaxed);
 	}
 
 	pub fn summary(&self) -> ColumnStatSummary {
@@ -200,6 +241,7 @@ impl ColumnStats {
 			total_values: self.total_values.load(Ordering::Relaxed),
 			total_bytes: self.total_bytes.load(Ordering::Relaxed),
 			uncompressed_bytes: self.uncompressed_bytes.load(Ordering::Relaxed),
+			redundant_writes: self.redundant_writes.load(Ordering::Relaxed),
 		}
 	}
 
@@ -227,6 +269,7 @@ impl ColumnStats {
 		}
 		write_u64(&mut cursor, &self.reference_increase_hit);
 		write_u64(&mut cursor, &self.reference_increase_miss);
+		write_u64(&mut cursor, &self.redundant_writes);
 	}
 
 	pub fn write_stats_text(&self, writer: &mut impl Write, col: ColId) -> std::io::Result<()> {
@@ -256,6 +299,7 @@ impl ColumnStats {
 			"Missed reference increases: {}",
 			self.reference_increase_miss.load(Ordering::Relaxed)
 		)?;
+		writeln!(writer, "Redundant writes: {}", self.redundant_writes.load(Ordering::Relaxed))?;
 		writeln!(writer, "Removals: {}", self.removed_hit.load(Ordering::Relaxed))?;
 		writeln!(writer, "Missed removals: {}", self.removed_miss.load(Ordering::Relaxed))?;
 		writeln!(
@@ -367,7 +411,449 @@ impl ColumnStats {
 		self.insert(new, new_compressed);
 	}
 
+	pub fn redundant_write(&self) {
+		self.redundant_writes.fetch_add(1, Ordering::Relaxed);
+	}
+
 	pub fn commit(&self) {
 		self.commits.fetch_add(1, Ordering::Relaxed);
 	}
 }
+
+/// Derived index metrics of a hash column. Computing these requires a full index scan, so they
+/// are cached, see `Db::precompute_index_stats`.
+#[derive(Clone, Debug)]
+pub struct CachedStats {
+	/// Number of index entries, including tables that are being reindexed.
+	pub index_entries: u64,
+	/// Number of index entry slots, including tables that are being reindexed.
+	pub index_capacity: u64,
+	/// Ratio of occupied index entry slots.
+	pub fill_rate: f64,
+	/// Number of index entries by probe distance, see `Db::dump`.
+	pub probe_distance_histogram: Vec<u64>,
+	/// When the statistics were computed.
+	pub computed_at: SystemTime,
+}
+
+/// Space usage of a single value table, see `FragmentationReport`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TierFragmentation {
+	/// Column of the value table.
+	pub col: ColId,
+	/// Size tier of the value table.
+	pub tier: u8,
+	/// Bytes taken by allocated entries, live or removed.
+	pub bytes_allocated: u64,
+	/// Bytes taken by live entries.
+	pub bytes_live: u64,
+	/// Bytes taken by removed entries that are waiting to be reused.
+	pub bytes_free: u64,
+}
+
+/// Wasted space in value tables of all columns, see `Db::get_fragmentation_report`.
+#[derive(Clone, Debug, Default)]
+pub struct FragmentationReport {
+	pub total_bytes_allocated: u64,
+	pub total_bytes_live: u64,
+	pub total_bytes_free: u64,
+	/// Value tables with any allocated entries.
+	pub tiers: Vec<TierFragmentation>,
+	/// Human readable hints for heavily fragmented tables.
+	pub recommendations: Vec<String>,
+}
+
+impl FragmentationReport {
+	pub fn add(&mut self, tier: TierFragmentation) {
+		self.total_bytes_allocated += tier.bytes_allocated;
+		self.total_bytes_live += tier.bytes_live;
+		self.total_bytes_free += tier.bytes_free;
+		if tier.bytes_allocated >= FRAGMENTATION_MIN_BYTES {
+			let percent = tier.bytes_free * 100 / tier.bytes_allocated;
+			if percent >= FRAGMENTATION_WARN_PERCENT {
+				self.recommendations.push(format!(
+					"Column {} tier {} is {}% fragmented; consider compacting.",
+					tier.col, tier.tier, percent
+				));
+			}
+		}
+		self.tiers.push(tier);
+	}
+}
+
+/// Urgency of a `MaintenanceAction`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MaintenanceSeverity {
+	/// Can be done at any convenient time.
+	Low,
+	/// Disk usage or performance is affected.
+	Medium,
+	/// Should be done soon.
+	High,
+}
+
+/// Maintenance operation recommended by `Db::maintenance_report`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MaintenanceKind {
+	/// Value tables hold many removed entries. The space is only reused by inserts of the same
+	/// size tier. `Db::punch_holes` releases it on disk for large entries, migrating the column
+	/// reclaims it fully.
+	Compact,
+	/// The index is nearly full and will be reindexed soon, see `Db::resize_index_in_place`.
+	ResizeIndex,
+	/// Several index tables are waiting to be reindexed, see `Db::trim_rebalance_queue`.
+	TrimRebalanceQueue,
+	/// Removed keys still match the Bloom filter, see `Db::rebuild_bloom`.
+	RebuildBloom,
+}
+
+/// A single recommendation of `MaintenanceReport`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MaintenanceAction {
+	pub severity: MaintenanceSeverity,
+	pub col: ColId,
+	pub action: MaintenanceKind,
+	/// Human readable reason for the recommendation.
+	pub reason: String,
+	/// Rough estimate based on the amount of data the action reads.
+	pub estimated_duration: Duration,
+}
+
+/// Recommended maintenance actions for all columns, see `Db::maintenance_report`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct MaintenanceReport {
+	/// Most severe first, then by column.
+	pub actions: Vec<MaintenanceAction>,
+}
+
+impl MaintenanceReport {
+	fn push(
+		&mut self,
+		severity: MaintenanceSeverity,
+		col: ColId,
+		action: MaintenanceKind,
+		reason: String,
+		bytes_read: u64,
+	) {
+		let estimated_duration =
+			Duration::from_secs_f64(bytes_read as f64 / MAINTENANCE_BYTES_PER_SEC as f64);
+		self.actions
+			.push(MaintenanceAction { severity, col, action, reason, estimated_duration });
+	}
+
+	/// Check the share of removed entries of each column, ignoring small value tables.
+	pub fn check_fragmentation(&mut self, report: &FragmentationReport) {
+		let mut columns: Vec<(ColId, u64, u64)> = Vec::new();
+		for tier in report.tiers.iter().filter(|t| t.bytes_allocated >= FRAGMENTATION_MIN_BYTES) {
+			match columns.iter_mut().find(|(col, _, _)| *col == tier.col) {
+				Some((_, allocated, free)) => {
+					*allocated += tier.bytes_allocated;
+					*free += tier.bytes_free;
+				},
+				None => columns.push((tier.col, tier.bytes_allocated, tier.bytes_free)),
+			}
+		}
+		for (col, allocated, free) in columns {
+			let percent = free * 100 / allocated;
+			let severity = match percent {
+				p if p >= FRAGMENTATION_HIGH_PERCENT => MaintenanceSeverity::High,
+				p if p >= FRAGMENTATION_WARN_PERCENT => MaintenanceSeverity::Medium,
+				_ => continue,
+			};
+			let reason = format!("{}% of {} value table bytes are free", percent, allocated);
+			self.push(severity, col, MaintenanceKind::Compact, reason, allocated);
+		}
+	}
+
+	/// Check the index fill rate and the number of tables waiting to be reindexed.
+	pub fn check_index(&mut self, col: ColId, stats: &CachedStats, queue_depth: usize) {
+		let index_bytes = stats.index_capacity * 8;
+		let severity = match stats.fill_rate {
+			f if f >= INDEX_FILL_HIGH => Some(MaintenanceSeverity::High),
+			f if f >= INDEX_FILL_WARN => Some(MaintenanceSeverity::Medium),
+			_ => None,
+		};
+		if let Some(severity) = severity {
+			let reason = format!("Index is {:.0}% full", stats.fill_rate * 100.0);
+			self.push(severity, col, MaintenanceKind::ResizeIndex, reason, index_bytes);
+		}
+		if queue_depth >= REBALANCE_QUEUE_WARN {
+			let reason = format!("{} index tables are waiting to be reindexed", queue_depth);
+			self.push(
+				MaintenanceSeverity::Medium,
+				col,
+				MaintenanceKind::TrimRebalanceQueue,
+				reason,
+				index_bytes,
+			);
+		}
+	}
+
+	/// Check the share of keys removed from the Bloom filter since it was built.
+	#[cfg(feature = "bloom")]
+	pub fn check_bloom(&mut self, col: ColId, bloom: &crate::BloomStats, index: &CachedStats) {
+		let total = bloom.entries + bloom.removed;
+		if total == 0 {
+			return
+		}
+		let percent = bloom.removed * 100 / total;
+		let severity = match percent {
+			p if p >= BLOOM_STALE_HIGH_PERCENT => MaintenanceSeverity::Medium,
+			p if p >= BLOOM_STALE_WARN_PERCENT => MaintenanceSeverity::Low,
+			_ => return,
+		};
+		let reason = format!("{}% of the keys in the Bloom filter were removed", percent);
+		let index_bytes = index.index_capacity * 8;
+		self.push(severity, col, MaintenanceKind::RebuildBloom, reason, index_bytes);
+	}
+
+	pub fn sort(&mut self) {
+		self.actions.sort_by(|a, b| b.severity.cmp(&a.severity).then(a.col.cmp(&b.col)));
+	}
+}
+
+/// File system metadata of a single database file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FileInfo {
+	pub path: std::path::PathBuf,
+	/// File size in bytes.
+	pub size: u64,
+	pub last_modified: SystemTime,
+}
+
+/// Files in the database directory, see `Db::get_metadata_stats`.
+#[derive(Clone, Debug, Default)]
+pub struct DbFileStats {
+	/// All files, sorted by path.
+	pub files: Vec<FileInfo>,
+}
+
+impl DbFileStats {
+	/// Total size of all files in bytes.
+	pub fn total_size(&self) -> u64 {
+		self.files.iter().map(|f| f.size).sum()
+	}
+}
+
+const SKETCH_WIDTH_BITS: usize = 12;
+const SKETCH_WIDTH: usize = 1 << SKETCH_WIDTH_BITS;
+const SKETCH_DEPTH: usize = 5;
+/// Maximum number of keys returned by `CmSketch::top`.
+const HOT_KEY_CANDIDATES: usize = 1024;
+
+/// Count-Min Sketch of key reads. Read counts are estimates that may be too high but never too
+/// low. The sketch can't list the keys it counts, so the keys with the highest estimates are
+/// tracked alongside it.
+#[derive(Debug)]
+pub struct CmSketch {
+	counters: Vec<u64>,
+	candidates: HashMap<Key, u64>,
+	// Lowest count in `candidates`, once it is full.
+	min_candidate: u64,
+}
+
+impl CmSketch {
+	pub fn new() -> CmSketch {
+		CmSketch {
+			counters: vec![0; SKETCH_WIDTH * SKETCH_DEPTH],
+			candidates: HashMap::new(),
+			min_candidate: 0,
+		}
+	}
+
+	// Keys are hashed or uniform, so each row uses different key bytes.
+	fn cell(row: usize, key: &Key) -> usize {
+		let bytes = u32::from_le_bytes(key[row * 4..row * 4 + 4].try_into().unwrap());
+		row * SKETCH_WIDTH + (bytes as usize & (SKETCH_WIDTH - 1))
+	}
+
+	pub fn increment(&mut self, key: &Key) {
+		let mut estimate = u64::MAX;
+		for row in 0..SKETCH_DEPTH {
+			let counter = &mut self.counters[Self::cell(row, key)];
+			*counter += 1;
+			estimate = std::cmp::min(estimate, *counter);
+		}
+		if let Some(count) = self.candidates.get_mut(key) {
+			*count = estimate;
+			return
+		}
+		if self.candidates.len() < HOT_KEY_CANDIDATES {
+			self.candidates.insert(*key, estimate);
+		} else if estimate > self.min_candidate {
+			if let Some(coldest) =
+				self.candidates.iter().min_by_key(|(_, count)| **count).map(|(key, _)| *key)
+			{
+				self.candidates.remove(&coldest);
+			}
+			self.candidates.insert(*key, estimate);
+		} else {
+			return
+		}
+		if self.candidates.len() == HOT_KEY_CANDIDATES {
+			self.min_candidate = self.candidates.values().copied().min().unwrap_or(0);
+		}
+	}
+
+	/// Up to `n` keys with the highest estimated read counts, most read first.
+	pub fn top(&self, n: usize) -> Vec<(Key, u64)> {
+		let mut top: Vec<_> = self.candidates.iter().map(|(key, count)| (*key, *count)).collect();
+		top.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+		top.truncate(n);
+		top
+	}
+
+	pub fn reset(&mut self) {
+		*self = CmSketch::new();
+	}
+}
+
+impl Default for CmSketch {
+	fn default() -> CmSketch {
+		CmSketch::new()
+	}
+}
+
+/// Operation counters of a column. Counters start at zero when the database is opened and are
+/// always collected, regardless of `Options::stats`.
+#[derive(Debug, Default)]
+pub struct ColumnCounters {
+	/// Key lookups.
+	pub reads: AtomicU64,
+	/// Inserted or updated values, counted when the commit is written to the log.
+	pub writes: AtomicU64,
+	/// Removed keys, counted when the commit is written to the log.
+	pub deletes: AtomicU64,
+	/// Lookups answered from memory, either from the commit queue or the value cache.
+	pub cache_hits: AtomicU64,
+	/// Index entries that matched a key prefix but held a different key.
+	pub index_conflicts: AtomicU64,
+	/// Reindex operations started because an index chunk was full.
+	pub rebalance_count: AtomicU64,
+	/// Total size of returned values.
+	pub bytes_read: AtomicU64,
+	/// Total size of written values.
+	pub bytes_written: AtomicU64,
+}
+
+impl ColumnCounters {
+	pub fn snapshot(&self) -> ColumnCountersSnapshot {
+		ColumnCountersSnapshot {
+			reads: self.reads.load(Ordering::Relaxed),
+			writes: self.writes.load(Ordering::Relaxed),
+			deletes: self.deletes.load(Ordering::Relaxed),
+			cache_hits: self.cache_hits.load(Ordering::Relaxed),
+			index_conflicts: self.index_conflicts.load(Ordering::Relaxed),
+			rebalance_count: self.rebalance_count.load(Ordering::Relaxed),
+			bytes_read: self.bytes_read.load(Ordering::Relaxed),
+			bytes_written: self.bytes_written.load(Ordering::Relaxed),
+		}
+	}
+}
+
+/// Values of `ColumnCounters` at some point in time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ColumnCountersSnapshot {
+	pub reads: u64,
+	pub writes: u64,
+	pub deletes: u64,
+	pub cache_hits: u64,
+	pub index_conflicts: u64,
+	pub rebalance_count: u64,
+	pub bytes_read: u64,
+	pub bytes_written: u64,
+}
+
+/// Operation counters of all columns, see `Db::counters`.
+#[derive(Debug)]
+pub struct DatabaseStats {
+	columns: Vec<Arc<ColumnCounters>>,
+}
+
+impl DatabaseStats {
+	pub(crate) fn new(num_columns: usize) -> DatabaseStats {
+		DatabaseStats { columns: (0..num_columns).map(|_| Default::default()).collect() }
+	}
+
+	/// Counters of a column, or `None` if the column does not exist.
+	pub fn column(&self, col: ColId) -> Option<&ColumnCounters> {
+		self.columns.get(col as usize).map(|c| c.as_ref())
+	}
+
+	pub(crate) fn get(&self, col: ColId) -> &ColumnCounters {
+		&self.columns[col as usize]
+	}
+
+	pub(crate) fn column_arc(&self, col: ColId) -> Arc<ColumnCounters> {
+		self.columns[col as usize].clone()
+	}
+
+	/// Current counter values of all columns. Counters are read one by one, so values may be
+	/// slightly inconsistent while the database is in use.
+	pub fn snapshot(&self) -> StatsSnapshot {
+		StatsSnapshot { columns: self.columns.iter().map(|c| c.snapshot()).collect() }
+	}
+}
+
+/// Values of `DatabaseStats` at some point in time, indexed by column.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StatsSnapshot {
+	pub columns: Vec<ColumnCountersSnapshot>,
+}
+
+/// Log enactment throughput, kept as an exponential moving average of bytes per second.
+#[derive(Debug, Default)]
+pub struct EnactStats {
+	bytes: u64,
+	time: Duration,
+	throughput: Option<f64>,
+}
+
+impl EnactStats {
+	pub fn load(path: &Path) -> Result<EnactStats> {
+		Ok(EnactStats { throughput: Self::load_throughput(path)?, ..Default::default() })
+	}
+
+	/// Load the stored throughput in bytes per second, if any.
+	pub fn load_throughput(path: &Path) -> Result<Option<f64>> {
+		let path = path.join(STATS_FILE);
+		if !path.exists() {
+			return Ok(None)
+		}
+		let content = try_io!(std::fs::read_to_string(path));
+		Ok(content.lines().find_map(|l| {
+			l.strip_prefix("enact_throughput=")
+				.and_then(|v| v.parse::<f64>().ok())
+				.filter(|v| v.is_finite() && *v > 0.0)
+		}))
+	}
+
+	pub fn record(&mut self, bytes: u64, time: Duration) {
+		self.bytes += bytes;
+		self.time += time;
+	}
+
+	/// Add collected enactment time to the average and store it when there is enough of it.
+	pub fn save(&mut self, path: &Path) -> Result<()> {
+		if self.time < MIN_SAMPLE_TIME {
+			return Ok(())
+		}
+		let sample = self.bytes as f64 / self.time.as_secs_f64();
+		let throughput = match self.throughput {
+			Some(t) => t + THROUGHPUT_SMOOTHING * (sample - t),
+			None => sample,
+		};
+		self.throughput = Some(throughput);
+		self.bytes = 0;
+		self.time = Duration::ZERO;
+		try_io!(std::fs::write(
+			path.join(STATS_FILE),
+			format!("enact_throughput={}\n", throughput)
+		));
+		Ok(())
+	}
+}
@@ -10,20 +10,22 @@
 // FILLED - highest index filled with live data
 //
 // Complete entry:
-// [SIZE: 2][REFS: 4][KEY: 26][VALUE]
+// [SIZE: 2][REFS: 4][CHECKSUM: 4][KEY: 26][VALUE]
 // SIZE: 15-bit value size. Sizes up to 0x7ffc are allowed.
-// This includes size of REFS and KEY.
+// This includes size of REFS, CHECKSUM and KEY.
 // The highest bit is reserved to indicate if compression is applied.
 // REF: 32-bit reference counter (optional).
+// CHECKSUM: CRC32 of KEY and the complete VALUE (optional).
 // KEY: lower 26 bytes of the key (optional for btree nodes).
 // VALUE: payload bytes.
 //
 // Partial entry (first part):
-// [MULTIHEAD: 2][NEXT: 8][REFS: 4][KEY: 26][VALUE]
+// [MULTIHEAD: 2][NEXT: 8][REFS: 4][CHECKSUM: 4][KEY: 26][VALUE]
 // MULTIHEAD - Split entry head marker. 0xfffd.
 // NEXT - 64-bit index of the entry that holds the next part.
 // take all available space in this entry.
 // REF: 32-bit reference counter (optional).
+// CHECKSUM: CRC32 of KEY and the VALUE of all parts (optional).
 // KEY: lower 26 bytes of the key (optional for btree nodes).
 // VALUE: The rest of the entry is filled with payload bytes.
 //
@@ -69,9 +71,12 @@ pub const COMPRESSED_MASK: u16 = 0x80_00;
 pub const MAX_ENTRY_SIZE: usize = 0x7ff8; // Actual max size in V4 was 0x7dfe
 pub const MIN_ENTRY_SIZE: usize = 32;
 const REFS_SIZE: usize = 4;
+const CHECKSUM_SIZE: usize = 4;
 const SIZE_SIZE: usize = 2;
 const INDEX_SIZE: usize = 8;
 const MAX_ENTRY_BUF_SIZE: usize = 0x8000;
+// Upper bound for a single merged read issued by `bulk_read`.
+const MAX_BULK_READ_SIZE: usize = 0x10_0000;
 
 const TOMBSTONE: &[u8] = &[0xff, 0xff];
 const MULTIPART_V4: &[u8] = &[0xff, 0xfe];
@@ -81,6 +86,9 @@ const MULTIHEAD: &[u8] = &[0xfd, 0xff];
 const MULTIHEAD_COMPRESSED: &[u8] = &[0xfd, 0x7f];
 // When a rc reach locked ref, it is locked in db.
 const LOCKED_REF: u32 = u32::MAX;
+// Granularity of file ranges released by `ValueTable::punch_holes`.
+#[cfg(target_os = "linux")]
+const PUNCH_HOLE_ALIGN: u64 = 4096;
 
 pub type Value = Vec<u8>;
 
@@ -123,6 +131,27 @@ impl std::fmt::Display for TableId {
 	}
 }
 
+/// Value table free list check results.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FsckReport {
+	/// Free list pointers that lead to a removed entry.
+	pub healthy: u64,
+	/// Free list pointers that are out of bounds, lead to a live entry or close a cycle.
+	pub corrupt: u64,
+	/// Corrupt pointers that were reset.
+	pub repaired: u64,
+	/// Pairs of values that share an entry. These are not repaired.
+	pub overlapping: u64,
+}
+
+/// Allocation state of a value table, see `ValueTable::checkpoint`.
+#[derive(Debug, Clone, Copy)]
+pub struct TableCheckpoint {
+	filled: u64,
+	last_removed: u64,
+	dirty_header: bool,
+}
+
 #[derive(Debug)]
 pub struct ValueTable {
 	pub id: TableId,
@@ -133,6 +162,7 @@ pub struct ValueTable {
 	dirty_header: AtomicBool,
 	multipart: bool,
 	ref_counted: bool,
+	checksums: bool,
 	db_version: u32,
 }
 
@@ -352,6 +382,15 @@ impl<B: AsRef<[u8]> + AsMut<[u8]>> std::ops::IndexMut<std::ops::Range<usize>> fo
 	}
 }
 
+/// Link to the next free entry if `entry` is a deleted entry, as written by `clear_slot`.
+pub fn removed_entry_link(entry: &[u8]) -> Option<&[u8]> {
+	if entry.len() == SIZE_SIZE + INDEX_SIZE && &entry[0..SIZE_SIZE] == TOMBSTONE {
+		Some(&entry[SIZE_SIZE..])
+	} else {
+		None
+	}
+}
+
 impl ValueTable {
 	pub fn open(
 		path: Arc<std::path::PathBuf>,
@@ -394,12 +433,25 @@ impl ValueTable {
 			dirty_header: AtomicBool::new(false),
 			multipart,
 			ref_counted: options.ref_counted,
+			checksums: options.checksums,
 			db_version,
 		})
 	}
 
+	/// Serve reads of the last `window_size` bytes of written entries, and of the entries
+	/// appended after them, from a memory map that follows the end of the table file, see
+	/// `TableFile::map_tail`. Meant for append-only workloads, where recent values are read the
+	/// most. Disabled if `window_size` is zero.
+	pub fn tail_mmap(&self, window_size: usize) -> Result<()> {
+		let data_end = self.filled.load(Ordering::Relaxed) * self.entry_size as u64;
+		self.file.map_tail(window_size as u64, data_end)
+	}
+
 	pub fn value_size(&self, key: &TableKey) -> Option<u16> {
-		let base = self.entry_size - SIZE_SIZE as u16 - self.ref_size() as u16;
+		let base = self.entry_size -
+			SIZE_SIZE as u16 -
+			self.ref_size() as u16 -
+			self.checksum_size() as u16;
 		let k_encoded = key.encoded_size() as u16;
 		if base < k_encoded {
 			None
@@ -421,6 +473,9 @@ impl ValueTable {
 		let mut part = 0;
 		let mut compressed = false;
 		let mut rc = 1;
+		// Stored checksum and the checksum of the parts read so far.
+		let mut checksum = None;
+		let first = index;
 		let entry_size = self.entry_size as usize;
 		loop {
 			let buf = if log.value(self.id, index, buf.as_mut()) {
@@ -461,6 +516,10 @@ impl ValueTable {
 				if self.ref_counted {
 					rc = buf.read_rc();
 				}
+				if self.checksums {
+					checksum = Some((buf.read_u32(), crc32fast::Hasher::new()));
+				}
+				let key_start = buf.offset();
 				match key {
 					TableKeyQuery::Fetch(Some(to_fetch)) => {
 						**to_fetch = TableKey::fetch_partial(buf)?;
@@ -482,6 +541,9 @@ impl ValueTable {
 						}
 					},
 				}
+				if let Some((_, hasher)) = &mut checksum {
+					hasher.update(&buf[key_start..buf.offset()]);
+				}
 			}
 
 			if buf.offset() > entry_end {
@@ -491,11 +553,26 @@ impl ValueTable {
 				)))
 			}
 
-			if !f(buf.remaining_to(entry_end)) {
+			let data = buf.remaining_to(entry_end);
+			if !f(data) {
 				break
 			};
+			if let Some((_, hasher)) = &mut checksum {
+				hasher.update(data);
+			}
 
 			if next == 0 {
+				if let Some((stored, hasher)) = checksum {
+					let computed = hasher.finalize();
+					if stored != computed {
+						return Err(crate::error::Error::ChecksumMismatch {
+							table: self.id.file_name(),
+							offset: first * self.entry_size as u64,
+							stored,
+							computed,
+						})
+					}
+				}
 				break
 			}
 			part += 1;
@@ -536,6 +613,20 @@ impl ValueTable {
 		Ok(None)
 	}
 
+	/// Pass the parts of the value at `index` to `f` as they are read, if the key matches. `f`
+	/// may return `false` to stop reading. Returns whether the value is compressed, or `None` if
+	/// there is no value for the key.
+	pub fn for_each_part(
+		&self,
+		key: &TableKey,
+		index: u64,
+		log: &impl LogQuery,
+		f: impl FnMut(&[u8]) -> bool,
+	) -> Result<Option<bool>> {
+		let (rc, compressed) = self.for_parts(&mut TableKeyQuery::Check(key), index, log, f)?;
+		Ok((rc > 0).then_some(compressed))
+	}
+
 	#[allow(clippy::type_complexity)]
 	pub fn get_with_meta(
 		&self,
@@ -569,7 +660,7 @@ impl ValueTable {
 		Ok(None)
 	}
 
-	pub fn has_key_at(&self, index: u64, key: &TableKey, log: &LogWriter) -> Result<bool> {
+	pub fn has_key_at(&self, index: u64, key: &TableKey, log: &impl LogQuery) -> Result<bool> {
 		match key {
 			TableKey::Partial(k) => Ok(match self.partial_key_at(index, log)? {
 				Some(existing_key) => &existing_key[..] == key::partial_key(k),
@@ -612,7 +703,7 @@ impl ValueTable {
 		Ok(buf.read_next())
 	}
 
-	pub fn read_next_part(&self, index: u64, log: &LogWriter) -> Result<Option<u64>> {
+	pub fn read_next_part(&self, index: u64, log: &impl LogQuery) -> Result<Option<u64>> {
 		let mut buf = PartialEntry::new_uninit();
 		if !log.value(self.id, index, buf.as_mut()) {
 			self.file.read_at(buf.as_mut(), index * self.entry_size as u64)?;
@@ -660,7 +751,8 @@ impl ValueTable {
 		at: Option<u64>,
 		compressed: bool,
 	) -> Result<u64> {
-		let mut remainder = value.len() + self.ref_size() + key.encoded_size();
+		let mut remainder =
+			value.len() + self.ref_size() + self.checksum_size() + key.encoded_size();
 		let mut offset = 0;
 		let mut start = 0;
 		assert!(self.multipart || value.len() <= self.value_size(key).unwrap() as usize);
@@ -715,6 +807,12 @@ impl ValueTable {
 					// first rc.
 					buf.write_rc(1u32);
 				}
+				if self.checksums {
+					let mut hasher = crc32fast::Hasher::new();
+					hasher.update(key.encoded());
+					hasher.update(value);
+					buf.write_u32(hasher.finalize());
+				}
 				key.write(&mut buf);
 			}
 			let written = buf.offset() - init_offset;
@@ -753,6 +851,262 @@ impl ValueTable {
 		}
 	}
 
+	/// Free all slots in `start..end` that are not free already, regardless of what they hold.
+	/// Returns the number of freed slots.
+	pub fn write_free_range_plan(&self, start: u64, end: u64, log: &mut LogWriter) -> Result<u64> {
+		let end = std::cmp::min(end, self.filled.load(Ordering::Relaxed));
+		let mut freed = 0;
+		for index in std::cmp::max(start, 1)..end {
+			if !self.is_tombstone(index, &*log)? {
+				self.clear_slot(index, log)?;
+				freed += 1;
+			}
+		}
+		Ok(freed)
+	}
+
+	/// Slots below the fill mark that hold removed entries, in increasing order.
+	pub fn free_slots(&self, log: &impl LogQuery) -> Result<Vec<u64>> {
+		let mut free = Vec::new();
+		for index in 1..self.filled() {
+			if self.is_tombstone(index, log)? {
+				free.push(index);
+			}
+		}
+		Ok(free)
+	}
+
+	/// Plan copying the entry in slot `from` to the free slot `to`. Slot `from` is left as is,
+	/// the caller updates whatever references it.
+	pub fn write_move_plan(&self, from: u64, to: u64, log: &mut LogWriter) -> Result<()> {
+		let mut buf = FullEntry::new_uninit_full_entry();
+		if !log.value(self.id, from, buf.as_mut()) {
+			self.file
+				.read_at(&mut buf[0..self.entry_size as usize], from * self.entry_size as u64)?;
+		}
+		if buf.is_tombstone() {
+			return Err(crate::error::Error::Corruption(format!(
+				"{}: Moving removed entry {}",
+				self.id, from
+			)))
+		}
+		let size = if self.multipart && buf.is_multi(self.db_version) {
+			self.entry_size as usize
+		} else {
+			let (size, _compressed) = buf.read_size();
+			buf.offset() + size as usize
+		};
+		log::trace!(target: "parity-db", "{}: Moving slot {} to {}", self.id, from, to);
+		log.insert_value(self.id, to, buf[0..size].to_vec());
+		Ok(())
+	}
+
+	/// Plan lowering the fill mark to `filled`, dropping all slots from there on. The removed
+	/// entries list is rebuilt from the `free` slots, which must all be below the new mark.
+	pub fn write_truncate_plan(
+		&self,
+		filled: u64,
+		free: &[u64],
+		log: &mut LogWriter,
+	) -> Result<()> {
+		self.filled.store(filled, Ordering::Relaxed);
+		self.last_removed.store(0, Ordering::Relaxed);
+		self.dirty_header.store(true, Ordering::Relaxed);
+		// Lowest slots are reused first.
+		for index in free.iter().rev() {
+			self.clear_slot(*index, log)?;
+		}
+		Ok(())
+	}
+
+	/// Truncate the file after the last filled slot. All changes to this table must be enacted
+	/// first. Returns the number of released bytes.
+	pub fn shrink_file(&self) -> Result<u64> {
+		self.file.shrink(self.filled(), self.entry_size)
+	}
+
+	/// Check the removed entries list. A cycle is detected with Floyd's algorithm. With `repair`
+	/// the list is truncated at the first corrupt pointer. Slots past that point are leaked.
+	pub fn fsck(&self, repair: bool, log: &mut LogWriter) -> Result<FsckReport> {
+		let mut report = FsckReport::default();
+		let filled = self.filled.load(Ordering::Relaxed);
+		// Next list entry, or `None` if `index` is not a removed slot.
+		let next = |index: u64, log: &LogWriter| -> Result<Option<u64>> {
+			if index == 0 || index >= filled || !self.is_tombstone(index, log)? {
+				return Ok(None)
+			}
+			Ok(Some(self.read_next_free(index, log)?))
+		};
+
+		let head = self.last_removed.load(Ordering::Relaxed);
+		// Slot holding the first corrupt pointer. 0 stands for the header.
+		let mut corrupt_at = None;
+		let mut prev = 0;
+		let mut slow = head;
+		let mut fast = Some(head);
+		while slow != 0 {
+			let next_slow = match next(slow, log)? {
+				Some(n) => n,
+				None => {
+					corrupt_at = Some(prev);
+					break
+				},
+			};
+			report.healthy += 1;
+			prev = slow;
+			slow = next_slow;
+			for _ in 0..2 {
+				fast = match fast {
+					Some(f) if f != 0 => next(f, log)?,
+					_ => None,
+				};
+			}
+			if slow != 0 && fast == Some(slow) {
+				// Find the start of the cycle and the entry that points back to it.
+				let mut start = head;
+				let mut meet = slow;
+				while start != meet {
+					start = next(start, log)?.unwrap_or(0);
+					meet = next(meet, log)?.unwrap_or(0);
+				}
+				let mut last = start;
+				loop {
+					match next(last, log)? {
+						Some(n) if n != start && n != 0 => last = n,
+						_ => break,
+					}
+				}
+				corrupt_at = Some(last);
+				break
+			}
+		}
+
+		if let Some(at) = corrupt_at {
+			log::warn!(target: "parity-db", "{}: Corrupt free list pointer in slot {}", self.id, at);
+			report.corrupt += 1;
+			if repair {
+				if at == 0 {
+					self.last_removed.store(0, Ordering::Relaxed);
+					self.dirty_header.store(true, Ordering::Relaxed);
+				} else {
+					let mut buf = PartialEntry::new_uninit();
+					buf.write_tombstone();
+					buf.write_next(0);
+					log.insert_value(self.id, at, buf[0..buf.offset()].to_vec());
+				}
+				report.repaired += 1;
+			}
+		}
+		let overlapping = self.verify_no_overlap(&*log)?;
+		for (a, b) in &overlapping {
+			log::warn!(target: "parity-db", "{}: Values at offsets {} and {} overlap", self.id, a, b);
+		}
+		report.overlapping = overlapping.len() as u64;
+		Ok(report)
+	}
+
+	/// Check that no entry is part of more than one value, e.g. because a multipart value links
+	/// to an entry of another value. Each value is followed from its first entry and the entries
+	/// are sorted by offset, so that shared entries end up next to each other. Returns pairs of
+	/// file offsets of the first entries of values that overlap. A value stored in a single entry
+	/// that another value links to can't be told apart from a last part, and is not reported.
+	pub fn verify_no_overlap(&self, log: &impl LogQuery) -> Result<Vec<(u64, u64)>> {
+		let filled = self.filled.load(Ordering::Relaxed);
+		// Next part of each multipart entry, and whether the entry may be the first part of a
+		// value.
+		let mut next = vec![None; filled as usize];
+		let mut head = vec![false; filled as usize];
+		let mut linked = vec![false; filled as usize];
+		for index in 1..filled {
+			let mut buf = PartialEntry::new_uninit();
+			if !log.value(self.id, index, buf.as_mut()) {
+				self.file.read_at(buf.as_mut(), index * self.entry_size as u64)?;
+			}
+			if buf.is_tombstone() {
+				continue
+			}
+			if self.multipart && buf.is_multi(self.db_version) {
+				head[index as usize] = buf.is_multihead() || buf.is_multihead_v4();
+				buf.skip_size();
+				let n = buf.read_next();
+				next[index as usize] = Some(n);
+				if n < filled {
+					linked[n as usize] = true;
+				}
+			} else {
+				head[index as usize] = true;
+			}
+		}
+
+		// (entry, first entry of the value it belongs to)
+		let mut parts = Vec::new();
+		for first in 1..filled {
+			let i = first as usize;
+			// A linked single entry is the last part of another value.
+			if !head[i] || (linked[i] && next[i].is_none()) {
+				continue
+			}
+			let mut index = first;
+			let mut visited = std::collections::HashSet::new();
+			while index != 0 && index < filled && visited.insert(index) {
+				parts.push((index, first));
+				index = next[index as usize].unwrap_or(0);
+			}
+		}
+		parts.sort_unstable();
+		let entry_size = self.entry_size as u64;
+		let mut overlapping = Vec::new();
+		for pair in parts.windows(2) {
+			let ((a, first_a), (b, first_b)) = (pair[0], pair[1]);
+			if a == b && first_a != first_b {
+				let overlap = (first_a * entry_size, first_b * entry_size);
+				if !overlapping.contains(&overlap) {
+					overlapping.push(overlap);
+				}
+			}
+		}
+		Ok(overlapping)
+	}
+
+	/// Free all occupied entries that are not reachable from `heads`. Continuation parts of
+	/// multipart values are followed, as only the first part is referenced by the index.
+	/// Returns the number of freed entries.
+	pub fn write_garbage_plan(
+		&self,
+		heads: impl IntoIterator<Item = u64>,
+		log: &mut LogWriter,
+	) -> Result<u64> {
+		let orphaned = self.orphaned_slots(heads, log)?;
+		for index in &orphaned {
+			log::debug!(target: "parity-db", "{}: Freeing orphaned slot {}", self.id, index);
+			self.clear_slot(*index, log)?;
+		}
+		Ok(orphaned.len() as u64)
+	}
+
+	/// Occupied slots that don't belong to any of the values starting at `heads`.
+	pub fn orphaned_slots(
+		&self,
+		heads: impl IntoIterator<Item = u64>,
+		log: &impl LogQuery,
+	) -> Result<Vec<u64>> {
+		let filled = self.filled.load(Ordering::Relaxed);
+		let mut live = std::collections::HashSet::new();
+		for head in heads {
+			let mut index = head;
+			while index != 0 && index < filled && live.insert(index) {
+				index = self.read_next_part(index, log)?.unwrap_or(0);
+			}
+		}
+		let mut orphaned = Vec::new();
+		for index in 1..filled {
+			if !live.contains(&index) && !self.is_tombstone(index, log)? {
+				orphaned.push(index);
+			}
+		}
+		Ok(orphaned)
+	}
+
 	fn clear_slot(&self, index: u64, log: &mut LogWriter) -> Result<()> {
 		let last_removed = self.last_removed.load(Ordering::Relaxed);
 		log::trace!(
@@ -886,6 +1240,7 @@ impl ValueTable {
 			log::trace!(target: "parity-db", "{}: Enacted multipart in slot {}", self.id, index);
 		} else {
 			let (len, _compressed) = buf.read_size();
+			self.check_logged_size(len)?;
 			log.read(&mut buf[SIZE_SIZE..SIZE_SIZE + len as usize])?;
 			self.file
 				.write_at(&buf[0..(SIZE_SIZE + len as usize)], index * (self.entry_size as u64))?;
@@ -894,6 +1249,18 @@ impl ValueTable {
 		Ok(())
 	}
 
+	pub fn enact_delete_plan(&self, index: u64, log: &mut LogReader) -> Result<()> {
+		while index >= self.file.capacity.load(Ordering::Relaxed) {
+			self.file.grow(self.entry_size)?;
+		}
+		let mut buf = [0u8; SIZE_SIZE + INDEX_SIZE];
+		buf[0..SIZE_SIZE].copy_from_slice(TOMBSTONE);
+		log.read(&mut buf[SIZE_SIZE..])?;
+		self.file.write_at(&buf, index * (self.entry_size as u64))?;
+		log::trace!(target: "parity-db", "{}: Enacted tombstone in slot {}", self.id, index);
+		Ok(())
+	}
+
 	pub fn validate_plan(&self, index: u64, log: &mut LogReader) -> Result<()> {
 		if index == 0 {
 			let mut header = Header::default();
@@ -911,14 +1278,35 @@ impl ValueTable {
 			log.read(&mut buf[SIZE_SIZE..entry_size])?;
 			log::trace!(target: "parity-db", "{}: Validated multipart in slot {}", self.id, index);
 		} else {
-			// TODO: check len
 			let (len, _compressed) = buf.read_size();
+			self.check_logged_size(len)?;
 			log.read(&mut buf[SIZE_SIZE..SIZE_SIZE + len as usize])?;
 			log::trace!(target: "parity-db", "{}: Validated {}: {}, {} bytes", self.id, index, hex(&buf[SIZE_SIZE..32]), len);
 		}
 		Ok(())
 	}
 
+	// An entry in the log must fit a slot of this table.
+	fn check_logged_size(&self, len: u16) -> Result<()> {
+		if SIZE_SIZE + len as usize > self.entry_size as usize {
+			return Err(crate::error::Error::Corruption(format!(
+				"{}: Logged entry of {} bytes does not fit",
+				self.id, len
+			)))
+		}
+		Ok(())
+	}
+
+	pub fn validate_delete_plan(&self, index: u64, log: &mut LogReader) -> Result<()> {
+		if index == 0 {
+			return Err(crate::error::Error::Corruption("Deleting value table header".into()))
+		}
+		let mut next = [0u8; INDEX_SIZE];
+		log.read(&mut next)?;
+		log::trace!(target: "parity-db", "{}: Validated tombstone in slot {}", self.id, index);
+		Ok(())
+	}
+
 	pub fn refresh_metadata(&self) -> Result<()> {
 		if self.file.file.read().is_none() {
 			return Ok(())
@@ -935,6 +1323,23 @@ impl ValueTable {
 		Ok(())
 	}
 
+	/// Save the allocation state, so that planned changes can be dropped with `rollback`.
+	pub fn checkpoint(&self) -> TableCheckpoint {
+		TableCheckpoint {
+			filled: self.filled.load(Ordering::Relaxed),
+			last_removed: self.last_removed.load(Ordering::Relaxed),
+			dirty_header: self.dirty_header.load(Ordering::Relaxed),
+		}
+	}
+
+	/// Restore the allocation state saved by `checkpoint`. Changes planned since then must be
+	/// discarded along with their log record.
+	pub fn rollback(&self, checkpoint: &TableCheckpoint) {
+		self.filled.store(checkpoint.filled, Ordering::Relaxed);
+		self.last_removed.store(checkpoint.last_removed, Ordering::Relaxed);
+		self.dirty_header.store(checkpoint.dirty_header, Ordering::Relaxed);
+	}
+
 	pub fn complete_plan(&self, log: &mut LogWriter) -> Result<()> {
 		if let Ok(true) =
 			self.dirty_header
@@ -963,36 +1368,221 @@ impl ValueTable {
 		}
 	}
 
+	fn checksum_size(&self) -> usize {
+		if self.checksums {
+			CHECKSUM_SIZE
+		} else {
+			0
+		}
+	}
+
 	pub fn iter_while(
 		&self,
 		log: &impl LogQuery,
 		mut f: impl FnMut(u64, u32, Vec<u8>, bool) -> bool,
 	) -> Result<()> {
 		let filled = self.filled.load(Ordering::Relaxed);
-		for index in 1..filled {
-			let mut result = Vec::new();
-			// expect only indexed key.
-			let mut _fetch_key = Default::default();
-			match self.for_parts(
-				&mut TableKeyQuery::Fetch(Some(&mut _fetch_key)),
-				index,
-				log,
-				|buf| {
-					result.extend_from_slice(buf);
-					true
-				},
-			) {
-				Ok((rc, compressed)) =>
-					if rc > 0 && !f(index, rc, result, compressed) {
-						break
-					},
-				Err(crate::error::Error::InvalidValueData) => (), // ignore, can be external index.
-				Err(e) => return Err(e),
+		let batch = (MAX_BULK_READ_SIZE / self.entry_size as usize).max(1) as u64;
+		let mut start = 1;
+		while start < filled {
+			let end = (start + batch).min(filled);
+			let offsets: Vec<u64> = (start..end).collect();
+			let mut done = false;
+			self.bulk_for_each(&offsets, log, |index, entry| {
+				match entry {
+					Ok(Some((value, rc, _key, compressed))) =>
+						if !f(index, rc, value, compressed) {
+							done = true;
+							return Ok(false)
+						},
+					Ok(None) => (),
+					// Ignore, can be external index.
+					Err(crate::error::Error::InvalidValueData) => (),
+					Err(e) => return Err(e),
+				}
+				Ok(true)
+			})?;
+			if done {
+				break
 			}
+			start = end;
 		}
 		Ok(())
 	}
 
+	/// Read entries at the given slot indices. Indices are sorted and runs of adjacent
+	/// slots are fetched with a single read. Results are returned in the order of `offsets`.
+	#[allow(clippy::type_complexity)]
+	pub fn bulk_read(
+		&self,
+		offsets: &[u64],
+		log: &impl LogQuery,
+	) -> Result<Vec<Option<(Value, u32, [u8; PARTIAL_SIZE], bool)>>> {
+		let mut result = vec![None; offsets.len()];
+		let mut positions: std::collections::HashMap<u64, Vec<usize>> = Default::default();
+		for (i, offset) in offsets.iter().enumerate() {
+			positions.entry(*offset).or_default().push(i);
+		}
+		let mut sorted: Vec<u64> = positions.keys().cloned().collect();
+		sorted.sort_unstable();
+		self.bulk_for_each(&sorted, log, |index, entry| {
+			let entry = entry?;
+			for i in &positions[&index] {
+				result[*i] = entry.clone();
+			}
+			Ok(true)
+		})?;
+		Ok(result)
+	}
+
+	// Fetch sorted, deduplicated `offsets` run by run and pass each decoded entry to `f`.
+	// Stops when `f` returns `false`.
+	#[allow(clippy::type_complexity)]
+	fn bulk_for_each(
+		&self,
+		offsets: &[u64],
+		log: &impl LogQuery,
+		mut f: impl FnMut(u64, Result<Option<(Value, u32, [u8; PARTIAL_SIZE], bool)>>) -> Result<bool>,
+	) -> Result<()> {
+		let entry_size = self.entry_size as usize;
+		let max_run = (MAX_BULK_READ_SIZE / entry_size).max(1);
+		let mut buf = Vec::new();
+		let mut run_start = 0;
+		while run_start < offsets.len() {
+			let mut run_end = run_start + 1;
+			while run_end < offsets.len() &&
+				run_end - run_start < max_run &&
+				offsets[run_end] == offsets[run_end - 1] + 1
+			{
+				run_end += 1;
+			}
+			let first = offsets[run_start];
+			// Entries past the end of the file may only exist in the log.
+			let in_file = self.file.capacity.load(Ordering::Relaxed).saturating_sub(first);
+			let len = std::cmp::min((run_end - run_start) as u64, in_file) as usize * entry_size;
+			buf.resize(len, 0u8);
+			log::trace!(
+				target: "parity-db",
+				"{}: Query slots {}..{}",
+				self.id,
+				first,
+				first + (run_end - run_start) as u64,
+			);
+			if len > 0 {
+				self.file.read_at(&mut buf[..len], first * entry_size as u64)?;
+			}
+			let prefetched =
+				Prefetched { log, table: self.id, entry_size, first, data: &buf[..len] };
+			for index in &offsets[run_start..run_end] {
+				if !f(*index, self.get_with_meta(*index, &prefetched))? {
+					return Ok(())
+				}
+			}
+			run_start = run_end;
+		}
+		Ok(())
+	}
+
+	/// Iterate over all occupied slots in file order. Slot content is returned as is, with
+	/// no key check and no handling of multipart values. Pending log changes are ignored.
+	pub fn raw_iter(&self) -> RawSlotIter<'_> {
+		let filled = if self.is_init() { self.filled.load(Ordering::Relaxed) } else { 1 };
+		RawSlotIter { table: self, index: 1, filled, buf: vec![0; self.entry_size as usize] }
+	}
+
+	/// Delete the table file and reset the table to the empty state.
+	pub fn clear(&self) -> Result<()> {
+		self.file.remove()?;
+		self.file.capacity.store(0, Ordering::Relaxed);
+		self.filled.store(1, Ordering::Relaxed);
+		self.last_removed.store(0, Ordering::Relaxed);
+		self.dirty_header.store(false, Ordering::Relaxed);
+		Ok(())
+	}
+
+	/// Release disk blocks taken by removed entries. Removed entries keep their header, which
+	/// links them into the removed entries list, so only page aligned ranges past the header are
+	/// released. This only applies to tables with entries larger than a page. Entries with
+	/// changes pending in the log are skipped. Returns the number of released bytes.
+	#[cfg(target_os = "linux")]
+	pub fn punch_holes(&self, log: &impl LogQuery) -> Result<u64> {
+		let entry_size = self.entry_size as u64;
+		let align_up = |offset: u64| offset.div_ceil(PUNCH_HOLE_ALIGN) * PUNCH_HOLE_ALIGN;
+		let align_down = |offset: u64| offset / PUNCH_HOLE_ALIGN * PUNCH_HOLE_ALIGN;
+		let mut released = 0;
+		if entry_size < PUNCH_HOLE_ALIGN {
+			return Ok(released)
+		}
+		for index in 1..self.filled() {
+			let mut buf = PartialEntry::new_uninit();
+			if log.value(self.id, index, buf.as_mut()) {
+				continue
+			}
+			self.file.read_at(buf.as_mut(), index * entry_size)?;
+			if !buf.is_tombstone() {
+				continue
+			}
+			let start = align_up(index * entry_size + (SIZE_SIZE + INDEX_SIZE) as u64);
+			let end = align_down((index + 1) * entry_size);
+			if end > start {
+				if !self.file.punch_hole(start, end - start)? {
+					log::debug!(target: "parity-db", "{}: Hole punching is not supported", self.id);
+					break
+				}
+				released += end - start;
+			}
+		}
+		Ok(released)
+	}
+
+	#[cfg(not(target_os = "linux"))]
+	pub fn punch_holes(&self, _log: &impl LogQuery) -> Result<u64> {
+		Ok(0)
+	}
+
+	/// Number of removed entries below the fill mark. This reads every entry.
+	pub fn count_free(&self, log: &impl LogQuery) -> Result<u64> {
+		let mut free = 0;
+		for index in 1..self.filled() {
+			if self.is_tombstone(index, log)? {
+				free += 1;
+			}
+		}
+		Ok(free)
+	}
+
+	/// Number of values stored in the table. A multipart value is counted once, however many
+	/// entries it takes. This reads every entry.
+	pub fn count_values(&self, log: &impl LogQuery) -> Result<u64> {
+		let mut live = 0u64;
+		let mut linked = 0u64;
+		for index in 1..self.filled() {
+			let mut buf = PartialEntry::new_uninit();
+			if !log.value(self.id, index, buf.as_mut()) {
+				self.file.read_at(buf.as_mut(), index * self.entry_size as u64)?;
+			}
+			if buf.is_tombstone() {
+				continue
+			}
+			live += 1;
+			// All parts but the last one link to the next part.
+			if self.multipart && buf.is_multi(self.db_version) {
+				linked += 1;
+			}
+		}
+		Ok(live.saturating_sub(linked))
+	}
+
+	/// Values too large for a single entry are split over multiple entries of this table.
+	pub fn is_multipart(&self) -> bool {
+		self.multipart
+	}
+
+	/// Number of allocated entries, including the reserved header entry.
+	pub fn filled(&self) -> u64 {
+		self.filled.load(Ordering::Relaxed)
+	}
+
 	pub fn is_init(&self) -> bool {
 		self.file.file.read().is_some()
 	}
@@ -1023,6 +1613,66 @@ impl ValueTable {
 	}
 }
 
+pub struct RawSlotIter<'a> {
+	table: &'a ValueTable,
+	index: u64,
+	filled: u64,
+	buf: Vec<u8>,
+}
+
+impl<'a> RawSlotIter<'a> {
+	/// Returns next non-deleted slot index and its raw content.
+	#[allow(clippy::should_implement_trait)]
+	pub fn next(&mut self) -> Result<Option<(u64, &[u8])>> {
+		while self.index < self.filled {
+			let index = self.index;
+			self.index += 1;
+			self.table.file.read_at(&mut self.buf, index * self.table.entry_size as u64)?;
+			if &self.buf[0..SIZE_SIZE] != TOMBSTONE {
+				return Ok(Some((index, &self.buf)))
+			}
+		}
+		Ok(None)
+	}
+}
+
+// Serves value table reads from a block of slots that was already read from disk.
+// Pending log changes take priority.
+struct Prefetched<'a, L: LogQuery> {
+	log: &'a L,
+	table: TableId,
+	entry_size: usize,
+	first: u64,
+	data: &'a [u8],
+}
+
+impl<'a, L: LogQuery> LogQuery for Prefetched<'a, L> {
+	fn with_index<R, F: FnOnce(&crate::index::Chunk) -> R>(
+		&self,
+		table: crate::index::TableId,
+		index: u64,
+		f: F,
+	) -> Option<R> {
+		self.log.with_index(table, index, f)
+	}
+
+	fn value(&self, table: TableId, index: u64, dest: &mut [u8]) -> bool {
+		if self.log.value(table, index, dest) {
+			return true
+		}
+		if table != self.table || index < self.first {
+			return false
+		}
+		let start = (index - self.first) as usize * self.entry_size;
+		if start >= self.data.len() {
+			return false
+		}
+		let len = dest.len().min(self.entry_size);
+		dest[..len].copy_from_slice(&self.data[start..start + len]);
+		true
+	}
+}
+
 pub mod key {
 	use super::FullEntry;
 	use crate::{Key, Result};
@@ -1046,6 +1696,13 @@ pub mod key {
 			}
 		}
 
+		pub fn encoded(&self) -> &[u8] {
+			match self {
+				TableKey::Partial(k) => partial_key(k),
+				TableKey::NoHash => &[],
+			}
+		}
+
 		pub fn index_from_partial(partial: &[u8]) -> u64 {
 			u64::from_be_bytes((partial[0..8]).try_into().unwrap())
 		}
@@ -1104,7 +1761,7 @@ pub mod key {
 mod test {
 	const ENTRY_SIZE: u16 = 64;
 
-	use super::{TableId, Value, ValueTable};
+	use super::{FsckReport, TableId, Value, ValueTable};
 	use crate::{
 		log::{Log, LogAction, LogWriter},
 		options::{ColumnOptions, Options, CURRENT_VERSION},
@@ -1136,7 +1793,12 @@ mod test {
 			match reader.next().unwrap() {
 				LogAction::BeginRecord |
 				LogAction::InsertIndex { .. } |
-				LogAction::DropTable { .. } => {
+				LogAction::DeleteIndex { .. } |
+				LogAction::DropTable { .. } |
+				LogAction::Sequence(_) |
+				LogAction::Barrier(_) |
+				LogAction::CreateColumn { .. } |
+				LogAction::DropColumn { .. } => {
 					panic!("Unexpected log entry");
 				},
 				LogAction::EndRecord => {
@@ -1147,6 +1809,9 @@ mod test {
 				LogAction::InsertValue(insertion) => {
 					table.enact_plan(insertion.index, &mut reader).unwrap();
 				},
+				LogAction::DeleteValue { index, .. } => {
+					table.enact_delete_plan(index, &mut reader).unwrap();
+				},
 			}
 		}
 	}
@@ -1247,6 +1912,163 @@ mod test {
 		assert_eq!(table.last_removed.load(Ordering::Relaxed), 0);
 	}
 
+	#[test]
+	fn tail_mmap() {
+		let dir = tempdir().unwrap();
+		let table = new_table(&dir, Some(ENTRY_SIZE), &Default::default());
+		let log = new_log(&dir);
+		table.tail_mmap(64 * 1024).unwrap();
+		assert_eq!(table.file.tail_mapped_bytes(), 0);
+
+		// Grows the file past several preallocation steps.
+		let values: Vec<_> = (0..10_000).map(|_| value(19)).collect();
+		write_ops(&table, &log, |writer| {
+			for (i, val) in values.iter().enumerate() {
+				let key = TableKey::Partial(key(i as u32));
+				assert_eq!(
+					table.write_insert_plan(&key, val, writer, false).unwrap(),
+					i as u64 + 1
+				);
+			}
+		});
+		// The window and the space preallocated after it.
+		let mapped = table.file.tail_mapped_bytes();
+		assert!((320 * 1024..384 * 1024).contains(&mapped));
+
+		// Entries at both ends of the file, read with a system call and from the map.
+		for i in [0, 9_999] {
+			let key = TableKey::Partial(key(i as u32));
+			let expected = Some((values[i].clone(), false));
+			assert_eq!(table.get(&key, i as u64 + 1, log.overlays()).unwrap(), expected);
+		}
+
+		// Writes are visible through the map.
+		let key = TableKey::Partial(key(9_999));
+		let val = value(19);
+		write_ops(&table, &log, |writer| {
+			table.write_replace_plan(10_000, &key, &val, writer, false).unwrap();
+		});
+		assert_eq!(table.get(&key, 10_000, log.overlays()).unwrap(), Some((val, false)));
+
+		table.tail_mmap(0).unwrap();
+		assert_eq!(table.file.tail_mapped_bytes(), 0);
+	}
+
+	#[test]
+	fn raw_iter_skips_removed() {
+		let dir = tempdir().unwrap();
+		let table = new_table(&dir, Some(ENTRY_SIZE), &Default::default());
+		let log = new_log(&dir);
+		assert!(table.raw_iter().next().unwrap().is_none());
+
+		let key1 = &TableKey::Partial(key(1));
+		let key2 = &TableKey::Partial(key(2));
+		let key3 = &TableKey::Partial(key(3));
+		let val = value(11);
+
+		write_ops(&table, &log, |writer| {
+			table.write_insert_plan(key1, &val, writer, false).unwrap();
+			table.write_insert_plan(key2, &val, writer, false).unwrap();
+			table.write_insert_plan(key3, &val, writer, false).unwrap();
+		});
+		write_ops(&table, &log, |writer| {
+			table.write_remove_plan(2, writer).unwrap();
+		});
+
+		let mut iter = table.raw_iter();
+		let mut indices = Vec::new();
+		while let Some((index, slot)) = iter.next().unwrap() {
+			assert_eq!(slot.len(), ENTRY_SIZE as usize);
+			indices.push(index);
+		}
+		assert_eq!(indices, vec![1, 3]);
+	}
+
+	#[test]
+	fn bulk_read_matches_single_reads() {
+		let dir = tempdir().unwrap();
+		let table = new_table(&dir, Some(ENTRY_SIZE), &rc_options());
+		let log = new_log(&dir);
+
+		write_ops(&table, &log, |writer| {
+			for n in 1..=4u32 {
+				table
+					.write_insert_plan(
+						&TableKey::Partial(key(n)),
+						&value(n as usize),
+						writer,
+						false,
+					)
+					.unwrap();
+			}
+		});
+		write_ops(&table, &log, |writer| {
+			table.write_remove_plan(2, writer).unwrap();
+		});
+
+		let offsets = [4, 1, 2, 3, 1];
+		let bulk = table.bulk_read(&offsets, log.overlays()).unwrap();
+		assert_eq!(bulk.len(), offsets.len());
+		assert!(bulk[2].is_none());
+		for (offset, entry) in offsets.iter().zip(bulk) {
+			assert_eq!(entry, table.get_with_meta(*offset, log.overlays()).unwrap());
+		}
+	}
+
+	#[test]
+	fn fsck_free_list() {
+		let dir = tempdir().unwrap();
+		let table = new_table(&dir, Some(ENTRY_SIZE), &Default::default());
+		let log = new_log(&dir);
+		let fsck = |repair: bool| {
+			let mut report = None;
+			write_ops(&table, &log, |writer| report = Some(table.fsck(repair, writer).unwrap()));
+			report.unwrap()
+		};
+		let set_next = |slot: u64, next: u64| {
+			let mut entry = vec![0xff, 0xff];
+			entry.extend_from_slice(&next.to_le_bytes());
+			write_ops(&table, &log, |writer| writer.insert_value(table.id, slot, entry));
+		};
+
+		write_ops(&table, &log, |writer| {
+			for n in 1..=5u32 {
+				table
+					.write_insert_plan(&TableKey::Partial(key(n)), &value(8), writer, false)
+					.unwrap();
+			}
+		});
+		write_ops(&table, &log, |writer| {
+			table.write_remove_plan(2, writer).unwrap();
+			table.write_remove_plan(4, writer).unwrap();
+			table.complete_plan(writer).unwrap();
+		});
+		// 4 -> 2 -> end
+		assert_eq!(fsck(false), FsckReport { healthy: 2, corrupt: 0, repaired: 0, overlapping: 0 });
+
+		// Cycle, out of bounds and live entry pointers.
+		for next in [4, 100, 3] {
+			set_next(2, next);
+			assert_eq!(
+				fsck(false),
+				FsckReport { healthy: 2, corrupt: 1, repaired: 0, overlapping: 0 }
+			);
+			assert_eq!(
+				fsck(true),
+				FsckReport { healthy: 2, corrupt: 1, repaired: 1, overlapping: 0 }
+			);
+			assert_eq!(
+				fsck(false),
+				FsckReport { healthy: 2, corrupt: 0, repaired: 0, overlapping: 0 }
+			);
+		}
+
+		// Corrupt list head.
+		table.last_removed.store(3, Ordering::Relaxed);
+		assert_eq!(fsck(true), FsckReport { healthy: 0, corrupt: 1, repaired: 1, overlapping: 0 });
+		assert_eq!(table.last_removed.load(Ordering::Relaxed), 0);
+	}
+
 	#[test]
 	fn replace_simple() {
 		replace_simple_inner(&Default::default(), simple_key);
@@ -1408,6 +2230,44 @@ mod test {
 		assert_eq!(table.get(key, 1, log.overlays()).unwrap(), None);
 	}
 
+	#[test]
+	fn verify_no_overlap() {
+		let dir = tempdir().unwrap();
+		let table = new_table(&dir, None, &Default::default());
+		let log = new_log(&dir);
+		let entry_size = table.entry_size as u64;
+		let size = 3 * entry_size as usize;
+		write_ops(&table, &log, |writer| {
+			for n in 1..=3u32 {
+				table
+					.write_insert_plan(&TableKey::Partial(key(n)), &value(size), writer, false)
+					.unwrap();
+			}
+			// Single entry value.
+			table
+				.write_insert_plan(&TableKey::Partial(key(4)), &value(8), writer, false)
+				.unwrap();
+		});
+		let verify = || {
+			let mut overlapping = None;
+			write_ops(&table, &log, |writer| {
+				overlapping = Some(table.verify_no_overlap(&*writer).unwrap())
+			});
+			overlapping.unwrap()
+		};
+		assert!(verify().is_empty());
+
+		// Link the head of the second value to the second part of the first value.
+		let mut head = vec![0xfd, 0xff];
+		head.extend_from_slice(&2u64.to_le_bytes());
+		head.resize(entry_size as usize, 0);
+		write_ops(&table, &log, |writer| writer.insert_value(table.id, 5, head));
+		assert_eq!(verify(), vec![(entry_size, 5 * entry_size)]);
+		let mut report = None;
+		write_ops(&table, &log, |writer| report = Some(table.fsck(false, writer).unwrap()));
+		assert_eq!(report.unwrap().overlapping, 1);
+	}
+
 	#[test]
 	fn multipart_collision() {
 		use super::MAX_ENTRY_SIZE;
@@ -1470,4 +2330,37 @@ mod test {
 			Err(crate::error::Error::Corruption(_))
 		));
 	}
+
+	#[test]
+	fn checksum_mismatch() {
+		let options = ColumnOptions { ref_counted: true, checksums: true, ..Default::default() };
+		for (size, val, corrupt_at) in [
+			(Some(ENTRY_SIZE), value(20), ENTRY_SIZE as u64 + 55),
+			(None, value(10000), 4096 * 2 + 100),
+		] {
+			let dir = tempdir().unwrap();
+			let table = new_table(&dir, size, &options);
+			let log = new_log(&dir);
+			let key = &TableKey::Partial(key(1));
+			assert_eq!(table.value_size(key), Some(table.entry_size - 36));
+
+			write_ops(&table, &log, |writer| {
+				table.write_insert_plan(key, &val, writer, false).unwrap();
+			});
+			// Reference counter changes keep the checksum valid.
+			write_ops(&table, &log, |writer| {
+				table.write_inc_ref(1, writer).unwrap();
+			});
+			assert_eq!(table.get(key, 1, log.overlays()).unwrap(), Some((val.clone(), false)));
+
+			let mut byte = [0u8];
+			table.file.read_at(&mut byte, corrupt_at).unwrap();
+			table.file.write_at(&[byte[0] ^ 1], corrupt_at).unwrap();
+			let log = new_log(&dir);
+			assert!(matches!(
+				table.get(key, 1, log.overlays()),
+				Err(crate::error::Error::ChecksumMismatch { offset, .. }) if offset == table.entry_size as u64
+			));
+		}
+	}
 }
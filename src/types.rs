@@ -0,0 +1,150 @@
+// Copyright 2021-2022 Parity Technologies (UK) Ltd.
+// This file is dual-licensed as Apache-2.0 or MIT.
+
+//! Owned key and value wrappers for use in application types and configuration.
+//!
+//! The database API itself works with plain `[u8; 32]` keys and `Vec<u8>` values. These
+//! wrappers convert to and from them at no cost. With the `serde` feature enabled both are
+//! serialized as base64 strings.
+
+use crate::KEY_SIZE;
+
+/// 32-byte database key.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Key(#[cfg_attr(feature = "serde", serde(with = "base64_bytes"))] pub crate::Key);
+
+/// Database value.
+#[derive(Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Value(#[cfg_attr(feature = "serde", serde(with = "base64_bytes"))] pub crate::Value);
+
+impl std::fmt::Debug for Key {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", crate::display::hex(&self.0))
+	}
+}
+
+impl std::fmt::Debug for Value {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}", crate::display::hex(&self.0))
+	}
+}
+
+impl From<crate::Key> for Key {
+	fn from(key: crate::Key) -> Self {
+		Key(key)
+	}
+}
+
+impl From<Key> for crate::Key {
+	fn from(key: Key) -> Self {
+		key.0
+	}
+}
+
+impl TryFrom<&[u8]> for Key {
+	type Error = std::array::TryFromSliceError;
+
+	fn try_from(key: &[u8]) -> Result<Self, Self::Error> {
+		Ok(Key(<[u8; KEY_SIZE]>::try_from(key)?))
+	}
+}
+
+impl From<crate::Value> for Value {
+	fn from(value: crate::Value) -> Self {
+		Value(value)
+	}
+}
+
+impl From<Value> for crate::Value {
+	fn from(value: Value) -> Self {
+		value.0
+	}
+}
+
+impl From<&[u8]> for Value {
+	fn from(value: &[u8]) -> Self {
+		Value(value.to_vec())
+	}
+}
+
+impl AsRef<[u8]> for Key {
+	fn as_ref(&self) -> &[u8] {
+		&self.0
+	}
+}
+
+impl AsRef<[u8]> for Value {
+	fn as_ref(&self) -> &[u8] {
+		&self.0
+	}
+}
+
+impl std::ops::Deref for Key {
+	type Target = crate::Key;
+
+	fn deref(&self) -> &Self::Target {
+		&self.0
+	}
+}
+
+impl std::ops::Deref for Value {
+	type Target = crate::Value;
+
+	fn deref(&self) -> &Self::Target {
+		&self.0
+	}
+}
+
+#[cfg(feature = "serde")]
+mod base64_bytes {
+	use base64::{engine::general_purpose::STANDARD, Engine};
+	use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+	pub fn serialize<T: AsRef<[u8]>, S: Serializer>(
+		bytes: &T,
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&STANDARD.encode(bytes))
+	}
+
+	pub fn deserialize<'de, T: TryFrom<Vec<u8>>, D: Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<T, D::Error> {
+		let encoded = String::deserialize(deserializer)?;
+		let bytes = STANDARD.decode(encoded.as_bytes()).map_err(D::Error::custom)?;
+		let len = bytes.len();
+		T::try_from(bytes).map_err(|_| D::Error::invalid_length(len, &"a valid key or value"))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::{Key, Value};
+
+	#[test]
+	fn conversions() {
+		let raw = [7u8; 32];
+		let key: Key = raw.into();
+		assert_eq!(crate::Key::from(key), raw);
+		assert_eq!(Key::try_from(&raw[..]).unwrap(), key);
+		assert!(Key::try_from(&raw[1..]).is_err());
+		let value: Value = b"value"[..].into();
+		assert_eq!(value.as_ref(), b"value");
+		assert_eq!(crate::Value::from(value), b"value".to_vec());
+	}
+
+	#[cfg(feature = "serde")]
+	#[test]
+	fn serde_base64() {
+		let key = Key([1u8; 32]);
+		let value = Value(b"hello".to_vec());
+		let encoded = serde_json::to_string(&(key, value.clone())).unwrap();
+		assert_eq!(encoded, "[\"AQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQEBAQE=\",\"aGVsbG8=\"]");
+		assert_eq!(serde_json::from_str::<(Key, Value)>(&encoded).unwrap(), (key, value));
+		assert!(serde_json::from_str::<Key>("\"aGVsbG8=\"").is_err());
+	}
+}